@@ -0,0 +1,233 @@
+//! Formatting log lines straight into PUBLISH packets.
+//!
+//! `LogWriter` implements `fmt::Write` on top of the streaming
+//! `event::Encoder`, so `write!` formats a line directly into the payload of
+//! a QoS 0 PUBLISH without an intermediate string buffer. With the `log`
+//! feature `Logger` is a `log::Log` backend sending a PUBLISH per record over
+//! a `PacketSink`.
+
+use core::{fmt, result::Result};
+
+use crate::{
+    error::EncodeError,
+    event::{Encoder, Event, Field},
+    fixed_header::{FixedHeader, PacketFlags, PacketType},
+    topic::TopicName,
+};
+
+/// A QoS 0 PUBLISH whose payload is written with `fmt::Write`.
+///
+/// A write that does not fit the rest of the buffer returns `fmt::Error` and
+/// leaves the payload as it was, `finish` still encodes the text written
+/// before it.
+#[derive(Debug)]
+pub struct LogWriter<'b> {
+    encoder: Encoder<'b>,
+}
+
+impl<'b> LogWriter<'b> {
+    /// Start a PUBLISH to `topic` in `bytes`.
+    pub fn new(bytes: &'b mut [u8], topic: TopicName) -> Result<Self, EncodeError> {
+        let mut encoder = Encoder::new(bytes)?;
+        encoder.write(Event::FixedHeader(FixedHeader::new(
+            PacketType::Publish,
+            PacketFlags(0),
+            0,
+        )))?;
+        encoder.write(Event::Field(Field::Topic(topic.as_str())))?;
+        Ok(LogWriter { encoder })
+    }
+
+    /// The encoded PUBLISH, which may not start at the beginning of the
+    /// buffer.
+    pub fn finish(mut self) -> Result<&'b [u8], EncodeError> {
+        self.encoder.write(Event::PacketEnd)?;
+        self.encoder.finish()
+    }
+}
+
+impl<'b> fmt::Write for LogWriter<'b> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.encoder
+            .write(Event::PayloadChunk(s.as_bytes()))
+            .map_err(|_| fmt::Error)
+    }
+}
+
+#[cfg(feature = "log")]
+pub use self::logger::Logger;
+
+#[cfg(feature = "log")]
+mod logger {
+    use core::fmt::Write;
+    use std::{sync::Mutex, vec::Vec};
+
+    use log::{LevelFilter, Log, Metadata, Record};
+
+    use super::LogWriter;
+    use crate::{topic::TopicName, transport::PacketSink};
+
+    #[derive(Debug)]
+    struct Inner<S> {
+        sink: S,
+        buf: Vec<u8>,
+    }
+
+    /// A `log::Log` backend publishing each record at QoS 0 to a topic.
+    ///
+    /// Records are formatted as `LEVEL target: message` into a buffer of
+    /// `capacity` bytes, a message too long for it is cut at the last write
+    /// that fit. Logging is best effort, a record the sink refuses or fails
+    /// to send is dropped.
+    #[derive(Debug)]
+    pub struct Logger<S> {
+        topic: TopicName<'static>,
+        level: LevelFilter,
+        inner: Mutex<Inner<S>>,
+    }
+
+    impl<S: PacketSink + Send> Logger<S> {
+        pub fn new(
+            sink: S,
+            topic: TopicName<'static>,
+            level: LevelFilter,
+            capacity: usize,
+        ) -> Self {
+            Logger {
+                topic,
+                level,
+                inner: Mutex::new(Inner {
+                    sink,
+                    buf: vec![0; capacity],
+                }),
+            }
+        }
+
+        /// Give back the sink.
+        pub fn into_inner(self) -> S {
+            let inner = match self.inner.into_inner() {
+                Ok(inner) => inner,
+                Err(poisoned) => poisoned.into_inner(),
+            };
+            inner.sink
+        }
+    }
+
+    impl<S: PacketSink + Send> Log for Logger<S> {
+        fn enabled(&self, metadata: &Metadata) -> bool {
+            metadata.level() <= self.level
+        }
+
+        fn log(&self, record: &Record) {
+            if !self.enabled(record.metadata()) {
+                return;
+            }
+
+            let mut inner = match self.inner.lock() {
+                Ok(inner) => inner,
+                Err(poisoned) => poisoned.into_inner(),
+            };
+            let Inner { sink, buf } = &mut *inner;
+
+            let mut writer = match LogWriter::new(buf, self.topic) {
+                Ok(writer) => writer,
+                Err(_) => return,
+            };
+            let _ = write!(
+                writer,
+                "{} {}: {}",
+                record.level(),
+                record.target(),
+                record.args()
+            );
+            if let Ok(packet) = writer.finish() {
+                let _ = sink.send(packet);
+            }
+        }
+
+        fn flush(&self) {}
+    }
+}
+
+#[cfg(all(test, feature = "decode"))]
+mod tests {
+    use core::{convert::TryFrom, fmt::Write};
+
+    use super::*;
+    use crate::{packet::Packet, payload::Payload, variable_header::VariableHeader};
+
+    fn published(packet: &[u8]) -> (&str, &[u8]) {
+        let packet = Packet::try_from(packet).unwrap();
+        let topic = match packet.variable_header() {
+            Some(VariableHeader::Publish(publish)) => publish.topic_name(),
+            _ => panic!("not a PUBLISH"),
+        };
+        match packet.payload() {
+            Payload::Bytes(payload) => (topic, payload),
+            _ => panic!("not a PUBLISH"),
+        }
+    }
+
+    #[test]
+    fn write() {
+        let mut buf = [0u8; 64];
+        let mut writer = LogWriter::new(&mut buf, TopicName::new("dev/log").unwrap()).unwrap();
+        let firmware = "fw";
+        write!(writer, "boot {} of {}", 3, firmware).unwrap();
+
+        let packet = writer.finish().unwrap();
+        assert_eq!(0x30, packet[0]);
+        assert_eq!(("dev/log", &b"boot 3 of fw"[..]), published(packet));
+    }
+
+    #[test]
+    fn overflow() {
+        let mut buf = [0u8; 16];
+        let mut writer = LogWriter::new(&mut buf, TopicName::new("log").unwrap()).unwrap();
+        writer.write_str("abc").unwrap();
+        assert_eq!(Err(fmt::Error), writer.write_str("defghijk"));
+
+        assert_eq!(("log", &b"abc"[..]), published(writer.finish().unwrap()));
+    }
+
+    #[cfg(feature = "log")]
+    #[test]
+    fn logger() {
+        use log::{Level, LevelFilter, Log, Record};
+        use std::vec::Vec;
+
+        use crate::transport::PacketSink;
+
+        #[derive(Default)]
+        struct Sent(Vec<Vec<u8>>);
+
+        impl PacketSink for Sent {
+            type Error = ();
+
+            fn send(&mut self, packet: &[u8]) -> Result<bool, ()> {
+                self.0.push(packet.to_vec());
+                Ok(true)
+            }
+        }
+
+        let logger = Logger::new(
+            Sent::default(),
+            TopicName::new("dev/log").unwrap(),
+            LevelFilter::Info,
+            32,
+        );
+        for (level, message) in [(Level::Info, "up"), (Level::Debug, "hidden")] {
+            logger.log(
+                &Record::builder()
+                    .level(level)
+                    .target("net")
+                    .args(format_args!("{}", message))
+                    .build(),
+            );
+        }
+
+        let sent = logger.into_inner().0;
+        assert_eq!(1, sent.len());
+        assert_eq!(("dev/log", &b"INFO net: up"[..]), published(&sent[0]));
+    }
+}
@@ -0,0 +1,34 @@
+//! Write the `corpus::seeds` packets to a directory, one file per packet.
+//!
+//! Usage: `mqtt-seed-corpus <dir>`
+
+extern crate embedded_mqtt;
+
+use std::{env, fs, path::PathBuf, process};
+
+fn main() {
+    let dir = match env::args_os().nth(1) {
+        Some(dir) => PathBuf::from(dir),
+        None => {
+            eprintln!("usage: mqtt-seed-corpus <dir>");
+            process::exit(2);
+        }
+    };
+
+    if let Err(e) = run(&dir) {
+        eprintln!("mqtt-seed-corpus: {}", e);
+        process::exit(1);
+    }
+}
+
+fn run(dir: &PathBuf) -> Result<(), Box<dyn std::error::Error>> {
+    let seeds = embedded_mqtt::corpus::seeds()?;
+
+    fs::create_dir_all(dir)?;
+    for seed in &seeds {
+        fs::write(dir.join(&seed.name), &seed.bytes)?;
+    }
+
+    println!("wrote {} seeds to {}", seeds.len(), dir.display());
+    Ok(())
+}
@@ -0,0 +1,141 @@
+//! Time for the timing components.
+//!
+//! Instants are millisecond ticks from a monotonic clock compared with
+//! wrapping arithmetic, so a 32-bit tick counter may overflow freely as long as
+//! the durations measured are shorter than ~24 days.
+//!
+//! The timing components read the time from a `Clock`. Any closure returning
+//! an `Instant` is a clock, so adapting a platform clock such as
+//! `embassy-time` only takes
+//! `|| Instant::from_millis(embassy_time::Instant::now().as_millis() as u32)`.
+//! The `fugit` feature converts millisecond `fugit` timer instants, e.g.
+//! `|| Instant::from(timer.now())`. An `Instant` is also a clock that always
+//! reads the same time, for callers that already took the time.
+
+use core::ops::Add;
+
+/// A point in time from a monotonic millisecond clock.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Default, Debug)]
+pub struct Instant(u32);
+
+impl Instant {
+    pub const fn from_millis(millis: u32) -> Self {
+        Instant(millis)
+    }
+
+    pub const fn as_millis(self) -> u32 {
+        self.0
+    }
+
+    /// The time elapsed from `earlier` to `self`, allowing for the tick
+    /// counter wrapping in between.
+    pub const fn duration_since(self, earlier: Instant) -> Duration {
+        Duration(self.0.wrapping_sub(earlier.0))
+    }
+}
+
+impl Add<Duration> for Instant {
+    type Output = Instant;
+
+    fn add(self, duration: Duration) -> Instant {
+        Instant(self.0.wrapping_add(duration.0))
+    }
+}
+
+/// A span of time in milliseconds.
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Default, Debug)]
+pub struct Duration(u32);
+
+impl Duration {
+    pub const ZERO: Duration = Duration(0);
+
+    pub const fn from_millis(millis: u32) -> Self {
+        Duration(millis)
+    }
+
+    pub const fn from_secs(secs: u32) -> Self {
+        Duration(secs.saturating_mul(1000))
+    }
+
+    pub const fn as_millis(self) -> u32 {
+        self.0
+    }
+
+    pub const fn saturating_sub(self, other: Duration) -> Duration {
+        Duration(self.0.saturating_sub(other.0))
+    }
+}
+
+/// A monotonic clock.
+pub trait Clock {
+    fn now(&self) -> Instant;
+}
+
+impl<F: Fn() -> Instant> Clock for F {
+    fn now(&self) -> Instant {
+        self()
+    }
+}
+
+impl Clock for Instant {
+    fn now(&self) -> Instant {
+        *self
+    }
+}
+
+#[cfg(feature = "fugit")]
+impl From<fugit::TimerInstantU32<1000>> for Instant {
+    fn from(instant: fugit::TimerInstantU32<1000>) -> Self {
+        Instant(instant.ticks())
+    }
+}
+
+#[cfg(feature = "fugit")]
+impl From<fugit::TimerDurationU32<1000>> for Duration {
+    fn from(duration: fugit::TimerDurationU32<1000>) -> Self {
+        Duration(duration.ticks())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::cell::Cell;
+
+    #[test]
+    fn wrapping() {
+        let earlier = Instant::from_millis(u32::MAX - 99);
+        let later = earlier + Duration::from_millis(200);
+        assert_eq!(Instant::from_millis(100), later);
+        assert_eq!(Duration::from_millis(200), later.duration_since(earlier));
+        assert_eq!(
+            Duration::from_millis(u32::MAX),
+            Duration::from_secs(u32::MAX)
+        );
+    }
+
+    #[test]
+    fn closure_clock() {
+        let ticks = Cell::new(5);
+        let clock = || Instant::from_millis(ticks.get());
+        assert_eq!(Instant::from_millis(5), clock.now());
+        ticks.set(6);
+        assert_eq!(Instant::from_millis(6), clock.now());
+        assert_eq!(Instant::from_millis(6), Instant::from_millis(6).now());
+    }
+
+    #[cfg(feature = "fugit")]
+    #[test]
+    fn fugit() {
+        let instant = fugit::TimerInstantU32::<1000>::from_ticks(u32::MAX);
+        let later = instant + fugit::TimerDurationU32::<1000>::millis(2);
+        assert_eq!(
+            Duration::from_millis(2),
+            Instant::from(later).duration_since(Instant::from(instant))
+        );
+        assert_eq!(
+            Duration::from_secs(3),
+            Duration::from(fugit::TimerDurationU32::<1000>::secs(3))
+        );
+    }
+}
@@ -0,0 +1,92 @@
+use core::result::Result;
+
+use crate::error::EncodeError;
+
+use super::{values, Encodable};
+
+/// Writes encoded values one after another into a buffer.
+#[derive(Debug)]
+pub struct EncodeCursor<'b> {
+    bytes: &'b mut [u8],
+    offset: usize,
+}
+
+impl<'b> EncodeCursor<'b> {
+    pub fn new(bytes: &'b mut [u8]) -> Self {
+        EncodeCursor { bytes, offset: 0 }
+    }
+
+    /// Start writing at `offset`, e.g. after space reserved for a header.
+    pub fn at(bytes: &'b mut [u8], offset: usize) -> Result<Self, EncodeError> {
+        if offset > bytes.len() {
+            return Err(EncodeError::OutOfSpace);
+        }
+
+        Ok(EncodeCursor { bytes, offset })
+    }
+
+    /// The offset the next value is written at.
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    /// Encode `value` at the cursor and advance past it.
+    pub fn write<E: Encodable + ?Sized>(&mut self, value: &E) -> Result<usize, EncodeError> {
+        if value.encoded_len() > self.bytes.len() - self.offset {
+            return Err(EncodeError::OutOfSpace);
+        }
+
+        let len = value.encode(&mut self.bytes[self.offset..])?;
+        self.offset += len;
+        Ok(len)
+    }
+
+    pub fn write_u8(&mut self, value: u8) -> Result<usize, EncodeError> {
+        let len = values::encode_u8(value, &mut self.bytes[self.offset..])?;
+        self.offset += len;
+        Ok(len)
+    }
+
+    pub fn write_u16(&mut self, value: u16) -> Result<usize, EncodeError> {
+        let len = values::encode_u16(value, &mut self.bytes[self.offset..])?;
+        self.offset += len;
+        Ok(len)
+    }
+
+    /// Copy `value` to the cursor without a length prefix.
+    pub fn write_raw(&mut self, value: &[u8]) -> Result<usize, EncodeError> {
+        let end = self.offset + value.len();
+        self.bytes
+            .get_mut(self.offset..end)
+            .ok_or(EncodeError::OutOfSpace)?
+            .copy_from_slice(value);
+        self.offset = end;
+        Ok(value.len())
+    }
+
+    /// The whole buffer, including bytes before the cursor.
+    pub fn get_mut(&mut self) -> &mut [u8] {
+        self.bytes
+    }
+
+    pub fn into_inner(self) -> &'b mut [u8] {
+        self.bytes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn write() {
+        let mut buf = [0u8; 8];
+        let mut cursor = EncodeCursor::new(&mut buf);
+        assert_eq!(Ok(5), cursor.write("a/b"));
+        assert_eq!(Ok(2), cursor.write_u16(7));
+        assert_eq!(Err(EncodeError::OutOfSpace), cursor.write_raw(b"{}"));
+        assert_eq!(Ok(1), cursor.write_u8(b'{'));
+        assert_eq!(8, cursor.offset());
+        assert_eq!(b"\x00\x03a/b\x00\x07{", cursor.into_inner());
+    }
+}
@@ -1,3 +1,5 @@
+use core::convert::TryFrom;
+
 use crate::{
     error::{DecodeError, EncodeError},
     status::Status,
@@ -6,6 +8,78 @@ use crate::{
 pub mod string;
 pub mod values;
 
+#[cfg(feature = "encode")]
+mod cursor;
+
+#[cfg(feature = "encode")]
+pub use self::cursor::EncodeCursor;
+
+/// Limits applied while decoding packets from untrusted peers.
+///
+/// The default options apply no limits beyond those of the protocol.
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default)]
+pub struct DecodeOptions {
+    /// Maximum length in bytes of a topic name or filter.
+    pub max_topic_len: Option<usize>,
+    /// Maximum number of `/` separated levels in a topic name or filter.
+    pub max_topic_levels: Option<usize>,
+    /// Maximum number of topic filters in a SUBSCRIBE or UNSUBSCRIBE.
+    pub max_subscriptions: Option<usize>,
+    /// Decode reserved packet types as `PacketType::Reserved` with an opaque
+    /// payload instead of failing, e.g. to forward MQTT 5 AUTH packets.
+    pub allow_reserved_types: bool,
+    /// Take SUBACK return codes without validating them, each is validated as
+    /// `Suback::lazy_return_codes` yields it instead.
+    pub lazy_suback: bool,
+}
+
+impl DecodeOptions {
+    /// Check a decoded topic name or filter against the configured limits.
+    pub fn check_topic(&self, topic: &str) -> Result<(), DecodeError> {
+        if let Some(max) = self.max_topic_len {
+            if topic.len() > max {
+                return Err(DecodeError::TopicTooLong);
+            }
+        }
+
+        if let Some(max) = self.max_topic_levels {
+            if topic.split('/').nth(max).is_some() {
+                return Err(DecodeError::TooManyTopicLevels);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Check the number of length prefixed topic filters in SUBSCRIBE or
+    /// UNSUBSCRIBE payload bytes against the configured limit, `suffix_len` is
+    /// the number of bytes following each filter.
+    ///
+    /// Counting stops as soon as the limit is exceeded, so an oversized packet
+    /// is rejected without parsing its filters. Malformed bytes are left for
+    /// the payload decoder to reject.
+    pub fn check_subscriptions(&self, bytes: &[u8], suffix_len: usize) -> Result<(), DecodeError> {
+        let max = match self.max_subscriptions {
+            Some(max) => max,
+            None => return Ok(()),
+        };
+
+        let mut offset = 0;
+        let mut count = 0;
+        while offset + 2 <= bytes.len() {
+            count += 1;
+            if count > max {
+                return Err(DecodeError::TooManySubscriptions);
+            }
+
+            let filter_len = usize::from(u16::from_be_bytes([bytes[offset], bytes[offset + 1]]));
+            offset += 2 + filter_len + suffix_len;
+        }
+
+        Ok(())
+    }
+}
+
 pub trait Decodable<'a>
 where
     Self: core::marker::Sized,
@@ -17,3 +91,56 @@ pub trait Encodable {
     fn encoded_len(&self) -> usize;
     fn encode(&self, bytes: &mut [u8]) -> Result<usize, EncodeError>;
 }
+
+/// Encodes a packet only if it is no larger than the Maximum Packet Size the
+/// peer advertised in its MQTT 5 CONNECT or CONNACK.
+///
+/// Larger packets must not be sent, encoding them fails with
+/// `EncodeError::PacketTooLarge` before anything is written.
+#[derive(Copy, Clone, Debug)]
+pub struct MaxPacketSize<'a, E: ?Sized> {
+    value: &'a E,
+    max: u32,
+}
+
+impl<'a, E: Encodable + ?Sized> MaxPacketSize<'a, E> {
+    pub fn new(value: &'a E, max: u32) -> Self {
+        MaxPacketSize { value, max }
+    }
+
+    /// Whether the packet fits within the limit.
+    pub fn fits(&self) -> bool {
+        u32::try_from(self.value.encoded_len()).is_ok_and(|len| len <= self.max)
+    }
+}
+
+impl<'a, E: Encodable + ?Sized> Encodable for MaxPacketSize<'a, E> {
+    fn encoded_len(&self) -> usize {
+        self.value.encoded_len()
+    }
+
+    fn encode(&self, bytes: &mut [u8]) -> Result<usize, EncodeError> {
+        if !self.fits() {
+            return Err(EncodeError::PacketTooLarge);
+        }
+
+        self.value.encode(bytes)
+    }
+}
+
+#[cfg(all(test, feature = "encode"))]
+mod tests {
+    use super::*;
+    use crate::packet::Packet;
+
+    #[test]
+    fn max_packet_size() {
+        let mut buf = [0u8; 2];
+        let pingreq = Packet::pingreq();
+        assert_eq!(Ok(2), MaxPacketSize::new(&pingreq, 2).encode(&mut buf));
+
+        let limited = MaxPacketSize::new(&pingreq, 1);
+        assert!(!limited.fits());
+        assert_eq!(Err(EncodeError::PacketTooLarge), limited.encode(&mut buf));
+    }
+}
@@ -5,14 +5,20 @@ use crate::{
     status::Status,
 };
 
-use super::{values, Decodable, Encodable};
+use super::values;
+#[cfg(feature = "decode")]
+use super::Decodable;
+#[cfg(feature = "encode")]
+use super::Encodable;
 
+#[cfg(feature = "decode")]
 impl<'buf> Decodable<'buf> for &'buf str {
     fn decode(bytes: &'buf [u8]) -> Result<Status<(usize, &'buf str)>, DecodeError> {
         parse_string(bytes)
     }
 }
 
+#[cfg(feature = "encode")]
 impl Encodable for str {
     fn encoded_len(&self) -> usize {
         2 + self.len()
@@ -42,7 +48,7 @@ pub fn parse_string(bytes: &[u8]) -> Result<Status<(usize, &str)>, DecodeError>
         //
         // Rust utf-8 decoding also takes care of MQTT-1.5.3-3. U+FEFF does not
         // get ignored/stripped off.
-        str::from_utf8(&bytes[2..(2 + string_len) as usize])?
+        str::from_utf8(&bytes[2..2 + string_len as usize])?
     } else {
         ""
     };
@@ -53,7 +59,7 @@ pub fn parse_string(bytes: &[u8]) -> Result<Status<(usize, &str)>, DecodeError>
         return Err(DecodeError::Utf8);
     }
 
-    Ok(Status::Complete(((2 + string_len) as usize, val)))
+    Ok(Status::Complete((2 + string_len as usize, val)))
 }
 
 pub fn encode_string(string: &str, bytes: &mut [u8]) -> Result<usize, EncodeError> {
@@ -62,12 +68,12 @@ pub fn encode_string(string: &str, bytes: &mut [u8]) -> Result<usize, EncodeErro
         Ok(s) => s,
     };
 
-    if bytes.len() < (2 + size) as usize {
+    if bytes.len() < 2 + size as usize {
         return Err(EncodeError::OutOfSpace);
     }
 
     values::encode_u16(size, &mut bytes[0..2])?;
-    (&mut bytes[2..2 + size as usize]).copy_from_slice(string.as_bytes());
+    bytes[2..2 + size as usize].copy_from_slice(string.as_bytes());
 
     Ok(2 + size as usize)
 }
@@ -83,8 +89,6 @@ mod tests {
 
     use byteorder::{BigEndian, ByteOrder};
 
-    use byteorder::WriteBytesExt;
-
     #[test]
     fn small_buffer() {
         assert_eq!(Ok(Status::Partial(2)), parse_string(&[]));
@@ -103,10 +107,11 @@ mod tests {
     }
 
     #[test]
+    #[allow(clippy::unused_io_amount)]
     fn parse_str() {
         let inp = "don't panic!";
         let mut buf = Cursor::new(Vec::new());
-        buf.write_u16::<BigEndian>(inp.len() as u16).unwrap();
+        buf.write_all(&(inp.len() as u16).to_be_bytes()).unwrap();
         buf.write(inp.as_bytes()).unwrap();
         assert_eq!(
             Status::Complete((14, inp)),
@@ -115,19 +120,21 @@ mod tests {
     }
 
     #[test]
+    #[allow(clippy::unused_io_amount)]
     fn invalid_utf8() {
         let inp = [0, 159, 146, 150];
         let mut buf = Cursor::new(Vec::new());
-        buf.write_u16::<BigEndian>(inp.len() as u16).unwrap();
+        buf.write_all(&(inp.len() as u16).to_be_bytes()).unwrap();
         buf.write(&inp).unwrap();
         assert_eq!(Err(DecodeError::Utf8), parse_string(buf.get_ref().as_ref()));
     }
 
     #[test]
+    #[allow(clippy::unused_io_amount)]
     fn null_utf8() {
         let inp = format!("don't {} panic!", '\u{0000}');
         let mut buf = Cursor::new(Vec::new());
-        buf.write_u16::<BigEndian>(inp.len() as u16).unwrap();
+        buf.write_all(&(inp.len() as u16).to_be_bytes()).unwrap();
         buf.write(inp.as_bytes()).unwrap();
         assert_eq!(Err(DecodeError::Utf8), parse_string(buf.get_ref().as_ref()));
     }
@@ -139,4 +146,15 @@ mod tests {
         assert_eq!(result, Ok(3));
         assert_eq!(buf, [0b00000000, 0b00000001, 0x61]);
     }
+
+    #[test]
+    fn max_len() {
+        let inp = "a".repeat(u16::MAX as usize);
+        let mut buf = vec![0u8; 2 + inp.len()];
+        assert_eq!(Ok(buf.len()), encode_string(&inp, &mut buf));
+        assert_eq!(
+            Ok(Status::Complete((buf.len(), inp.as_str()))),
+            parse_string(&buf)
+        );
+    }
 }
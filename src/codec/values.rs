@@ -5,7 +5,10 @@ use crate::{
     status::Status,
 };
 
-use super::{Decodable, Encodable};
+#[cfg(feature = "decode")]
+use super::Decodable;
+#[cfg(feature = "encode")]
+use super::Encodable;
 
 use byteorder::{BigEndian, ByteOrder};
 
@@ -43,12 +46,14 @@ pub fn encode_u16(value: u16, bytes: &mut [u8]) -> Result<usize, EncodeError> {
     Ok(2)
 }
 
+#[cfg(feature = "decode")]
 impl<'buf> Decodable<'buf> for &'buf [u8] {
     fn decode(bytes: &'buf [u8]) -> Result<Status<(usize, &'buf [u8])>, DecodeError> {
         parse_bytes(bytes)
     }
 }
 
+#[cfg(feature = "encode")]
 impl Encodable for [u8] {
     fn encoded_len(&self) -> usize {
         2 + self.len()
@@ -73,6 +78,7 @@ pub fn parse_bytes(bytes: &[u8]) -> Result<Status<(usize, &[u8])>, DecodeError>
     Ok(Status::Complete((offset + len as usize, payload)))
 }
 
+#[allow(clippy::needless_borrow, clippy::unnecessary_cast)]
 pub fn encode_bytes(value: &[u8], bytes: &mut [u8]) -> Result<usize, EncodeError> {
     let size = match u16::try_from(value.len()) {
         Err(_) => return Err(EncodeError::ValueTooBig),
@@ -0,0 +1,282 @@
+//! Valid encoded packets to seed fuzzing corpora, enabled with the `corpus`
+//! feature.
+//!
+//! `seeds` covers every packet type with each combination of fixed header
+//! flags, QoS and return code the protocol allows. The `mqtt-seed-corpus`
+//! binary writes them to a directory for cargo-fuzz or AFL:
+//!
+//! ```text
+//! cargo run --features corpus --bin mqtt-seed-corpus -- fuzz/corpus/decode
+//! ```
+
+use core::result::Result;
+
+use std::{format, string::String, vec::Vec};
+
+use crate::{
+    codec::Encodable,
+    error::EncodeError,
+    fixed_header::{PacketFlags, PacketType, PublishFlags},
+    packet::{self, Packet},
+    payload, qos,
+    variable_header::{self, packet_identifier::PacketIdentifier, PacketId},
+};
+
+const QOS: [qos::QoS; 3] = [
+    qos::QoS::AtMostOnce,
+    qos::QoS::AtLeastOnce,
+    qos::QoS::ExactlyOnce,
+];
+
+/// A named encoded packet.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Seed {
+    /// A file name describing the packet, unique within `seeds`
+    pub name: String,
+    pub bytes: Vec<u8>,
+}
+
+/// Encode every seed packet.
+pub fn seeds() -> Result<Vec<Seed>, EncodeError> {
+    let mut seeds = Vec::new();
+
+    connect(&mut seeds)?;
+    connack(&mut seeds)?;
+    publish(&mut seeds)?;
+
+    for &(name, r#type, flags) in &[
+        ("puback", PacketType::Puback, PacketFlags::PUBACK),
+        ("pubrec", PacketType::Pubrec, PacketFlags::PUBREC),
+        ("pubrel", PacketType::Pubrel, PacketFlags::PUBREL),
+        ("pubcomp", PacketType::Pubcomp, PacketFlags::PUBCOMP),
+        ("unsuback", PacketType::Unsuback, PacketFlags::UNSUBACK),
+    ] {
+        push_raw(&mut seeds, name.into(), r#type, flags, &[0x12, 0x34])?;
+    }
+
+    subscribe(&mut seeds)?;
+    suback(&mut seeds)?;
+    push_raw(
+        &mut seeds,
+        "unsubscribe".into(),
+        PacketType::Unsubscribe,
+        PacketFlags::UNSUBSCRIBE,
+        &[0x12, 0x34, 0x00, 0x03, b'a', b'/', b'b', 0x00, 0x01, b'#'],
+    )?;
+
+    push(&mut seeds, "pingreq".into(), &Packet::pingreq())?;
+    push(&mut seeds, "pingresp".into(), &Packet::pingresp())?;
+    push_raw(
+        &mut seeds,
+        "disconnect".into(),
+        PacketType::Disconnect,
+        PacketFlags::DISCONNECT,
+        &[],
+    )?;
+
+    Ok(seeds)
+}
+
+fn connect(seeds: &mut Vec<Seed>) -> Result<(), EncodeError> {
+    let password = payload::connect::SecretBytes::new(b"secret");
+
+    for &clean_session in &[false, true] {
+        for &credentials in &[false, true] {
+            let username = if credentials { Some("user") } else { None };
+            let password = if credentials { Some(password) } else { None };
+            let flags = variable_header::connect::Flags {
+                has_username: credentials,
+                has_password: credentials,
+                will: None,
+                clean_session,
+            };
+
+            let name = format!(
+                "connect-clean{}-credentials{}",
+                clean_session as u8, credentials as u8
+            );
+            let payload = payload::connect::Connect::new("client", None, username, password);
+            push(seeds, name, &connect_packet(flags, payload)?)?;
+
+            for &qos in &QOS {
+                for &retain in &[false, true] {
+                    let name = format!(
+                        "connect-clean{}-credentials{}-will-qos{}-retain{}",
+                        clean_session as u8,
+                        credentials as u8,
+                        u8::from(qos),
+                        retain as u8
+                    );
+                    let will = payload::connect::Will::new_str("will/topic", "gone");
+                    let payload =
+                        payload::connect::Connect::new("client", Some(will), username, password);
+                    let flags = variable_header::connect::Flags {
+                        will: Some(variable_header::connect::WillFlags { qos, retain }),
+                        ..flags
+                    };
+                    push(seeds, name, &connect_packet(flags, payload)?)?;
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn connect_packet<'a>(
+    flags: variable_header::connect::Flags,
+    payload: payload::connect::Connect<'a>,
+) -> Result<Packet<'a>, EncodeError> {
+    Packet::connect(
+        variable_header::connect::Connect::new(
+            variable_header::connect::Protocol::MQTT,
+            variable_header::connect::Level::Level3_1_1,
+            flags,
+            variable_header::connect::KeepAliveInterval::from(60),
+        ),
+        payload,
+    )
+}
+
+fn connack(seeds: &mut Vec<Seed>) -> Result<(), EncodeError> {
+    for return_code in 0..=5 {
+        // session present must be 0 unless the connection is accepted
+        // [MQTT-3.2.2-4]
+        let session_present: &[u8] = if return_code == 0 { &[0, 1] } else { &[0] };
+        for &session_present in session_present {
+            let name = format!("connack-rc{}-session{}", return_code, session_present);
+            push_raw(
+                seeds,
+                name,
+                PacketType::Connack,
+                PacketFlags::CONNACK,
+                &[session_present, return_code],
+            )?;
+        }
+    }
+
+    Ok(())
+}
+
+fn publish(seeds: &mut Vec<Seed>) -> Result<(), EncodeError> {
+    for &qos in &QOS {
+        // dup must be 0 for QoS 0 [MQTT-3.3.1-2]
+        let dup: &[bool] = if qos == qos::QoS::AtMostOnce {
+            &[false]
+        } else {
+            &[false, true]
+        };
+
+        for &dup in dup {
+            for &retain in &[false, true] {
+                for &payload in &[&b""[..], &b"{\"temp\":21.5}"[..]] {
+                    let packet_identifier = if qos == qos::QoS::AtMostOnce {
+                        None
+                    } else {
+                        Some(PacketId::new(0x1234))
+                    };
+                    let name = format!(
+                        "publish-qos{}-dup{}-retain{}-payload{}",
+                        u8::from(qos),
+                        dup as u8,
+                        retain as u8,
+                        payload.len()
+                    );
+                    let packet = Packet::publish(
+                        PublishFlags::new(qos, dup, retain),
+                        variable_header::publish::Publish::new("sensors/1/temp", packet_identifier),
+                        payload,
+                    )?;
+                    push(seeds, name, &packet)?;
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn subscribe(seeds: &mut Vec<Seed>) -> Result<(), EncodeError> {
+    for &qos in &QOS {
+        let topics = [("sensors/+/temp", qos), ("alerts/#", qos)];
+        let packet = Packet::subscribe(
+            PacketIdentifier::new(PacketId::new(0x1234)),
+            payload::subscribe::Subscribe::new(&topics),
+        )?;
+        push(seeds, format!("subscribe-qos{}", u8::from(qos)), &packet)?;
+    }
+
+    Ok(())
+}
+
+fn suback(seeds: &mut Vec<Seed>) -> Result<(), EncodeError> {
+    for &(name, return_code) in &[
+        ("suback-qos0", 0x00),
+        ("suback-qos1", 0x01),
+        ("suback-qos2", 0x02),
+        ("suback-failure", 0x80),
+    ] {
+        push_raw(
+            seeds,
+            name.into(),
+            PacketType::Suback,
+            PacketFlags::SUBACK,
+            &[0x12, 0x34, return_code],
+        )?;
+    }
+
+    Ok(())
+}
+
+fn push(seeds: &mut Vec<Seed>, name: String, packet: &Packet) -> Result<(), EncodeError> {
+    let mut bytes = vec![0; packet.encoded_len()];
+    packet.encode(&mut bytes)?;
+    seeds.push(Seed { name, bytes });
+    Ok(())
+}
+
+fn push_raw(
+    seeds: &mut Vec<Seed>,
+    name: String,
+    r#type: PacketType,
+    flags: PacketFlags,
+    body: &[u8],
+) -> Result<(), EncodeError> {
+    let mut buf = vec![0; body.len() + 5];
+    let bytes = packet::encode_backfilled(r#type, flags, &mut buf, |bytes| {
+        bytes[..body.len()].copy_from_slice(body);
+        Ok(body.len())
+    })?;
+    seeds.push(Seed {
+        name,
+        bytes: bytes.into(),
+    });
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{codec::Decodable, status::Status};
+    use std::collections::BTreeSet;
+
+    #[test]
+    fn seeds_decode() {
+        let seeds = seeds().expect("encoded");
+
+        let names: BTreeSet<_> = seeds.iter().map(|seed| &seed.name).collect();
+        assert_eq!(seeds.len(), names.len());
+
+        let mut types = BTreeSet::new();
+        for seed in &seeds {
+            match Packet::decode(&seed.bytes) {
+                Ok(Status::Complete((consumed, packet))) => {
+                    assert_eq!(seed.bytes.len(), consumed, "{}", seed.name);
+                    types.insert(format!("{:?}", packet.fixed_header().r#type()));
+                }
+                result => panic!("{} did not decode: {:?}", seed.name, result.map(|_| ())),
+            }
+        }
+        assert_eq!(14, types.len());
+    }
+}
@@ -0,0 +1,114 @@
+//! Correlation of in flight packet identifiers with application tokens.
+//!
+//! An application publishing at QoS 1 or QoS 2 usually wants its own message
+//! handle back when the acknowledgement arrives, not the packet identifier.
+//! Record the token alongside `redelivery::Redelivery::publish_sent` and take
+//! it back when `completed` reports the message done.
+
+use core::result::Result;
+
+use crate::{redelivery::Error, variable_header::PacketId};
+
+/// Tokens for up to `N` in flight packet identifiers.
+#[derive(Clone, Debug)]
+pub struct Correlation<T, const N: usize> {
+    entries: [Option<(PacketId, T)>; N],
+    len: usize,
+}
+
+impl<T, const N: usize> Default for Correlation<T, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T, const N: usize> Correlation<T, N> {
+    pub fn new() -> Self {
+        Self {
+            entries: core::array::from_fn(|_| None),
+            len: 0,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Pair a packet identifier that was just sent with the caller's token.
+    pub fn insert(&mut self, id: PacketId, token: T) -> Result<(), Error> {
+        if self.position(id).is_some() {
+            return Err(Error::InUse);
+        }
+
+        let free = self
+            .entries
+            .iter()
+            .position(Option::is_none)
+            .ok_or(Error::Full)?;
+        self.entries[free] = Some((id, token));
+        self.len += 1;
+
+        Ok(())
+    }
+
+    /// The token paired with a packet identifier.
+    pub fn get(&self, id: PacketId) -> Option<&T> {
+        self.position(id)
+            .and_then(|index| self.entries[index].as_ref())
+            .map(|(_, token)| token)
+    }
+
+    /// Take the token of an acknowledged packet identifier, `None` if the
+    /// identifier was not in flight.
+    pub fn acknowledged(&mut self, id: PacketId) -> Option<T> {
+        let index = self.position(id)?;
+        self.len -= 1;
+        self.entries[index].take().map(|(_, token)| token)
+    }
+
+    /// Forget all tokens, when a clean session starts.
+    pub fn clear(&mut self) {
+        self.entries.iter_mut().for_each(|entry| *entry = None);
+        self.len = 0;
+    }
+
+    fn position(&self, id: PacketId) -> Option<usize> {
+        self.entries
+            .iter()
+            .position(|entry| matches!(entry, Some((stored, _)) if *stored == id))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::redelivery::Redelivery;
+
+    #[test]
+    fn tokens() {
+        let mut redelivery = Redelivery::<2>::new();
+        let mut tokens = Correlation::<u32, 2>::new();
+
+        for (id, token) in [(1, 100), (2, 200)] {
+            redelivery.publish_sent(PacketId::new(id)).unwrap();
+            tokens.insert(PacketId::new(id), token).unwrap();
+        }
+        assert_eq!(Err(Error::Full), tokens.insert(PacketId::new(3), 300));
+        assert_eq!(Err(Error::InUse), tokens.insert(PacketId::new(1), 300));
+        assert_eq!(Some(&200), tokens.get(PacketId::new(2)));
+
+        assert!(redelivery.completed(PacketId::new(2)));
+        assert_eq!(Some(200), tokens.acknowledged(PacketId::new(2)));
+        assert_eq!(None, tokens.acknowledged(PacketId::new(2)));
+        assert_eq!(1, tokens.len());
+
+        tokens.insert(PacketId::new(3), 300).unwrap();
+        tokens.clear();
+        assert!(tokens.is_empty());
+        assert_eq!(None, tokens.get(PacketId::new(1)));
+    }
+}
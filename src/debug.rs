@@ -0,0 +1,93 @@
+//! Debug formatting for the flag types and for payloads and topics too long
+//! to print in full.
+
+/// Implement `Debug` for a bitfield newtype listing each named field, or just
+/// the raw bits with the `tiny` feature to avoid the per field formatting code.
+macro_rules! flags_debug {
+    ($name:ident { $($fields:tt)* }) => {
+        #[cfg(not(feature = "tiny"))]
+        impl ::core::fmt::Debug for $name {
+            bitfield_debug! {
+                struct $name;
+                $($fields)*
+            }
+        }
+
+        #[cfg(feature = "tiny")]
+        impl ::core::fmt::Debug for $name {
+            fn fmt(&self, f: &mut ::core::fmt::Formatter) -> ::core::fmt::Result {
+                f.debug_tuple(stringify!($name)).field(&self.0).finish()
+            }
+        }
+    };
+}
+
+/// Implement `Debug` printing the wire byte with the `tiny` feature for a flags
+/// struct which otherwise derives it, `u8` must implement `From` the flags.
+macro_rules! bits_debug {
+    ($name:ident) => {
+        #[cfg(feature = "tiny")]
+        impl ::core::fmt::Debug for $name {
+            fn fmt(&self, f: &mut ::core::fmt::Formatter) -> ::core::fmt::Result {
+                f.debug_tuple(stringify!($name))
+                    .field(&u8::from(*self))
+                    .finish()
+            }
+        }
+    };
+}
+
+/// The most bytes of a payload or topic `Preview` prints.
+pub(crate) const PREVIEW_LEN: usize = 32;
+
+/// `Debug` for a payload or topic that can be kilobytes long, printing the
+/// first `PREVIEW_LEN` bytes and the total length so a large packet does not
+/// flood a slow log channel.
+pub(crate) struct Preview<T>(pub T);
+
+impl ::core::fmt::Debug for Preview<&[u8]> {
+    fn fmt(&self, f: &mut ::core::fmt::Formatter) -> ::core::fmt::Result {
+        if self.0.len() <= PREVIEW_LEN {
+            return ::core::fmt::Debug::fmt(self.0, f);
+        }
+
+        ::core::fmt::Debug::fmt(&self.0[..PREVIEW_LEN], f)?;
+        write!(f, ".. ({} bytes)", self.0.len())
+    }
+}
+
+impl ::core::fmt::Debug for Preview<&str> {
+    fn fmt(&self, f: &mut ::core::fmt::Formatter) -> ::core::fmt::Result {
+        if self.0.len() <= PREVIEW_LEN {
+            return ::core::fmt::Debug::fmt(self.0, f);
+        }
+
+        let end = (0..=PREVIEW_LEN)
+            .rev()
+            .find(|&end| self.0.is_char_boundary(end))
+            .unwrap_or(0);
+        ::core::fmt::Debug::fmt(&self.0[..end], f)?;
+        write!(f, ".. ({} bytes)", self.0.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn preview() {
+        assert_eq!("[1, 2]", format!("{:?}", Preview(&[1u8, 2][..])));
+        assert_eq!(
+            "[0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, \
+             0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0].. (1024 bytes)",
+            format!("{:?}", Preview(&[0u8; 1024][..]))
+        );
+
+        let topic = "é".repeat(20);
+        assert_eq!(
+            format!("{:?}.. (40 bytes)", "é".repeat(16)),
+            format!("{:?}", Preview(topic.as_str()))
+        );
+    }
+}
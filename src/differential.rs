@@ -0,0 +1,153 @@
+//! Differential testing of the decoder against `rumqttc`, enabled with the
+//! `differential` feature.
+//!
+//! `compare` decodes the same bytes with both implementations and reports
+//! where they disagree on accepting the bytes, the length of the packet or the
+//! decoded fields. Feeding it fuzzer output or the `corpus` seeds finds
+//! conformance divergences in either implementation, each one still needs
+//! checking against the specification to decide which side is wrong.
+
+use core::fmt;
+
+use std::boxed::Box;
+
+use bytes::BytesMut;
+use rumqttc::mqttbytes::{self, v4};
+
+use crate::{
+    codec::Decodable,
+    error::DecodeError,
+    interop::{self, to_rumqttc},
+    packet::Packet,
+    status::Status,
+};
+
+/// Where both implementations agree.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Outcome {
+    /// Both decoded the same packet of the given length
+    Decoded(usize),
+    /// Both need more bytes
+    Incomplete,
+    /// Both rejected the bytes
+    Rejected,
+}
+
+/// Where the implementations disagree.
+#[derive(Debug)]
+pub enum Divergence {
+    /// This crate decoded a packet `rumqttc` rejected
+    Accepted(mqttbytes::Error),
+    /// This crate rejected a packet `rumqttc` decoded
+    Rejected(DecodeError),
+    /// One side needs more bytes while the other decided
+    Incomplete,
+    /// Both decoded a packet but of different lengths
+    Length { ours: usize, theirs: usize },
+    /// Both decoded a packet of the same length but with different fields
+    Fields {
+        ours: Box<v4::Packet>,
+        theirs: Box<v4::Packet>,
+    },
+    /// The packet this crate decoded could not be compared
+    Interop(interop::Error),
+}
+
+impl fmt::Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Divergence::Accepted(e) => write!(f, "decoded but rumqttc rejected: {}", e),
+            Divergence::Rejected(e) => write!(f, "rumqttc decoded but rejected: {}", e),
+            Divergence::Incomplete => f.write_str("only one side needs more bytes"),
+            Divergence::Length { ours, theirs } => {
+                write!(f, "decoded {} bytes, rumqttc decoded {}", ours, theirs)
+            }
+            Divergence::Fields { ours, theirs } => {
+                write!(f, "decoded {:?}, rumqttc decoded {:?}", ours, theirs)
+            }
+            Divergence::Interop(e) => write!(f, "comparison failed: {}", e),
+        }
+    }
+}
+
+impl ::std::error::Error for Divergence {}
+
+/// Decode a packet from the start of `bytes` with both implementations and
+/// compare the results.
+///
+/// Fields are compared by converting the packet this crate decoded to a
+/// `rumqttc` packet, so a divergence can also come from this crate's encoder.
+pub fn compare(bytes: &[u8]) -> Result<Outcome, Divergence> {
+    let ours = Packet::decode(bytes);
+
+    let mut stream = BytesMut::from(bytes);
+    let theirs = v4::read(&mut stream, usize::MAX);
+    let theirs_len = bytes.len() - stream.len();
+
+    match (ours, theirs) {
+        (Ok(Status::Complete((ours_len, packet))), Ok(theirs)) => {
+            if ours_len != theirs_len {
+                return Err(Divergence::Length {
+                    ours: ours_len,
+                    theirs: theirs_len,
+                });
+            }
+
+            let ours = to_rumqttc(&packet).map_err(Divergence::Interop)?;
+            if ours != theirs {
+                return Err(Divergence::Fields {
+                    ours: Box::new(ours),
+                    theirs: Box::new(theirs),
+                });
+            }
+
+            Ok(Outcome::Decoded(ours_len))
+        }
+        (Ok(Status::Partial(_)), Err(mqttbytes::Error::InsufficientBytes(_))) => {
+            Ok(Outcome::Incomplete)
+        }
+        (Ok(Status::Partial(_)), _) | (_, Err(mqttbytes::Error::InsufficientBytes(_))) => {
+            Err(Divergence::Incomplete)
+        }
+        (Ok(Status::Complete(_)), Err(e)) => Err(Divergence::Accepted(e)),
+        (Err(e), Ok(_)) => Err(Divergence::Rejected(e)),
+        (Err(_), Err(_)) => Ok(Outcome::Rejected),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::from_hex;
+
+    fn hex(hex: &str, buf: &mut [u8]) -> usize {
+        from_hex(hex, buf).expect("valid hex")
+    }
+
+    #[test]
+    fn agreement() {
+        let mut buf = [0u8; 32];
+        // PUBLISH QoS 1 "a/b" id 0x1234 payload "{}"
+        let len = hex("32 09 00 03 61 2f 62 12 34 7b 7d", &mut buf);
+
+        assert_eq!(Outcome::Decoded(11), compare(&buf[..len]).unwrap());
+        for partial in 1..len {
+            assert_eq!(Outcome::Incomplete, compare(&buf[..partial]).unwrap());
+        }
+
+        // PUBLISH with both QoS bits set
+        let len = hex("36 07 00 03 61 2f 62 12 34", &mut buf);
+        assert_eq!(Outcome::Rejected, compare(&buf[..len]).unwrap());
+    }
+
+    #[cfg(feature = "corpus")]
+    #[test]
+    fn corpus_agrees() {
+        for seed in crate::corpus::seeds().expect("encoded") {
+            match compare(&seed.bytes) {
+                Ok(Outcome::Decoded(len)) => assert_eq!(seed.bytes.len(), len),
+                result => panic!("{}: {:?}", seed.name, result),
+            }
+        }
+    }
+}
@@ -16,19 +16,105 @@ pub enum DecodeError {
     Utf8,
     /// Invalid QoS value
     InvalidQoS(qos::Error),
+    /// Invalid protocol name
+    InvalidProtocolName,
     /// Invalid protocol level
     InvalidProtocolLevel,
     /// Invalid connect flag value
-    InvalidConnectFlag,
+    InvalidConnectFlag(ConnectFlagError),
     /// Invalid Connack flag
     InvalidConnackFlag,
     /// Invalid Connack Return Code
     InvalidConnackReturnCode,
     /// Invalid Suback Return Code
     InvalidSubackReturnCode,
+    /// Topic longer than the configured limit
+    TopicTooLong,
+    /// Topic has more levels than the configured limit
+    TooManyTopicLevels,
+    /// More topic filters than the configured limit
+    TooManySubscriptions,
+    /// Packet larger than this target can address
+    PacketTooLarge,
+    /// The buffer ends this many bytes before the packet does, only returned
+    /// when the buffer was expected to hold the whole packet
+    Incomplete(usize),
+    /// Malformed entry in the topic filter list of a SUBSCRIBE or UNSUBSCRIBE
+    Subscription(SubscriptionError),
+}
+
+/// Which rule a CONNECT flags byte breaks.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum ConnectFlagError {
+    /// The reserved flag is set
+    Reserved,
+    /// The will QoS is 3
+    WillQoS,
+    /// A will QoS is set without the will flag
+    WillQoSWithoutWill,
+    /// The will retain flag is set without the will flag
+    WillRetainWithoutWill,
+    /// The password flag is set without the username flag
+    PasswordWithoutUsername,
+}
+
+/// Which entry of a SUBSCRIBE or UNSUBSCRIBE payload is malformed and why.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct SubscriptionError {
+    /// The zero-based index of the entry
+    pub index: usize,
+    /// The byte offset of the entry from the start of the topic filter list
+    pub offset: usize,
+    pub kind: SubscriptionErrorKind,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum SubscriptionErrorKind {
+    /// The entry runs past the end of the payload
+    Truncated,
+    /// The topic filter is not valid UTF-8
+    Utf8,
+    /// Invalid requested QoS value
+    InvalidQoS(qos::Error),
+}
+
+impl SubscriptionError {
+    #[cfg(not(feature = "tiny"))]
+    fn desc(&self) -> &'static str {
+        match self.kind {
+            SubscriptionErrorKind::Truncated => "truncated topic filter entry",
+            SubscriptionErrorKind::Utf8 => "invalid utf-8 encoding in topic filter",
+            SubscriptionErrorKind::InvalidQoS(_) => "invalid requested QoS",
+        }
+    }
+
+    #[cfg(feature = "tiny")]
+    fn desc(&self) -> &'static str {
+        "decode error"
+    }
+}
+
+impl fmt::Display for SubscriptionError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} at index {}, offset {}",
+            self.desc(),
+            self.index,
+            self.offset
+        )
+    }
+}
+
+#[cfg(feature = "std")]
+impl ::std::error::Error for SubscriptionError {
+    fn description(&self) -> &str {
+        self.desc()
+    }
 }
 
 impl DecodeError {
+    #[cfg(not(feature = "tiny"))]
     fn desc(&self) -> &'static str {
         match *self {
             DecodeError::PacketType => "invalid packet type in header",
@@ -37,18 +123,116 @@ impl DecodeError {
             DecodeError::InvalidLength => "invalid buffer length",
             DecodeError::Utf8 => "invalid utf-8 encoding",
             DecodeError::InvalidQoS(_) => "invalid QoS bit pattern",
+            DecodeError::InvalidProtocolName => "invalid protocol name",
             DecodeError::InvalidProtocolLevel => "invalid protocol level",
-            DecodeError::InvalidConnectFlag => "invalid connect flag value",
+            DecodeError::InvalidConnectFlag(_) => "invalid connect flag value",
             DecodeError::InvalidConnackFlag => "invalid connack flag value",
             DecodeError::InvalidConnackReturnCode => "invalid connack return code",
             DecodeError::InvalidSubackReturnCode => "invalid suback return code",
+            DecodeError::TopicTooLong => "topic longer than limit",
+            DecodeError::TooManyTopicLevels => "topic has more levels than limit",
+            DecodeError::TooManySubscriptions => "more topic filters than limit",
+            DecodeError::PacketTooLarge => "packet too large for target",
+            DecodeError::Incomplete(_) => "buffer ends before packet",
+            DecodeError::Subscription(ref err) => err.desc(),
         }
     }
+
+    #[cfg(feature = "tiny")]
+    fn desc(&self) -> &'static str {
+        "decode error"
+    }
+}
+
+/// A normative statement of the MQTT 3.1.1 specification.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct SpecReference {
+    /// The statement identifier, e.g. `MQTT-3.1.2-1`
+    pub statement: &'static str,
+    /// What the statement requires
+    pub reason: &'static str,
+}
+
+impl DecodeError {
+    /// The normative statement a packet rejected with this error violated.
+    ///
+    /// Returns `None` for malformed packets not covered by a numbered
+    /// statement and for limits configured in `DecodeOptions`. The table is
+    /// left out with the `tiny` feature and `None` is always returned.
+    #[cfg(not(feature = "tiny"))]
+    pub fn spec_reference(&self) -> Option<SpecReference> {
+        let (statement, reason) = match *self {
+            DecodeError::PacketFlag => (
+                "MQTT-2.2.2-2",
+                "invalid fixed header flags must close the connection",
+            ),
+            DecodeError::Utf8 => ("MQTT-1.5.3-1", "strings must be well-formed UTF-8"),
+            DecodeError::InvalidQoS(_) => ("MQTT-3.3.1-4", "both QoS bits must not be set"),
+            DecodeError::InvalidProtocolName => {
+                ("MQTT-3.1.2-1", "the protocol name must be \"MQTT\"")
+            }
+            DecodeError::InvalidProtocolLevel => (
+                "MQTT-3.1.2-2",
+                "unsupported protocol levels must be refused",
+            ),
+            DecodeError::InvalidConnectFlag(kind) => match kind {
+                ConnectFlagError::Reserved => {
+                    ("MQTT-3.1.2-3", "the reserved connect flag must be zero")
+                }
+                ConnectFlagError::WillQoS => ("MQTT-3.1.2-14", "the will QoS must not be 3"),
+                ConnectFlagError::WillQoSWithoutWill => (
+                    "MQTT-3.1.2-13",
+                    "the will QoS must be 0 without the will flag",
+                ),
+                ConnectFlagError::WillRetainWithoutWill => (
+                    "MQTT-3.1.2-15",
+                    "the will retain flag must be 0 without the will flag",
+                ),
+                ConnectFlagError::PasswordWithoutUsername => (
+                    "MQTT-3.1.2-22",
+                    "the password flag must be 0 without the username flag",
+                ),
+            },
+            DecodeError::InvalidSubackReturnCode => (
+                "MQTT-3.9.3-2",
+                "reserved SUBACK return codes must not be used",
+            ),
+            DecodeError::Subscription(SubscriptionError { kind, .. }) => match kind {
+                SubscriptionErrorKind::Utf8 => {
+                    ("MQTT-1.5.3-1", "strings must be well-formed UTF-8")
+                }
+                SubscriptionErrorKind::InvalidQoS(_) => {
+                    ("MQTT-3.8.3-4", "the requested QoS must be 0, 1 or 2")
+                }
+                SubscriptionErrorKind::Truncated => return None,
+            },
+            DecodeError::PacketType
+            | DecodeError::RemainingLength
+            | DecodeError::InvalidLength
+            | DecodeError::InvalidConnackFlag
+            | DecodeError::InvalidConnackReturnCode
+            | DecodeError::TopicTooLong
+            | DecodeError::TooManyTopicLevels
+            | DecodeError::TooManySubscriptions
+            | DecodeError::PacketTooLarge
+            | DecodeError::Incomplete(_) => return None,
+        };
+
+        Some(SpecReference { statement, reason })
+    }
+
+    #[cfg(feature = "tiny")]
+    pub fn spec_reference(&self) -> Option<SpecReference> {
+        None
+    }
 }
 
 impl fmt::Display for DecodeError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        f.write_str(self.desc())
+        match self {
+            DecodeError::Subscription(err) => fmt::Display::fmt(err, f),
+            _ => f.write_str(self.desc()),
+        }
     }
 }
 
@@ -65,6 +249,12 @@ impl From<Utf8Error> for DecodeError {
     }
 }
 
+impl From<core::num::TryFromIntError> for DecodeError {
+    fn from(_err: core::num::TryFromIntError) -> DecodeError {
+        DecodeError::PacketTooLarge
+    }
+}
+
 impl From<qos::Error> for DecodeError {
     fn from(err: qos::Error) -> Self {
         DecodeError::InvalidQoS(err)
@@ -77,15 +267,56 @@ pub enum EncodeError {
     OutOfSpace,
     /// Value too big for field
     ValueTooBig,
+    /// Packet larger than the peer's maximum packet size
+    PacketTooLarge,
+    /// Field empty or longer than the protocol allows
+    FieldLength(Field),
+    /// Event out of wire order, see `event::Encoder`
+    EventOrder,
+    /// QoS 1 or 2 PUBLISH without a packet identifier
+    MissingPacketId,
+    /// QoS 0 PUBLISH with a packet identifier
+    UnexpectedPacketId,
+}
+
+/// A length limited field of a packet being constructed.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Field {
+    ClientId,
+    Username,
+    Password,
+    WillTopic,
+    WillMessage,
+    TopicName,
+    TopicFilter,
 }
 
 impl EncodeError {
+    #[cfg(not(feature = "tiny"))]
     fn desc(&self) -> &'static str {
         match *self {
             EncodeError::OutOfSpace => "not enough space in encode buffer",
             EncodeError::ValueTooBig => "value too big to ever be encoded",
+            EncodeError::PacketTooLarge => "packet larger than peer maximum packet size",
+            EncodeError::FieldLength(field) => match field {
+                Field::ClientId => "client identifier longer than 65535 bytes",
+                Field::Username => "username longer than 65535 bytes",
+                Field::Password => "password longer than 65535 bytes",
+                Field::WillTopic => "will topic empty or longer than 65535 bytes",
+                Field::WillMessage => "will message longer than 65535 bytes",
+                Field::TopicName => "topic name empty or longer than 65535 bytes",
+                Field::TopicFilter => "topic filter empty or longer than 65535 bytes",
+            },
+            EncodeError::EventOrder => "event out of wire order",
+            EncodeError::MissingPacketId => "QoS 1 or 2 publish without packet identifier",
+            EncodeError::UnexpectedPacketId => "QoS 0 publish with packet identifier",
         }
     }
+
+    #[cfg(feature = "tiny")]
+    fn desc(&self) -> &'static str {
+        "encode error"
+    }
 }
 
 impl fmt::Display for EncodeError {
@@ -106,3 +337,25 @@ impl From<core::num::TryFromIntError> for EncodeError {
         EncodeError::ValueTooBig
     }
 }
+
+#[cfg(all(test, not(feature = "tiny")))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn spec_reference() {
+        assert_eq!(
+            Some("MQTT-3.1.2-2"),
+            DecodeError::InvalidProtocolLevel
+                .spec_reference()
+                .map(|r| r.statement)
+        );
+        assert_eq!(
+            Some("MQTT-3.1.2-22"),
+            DecodeError::InvalidConnectFlag(ConnectFlagError::PasswordWithoutUsername)
+                .spec_reference()
+                .map(|r| r.statement)
+        );
+        assert_eq!(None, DecodeError::TopicTooLong.spec_reference());
+    }
+}
@@ -0,0 +1,223 @@
+use core::{cmp::min, convert::TryFrom, result::Result};
+
+use crate::{
+    codec::{self, Decodable},
+    error::DecodeError,
+    fixed_header::{FixedHeader, PacketType, PublishFlags},
+    qos,
+    status::Status,
+    variable_header::PacketId,
+};
+
+use super::{Event, Field};
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+enum State {
+    FixedHeader,
+    Topic { packet_id: bool },
+    PacketId,
+    Payload,
+}
+
+/// Decodes a stream of packets into events.
+///
+/// Events are pulled one at a time with `next_event`, or pushed to a callback
+/// with `feed`.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct Decoder {
+    state: State,
+    remaining: usize,
+}
+
+impl Default for Decoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Decoder {
+    pub const fn new() -> Self {
+        Decoder {
+            state: State::FixedHeader,
+            remaining: 0,
+        }
+    }
+
+    /// Decode the next event from the start of `bytes`.
+    ///
+    /// Returns the number of bytes consumed and the event, or how many more
+    /// bytes are needed. A field must be received in full before it is
+    /// decoded, the payload is returned in chunks of whatever has been
+    /// received. Unconsumed bytes must be passed again with the next call.
+    pub fn next_event<'a>(
+        &mut self,
+        bytes: &'a [u8],
+    ) -> Result<Status<(usize, Event<'a>)>, DecodeError> {
+        match self.state {
+            State::FixedHeader => {
+                let (offset, header) = complete!(FixedHeader::decode(bytes));
+                self.remaining = usize::try_from(header.len())?;
+                self.state = match header.r#type() {
+                    PacketType::Publish => State::Topic {
+                        packet_id: PublishFlags::try_from(header.flags())?.qos
+                            != qos::QoS::AtMostOnce,
+                    },
+                    PacketType::Puback
+                    | PacketType::Pubrec
+                    | PacketType::Pubrel
+                    | PacketType::Pubcomp
+                    | PacketType::Subscribe
+                    | PacketType::Suback
+                    | PacketType::Unsubscribe
+                    | PacketType::Unsuback => State::PacketId,
+                    _ => State::Payload,
+                };
+
+                Ok(Status::Complete((offset, Event::FixedHeader(header))))
+            }
+            State::Topic { packet_id } => {
+                let (offset, topic) = complete!(self.field(codec::string::parse_string, bytes));
+                self.state = if packet_id {
+                    State::PacketId
+                } else {
+                    State::Payload
+                };
+
+                Ok(Status::Complete((
+                    offset,
+                    Event::Field(Field::Topic(topic)),
+                )))
+            }
+            State::PacketId => {
+                let (offset, packet_id) = complete!(self.field(codec::values::parse_u16, bytes));
+                self.state = State::Payload;
+
+                Ok(Status::Complete((
+                    offset,
+                    Event::Field(Field::PacketId(PacketId::new(packet_id))),
+                )))
+            }
+            State::Payload if self.remaining == 0 => {
+                self.state = State::FixedHeader;
+                Ok(Status::Complete((0, Event::PacketEnd)))
+            }
+            State::Payload => {
+                if bytes.is_empty() {
+                    return Ok(Status::Partial(self.remaining));
+                }
+
+                let len = min(bytes.len(), self.remaining);
+                self.remaining -= len;
+
+                Ok(Status::Complete((len, Event::PayloadChunk(&bytes[..len]))))
+            }
+        }
+    }
+
+    /// Decode every event that can be decoded from `bytes`, passing each one
+    /// to `event`.
+    ///
+    /// Returns the number of bytes consumed, the rest must be passed again
+    /// once more bytes have been received.
+    pub fn feed<'a, F>(&mut self, bytes: &'a [u8], mut event: F) -> Result<usize, DecodeError>
+    where
+        F: FnMut(Event<'a>),
+    {
+        let mut offset = 0;
+        while let Status::Complete((consumed, next)) = self.next_event(&bytes[offset..])? {
+            offset += consumed;
+            event(next);
+        }
+
+        Ok(offset)
+    }
+
+    /// Parse a variable header field, which must end within the packet.
+    #[allow(clippy::type_complexity)]
+    fn field<'a, T>(
+        &mut self,
+        parse: fn(&'a [u8]) -> Result<Status<(usize, T)>, DecodeError>,
+        bytes: &'a [u8],
+    ) -> Result<Status<(usize, T)>, DecodeError> {
+        let available = &bytes[..min(bytes.len(), self.remaining)];
+        match parse(available)? {
+            Status::Complete((offset, value)) => {
+                self.remaining -= offset;
+                Ok(Status::Complete((offset, value)))
+            }
+            Status::Partial(_) if available.len() == self.remaining => {
+                Err(DecodeError::InvalidLength)
+            }
+            Status::Partial(needed) => Ok(Status::Partial(needed)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::vec::Vec;
+
+    #[test]
+    fn publish() {
+        // PUBLISH QoS 1 topic a/b packet identifier 7 followed by PINGREQ
+        let bytes = [
+            0x32, 0x09, 0x00, 0x03, b'a', b'/', b'b', 0x00, 0x07, b'{', b'}', 0xc0, 0x00,
+        ];
+
+        let mut decoder = Decoder::new();
+        let mut events = Vec::new();
+        assert_eq!(Ok(13), decoder.feed(&bytes, |event| events.push(event)));
+        assert_eq!(
+            &[
+                Event::FixedHeader(FixedHeader::decode(&bytes).unwrap().unwrap().1),
+                Event::Field(Field::Topic("a/b")),
+                Event::Field(Field::PacketId(PacketId::new(7))),
+                Event::PayloadChunk(b"{}"),
+                Event::PacketEnd,
+                Event::FixedHeader(FixedHeader::decode(&bytes[11..]).unwrap().unwrap().1),
+                Event::PacketEnd,
+            ][..],
+            &events[..]
+        );
+    }
+
+    #[test]
+    fn partial() {
+        let bytes = [0x30, 0x07, 0x00, 0x03, b'a', b'/', b'b', b'{', b'}'];
+
+        let mut decoder = Decoder::new();
+        let mut events = Vec::new();
+        // the fixed header and half of the topic
+        assert_eq!(Ok(2), decoder.feed(&bytes[..5], |event| events.push(event)));
+        assert_eq!(Ok(Status::Partial(2)), decoder.next_event(&bytes[2..5]));
+        // the topic and part of the payload
+        assert_eq!(
+            Ok(6),
+            decoder.feed(&bytes[2..8], |event| events.push(event))
+        );
+        assert_eq!(Ok(1), decoder.feed(&bytes[8..], |event| events.push(event)));
+
+        assert_eq!(
+            &[
+                Event::Field(Field::Topic("a/b")),
+                Event::PayloadChunk(b"{"),
+                Event::PayloadChunk(b"}"),
+                Event::PacketEnd,
+            ][..],
+            &events[1..]
+        );
+    }
+
+    #[test]
+    fn field_overrun() {
+        // the topic length runs past the end of the packet
+        let bytes = [0x30, 0x03, 0x00, 0x05, b'a', 0xc0, 0x00, 0x00, 0x00];
+
+        let mut decoder = Decoder::new();
+        assert_eq!(
+            Err(DecodeError::InvalidLength),
+            decoder.feed(&bytes, |_| ())
+        );
+    }
+}
@@ -0,0 +1,150 @@
+use core::{convert::TryFrom, result::Result};
+
+use crate::{
+    codec::{Encodable, EncodeCursor},
+    error::EncodeError,
+    fixed_header::{self, FixedHeader, PacketFlags, PacketType},
+};
+
+use super::{Event, Field};
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+enum State {
+    Start,
+    Body(PacketType, PacketFlags),
+    Ended { start: usize },
+}
+
+/// Encodes the events of one packet into a buffer.
+///
+/// The largest possible fixed header is reserved at the start of the buffer
+/// and backfilled once `PacketEnd` is written, so the `len` of the
+/// `FixedHeader` event is ignored. As with `packet::encode_backfilled` the
+/// encoded packet may not start at the beginning of the buffer.
+#[derive(Debug)]
+pub struct Encoder<'b> {
+    cursor: EncodeCursor<'b>,
+    state: State,
+}
+
+impl<'b> Encoder<'b> {
+    pub fn new(bytes: &'b mut [u8]) -> Result<Self, EncodeError> {
+        Ok(Encoder {
+            cursor: EncodeCursor::at(bytes, fixed_header::MAX_ENCODED_LEN)?,
+            state: State::Start,
+        })
+    }
+
+    /// Write the next event of the packet.
+    ///
+    /// Events must be written in wire order from `FixedHeader` to `PacketEnd`,
+    /// returns `Err(EncodeError::EventOrder)` for an event before the packet
+    /// has started or after it has ended.
+    pub fn write(&mut self, event: Event) -> Result<(), EncodeError> {
+        match (self.state, event) {
+            (State::Start, Event::FixedHeader(header)) => {
+                self.state = State::Body(header.r#type(), header.flags());
+            }
+            (State::Body(..), Event::Field(Field::Topic(topic))) => {
+                self.cursor.write(topic)?;
+            }
+            (State::Body(..), Event::Field(Field::PacketId(packet_id))) => {
+                self.cursor.write_u16(packet_id.get())?;
+            }
+            (State::Body(..), Event::PayloadChunk(chunk)) => {
+                self.cursor.write_raw(chunk)?;
+            }
+            (State::Body(r#type, flags), Event::PacketEnd) => {
+                let reserved = fixed_header::MAX_ENCODED_LEN;
+                let len = u32::try_from(self.cursor.offset() - reserved)?;
+                if len > fixed_header::MAX_REMAINING_LENGTH {
+                    return Err(EncodeError::ValueTooBig);
+                }
+
+                let header = FixedHeader::new(r#type, flags, len);
+                let start = reserved - header.encoded_len();
+                header.encode(&mut self.cursor.get_mut()[start..reserved])?;
+                self.state = State::Ended { start };
+            }
+            _ => return Err(EncodeError::EventOrder),
+        }
+
+        Ok(())
+    }
+
+    /// The encoded packet, once `PacketEnd` has been written.
+    pub fn finish(self) -> Result<&'b [u8], EncodeError> {
+        match self.state {
+            State::Ended { start } => {
+                let end = self.cursor.offset();
+                Ok(&self.cursor.into_inner()[start..end])
+            }
+            _ => Err(EncodeError::EventOrder),
+        }
+    }
+}
+
+#[cfg(all(test, feature = "decode"))]
+mod tests {
+    use super::*;
+    use crate::{event::Decoder, variable_header::PacketId};
+
+    #[test]
+    fn round_trip() {
+        let bytes = [
+            0x32, 0x09, 0x00, 0x03, b'a', b'/', b'b', 0x00, 0x07, b'{', b'}',
+        ];
+
+        let mut buf = [0u8; 16];
+        let mut encoder = Encoder::new(&mut buf).unwrap();
+        let mut result = Ok(());
+        Decoder::new()
+            .feed(&bytes, |event| {
+                result = result.and_then(|_| encoder.write(event))
+            })
+            .unwrap();
+        result.unwrap();
+        assert_eq!(&bytes[..], encoder.finish().unwrap());
+    }
+
+    #[test]
+    fn chunks() {
+        let header = FixedHeader::new(PacketType::Publish, PacketFlags(0b0010), 0);
+
+        let mut buf = [0u8; 256];
+        let mut encoder = Encoder::new(&mut buf).unwrap();
+        encoder.write(Event::FixedHeader(header)).unwrap();
+        encoder.write(Event::Field(Field::Topic("a/b"))).unwrap();
+        encoder
+            .write(Event::Field(Field::PacketId(PacketId::new(7))))
+            .unwrap();
+        for chunk in [[b'x'; 64]; 2].iter() {
+            encoder.write(Event::PayloadChunk(chunk)).unwrap();
+        }
+        encoder.write(Event::PacketEnd).unwrap();
+        assert_eq!(
+            Err(EncodeError::EventOrder),
+            encoder.write(Event::PayloadChunk(b"x"))
+        );
+
+        let packet = encoder.finish().unwrap();
+        assert_eq!(3 + 7 + 128, packet.len());
+        assert_eq!([0x32, 0x87, 0x01, 0x00, 0x03], packet[..5]);
+    }
+
+    #[test]
+    fn order() {
+        let mut buf = [0u8; 16];
+        let mut encoder = Encoder::new(&mut buf).unwrap();
+        assert_eq!(
+            Err(EncodeError::EventOrder),
+            encoder.write(Event::PacketEnd)
+        );
+        assert_eq!(Err(EncodeError::EventOrder), encoder.finish().map(|_| ()));
+
+        assert_eq!(
+            Err(EncodeError::OutOfSpace),
+            Encoder::new(&mut buf[..4]).map(|_| ())
+        );
+    }
+}
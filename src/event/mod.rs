@@ -0,0 +1,44 @@
+//! Packets as a sequence of events in wire order.
+//!
+//! For the most RAM constrained targets `Decoder` turns bytes into events as
+//! they arrive, so a packet can be processed without ever holding its
+//! structured representation or even the whole packet. Only a field is ever
+//! borrowed from the receive buffer, the payload is handed out in chunks of
+//! whatever has been received.
+//!
+//! `Encoder` goes the other way, writing events into a buffer as they are
+//! produced so a packet can be built from data that cannot be borrowed all at
+//! once, e.g. a payload read from flash a page at a time.
+
+use crate::{fixed_header::FixedHeader, variable_header::PacketId};
+
+#[cfg(feature = "decode")]
+mod decoder;
+#[cfg(feature = "encode")]
+mod encoder;
+
+#[cfg(feature = "decode")]
+pub use self::decoder::Decoder;
+#[cfg(feature = "encode")]
+pub use self::encoder::Encoder;
+
+/// One step through an encoded packet.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Event<'a> {
+    /// The start of a packet
+    FixedHeader(FixedHeader),
+    /// A field of the variable header
+    Field(Field<'a>),
+    /// Part of the payload, undecoded
+    PayloadChunk(&'a [u8]),
+    /// The end of the packet started by the last `FixedHeader`
+    PacketEnd,
+}
+
+/// A variable header field.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Field<'a> {
+    /// The topic name of a PUBLISH
+    Topic(&'a str),
+    PacketId(PacketId),
+}
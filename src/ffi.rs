@@ -0,0 +1,621 @@
+//! A small C API, enabled with the `ffi` feature.
+//!
+//! The types are `#[repr(C)]` and the functions `extern "C"` so a header can
+//! be generated with cbindgen. Every function returns an `MqttResult`, nothing
+//! panics across the boundary and no memory is allocated, decoded views borrow
+//! from the caller's input buffer.
+
+use core::{convert::TryFrom, ptr, slice, str};
+
+use crate::{
+    codec::{Decodable, Encodable},
+    error::{DecodeError, EncodeError, SubscriptionErrorKind},
+    fixed_header::PublishFlags,
+    packet::{self, ConnectBuilder, Packet},
+    payload, qos,
+    status::Status,
+    variable_header::{self, PacketId},
+};
+
+/// The outcome of a call, `Ok` on success.
+#[repr(C)]
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum MqttResult {
+    Ok = 0,
+    /// More input is needed to decode a packet
+    Partial,
+    /// A null pointer, out of range QoS or missing packet identifier
+    InvalidArgument,
+    /// Not enough space in the encode buffer
+    OutOfSpace,
+    /// A value too big to be encoded
+    ValueTooBig,
+    InvalidPacketType,
+    InvalidPacketFlag,
+    InvalidRemainingLength,
+    InvalidLength,
+    InvalidUtf8,
+    InvalidQoS,
+    InvalidProtocolName,
+    InvalidProtocolLevel,
+    InvalidConnectFlag,
+    InvalidConnackFlag,
+    InvalidConnackReturnCode,
+    InvalidSubackReturnCode,
+    TopicTooLong,
+    TooManyTopicLevels,
+    TooManySubscriptions,
+    /// A packet too large for this target or the peer
+    PacketTooLarge,
+    /// A string or byte field empty or longer than the protocol allows
+    InvalidFieldLength,
+}
+
+impl From<DecodeError> for MqttResult {
+    fn from(err: DecodeError) -> Self {
+        match err {
+            DecodeError::PacketType => MqttResult::InvalidPacketType,
+            DecodeError::PacketFlag => MqttResult::InvalidPacketFlag,
+            DecodeError::RemainingLength => MqttResult::InvalidRemainingLength,
+            DecodeError::InvalidLength => MqttResult::InvalidLength,
+            DecodeError::Utf8 => MqttResult::InvalidUtf8,
+            DecodeError::InvalidQoS(_) => MqttResult::InvalidQoS,
+            DecodeError::InvalidProtocolName => MqttResult::InvalidProtocolName,
+            DecodeError::InvalidProtocolLevel => MqttResult::InvalidProtocolLevel,
+            DecodeError::InvalidConnectFlag(_) => MqttResult::InvalidConnectFlag,
+            DecodeError::InvalidConnackFlag => MqttResult::InvalidConnackFlag,
+            DecodeError::InvalidConnackReturnCode => MqttResult::InvalidConnackReturnCode,
+            DecodeError::InvalidSubackReturnCode => MqttResult::InvalidSubackReturnCode,
+            DecodeError::TopicTooLong => MqttResult::TopicTooLong,
+            DecodeError::TooManyTopicLevels => MqttResult::TooManyTopicLevels,
+            DecodeError::TooManySubscriptions => MqttResult::TooManySubscriptions,
+            DecodeError::PacketTooLarge => MqttResult::PacketTooLarge,
+            DecodeError::Incomplete(_) => MqttResult::Partial,
+            DecodeError::Subscription(err) => match err.kind {
+                SubscriptionErrorKind::Truncated => MqttResult::InvalidLength,
+                SubscriptionErrorKind::Utf8 => MqttResult::InvalidUtf8,
+                SubscriptionErrorKind::InvalidQoS(_) => MqttResult::InvalidQoS,
+            },
+        }
+    }
+}
+
+impl From<EncodeError> for MqttResult {
+    fn from(err: EncodeError) -> Self {
+        match err {
+            EncodeError::OutOfSpace => MqttResult::OutOfSpace,
+            EncodeError::ValueTooBig => MqttResult::ValueTooBig,
+            EncodeError::PacketTooLarge => MqttResult::PacketTooLarge,
+            EncodeError::FieldLength(_) => MqttResult::InvalidFieldLength,
+            EncodeError::EventOrder => MqttResult::InvalidArgument,
+            EncodeError::MissingPacketId | EncodeError::UnexpectedPacketId => {
+                MqttResult::InvalidArgument
+            }
+        }
+    }
+}
+
+/// A borrowed byte range, `ptr` may be null when `len` is zero.
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+pub struct MqttBytes {
+    pub ptr: *const u8,
+    pub len: usize,
+}
+
+impl Default for MqttBytes {
+    fn default() -> Self {
+        MqttBytes::EMPTY
+    }
+}
+
+impl MqttBytes {
+    const EMPTY: MqttBytes = MqttBytes {
+        ptr: ptr::null(),
+        len: 0,
+    };
+
+    fn new(bytes: &[u8]) -> Self {
+        MqttBytes {
+            ptr: bytes.as_ptr(),
+            len: bytes.len(),
+        }
+    }
+
+    unsafe fn as_slice<'a>(&self) -> Option<&'a [u8]> {
+        if self.len == 0 {
+            Some(&[])
+        } else if self.ptr.is_null() {
+            None
+        } else {
+            Some(slice::from_raw_parts(self.ptr, self.len))
+        }
+    }
+
+    unsafe fn as_str<'a>(&self) -> Result<&'a str, MqttResult> {
+        let bytes = self.as_slice().ok_or(MqttResult::InvalidArgument)?;
+        str::from_utf8(bytes).map_err(|_| MqttResult::InvalidUtf8)
+    }
+}
+
+/// A decoded packet, the byte ranges point into the decoded input.
+#[repr(C)]
+#[derive(Copy, Clone, Default, Debug)]
+pub struct MqttPacketView {
+    /// The packet type, 1 for CONNECT through 14 for DISCONNECT
+    pub packet_type: u8,
+    /// The low four bits of the first fixed header byte
+    pub flags: u8,
+    pub remaining_len: u32,
+    /// The packet identifier, zero if the packet does not carry one
+    pub packet_id: u16,
+    /// The PUBLISH topic name, empty for other packets
+    pub topic: MqttBytes,
+    /// The variable header
+    pub variable_header: MqttBytes,
+    /// The payload, the application message of a PUBLISH
+    pub payload: MqttBytes,
+}
+
+/// Parameters for `mqtt_encode_connect`, a null `username` or `password`
+/// leaves the field out. A password without a username is rejected with
+/// `InvalidArgument`.
+///
+/// A null `will_topic` leaves out the will, the other `will_` fields are then
+/// ignored.
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+pub struct MqttConnectParams {
+    pub client_id: MqttBytes,
+    pub username: MqttBytes,
+    pub password: MqttBytes,
+    pub keep_alive: u16,
+    pub clean_session: bool,
+    pub will_topic: MqttBytes,
+    pub will_message: MqttBytes,
+    pub will_qos: u8,
+    pub will_retain: bool,
+}
+
+/// Parameters for `mqtt_encode_publish`, `packet_id` must be non-zero for
+/// QoS 1 and 2.
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+pub struct MqttPublishParams {
+    pub topic: MqttBytes,
+    pub payload: MqttBytes,
+    pub qos: u8,
+    pub retain: bool,
+    pub dup: bool,
+    pub packet_id: u16,
+}
+
+/// Parameters for `mqtt_encode_subscribe`, a single topic filter.
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+pub struct MqttSubscribeParams {
+    pub packet_id: u16,
+    pub topic_filter: MqttBytes,
+    pub qos: u8,
+}
+
+fn parse_qos(value: u8) -> Result<qos::QoS, MqttResult> {
+    qos::QoS::try_from(value).map_err(|_| MqttResult::InvalidArgument)
+}
+
+unsafe fn encode_into(
+    packet: Result<Packet, EncodeError>,
+    buf: *mut u8,
+    len: usize,
+    written: *mut usize,
+) -> MqttResult {
+    if buf.is_null() || written.is_null() {
+        return MqttResult::InvalidArgument;
+    }
+
+    let bytes = slice::from_raw_parts_mut(buf, len);
+    match packet.and_then(|packet| packet.encode(bytes)) {
+        Ok(n) => {
+            *written = n;
+            MqttResult::Ok
+        }
+        Err(e) => e.into(),
+    }
+}
+
+/// Decode one packet from `bytes`.
+///
+/// On `Ok` the packet is described by `view` and `consumed` holds the number
+/// of bytes it occupied. On `Partial` `consumed` holds the number of further
+/// bytes needed.
+///
+/// # Safety
+///
+/// `bytes` must point to `len` readable bytes that outlive `view`, `view` and
+/// `consumed` must be valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn mqtt_decode(
+    bytes: *const u8,
+    len: usize,
+    view: *mut MqttPacketView,
+    consumed: *mut usize,
+) -> MqttResult {
+    if view.is_null() || consumed.is_null() {
+        return MqttResult::InvalidArgument;
+    }
+
+    let bytes = match (MqttBytes { ptr: bytes, len }).as_slice() {
+        Some(bytes) => bytes,
+        None => return MqttResult::InvalidArgument,
+    };
+
+    let (offset, packet) = match Packet::decode(bytes) {
+        Ok(Status::Complete(complete)) => complete,
+        Ok(Status::Partial(needed)) => {
+            *consumed = needed;
+            return MqttResult::Partial;
+        }
+        Err(e) => return e.into(),
+    };
+
+    let fixed_header = packet.fixed_header();
+    let start = fixed_header.encoded_len();
+    let payload_start = offset - packet.payload().encoded_len();
+
+    let packet_id = packet
+        .variable_header()
+        .and_then(variable_header::VariableHeader::packet_identifier)
+        .map_or(0, PacketId::get);
+    let topic = match packet.variable_header() {
+        Some(variable_header::VariableHeader::Publish(publish)) => {
+            MqttBytes::new(publish.topic_name().as_bytes())
+        }
+        _ => MqttBytes::EMPTY,
+    };
+
+    *view = MqttPacketView {
+        packet_type: bytes[0] >> 4,
+        flags: bytes[0] & 0x0F,
+        remaining_len: fixed_header.len(),
+        packet_id,
+        topic,
+        variable_header: MqttBytes::new(&bytes[start..payload_start]),
+        payload: MqttBytes::new(&bytes[payload_start..offset]),
+    };
+    *consumed = offset;
+
+    MqttResult::Ok
+}
+
+/// Encode a CONNECT packet into `buf`, the number of bytes used is stored in
+/// `written`.
+///
+/// # Safety
+///
+/// `params` must be valid for reads and its byte ranges readable, `buf` must
+/// point to `len` writable bytes and `written` must be valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn mqtt_encode_connect(
+    params: *const MqttConnectParams,
+    buf: *mut u8,
+    len: usize,
+    written: *mut usize,
+) -> MqttResult {
+    let params = match params.as_ref() {
+        Some(params) => params,
+        None => return MqttResult::InvalidArgument,
+    };
+
+    let client_id = match params.client_id.as_str() {
+        Ok(client_id) => client_id,
+        Err(e) => return e,
+    };
+    let username = if params.username.ptr.is_null() {
+        None
+    } else {
+        match params.username.as_str() {
+            Ok(username) => Some(username),
+            Err(e) => return e,
+        }
+    };
+    let password = if params.password.ptr.is_null() {
+        None
+    } else {
+        params.password.as_slice()
+    };
+
+    let builder = ConnectBuilder::new(client_id)
+        .keep_alive(params.keep_alive)
+        .clean_session(params.clean_session);
+    let builder = if params.will_topic.ptr.is_null() {
+        builder
+    } else {
+        let topic = match params.will_topic.as_str() {
+            Ok(topic) => topic,
+            Err(e) => return e,
+        };
+        let message = match params.will_message.as_slice() {
+            Some(message) => message,
+            None => return MqttResult::InvalidArgument,
+        };
+        let qos = match parse_qos(params.will_qos) {
+            Ok(qos) => qos,
+            Err(e) => return e,
+        };
+        builder.will(topic, message, qos, params.will_retain)
+    };
+    let builder = match (username, password) {
+        (Some(username), Some(password)) => builder.credentials(username, password),
+        (Some(username), None) => builder.username(username),
+        (None, None) => builder,
+        // [MQTT-3.1.2-22]
+        (None, Some(_)) => return MqttResult::InvalidArgument,
+    };
+
+    encode_into(builder.build(), buf, len, written)
+}
+
+/// Encode a PUBLISH packet into `buf`, the number of bytes used is stored in
+/// `written`.
+///
+/// # Safety
+///
+/// `params` must be valid for reads and its byte ranges readable, `buf` must
+/// point to `len` writable bytes and `written` must be valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn mqtt_encode_publish(
+    params: *const MqttPublishParams,
+    buf: *mut u8,
+    len: usize,
+    written: *mut usize,
+) -> MqttResult {
+    let params = match params.as_ref() {
+        Some(params) => params,
+        None => return MqttResult::InvalidArgument,
+    };
+
+    let qos = match parse_qos(params.qos) {
+        Ok(qos) => qos,
+        Err(e) => return e,
+    };
+    let packet_id = match (qos, params.packet_id) {
+        (qos::QoS::AtMostOnce, _) => None,
+        (_, 0) => return MqttResult::InvalidArgument,
+        (_, id) => Some(PacketId::new(id)),
+    };
+    let topic = match params.topic.as_str() {
+        Ok(topic) => topic,
+        Err(e) => return e,
+    };
+    let message = match params.payload.as_slice() {
+        Some(message) => message,
+        None => return MqttResult::InvalidArgument,
+    };
+
+    let packet = Packet::publish(
+        PublishFlags::new(qos, params.dup, params.retain),
+        variable_header::publish::Publish::new(topic, packet_id),
+        message,
+    );
+
+    encode_into(packet, buf, len, written)
+}
+
+/// Encode a SUBSCRIBE packet for a single topic filter into `buf`, the number
+/// of bytes used is stored in `written`.
+///
+/// # Safety
+///
+/// `params` must be valid for reads and its byte ranges readable, `buf` must
+/// point to `len` writable bytes and `written` must be valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn mqtt_encode_subscribe(
+    params: *const MqttSubscribeParams,
+    buf: *mut u8,
+    len: usize,
+    written: *mut usize,
+) -> MqttResult {
+    let params = match params.as_ref() {
+        Some(params) => params,
+        None => return MqttResult::InvalidArgument,
+    };
+
+    let qos = match parse_qos(params.qos) {
+        Ok(qos) => qos,
+        Err(e) => return e,
+    };
+    let topic_filter = match params.topic_filter.as_str() {
+        Ok(topic_filter) => topic_filter,
+        Err(e) => return e,
+    };
+    if params.packet_id == 0 {
+        return MqttResult::InvalidArgument;
+    }
+
+    let topics = [(topic_filter, qos)];
+    let packet = Packet::subscribe(
+        variable_header::packet_identifier::PacketIdentifier::new(PacketId::new(params.packet_id)),
+        payload::subscribe::Subscribe::new(&topics),
+    );
+
+    encode_into(packet, buf, len, written)
+}
+
+/// Encode a PUBACK packet into `buf`, the number of bytes used is stored in
+/// `written`. A zero `packet_id` is rejected with `InvalidArgument`.
+///
+/// # Safety
+///
+/// `buf` must point to `len` writable bytes and `written` must be valid for
+/// writes.
+#[no_mangle]
+pub unsafe extern "C" fn mqtt_encode_puback(
+    packet_id: u16,
+    buf: *mut u8,
+    len: usize,
+    written: *mut usize,
+) -> MqttResult {
+    // [MQTT-2.3.1-1]
+    if packet_id == 0 {
+        return MqttResult::InvalidArgument;
+    }
+
+    let packet = Packet::puback(variable_header::packet_identifier::PacketIdentifier::new(
+        PacketId::new(packet_id),
+    ));
+
+    encode_into(packet, buf, len, written)
+}
+
+/// Encode a PINGREQ packet into `buf`, the number of bytes used is stored in
+/// `written`.
+///
+/// # Safety
+///
+/// `buf` must point to `len` writable bytes and `written` must be valid for
+/// writes.
+#[no_mangle]
+pub unsafe extern "C" fn mqtt_encode_pingreq(
+    buf: *mut u8,
+    len: usize,
+    written: *mut usize,
+) -> MqttResult {
+    if buf.is_null() || written.is_null() {
+        return MqttResult::InvalidArgument;
+    }
+    if len < packet::PINGREQ_BYTES.len() {
+        return MqttResult::OutOfSpace;
+    }
+
+    slice::from_raw_parts_mut(buf, len)[..packet::PINGREQ_BYTES.len()]
+        .copy_from_slice(&packet::PINGREQ_BYTES);
+    *written = packet::PINGREQ_BYTES.len();
+
+    MqttResult::Ok
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn publish_round_trip() {
+        let params = MqttPublishParams {
+            topic: MqttBytes::new(b"a/b"),
+            payload: MqttBytes::new(b"{}"),
+            qos: 1,
+            retain: false,
+            dup: false,
+            packet_id: 7,
+        };
+
+        let mut buf = [0u8; 16];
+        let mut written = 0;
+        assert_eq!(MqttResult::Ok, unsafe {
+            mqtt_encode_publish(&params, buf.as_mut_ptr(), buf.len(), &mut written)
+        });
+        assert_eq!(11, written);
+
+        let mut view = MqttPacketView::default();
+        let mut consumed = 0;
+        assert_eq!(MqttResult::Partial, unsafe {
+            mqtt_decode(buf.as_ptr(), 4, &mut view, &mut consumed)
+        });
+        assert_eq!(MqttResult::Ok, unsafe {
+            mqtt_decode(buf.as_ptr(), written, &mut view, &mut consumed)
+        });
+        assert_eq!(written, consumed);
+        assert_eq!(3, view.packet_type);
+        assert_eq!(0b0010, view.flags);
+        assert_eq!(7, view.packet_id);
+        assert_eq!(Some(&b"a/b"[..]), unsafe { view.topic.as_slice() });
+        assert_eq!(7, view.variable_header.len);
+        assert_eq!(Some(&b"{}"[..]), unsafe { view.payload.as_slice() });
+    }
+
+    #[test]
+    fn connect_will() {
+        let params = MqttConnectParams {
+            client_id: MqttBytes::new(b"c"),
+            username: MqttBytes::EMPTY,
+            password: MqttBytes::EMPTY,
+            keep_alive: 60,
+            clean_session: true,
+            will_topic: MqttBytes::new(b"w"),
+            will_message: MqttBytes::new(b"bye"),
+            will_qos: 1,
+            will_retain: true,
+        };
+
+        let mut buf = [0u8; 32];
+        let mut written = 0;
+        assert_eq!(MqttResult::Ok, unsafe {
+            mqtt_encode_connect(&params, buf.as_mut_ptr(), buf.len(), &mut written)
+        });
+        let (_, packet) = Packet::decode(&buf[..written]).unwrap().unwrap();
+        let connect = packet.as_connect().unwrap();
+        let will = connect.will.unwrap();
+        assert_eq!(("w", &b"bye"[..]), (will.topic(), will.message()));
+        assert_eq!(
+            Some(variable_header::connect::WillFlags {
+                qos: qos::QoS::AtLeastOnce,
+                retain: true,
+            }),
+            connect.flags.will()
+        );
+
+        let params = MqttConnectParams {
+            will_qos: 3,
+            ..params
+        };
+        assert_eq!(MqttResult::InvalidArgument, unsafe {
+            mqtt_encode_connect(&params, buf.as_mut_ptr(), buf.len(), &mut written)
+        });
+    }
+
+    #[test]
+    fn invalid_arguments() {
+        let params = MqttPublishParams {
+            topic: MqttBytes::new(b"a/b"),
+            payload: MqttBytes::EMPTY,
+            qos: 1,
+            retain: false,
+            dup: false,
+            packet_id: 0,
+        };
+
+        let mut buf = [0u8; 16];
+        let mut written = 0;
+        assert_eq!(MqttResult::InvalidArgument, unsafe {
+            mqtt_encode_publish(&params, buf.as_mut_ptr(), buf.len(), &mut written)
+        });
+        let params = MqttConnectParams {
+            client_id: MqttBytes::new(b"c"),
+            username: MqttBytes::EMPTY,
+            password: MqttBytes::new(b"secret"),
+            keep_alive: 0,
+            clean_session: true,
+            will_topic: MqttBytes::EMPTY,
+            will_message: MqttBytes::EMPTY,
+            will_qos: 0,
+            will_retain: false,
+        };
+        assert_eq!(MqttResult::InvalidArgument, unsafe {
+            mqtt_encode_connect(&params, buf.as_mut_ptr(), buf.len(), &mut written)
+        });
+        assert_eq!(MqttResult::InvalidArgument, unsafe {
+            mqtt_encode_puback(0, buf.as_mut_ptr(), buf.len(), &mut written)
+        });
+        assert_eq!(MqttResult::OutOfSpace, unsafe {
+            mqtt_encode_pingreq(buf.as_mut_ptr(), 1, &mut written)
+        });
+        assert_eq!(MqttResult::InvalidPacketType, unsafe {
+            mqtt_decode(
+                [0xF0, 0x00].as_ptr(),
+                2,
+                &mut MqttPacketView::default(),
+                &mut written,
+            )
+        });
+    }
+}
@@ -1,19 +1,48 @@
-use core::result::Result;
+use core::{convert::TryFrom, result::Result};
 
+use crate::error::DecodeError;
+
+#[cfg(feature = "encode")]
+use crate::{
+    codec::{self, Encodable},
+    error::EncodeError,
+};
+#[cfg(feature = "decode")]
 use crate::{
-    codec::{self, Decodable, Encodable},
-    error::{DecodeError, EncodeError},
+    codec::{Decodable, DecodeOptions},
     status::Status,
 };
 
 mod packet_flags;
+mod packet_kind;
 mod packet_type;
 
 pub use self::{
     packet_flags::{PacketFlags, PublishFlags},
+    packet_kind::PacketKind,
     packet_type::PacketType,
 };
 
+/// The largest value the remaining length field can encode.
+pub const MAX_REMAINING_LENGTH: u32 = 268_435_455;
+
+/// The largest number of bytes an encoded fixed header can occupy.
+pub const MAX_ENCODED_LEN: usize = 5;
+
+/// The largest packet, fixed header included, that can be decoded on this
+/// target.
+///
+/// This is the protocol limit on 32 and 64 bit targets. On 16 bit targets a
+/// slice can be at most `isize::MAX` bytes, packets with a larger remaining
+/// length are rejected with `DecodeError::PacketTooLarge` rather than having
+/// their length truncated.
+pub const MAX_PACKET_LEN: usize =
+    if (isize::MAX as u64) < MAX_ENCODED_LEN as u64 + MAX_REMAINING_LENGTH as u64 {
+        isize::MAX as usize
+    } else {
+        MAX_ENCODED_LEN + MAX_REMAINING_LENGTH as usize
+    };
+
 #[derive(Copy, Clone, PartialEq, Eq, Debug)]
 pub struct FixedHeader {
     r#type: PacketType,
@@ -41,25 +70,85 @@ impl FixedHeader {
     pub fn is_empty(&self) -> bool {
         self.len == 0
     }
-}
 
-impl<'buf> Decodable<'buf> for FixedHeader {
-    fn decode(bytes: &'buf [u8]) -> Result<Status<(usize, Self)>, DecodeError> {
+    /// The type of the packet with the flags of a PUBLISH.
+    ///
+    /// Returns `Err(DecodeError::InvalidQoS)` for a PUBLISH with both QoS bits
+    /// set, which only the fixed header of an otherwise undecoded packet can
+    /// have.
+    pub fn kind(&self) -> Result<PacketKind, DecodeError> {
+        Ok(match self.r#type {
+            PacketType::Connect => PacketKind::Connect,
+            PacketType::Connack => PacketKind::Connack,
+            PacketType::Publish => PacketKind::Publish(PublishFlags::try_from(self.flags)?),
+            PacketType::Puback => PacketKind::Puback,
+            PacketType::Pubrec => PacketKind::Pubrec,
+            PacketType::Pubrel => PacketKind::Pubrel,
+            PacketType::Pubcomp => PacketKind::Pubcomp,
+            PacketType::Subscribe => PacketKind::Subscribe,
+            PacketType::Suback => PacketKind::Suback,
+            PacketType::Unsubscribe => PacketKind::Unsubscribe,
+            PacketType::Unsuback => PacketKind::Unsuback,
+            PacketType::Pingreq => PacketKind::Pingreq,
+            PacketType::Pingresp => PacketKind::Pingresp,
+            PacketType::Disconnect => PacketKind::Disconnect,
+            PacketType::Reserved(r#type) => PacketKind::Reserved(r#type),
+        })
+    }
+
+    /// Decode a fixed header, reserved packet types are decoded as
+    /// `PacketType::Reserved` if `options` allows them.
+    #[cfg(feature = "decode")]
+    pub fn decode_with(
+        bytes: &[u8],
+        options: &DecodeOptions,
+    ) -> Result<Status<(usize, Self)>, DecodeError> {
         // "bytes" must be at least 2 bytes long to be a valid fixed header
         if bytes.len() < 2 {
             return Ok(Status::Partial(2 - bytes.len()));
         }
 
-        let (r#type, flags) = parse_packet_type(bytes[0])?;
+        let (r#type, flags) = match parse_packet_type(bytes[0]) {
+            Err(DecodeError::PacketType) if options.allow_reserved_types => (
+                PacketType::Reserved((bytes[0] & 0xF0) >> 4),
+                PacketFlags(bytes[0] & 0xF),
+            ),
+            result => result?,
+        };
 
         let offset = 1;
 
         let (offset, len) = read!(parse_remaining_length, bytes, offset);
 
+        if offset as u64 + u64::from(len) > MAX_PACKET_LEN as u64 {
+            return Err(DecodeError::PacketTooLarge);
+        }
+
         Ok(Status::Complete((offset, Self { r#type, flags, len })))
     }
 }
 
+/// The total size of the packet starting at `bytes`, fixed header included,
+/// for reserving a buffer before the rest of the packet is read.
+///
+/// Only the fixed header is decoded. While the remaining length is still being
+/// received this returns how many more bytes are needed to determine the size,
+/// one at a time since the end of the remaining length is only known once its
+/// last byte arrives.
+#[cfg(feature = "decode")]
+pub fn needed_buffer_size(bytes: &[u8]) -> Result<Status<usize>, DecodeError> {
+    let (offset, header) = complete!(FixedHeader::decode(bytes));
+    Ok(Status::Complete(offset + usize::try_from(header.len)?))
+}
+
+#[cfg(feature = "decode")]
+impl<'buf> Decodable<'buf> for FixedHeader {
+    fn decode(bytes: &'buf [u8]) -> Result<Status<(usize, Self)>, DecodeError> {
+        Self::decode_with(bytes, &DecodeOptions::default())
+    }
+}
+
+#[cfg(feature = "encode")]
 impl Encodable for FixedHeader {
     fn encoded_len(&self) -> usize {
         let mut buf = [0u8; 4];
@@ -67,6 +156,7 @@ impl Encodable for FixedHeader {
         1 + u
     }
 
+    #[allow(clippy::needless_borrow)]
     fn encode(&self, bytes: &mut [u8]) -> Result<usize, EncodeError> {
         let mut offset = 0;
         offset += codec::values::encode_u8(
@@ -83,7 +173,8 @@ impl Encodable for FixedHeader {
     }
 }
 
-fn parse_remaining_length(bytes: &[u8]) -> Result<Status<(usize, u32)>, DecodeError> {
+#[cfg(feature = "decode")]
+pub(crate) fn parse_remaining_length(bytes: &[u8]) -> Result<Status<(usize, u32)>, DecodeError> {
     let mut multiplier = 1;
     let mut value = 0u32;
     let mut index = 0;
@@ -110,6 +201,7 @@ fn parse_remaining_length(bytes: &[u8]) -> Result<Status<(usize, u32)>, DecodeEr
     }
 }
 
+#[cfg(feature = "encode")]
 fn encode_remaining_length(mut len: u32, buf: &mut [u8; 4]) -> usize {
     let mut index = 0;
     loop {
@@ -127,6 +219,7 @@ fn encode_remaining_length(mut len: u32, buf: &mut [u8; 4]) -> usize {
     }
 }
 
+#[cfg(feature = "decode")]
 fn parse_packet_type(inp: u8) -> Result<(PacketType, PacketFlags), DecodeError> {
     // high 4 bits are the packet type
     let packet_type = match (inp & 0xF0) >> 4 {
@@ -153,6 +246,7 @@ fn parse_packet_type(inp: u8) -> Result<(PacketType, PacketFlags), DecodeError>
     validate_flag(packet_type, flags)
 }
 
+#[cfg(feature = "encode")]
 fn encode_packet_type(r#type: PacketType, flags: PacketFlags) -> u8 {
     let packet_type: u8 = match r#type {
         PacketType::Connect => 1,
@@ -169,11 +263,13 @@ fn encode_packet_type(r#type: PacketType, flags: PacketFlags) -> u8 {
         PacketType::Pingreq => 12,
         PacketType::Pingresp => 13,
         PacketType::Disconnect => 14,
+        PacketType::Reserved(r#type) => r#type & 0xF,
     };
 
     (packet_type << 4) | flags.0
 }
 
+#[cfg(feature = "decode")]
 fn validate_flag(
     packet_type: PacketType,
     flags: PacketFlags,
@@ -202,6 +298,8 @@ fn validate_flag(
         .and_then(|_| validate_flag_val(packet_type, flags, ONE_TYPES, PacketFlags(0b0010)))
 }
 
+#[cfg(feature = "decode")]
+#[allow(clippy::manual_contains)]
 fn validate_flag_val(
     packet_type: PacketType,
     flags: PacketFlags,
@@ -215,13 +313,16 @@ fn validate_flag_val(
     Ok((packet_type, flags))
 }
 
-#[cfg(test)]
+#[cfg(all(test, feature = "decode"))]
 mod tests {
     use super::*;
+    #[cfg(feature = "encode")]
     use rayon::prelude::*;
+    #[cfg(feature = "encode")]
     use std::format;
 
     #[test]
+    #[allow(clippy::zero_prefixed_literal, clippy::identity_op)]
     fn packet_type() {
         let mut inputs: [([u8; 1], PacketType); 14] = [
             ([01 << 4 | 0b0000], PacketType::Connect),
@@ -254,7 +355,28 @@ mod tests {
         assert_eq!(result, Err(DecodeError::PacketType));
     }
 
+    #[cfg(feature = "encode")]
     #[test]
+    fn reserved_packet_type() {
+        let buf = [15 << 4 | 0b0101, 0];
+        assert_eq!(FixedHeader::decode(&buf), Err(DecodeError::PacketType));
+
+        let options = DecodeOptions {
+            allow_reserved_types: true,
+            ..Default::default()
+        };
+        let (offset, header) = FixedHeader::decode_with(&buf, &options).unwrap().unwrap();
+        assert_eq!(offset, 2);
+        assert_eq!(header.r#type(), PacketType::Reserved(15));
+        assert_eq!(header.flags(), PacketFlags(0b0101));
+
+        let mut encoded = [0u8; 2];
+        assert_eq!(header.encode(&mut encoded), Ok(2));
+        assert_eq!(encoded, buf);
+    }
+
+    #[test]
+    #[allow(clippy::zero_prefixed_literal)]
     fn bad_zero_flags() {
         let mut inputs: [([u8; 1], PacketType); 10] = [
             ([01 << 4 | 1], PacketType::Connect),
@@ -275,6 +397,7 @@ mod tests {
     }
 
     #[test]
+    #[allow(clippy::zero_prefixed_literal, clippy::identity_op)]
     fn bad_one_flags() {
         let mut inputs: [([u8; 1], PacketType); 3] = [
             ([06 << 4 | 0], PacketType::Pubrel),
@@ -288,6 +411,7 @@ mod tests {
     }
 
     #[test]
+    #[allow(clippy::zero_prefixed_literal)]
     fn publish_flags() {
         for i in 0..15 {
             let input = 03 << 4 | i;
@@ -297,8 +421,10 @@ mod tests {
         }
     }
 
+    #[cfg(feature = "encode")]
     #[test]
     #[ignore]
+    #[allow(clippy::expect_fun_call)]
     fn remaining_length() {
         // NOTE: This test can take a while to complete.
         let _: u32 = (0u32..(268435455 + 1))
@@ -316,6 +442,15 @@ mod tests {
             .sum();
     }
 
+    #[test]
+    #[cfg(target_pointer_width = "64")]
+    fn max_packet_len() {
+        assert_eq!(
+            MAX_ENCODED_LEN + MAX_REMAINING_LENGTH as usize,
+            MAX_PACKET_LEN
+        );
+    }
+
     #[test]
     fn bad_remaining_length() {
         let buf = [0xFF, 0xFF, 0xFF, 0xFF];
@@ -331,6 +466,7 @@ mod tests {
     }
 
     #[test]
+    #[allow(clippy::zero_prefixed_literal, clippy::identity_op)]
     fn fixed_header1() {
         let buf = [
             01 << 4 | 0b0000, // PacketType::Connect
@@ -344,6 +480,7 @@ mod tests {
     }
 
     #[test]
+    #[allow(clippy::zero_prefixed_literal, clippy::identity_op)]
     fn fixed_header2() {
         let buf = [
             03 << 4 | 0b0000, // PacketType::Publish
@@ -360,6 +497,22 @@ mod tests {
     }
 
     #[test]
+    fn buffer_size() {
+        let buf = [0x30, 0x80, 0x01, 0x00];
+        assert_eq!(needed_buffer_size(&buf[..0]), Ok(Status::Partial(2)));
+        assert_eq!(needed_buffer_size(&buf[..1]), Ok(Status::Partial(1)));
+        assert_eq!(needed_buffer_size(&buf[..2]), Ok(Status::Partial(1)));
+        assert_eq!(needed_buffer_size(&buf[..3]), Ok(Status::Complete(131)));
+        assert_eq!(needed_buffer_size(&buf), Ok(Status::Complete(131)));
+
+        assert_eq!(
+            needed_buffer_size(&[0xFF, 0x00]),
+            Err(DecodeError::PacketType)
+        );
+    }
+
+    #[test]
+    #[allow(clippy::zero_prefixed_literal, clippy::identity_op)]
     fn bad_len() {
         let buf = [03 << 4 | 0];
         let result = FixedHeader::decode(&buf);
@@ -1,13 +1,10 @@
 use core::{
     convert::{From, TryFrom, TryInto},
-    fmt::Debug,
     result::Result,
 };
 
 use crate::qos;
 
-use bitfield::BitRange;
-
 #[derive(PartialEq, Eq, Debug, Clone, Copy)]
 pub struct PacketFlags(pub u8);
 
@@ -27,53 +24,106 @@ impl PacketFlags {
     pub const PINGREQ: PacketFlags = PacketFlags(0b0000);
     pub const PINGRESP: PacketFlags = PacketFlags(0b0000);
     pub const DISCONNECT: PacketFlags = PacketFlags(0b0000);
+
+    /// The flags of a PUBLISH packet, usable in const contexts unlike `From`.
+    pub const fn publish(flags: PublishFlags) -> Self {
+        PacketFlags(flags.bits())
+    }
+
+    pub const fn bits(self) -> u8 {
+        self.0
+    }
 }
 
 impl From<PublishFlags> for PacketFlags {
     fn from(flags: PublishFlags) -> Self {
-        PacketFlags(flags.0)
+        PacketFlags(flags.bits())
     }
 }
 
-#[derive(PartialEq, Eq, Clone, Copy, Default)]
-pub struct PublishFlags(u8);
+/// The flags of a PUBLISH packet, converted to and from the low four bits of
+/// the fixed header at the codec boundary.
+#[derive(PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(not(feature = "tiny"), derive(Debug))]
+pub struct PublishFlags {
+    pub dup: bool,
+    pub qos: qos::QoS,
+    pub retain: bool,
+}
 
-bitfield_bitrange! {
-    struct PublishFlags(u8)
+impl Default for PublishFlags {
+    fn default() -> Self {
+        Self::new(qos::QoS::AtMostOnce, false, false)
+    }
 }
 
 impl PublishFlags {
-    bitfield_fields! {
-        bool;
-        pub dup,    set_dup    : 3;
-        pub retain, set_retain : 0;
+    /// Construct flags for a PUBLISH packet, usable in const contexts.
+    pub const fn new(qos: qos::QoS, dup: bool, retain: bool) -> Self {
+        PublishFlags { dup, qos, retain }
     }
 
-    pub fn qos(&self) -> Result<qos::QoS, qos::Error> {
-        let qos_bits: u8 = self.bit_range(2, 1);
-        qos_bits.try_into()
-    }
+    /// The flags as the low four bits of the fixed header, usable in const
+    /// contexts unlike `From`.
+    pub const fn bits(self) -> u8 {
+        let qos = match self.qos {
+            qos::QoS::AtMostOnce => 0b00,
+            qos::QoS::AtLeastOnce => 0b01,
+            qos::QoS::ExactlyOnce => 0b10,
+        };
 
-    #[allow(dead_code)]
-    pub fn set_qos(&mut self, qos: qos::QoS) {
-        self.set_bit_range(2, 1, u8::from(qos))
+        (self.dup as u8) << 3 | qos << 1 | self.retain as u8
     }
 }
 
-impl Debug for PublishFlags {
-    bitfield_debug! {
-        struct PublishFlags;
-        pub dup, _                : 3;
-        pub into qos::QoS, qos, _ : 2, 1;
-        pub retain, _             : 0;
+impl From<PublishFlags> for u8 {
+    fn from(flags: PublishFlags) -> u8 {
+        flags.bits()
     }
 }
 
+bits_debug!(PublishFlags);
+
 impl TryFrom<PacketFlags> for PublishFlags {
     type Error = qos::Error;
     fn try_from(flags: PacketFlags) -> Result<Self, Self::Error> {
-        let flags = PublishFlags(flags.0);
-        flags.qos()?;
-        Ok(flags)
+        Ok(PublishFlags {
+            dup: flags.0 & 0b1000 != 0,
+            qos: ((flags.0 >> 1) & 0b11).try_into()?,
+            retain: flags.0 & 0b0001 != 0,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TELEMETRY: PublishFlags = PublishFlags::new(qos::QoS::AtLeastOnce, false, true);
+    const TELEMETRY_PACKET: PacketFlags = PacketFlags::publish(TELEMETRY);
+
+    #[test]
+    fn const_publish_flags() {
+        let flags = PublishFlags {
+            qos: qos::QoS::AtLeastOnce,
+            retain: true,
+            ..Default::default()
+        };
+        assert_eq!(flags, TELEMETRY);
+        assert_eq!(0b0011, TELEMETRY_PACKET.bits());
+    }
+
+    #[test]
+    fn publish_flags() {
+        let flags = PublishFlags::try_from(PacketFlags(0b1100)).unwrap();
+        assert!(flags.dup);
+        assert!(!flags.retain);
+        assert_eq!(qos::QoS::ExactlyOnce, flags.qos);
+        assert_eq!(0b1100, u8::from(flags));
+
+        assert_eq!(
+            Err(qos::Error::BadPattern),
+            PublishFlags::try_from(PacketFlags(0b0110))
+        );
     }
 }
@@ -0,0 +1,60 @@
+use super::{PacketType, PublishFlags};
+
+/// The type of a packet along with the flags of a PUBLISH, for branching on
+/// what a packet is without borrowing its fields.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum PacketKind {
+    Connect,
+    Connack,
+    Publish(PublishFlags),
+    Puback,
+    Pubrec,
+    Pubrel,
+    Pubcomp,
+    Subscribe,
+    Suback,
+    Unsubscribe,
+    Unsuback,
+    Pingreq,
+    Pingresp,
+    Disconnect,
+    /// A reserved packet type, only decoded when
+    /// `DecodeOptions::allow_reserved_types` is set.
+    Reserved(u8),
+}
+
+impl PacketKind {
+    pub const fn r#type(self) -> PacketType {
+        match self {
+            PacketKind::Connect => PacketType::Connect,
+            PacketKind::Connack => PacketType::Connack,
+            PacketKind::Publish(_) => PacketType::Publish,
+            PacketKind::Puback => PacketType::Puback,
+            PacketKind::Pubrec => PacketType::Pubrec,
+            PacketKind::Pubrel => PacketType::Pubrel,
+            PacketKind::Pubcomp => PacketType::Pubcomp,
+            PacketKind::Subscribe => PacketType::Subscribe,
+            PacketKind::Suback => PacketType::Suback,
+            PacketKind::Unsubscribe => PacketType::Unsubscribe,
+            PacketKind::Unsuback => PacketType::Unsuback,
+            PacketKind::Pingreq => PacketType::Pingreq,
+            PacketKind::Pingresp => PacketType::Pingresp,
+            PacketKind::Disconnect => PacketType::Disconnect,
+            PacketKind::Reserved(r#type) => PacketType::Reserved(r#type),
+        }
+    }
+
+    /// The flags of a PUBLISH, `None` for other packet types.
+    pub const fn publish_flags(self) -> Option<PublishFlags> {
+        match self {
+            PacketKind::Publish(flags) => Some(flags),
+            _ => None,
+        }
+    }
+}
+
+impl From<PacketKind> for PacketType {
+    fn from(kind: PacketKind) -> Self {
+        kind.r#type()
+    }
+}
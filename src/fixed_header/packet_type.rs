@@ -14,4 +14,7 @@ pub enum PacketType {
     Pingreq,
     Pingresp,
     Disconnect,
+    /// A reserved packet type, only decoded when
+    /// `DecodeOptions::allow_reserved_types` is set.
+    Reserved(u8),
 }
@@ -0,0 +1,230 @@
+//! A flight recorder keeping a summary of the last packets sent and received,
+//! enabled with the `flight-recorder` feature.
+//!
+//! Each `Record` is a few dozen bytes, so the last few hundred packets fit in a
+//! `Recorder` in a `static` and can be dumped over a debug channel after a
+//! fault to see what the connection was doing. `Recorder::new` is `const`, the
+//! application provides whatever lock its platform uses around the `static`.
+
+use core::fmt;
+
+use crate::{
+    clock::Instant,
+    fixed_header::{PacketFlags, PacketType},
+    variable_header::PacketId,
+    view::PacketView,
+};
+
+/// The number of topic bytes kept in a record, longer topics are truncated.
+pub const TOPIC_LEN: usize = 24;
+
+/// The summary of one packet.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct Record {
+    timestamp: Instant,
+    r#type: PacketType,
+    flags: PacketFlags,
+    len: u32,
+    packet_identifier: Option<PacketId>,
+    topic: [u8; TOPIC_LEN],
+    topic_len: u8,
+    truncated: bool,
+}
+
+impl Record {
+    /// Summarise a packet, fields which fail to decode are left out.
+    pub fn new(view: &PacketView, timestamp: Instant) -> Self {
+        let mut record = Record {
+            timestamp,
+            r#type: view.fixed_header().r#type(),
+            flags: view.fixed_header().flags(),
+            len: view.fixed_header().len(),
+            packet_identifier: view.packet_identifier().ok().flatten(),
+            topic: [0; TOPIC_LEN],
+            topic_len: 0,
+            truncated: false,
+        };
+
+        if let Ok(Some(topic)) = view.topic_name() {
+            let mut len = topic.len().min(TOPIC_LEN);
+            while !topic.is_char_boundary(len) {
+                len -= 1;
+            }
+            record.topic[..len].copy_from_slice(&topic.as_bytes()[..len]);
+            record.topic_len = len as u8;
+            record.truncated = len < topic.len();
+        }
+
+        record
+    }
+
+    pub fn timestamp(&self) -> Instant {
+        self.timestamp
+    }
+
+    pub fn r#type(&self) -> PacketType {
+        self.r#type
+    }
+
+    pub fn flags(&self) -> PacketFlags {
+        self.flags
+    }
+
+    /// The remaining length of the packet.
+    pub fn len(&self) -> u32 {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn packet_identifier(&self) -> Option<PacketId> {
+        self.packet_identifier
+    }
+
+    /// The start of the topic name of a PUBLISH, at most `TOPIC_LEN` bytes.
+    pub fn topic(&self) -> Option<&str> {
+        if self.topic_len == 0 {
+            return None;
+        }
+
+        core::str::from_utf8(&self.topic[..usize::from(self.topic_len)]).ok()
+    }
+
+    /// Whether `topic` is shorter than the topic name of the packet.
+    pub fn is_truncated(&self) -> bool {
+        self.truncated
+    }
+}
+
+impl fmt::Display for Record {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}ms {:?} flags {:#06b} len {}",
+            self.timestamp.as_millis(),
+            self.r#type,
+            self.flags.bits(),
+            self.len
+        )?;
+        if let Some(packet_identifier) = self.packet_identifier {
+            write!(f, " id {}", packet_identifier)?;
+        }
+        if let Some(topic) = self.topic() {
+            write!(f, " topic {}", topic)?;
+            if self.truncated {
+                f.write_str("...")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A ring buffer of the last `N` packet records.
+#[derive(Clone, Debug)]
+pub struct Recorder<const N: usize> {
+    records: [Option<Record>; N],
+    next: usize,
+    len: usize,
+}
+
+impl<const N: usize> Default for Recorder<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> Recorder<N> {
+    pub const fn new() -> Self {
+        Self {
+            records: [None; N],
+            next: 0,
+            len: 0,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Record a packet that was sent or received, overwriting the oldest
+    /// record once full.
+    pub fn record(&mut self, view: &PacketView, now: Instant) {
+        if N == 0 {
+            return;
+        }
+
+        self.records[self.next] = Some(Record::new(view, now));
+        self.next = (self.next + 1) % N;
+        self.len = (self.len + 1).min(N);
+    }
+
+    /// The records from oldest to newest.
+    pub fn iter(&self) -> impl Iterator<Item = &Record> + '_ {
+        let start = (self.next + N - self.len) % N.max(1);
+        (0..self.len).filter_map(move |index| self.records[(start + index) % N].as_ref())
+    }
+
+    /// Write the records from oldest to newest, one per line.
+    pub fn dump<W: fmt::Write>(&self, out: &mut W) -> fmt::Result {
+        self.iter()
+            .try_for_each(|record| writeln!(out, "{}", record))
+    }
+
+    pub fn clear(&mut self) {
+        self.records = [None; N];
+        self.next = 0;
+        self.len = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::packet;
+    use std::string::String;
+
+    fn view(bytes: &[u8]) -> PacketView<'_> {
+        PacketView::decode(bytes).unwrap().unwrap().1
+    }
+
+    #[test]
+    fn ring() {
+        let publish = [
+            0x32, 0x1f, 0x00, 0x1a, b's', b'i', b't', b'e', b'/', b'4', b'2', b'/', b's', b'e',
+            b'n', b's', b'o', b'r', b's', b'/', b't', b'e', b'm', b'p', b'e', b'r', b'a', b't',
+            b'u', b'r', 0x00, 0x07, b'1',
+        ];
+        let puback = [0x40, 0x02, 0x00, 0x07];
+
+        let mut recorder = Recorder::<2>::new();
+        recorder.record(&view(&packet::PINGREQ_BYTES), Instant::from_millis(1));
+        recorder.record(&view(&publish), Instant::from_millis(2));
+        recorder.record(&view(&puback), Instant::from_millis(3));
+        assert_eq!(2, recorder.len());
+
+        let records: std::vec::Vec<_> = recorder.iter().collect();
+        assert_eq!(PacketType::Publish, records[0].r#type());
+        assert_eq!(Some("site/42/sensors/temperat"), records[0].topic());
+        assert!(records[0].is_truncated());
+        assert_eq!(Some(PacketId::new(7)), records[1].packet_identifier());
+        assert_eq!(None, records[1].topic());
+
+        let mut dump = String::new();
+        recorder.dump(&mut dump).unwrap();
+        assert_eq!(
+            "2ms Publish flags 0b0010 len 31 id 7 topic site/42/sensors/temperat...\n\
+             3ms Puback flags 0b0000 len 2 id 7\n",
+            dump
+        );
+
+        recorder.clear();
+        assert!(recorder.is_empty());
+        assert_eq!(0, recorder.iter().count());
+    }
+}
@@ -0,0 +1,151 @@
+//! Conversions between this crate's packets and the `rumqttc` packet types,
+//! enabled with the `rumqttc` feature.
+//!
+//! Packets are bridged at the byte level, each side encodes the packet with its
+//! own encoder and the other side decodes it. This makes the conversions
+//! useful for cross-checking the two implementations in tests.
+
+use core::fmt;
+
+use bytes::BytesMut;
+use rumqttc::mqttbytes::{self, v4};
+
+use crate::{
+    codec::{Decodable, Encodable},
+    error::{DecodeError, EncodeError},
+    packet::Packet,
+    status::Status,
+};
+
+#[derive(Debug)]
+pub enum Error {
+    /// Encoding with this crate failed
+    Encode(EncodeError),
+    /// Decoding with this crate failed
+    Decode(DecodeError),
+    /// The bytes written by `rumqttc` did not contain a complete packet
+    Partial(usize),
+    /// Encoding or decoding with `rumqttc` failed
+    Rumqttc(mqttbytes::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Encode(e) => write!(f, "encode failed: {}", e),
+            Error::Decode(e) => write!(f, "decode failed: {}", e),
+            Error::Partial(n) => write!(f, "incomplete packet, {} more bytes needed", n),
+            Error::Rumqttc(e) => write!(f, "rumqttc failed: {}", e),
+        }
+    }
+}
+
+impl ::std::error::Error for Error {}
+
+impl From<EncodeError> for Error {
+    fn from(err: EncodeError) -> Self {
+        Error::Encode(err)
+    }
+}
+
+impl From<DecodeError> for Error {
+    fn from(err: DecodeError) -> Self {
+        Error::Decode(err)
+    }
+}
+
+impl From<mqttbytes::Error> for Error {
+    fn from(err: mqttbytes::Error) -> Self {
+        Error::Rumqttc(err)
+    }
+}
+
+/// Convert a packet into the equivalent `rumqttc` packet.
+pub fn to_rumqttc(packet: &Packet) -> Result<v4::Packet, Error> {
+    let mut bytes = BytesMut::new();
+    bytes.resize(packet.encoded_len(), 0);
+    packet.encode(&mut bytes[..])?;
+
+    Ok(v4::read(&mut bytes, usize::MAX)?)
+}
+
+/// Convert a `rumqttc` packet into the equivalent packet, using `buf` to hold
+/// the encoded bytes the returned packet borrows from.
+pub fn from_rumqttc<'a>(packet: &v4::Packet, buf: &'a mut [u8]) -> Result<Packet<'a>, Error> {
+    let mut bytes = BytesMut::new();
+    write(packet, &mut bytes)?;
+
+    if buf.len() < bytes.len() {
+        return Err(Error::Encode(EncodeError::OutOfSpace));
+    }
+    buf[..bytes.len()].copy_from_slice(&bytes);
+
+    let buf: &'a [u8] = buf;
+    match Packet::decode(&buf[..bytes.len()])? {
+        Status::Complete((_, packet)) => Ok(packet),
+        Status::Partial(n) => Err(Error::Partial(n)),
+    }
+}
+
+fn write(packet: &v4::Packet, bytes: &mut BytesMut) -> Result<usize, mqttbytes::Error> {
+    match packet {
+        v4::Packet::Connect(p) => p.write(bytes),
+        v4::Packet::ConnAck(p) => p.write(bytes),
+        v4::Packet::Publish(p) => p.write(bytes),
+        v4::Packet::PubAck(p) => p.write(bytes),
+        v4::Packet::PubRec(p) => p.write(bytes),
+        v4::Packet::PubRel(p) => p.write(bytes),
+        v4::Packet::PubComp(p) => p.write(bytes),
+        v4::Packet::Subscribe(p) => p.write(bytes),
+        v4::Packet::SubAck(p) => p.write(bytes),
+        v4::Packet::Unsubscribe(p) => p.write(bytes),
+        v4::Packet::UnsubAck(p) => p.write(bytes),
+        v4::Packet::PingReq => v4::PingReq.write(bytes),
+        v4::Packet::PingResp => v4::PingResp.write(bytes),
+        v4::Packet::Disconnect => v4::Disconnect.write(bytes),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{fixed_header, qos, variable_header};
+
+    #[test]
+    fn publish_to_rumqttc() {
+        let flags = fixed_header::PublishFlags::new(qos::QoS::AtLeastOnce, false, false);
+        let packet = Packet::publish(
+            flags,
+            variable_header::publish::Publish::new("a/b", Some(variable_header::PacketId::new(7))),
+            b"{}",
+        )
+        .expect("valid packet");
+
+        let mut expected = v4::Publish::new("a/b", mqttbytes::QoS::AtLeastOnce, &b"{}"[..]);
+        expected.pkid = 7;
+
+        assert_eq!(
+            to_rumqttc(&packet).expect("converted"),
+            v4::Packet::Publish(expected)
+        );
+    }
+
+    #[test]
+    fn subscribe_from_rumqttc() {
+        let mut subscribe = v4::Subscribe::new("c/a", mqttbytes::QoS::ExactlyOnce);
+        subscribe.pkid = 3;
+
+        let mut buf = [0u8; 16];
+        let packet = from_rumqttc(&v4::Packet::Subscribe(subscribe), &mut buf).expect("converted");
+
+        assert_eq!(
+            packet.fixed_header().r#type(),
+            fixed_header::PacketType::Subscribe
+        );
+        let subscribe = packet.as_subscribe_payload().expect("subscribe payload");
+        assert_eq!(
+            subscribe.topics().collect::<std::vec::Vec<_>>(),
+            [("c/a", qos::QoS::ExactlyOnce)]
+        );
+    }
+}
@@ -0,0 +1,115 @@
+//! Runtime checks that declared lengths match the bytes actually written and
+//! read, enabled with the `invariant-check` feature.
+//!
+//! A mismatch between `encoded_len()` and what `encode()` writes produces a
+//! packet whose remaining length disagrees with its body, which a peer will
+//! misframe rather than reject. With the feature enabled `Packet::encode`
+//! checks each part of the packet as it is written and `Packet::decode`
+//! checks that the decoded variable header and payload would encode to exactly
+//! the bytes they were decoded from, panicking on the first mismatch with the
+//! part at fault.
+//!
+//! The fixed header is not compared on decode, a peer may send a remaining
+//! length in more bytes than needed and decoding must not panic on anything
+//! received.
+//!
+//! The checks cost a second walk over every decoded packet, they are meant for
+//! debug builds and downstream integration tests rather than production.
+
+use core::convert::TryFrom;
+
+use crate::{
+    codec::Encodable,
+    fixed_header::{FixedHeader, PacketType},
+    packet::Packet,
+};
+
+/// Panic unless `encode()` wrote the `encoded_len()` it declared.
+#[track_caller]
+pub(crate) fn check_encoded(r#type: PacketType, part: &str, declared: usize, written: usize) {
+    assert!(
+        declared == written,
+        "{:?} {} encoded_len() is {} but encode() wrote {} bytes",
+        r#type,
+        part,
+        declared,
+        written
+    );
+}
+
+/// Panic unless the variable header and payload of a decoded packet declare
+/// the lengths they were decoded from.
+#[track_caller]
+pub(crate) fn check_decoded(packet: &Packet, variable_header_len: usize, payload_len: usize) {
+    let fixed_header = packet.fixed_header();
+    let r#type = fixed_header.r#type();
+    check_decoded_part(
+        r#type,
+        "variable header",
+        variable_header_len,
+        packet.variable_header().map_or(0, Encodable::encoded_len),
+    );
+    check_decoded_part(
+        r#type,
+        "payload",
+        payload_len,
+        packet.payload().encoded_len(),
+    );
+    check_decoded_part(
+        r#type,
+        "remaining length",
+        variable_header_len + payload_len,
+        remaining_len(fixed_header),
+    );
+}
+
+#[track_caller]
+fn check_decoded_part(r#type: PacketType, part: &str, decoded: usize, declared: usize) {
+    assert!(
+        decoded == declared,
+        "{:?} {} decoded from {} bytes but encoded_len() is {}",
+        r#type,
+        part,
+        decoded,
+        declared
+    );
+}
+
+fn remaining_len(fixed_header: &FixedHeader) -> usize {
+    usize::try_from(fixed_header.len()).unwrap_or(usize::MAX)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matching() {
+        check_encoded(PacketType::Pingreq, "fixed header", 2, 2);
+        check_decoded(&Packet::pingreq(), 0, 0);
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "Puback variable header encoded_len() is 2 but encode() wrote 3 bytes"
+    )]
+    fn encoded_mismatch() {
+        check_encoded(PacketType::Puback, "variable header", 2, 3);
+    }
+
+    #[test]
+    #[should_panic(expected = "Pingreq payload decoded from 1 bytes but encoded_len() is 0")]
+    fn decoded_mismatch() {
+        check_decoded(&Packet::pingreq(), 0, 1);
+    }
+
+    #[cfg(feature = "decode")]
+    #[test]
+    fn non_minimal_remaining_length() {
+        use crate::codec::Decodable;
+
+        let (len, packet) = Packet::decode(&[0xc0, 0x80, 0x00]).unwrap().unwrap();
+        assert_eq!(3, len);
+        assert_eq!(PacketType::Pingreq, packet.fixed_header().r#type());
+    }
+}
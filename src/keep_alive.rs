@@ -0,0 +1,182 @@
+//! Keep alive scheduling for clients.
+//!
+//! Times are read from a `clock::Clock`, see the `clock` module for how the
+//! tick counter may wrap.
+
+use crate::{
+    clock::{Clock, Duration, Instant},
+    variable_header::connect,
+};
+
+/// Decides when a client must send a PINGREQ so the server sees a control
+/// packet within the keep alive interval.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct KeepAlive {
+    interval: Option<Duration>,
+    last_sent: Instant,
+}
+
+impl KeepAlive {
+    /// Create a scheduler for the keep alive sent in CONNECT, a disabled keep
+    /// alive never schedules pings.
+    pub fn new(keep_alive: connect::KeepAliveInterval, clock: impl Clock) -> Self {
+        Self {
+            interval: keep_alive.interval(),
+            last_sent: clock.now(),
+        }
+    }
+
+    /// The keep alive interval, `None` if disabled.
+    pub fn interval(&self) -> Option<Duration> {
+        self.interval
+    }
+
+    /// Apply the CONNACK, `server_keep_alive` is the MQTT 5 Server Keep Alive
+    /// property if present.
+    ///
+    /// The client must use the keep alive the server returns in place of the
+    /// one it sent in CONNECT [MQTT-3.2.2-21], without the property the
+    /// CONNECT value stays in force.
+    pub fn connack_received(&mut self, server_keep_alive: Option<connect::KeepAliveInterval>) {
+        if let Some(keep_alive) = server_keep_alive {
+            self.interval = keep_alive.interval();
+        }
+    }
+
+    /// Record that a control packet was sent to the server.
+    pub fn packet_sent(&mut self, clock: impl Clock) {
+        self.last_sent = clock.now();
+    }
+
+    /// Whether a PINGREQ should be sent now.
+    pub fn ping_due(&self, clock: impl Clock) -> bool {
+        let now = clock.now();
+        self.interval
+            .is_some_and(|interval| now.duration_since(self.last_sent) >= interval)
+    }
+
+    /// Time until a PINGREQ is due, `None` if pings are disabled.
+    pub fn next_ping_in(&self, clock: impl Clock) -> Option<Duration> {
+        let now = clock.now();
+        self.interval
+            .map(|interval| interval.saturating_sub(now.duration_since(self.last_sent)))
+    }
+}
+
+/// Tracks an outstanding PINGREQ and reports the broker unresponsive if no
+/// PINGRESP arrives within the timeout.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct PingTracker {
+    timeout: Duration,
+    sent_at: Option<Instant>,
+}
+
+impl PingTracker {
+    /// Create a tracker that waits `timeout` for a PINGRESP.
+    pub fn new(timeout: Duration) -> Self {
+        Self {
+            timeout,
+            sent_at: None,
+        }
+    }
+
+    /// Record that a PINGREQ was sent.
+    ///
+    /// If a PINGREQ is already outstanding its send time is kept, so the
+    /// timeout is measured from the first unanswered ping.
+    pub fn ping_sent(&mut self, clock: impl Clock) {
+        if self.sent_at.is_none() {
+            self.sent_at = Some(clock.now());
+        }
+    }
+
+    /// Record that a PINGRESP was received.
+    pub fn pingresp_received(&mut self) {
+        self.sent_at = None;
+    }
+
+    /// Whether a PINGREQ is awaiting its PINGRESP.
+    pub fn is_outstanding(&self) -> bool {
+        self.sent_at.is_some()
+    }
+
+    /// Whether the outstanding PINGREQ has gone unanswered for longer than the
+    /// timeout, the client should close the connection and reconnect.
+    pub fn is_unresponsive(&self, clock: impl Clock) -> bool {
+        let now = clock.now();
+        match self.sent_at {
+            Some(sent_at) => now.duration_since(sent_at) >= self.timeout,
+            None => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(millis: u32) -> Instant {
+        Instant::from_millis(millis)
+    }
+
+    #[test]
+    fn keep_alive() {
+        let mut keep_alive = KeepAlive::new(connect::KeepAliveInterval::from(10), at(1_000));
+        assert!(!keep_alive.ping_due(at(10_999)));
+        assert_eq!(
+            keep_alive.next_ping_in(at(10_999)),
+            Some(Duration::from_millis(1))
+        );
+        assert!(keep_alive.ping_due(at(11_000)));
+
+        keep_alive.packet_sent(at(11_000));
+        assert!(!keep_alive.ping_due(at(11_000)));
+        assert_eq!(
+            keep_alive.next_ping_in(at(11_000)),
+            Some(Duration::from_secs(10))
+        );
+
+        assert_eq!(Some(Duration::from_secs(10)), keep_alive.interval());
+
+        let disabled = KeepAlive::new(connect::KeepAliveInterval::Disabled, at(0));
+        assert!(!disabled.ping_due(at(u32::MAX)));
+        assert_eq!(disabled.next_ping_in(at(0)), None);
+    }
+
+    #[test]
+    fn server_keep_alive() {
+        let mut keep_alive = KeepAlive::new(connect::KeepAliveInterval::from(60), at(0));
+        keep_alive.connack_received(None);
+        assert_eq!(Some(Duration::from_secs(60)), keep_alive.interval());
+
+        keep_alive.connack_received(Some(connect::KeepAliveInterval::from(10)));
+        assert!(keep_alive.ping_due(at(10_000)));
+
+        keep_alive.connack_received(Some(connect::KeepAliveInterval::Disabled));
+        assert!(!keep_alive.ping_due(at(u32::MAX)));
+    }
+
+    #[test]
+    fn keep_alive_wrapping() {
+        let keep_alive = KeepAlive::new(connect::KeepAliveInterval::from(1), at(u32::MAX - 499));
+        assert!(!keep_alive.ping_due(at(499)));
+        assert!(keep_alive.ping_due(at(500)));
+    }
+
+    #[test]
+    fn ping_tracker() {
+        let mut tracker = PingTracker::new(Duration::from_secs(5));
+        assert!(!tracker.is_outstanding());
+        assert!(!tracker.is_unresponsive(at(u32::MAX)));
+
+        tracker.ping_sent(at(1_000));
+        tracker.ping_sent(at(3_000));
+        assert!(tracker.is_outstanding());
+        assert!(!tracker.is_unresponsive(at(5_999)));
+        assert!(tracker.is_unresponsive(at(6_000)));
+
+        tracker.pingresp_received();
+        assert!(!tracker.is_outstanding());
+        assert!(!tracker.is_unresponsive(at(6_000)));
+    }
+}
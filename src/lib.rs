@@ -11,8 +11,21 @@ extern crate byteorder;
 #[macro_use]
 extern crate bitfield;
 
+#[cfg(feature = "rumqttc")]
+extern crate bytes;
+#[cfg(feature = "log")]
+extern crate log;
+#[cfg(feature = "rumqttc")]
+extern crate rumqttc;
+#[cfg(feature = "smoltcp")]
+extern crate smoltcp;
+#[cfg(feature = "tracing")]
+extern crate tracing;
+
 #[macro_use]
 pub mod status;
+#[macro_use]
+mod debug;
 pub mod error;
 
 pub mod codec;
@@ -20,6 +33,75 @@ pub mod codec;
 pub mod fixed_header;
 pub mod packet;
 pub mod payload;
+pub mod typed;
 pub mod variable_header;
+#[cfg(feature = "decode")]
+pub mod view;
 
 pub mod qos;
+
+#[cfg(feature = "encode")]
+pub mod appender;
+pub mod clock;
+pub mod correlation;
+pub mod event;
+pub mod keep_alive;
+pub mod qos2;
+pub mod queue;
+pub mod rate_limit;
+pub mod redelivery;
+pub mod shutdown;
+#[cfg(feature = "encode")]
+pub mod template;
+pub mod topic;
+pub mod topic_registry;
+pub mod topic_trie;
+pub mod transport;
+pub mod v5;
+
+#[cfg(feature = "stats")]
+pub mod stats;
+
+#[cfg(feature = "flight-recorder")]
+pub mod flight_recorder;
+
+#[cfg(feature = "invariant-check")]
+mod invariant;
+
+#[cfg(all(feature = "tracing", any(feature = "encode", feature = "decode")))]
+mod trace;
+
+#[cfg(feature = "rumqttc")]
+pub mod interop;
+
+#[cfg(feature = "differential")]
+pub mod differential;
+
+#[cfg(feature = "smoltcp")]
+pub mod socket;
+
+#[cfg(feature = "ffi")]
+pub mod ffi;
+
+#[cfg(all(feature = "std", feature = "decode"))]
+pub mod pcap;
+
+#[cfg(all(any(feature = "std", test), feature = "encode", feature = "decode"))]
+pub mod mock_broker;
+
+#[cfg(feature = "corpus")]
+pub mod corpus;
+
+#[cfg(all(
+    any(feature = "test-util", test),
+    feature = "encode",
+    feature = "decode"
+))]
+pub mod test_util;
+
+#[cfg(all(
+    any(feature = "test-util", test),
+    feature = "encode",
+    feature = "decode"
+))]
+pub mod loopback;
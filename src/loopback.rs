@@ -0,0 +1,331 @@
+//! In-memory links for testing protocol state machines without sockets,
+//! enabled with the `test-util` feature.
+//!
+//! `pair` returns the two ends of a link, each implementing `PacketSink` and
+//! `PacketSource`. Bytes sent on one end are received on the other once the
+//! link latency has passed, at most `chunk_size` bytes per `recv`. Time only
+//! moves when the test calls `advance`, and `clock` hands the same time to the
+//! keep alive and retry components, so timing behaviour is deterministic.
+//!
+//! `relay` serves one end from anything implementing both traits, such as the
+//! `mock_broker::MockBroker`, to put a broker behind a link.
+
+use core::{cell::Cell, fmt, result::Result};
+use std::{cell::RefCell, collections::VecDeque, rc::Rc, vec::Vec};
+
+use crate::{
+    clock::{Clock, Duration, Instant},
+    mock_broker,
+    transport::{PacketSink, PacketSource},
+};
+
+/// The other end of the link has been closed and everything it sent has been
+/// received.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct Closed;
+
+impl fmt::Display for Closed {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("loopback link closed")
+    }
+}
+
+impl ::std::error::Error for Closed {}
+
+impl From<Closed> for mock_broker::Error {
+    fn from(_: Closed) -> Self {
+        mock_broker::Error::Closed
+    }
+}
+
+/// The properties of both directions of a link.
+#[derive(Copy, Clone, PartialEq, Eq, Default, Debug)]
+pub struct Link {
+    /// The most bytes a single `recv` returns, zero for no limit
+    pub chunk_size: usize,
+    /// The time between sending bytes and them being received
+    pub latency: Duration,
+}
+
+#[derive(Default, Debug)]
+struct Queue {
+    /// Sent bytes and the time they arrive at the other end
+    segments: VecDeque<(Instant, Vec<u8>)>,
+    closed: bool,
+}
+
+/// One end of a loopback link.
+#[derive(Debug)]
+pub struct End {
+    link: Link,
+    now: Rc<Cell<Instant>>,
+    incoming: Rc<RefCell<Queue>>,
+    outgoing: Rc<RefCell<Queue>>,
+}
+
+/// Create a link, returning its two ends.
+///
+/// The ends share a clock starting at zero.
+pub fn pair(link: Link) -> (End, End) {
+    let now = Rc::new(Cell::new(Instant::default()));
+    let a = Rc::new(RefCell::new(Queue::default()));
+    let b = Rc::new(RefCell::new(Queue::default()));
+
+    (
+        End {
+            link,
+            now: now.clone(),
+            incoming: a.clone(),
+            outgoing: b.clone(),
+        },
+        End {
+            link,
+            now,
+            incoming: b,
+            outgoing: a,
+        },
+    )
+}
+
+impl End {
+    /// The current time of the link.
+    pub fn now(&self) -> Instant {
+        self.now.get()
+    }
+
+    /// Move the clock shared by both ends forward.
+    pub fn advance(&self, duration: Duration) {
+        self.now.set(self.now.get() + duration);
+    }
+
+    /// A clock reading the time of the link.
+    pub fn clock(&self) -> impl Clock {
+        let now = self.now.clone();
+        move || now.get()
+    }
+
+    /// The number of bytes sent to this end that have not been received yet,
+    /// whether or not they have arrived.
+    pub fn pending(&self) -> usize {
+        self.incoming
+            .borrow()
+            .segments
+            .iter()
+            .map(|(_, bytes)| bytes.len())
+            .sum()
+    }
+
+    /// Close this end, the other end receives `Err(Closed)` once it has
+    /// received everything sent before.
+    pub fn close(&mut self) {
+        self.outgoing.borrow_mut().closed = true;
+    }
+}
+
+impl PacketSink for End {
+    type Error = Closed;
+
+    fn send(&mut self, packet: &[u8]) -> Result<bool, Closed> {
+        let mut outgoing = self.outgoing.borrow_mut();
+        if outgoing.closed {
+            return Err(Closed);
+        }
+
+        if !packet.is_empty() {
+            let arrival = self.now.get() + self.link.latency;
+            outgoing.segments.push_back((arrival, packet.to_vec()));
+        }
+        Ok(true)
+    }
+}
+
+impl PacketSource for End {
+    type Error = Closed;
+
+    fn recv(&mut self, buf: &mut [u8]) -> Result<usize, Closed> {
+        let now = self.now.get();
+        let limit = match self.link.chunk_size {
+            0 => buf.len(),
+            chunk_size => buf.len().min(chunk_size),
+        };
+
+        let mut incoming = self.incoming.borrow_mut();
+        let mut len = 0;
+        while len < limit {
+            let segment = match incoming.segments.front_mut() {
+                Some((arrival, segment)) if arrived(*arrival, now) => segment,
+                _ => break,
+            };
+
+            let n = segment.len().min(limit - len);
+            buf[len..len + n].copy_from_slice(&segment[..n]);
+            segment.drain(..n);
+            len += n;
+
+            if segment.is_empty() {
+                incoming.segments.pop_front();
+            }
+        }
+
+        if len == 0 && incoming.closed && incoming.segments.is_empty() {
+            return Err(Closed);
+        }
+        Ok(len)
+    }
+}
+
+/// Whether bytes arriving at `arrival` have arrived by `now`, allowing for
+/// the tick counter wrapping.
+fn arrived(arrival: Instant, now: Instant) -> bool {
+    now.duration_since(arrival).as_millis() <= u32::MAX / 2
+}
+
+/// Pass everything that has arrived at `end` to `peer` and everything `peer`
+/// has to send back over the link, returning the number of bytes moved.
+///
+/// Call it after every `advance` to keep the peer serving the link.
+pub fn relay<P, E>(end: &mut End, peer: &mut P) -> Result<usize, E>
+where
+    P: PacketSink<Error = E> + PacketSource<Error = E>,
+    E: From<Closed>,
+{
+    let mut buf = [0u8; 256];
+    let mut moved = 0;
+
+    loop {
+        let len = end.recv(&mut buf)?;
+        if len == 0 {
+            break;
+        }
+        peer.send(&buf[..len])?;
+        moved += len;
+    }
+
+    loop {
+        let len = peer.recv(&mut buf)?;
+        if len == 0 {
+            break;
+        }
+        end.send(&buf[..len])?;
+        moved += len;
+    }
+
+    Ok(moved)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        fixed_header::PacketType,
+        keep_alive::KeepAlive,
+        mock_broker::MockBroker,
+        packet::Packet,
+        transport::{self, recv_packet, send_packet},
+        variable_header::connect,
+    };
+
+    #[test]
+    fn latency_and_chunks() {
+        let (mut a, mut b) = pair(Link {
+            chunk_size: 3,
+            latency: Duration::from_millis(50),
+        });
+
+        let mut scratch = [0u8; 8];
+        assert_eq!(
+            Ok(true),
+            send_packet(&mut a, &Packet::pingreq(), &mut scratch)
+        );
+        assert_eq!(Ok(true), b.send(&[0xd0, 0x00, 0xe0, 0x00]));
+
+        let mut buf = [0u8; 8];
+        let mut len = 0;
+        assert_eq!(Ok(0), b.recv(&mut buf));
+        assert_eq!(2, b.pending());
+
+        a.advance(Duration::from_millis(50));
+        assert_eq!(
+            PacketType::Pingreq,
+            recv_packet(&mut b, &mut buf, &mut len)
+                .unwrap()
+                .unwrap()
+                .1
+                .fixed_header()
+                .r#type()
+        );
+
+        // two packets in one segment, read three bytes at a time
+        assert_eq!(Ok(3), a.recv(&mut buf));
+        assert_eq!(Ok(1), a.recv(&mut buf));
+
+        b.close();
+        assert_eq!(Err(Closed), b.send(&[0xc0, 0x00]));
+        assert_eq!(Err(Closed), a.recv(&mut buf));
+    }
+
+    /// Read until a packet is complete and consume it, returning its type.
+    fn read(end: &mut End, buf: &mut [u8], len: &mut usize) -> Option<PacketType> {
+        for _ in 0..buf.len() {
+            if let Some((consumed, packet)) = recv_packet(end, buf, len).unwrap() {
+                let r#type = packet.fixed_header().r#type();
+                transport::consume(buf, len, consumed);
+                return Some(r#type);
+            }
+        }
+        None
+    }
+
+    #[test]
+    fn broker_keep_alive() {
+        const CONNECT: &[u8] = crate::template!(crate::packet::ConnectBuilder::new("client")
+            .keep_alive(1)
+            .clean_session(true));
+
+        let (mut client, mut server) = pair(Link {
+            chunk_size: 1,
+            latency: Duration::from_millis(100),
+        });
+        let mut broker = MockBroker::new();
+        let clock = client.clock();
+        let mut buf = [0u8; 16];
+        let mut len = 0;
+
+        let mut keep_alive = KeepAlive::new(connect::KeepAliveInterval::from(1), clock.now());
+        client.send(CONNECT).unwrap();
+        keep_alive.packet_sent(clock.now());
+
+        client.advance(Duration::from_millis(100));
+        relay(&mut server, &mut broker).unwrap();
+        assert!(broker.is_connected());
+        assert_eq!(None, read(&mut client, &mut buf, &mut len));
+        client.advance(Duration::from_millis(100));
+        assert_eq!(
+            Some(PacketType::Connack),
+            read(&mut client, &mut buf, &mut len)
+        );
+
+        client.advance(Duration::from_millis(800));
+        assert!(keep_alive.ping_due(clock.now()));
+        let mut scratch = [0u8; 2];
+        send_packet(&mut client, &Packet::pingreq(), &mut scratch).unwrap();
+        keep_alive.packet_sent(clock.now());
+
+        // the PINGRESP arrives a round trip later
+        client.advance(Duration::from_millis(100));
+        relay(&mut server, &mut broker).unwrap();
+        assert_eq!(None, read(&mut client, &mut buf, &mut len));
+        client.advance(Duration::from_millis(100));
+        assert_eq!(
+            Some(PacketType::Pingresp),
+            read(&mut client, &mut buf, &mut len)
+        );
+        assert!(!keep_alive.ping_due(clock.now()));
+
+        client.close();
+        assert_eq!(
+            Err(mock_broker::Error::Closed),
+            relay(&mut server, &mut broker)
+        );
+    }
+}
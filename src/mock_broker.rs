@@ -0,0 +1,561 @@
+//! An in-memory broker for tests, enabled with the `std` feature.
+//!
+//! `MockBroker` serves a single client in-process. It implements `PacketSink`
+//! and `PacketSource`, so client code written against the transport traits can
+//! use it in place of a socket: each packet sent to it is answered straight
+//! away and the replies are read back with `recv`. It accepts a CONNECT,
+//! acknowledges SUBSCRIBE, UNSUBSCRIBE and QoS 1 and 2 PUBLISH, answers
+//! PINGREQ and echoes a PUBLISH back to the client when it matches one of its
+//! subscriptions.
+//!
+//! `Behaviour` scripts the ways a broker can misbehave, such as holding
+//! acknowledgements back or sending packets with invalid flags, to test how a
+//! client copes.
+
+use core::{convert::TryFrom, fmt, mem, result::Result};
+use std::{string::String, vec::Vec};
+
+use crate::{
+    codec::{Decodable, Encodable},
+    error::{DecodeError, EncodeError},
+    fixed_header::{PacketType, PublishFlags},
+    packet::Packet,
+    payload::suback::ReturnCode,
+    qos::QoS,
+    status::Status,
+    topic::{TopicFilter, TopicName},
+    transport::{PacketSink, PacketSource},
+    variable_header::{connack, publish::Publish, PacketId, VariableHeader},
+};
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Error {
+    /// A packet from the client failed to decode
+    Decode(DecodeError),
+    /// A reply failed to encode
+    Encode(EncodeError),
+    /// A packet the client must not send at this point, e.g. anything before
+    /// CONNECT or a second CONNECT
+    Protocol(PacketType),
+    /// The connection has been closed
+    Closed,
+}
+
+impl Error {
+    fn desc(&self) -> &'static str {
+        match *self {
+            Error::Decode(_) => "client packet failed to decode",
+            Error::Encode(_) => "reply failed to encode",
+            Error::Protocol(_) => "unexpected packet from client",
+            Error::Closed => "connection closed",
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.desc())
+    }
+}
+
+impl ::std::error::Error for Error {}
+
+impl From<DecodeError> for Error {
+    fn from(err: DecodeError) -> Self {
+        Error::Decode(err)
+    }
+}
+
+impl From<EncodeError> for Error {
+    fn from(err: EncodeError) -> Self {
+        Error::Encode(err)
+    }
+}
+
+/// How the broker deviates from the protocol, everything off by default.
+#[derive(Copy, Clone, PartialEq, Eq, Default, Debug)]
+pub struct Behaviour {
+    /// Refuse the CONNECT with this return code and close the connection
+    pub refuse_connect: Option<connack::ReturnCode>,
+    /// Hold CONNACK, SUBACK, UNSUBACK, PUBACK, PUBREC and PUBCOMP back until
+    /// `release_acks` is called
+    pub hold_acks: bool,
+    /// Set the reserved flag bits of every packet sent to the client, making
+    /// each of them invalid
+    pub bad_flags: bool,
+}
+
+/// A single client broker driven through the transport traits.
+#[derive(Default, Debug)]
+pub struct MockBroker {
+    behaviour: Behaviour,
+    connected: bool,
+    closed: bool,
+    received: Vec<u8>,
+    outgoing: Vec<u8>,
+    held: Vec<Vec<u8>>,
+    subscriptions: Vec<(String, QoS)>,
+    packet_identifier: PacketId,
+}
+
+impl MockBroker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_behaviour(behaviour: Behaviour) -> Self {
+        Self {
+            behaviour,
+            ..Self::default()
+        }
+    }
+
+    /// Change the behaviour part way through a test, it applies to packets
+    /// received from now on.
+    pub fn behaviour_mut(&mut self) -> &mut Behaviour {
+        &mut self.behaviour
+    }
+
+    /// Whether a CONNECT has been accepted.
+    pub fn is_connected(&self) -> bool {
+        self.connected
+    }
+
+    /// Whether the connection has been closed, by a DISCONNECT, a refused
+    /// CONNECT or a protocol error.
+    pub fn is_closed(&self) -> bool {
+        self.closed
+    }
+
+    /// The topic filters the client is subscribed to and their granted QoS.
+    pub fn subscriptions(&self) -> impl Iterator<Item = (&str, QoS)> + '_ {
+        self.subscriptions
+            .iter()
+            .map(|(filter, qos)| (filter.as_str(), *qos))
+    }
+
+    /// The number of acknowledgements held back by `Behaviour::hold_acks`.
+    pub fn held_acks(&self) -> usize {
+        self.held.len()
+    }
+
+    /// Send the held acknowledgements in the order they were held.
+    pub fn release_acks(&mut self) {
+        for ack in mem::take(&mut self.held) {
+            self.reply(ack);
+        }
+    }
+
+    fn handle_all(&mut self, bytes: &[u8]) -> Result<usize, Error> {
+        let mut offset = 0;
+        while !self.closed {
+            match Packet::decode(&bytes[offset..])? {
+                Status::Complete((consumed, packet)) => {
+                    self.handle(&packet)?;
+                    offset += consumed;
+                }
+                Status::Partial(..) => break,
+            }
+        }
+
+        Ok(offset)
+    }
+
+    fn handle(&mut self, packet: &Packet) -> Result<(), Error> {
+        let r#type = packet.fixed_header().r#type();
+        match (self.connected, r#type) {
+            (false, PacketType::Connect) => {
+                let return_code = self
+                    .behaviour
+                    .refuse_connect
+                    .unwrap_or(connack::ReturnCode::Accepted);
+                let mut bytes = vec![0x20, 0x02, 0x00, 0x00];
+                return_code.encode(&mut bytes[3..])?;
+                self.ack(bytes);
+
+                self.connected = return_code == connack::ReturnCode::Accepted;
+                self.closed = !self.connected;
+            }
+            (true, PacketType::Subscribe) => self.subscribe(packet)?,
+            (true, PacketType::Unsubscribe) => self.unsubscribe(packet)?,
+            (true, PacketType::Publish) => self.publish(packet)?,
+            (true, PacketType::Pubrel) => self.ack(ack(0x70, packet)?),
+            // the client received an echoed QoS 2 message
+            (true, PacketType::Pubrec) => {
+                let pubrel = ack(0x62, packet)?;
+                self.reply(pubrel);
+            }
+            (true, PacketType::Puback) | (true, PacketType::Pubcomp) => {}
+            (true, PacketType::Pingreq) => self.reply(vec![0xd0, 0x00]),
+            (true, PacketType::Disconnect) => self.closed = true,
+            (_, r#type) => {
+                self.closed = true;
+                return Err(Error::Protocol(r#type));
+            }
+        }
+
+        Ok(())
+    }
+
+    fn subscribe(&mut self, packet: &Packet) -> Result<(), Error> {
+        let len = packet
+            .as_subscribe_payload()
+            .map(|payload| payload.topics().count())
+            .unwrap_or(0);
+        let mut return_codes = vec![ReturnCode::FAILURE; len];
+        let mut accepted = vec![("", QoS::AtMostOnce); len];
+
+        let (suback, accepted_len) =
+            match Packet::suback_for(packet, &mut return_codes, &mut accepted, |_, qos| Ok(qos)) {
+                Some(result) => result?,
+                None => return Err(Error::Protocol(PacketType::Subscribe)),
+            };
+
+        for &(filter, qos) in &accepted[..accepted_len] {
+            // [MQTT-3.8.4-3]
+            self.subscriptions
+                .retain(|(existing, _)| existing != filter);
+            self.subscriptions.push((filter.into(), qos));
+        }
+
+        let mut bytes = vec![0; suback.encoded_len()];
+        suback.encode(&mut bytes)?;
+        self.ack(bytes);
+
+        Ok(())
+    }
+
+    fn unsubscribe(&mut self, packet: &Packet) -> Result<(), Error> {
+        let unsubscribe = packet
+            .as_unsubscribe_payload()
+            .ok_or(Error::Protocol(PacketType::Unsubscribe))?;
+        for filter in unsubscribe.filters() {
+            self.subscriptions
+                .retain(|(existing, _)| existing != filter);
+        }
+
+        self.ack(ack(0xb0, packet)?);
+
+        Ok(())
+    }
+
+    fn publish(&mut self, packet: &Packet) -> Result<(), Error> {
+        let flags = PublishFlags::try_from(packet.fixed_header().flags())
+            .map_err(|_| DecodeError::PacketFlag)?;
+        match flags.qos {
+            QoS::AtMostOnce => {}
+            QoS::AtLeastOnce => self.ack(ack(0x40, packet)?),
+            QoS::ExactlyOnce => self.ack(ack(0x50, packet)?),
+        }
+
+        let topic = match packet.variable_header() {
+            Some(VariableHeader::Publish(publish)) => publish.topic_name(),
+            _ => return Err(Error::Protocol(PacketType::Publish)),
+        };
+        let topic_name = TopicName::new(topic).map_err(|_| Error::Protocol(PacketType::Publish))?;
+        let granted = self
+            .subscriptions
+            .iter()
+            .filter(|(filter, _)| {
+                TopicFilter::new(filter).is_ok_and(|filter| filter.matches(topic_name))
+            })
+            .map(|(_, qos)| *qos)
+            .max();
+        let qos = match granted {
+            Some(granted) => granted.min(flags.qos),
+            None => return Ok(()),
+        };
+
+        let packet_identifier = if qos == QoS::AtMostOnce {
+            None
+        } else {
+            self.packet_identifier = self.packet_identifier.next();
+            Some(self.packet_identifier)
+        };
+        let echo = Packet::publish(
+            PublishFlags::new(qos, false, false),
+            Publish::new(topic, packet_identifier),
+            packet.payload_bytes().unwrap_or(&[]),
+        )?;
+
+        let mut bytes = vec![0; echo.encoded_len()];
+        echo.encode(&mut bytes)?;
+        self.reply(bytes);
+
+        Ok(())
+    }
+
+    fn ack(&mut self, bytes: Vec<u8>) {
+        if self.behaviour.hold_acks {
+            self.held.push(bytes);
+        } else {
+            self.reply(bytes);
+        }
+    }
+
+    fn reply(&mut self, mut bytes: Vec<u8>) {
+        if self.behaviour.bad_flags {
+            // QoS 3 for a PUBLISH, reserved bits for any other type
+            bytes[0] |= 0b0110;
+        }
+
+        self.outgoing.extend_from_slice(&bytes);
+    }
+}
+
+impl PacketSink for MockBroker {
+    type Error = Error;
+
+    /// Receive bytes from the client and queue the replies to each packet
+    /// they complete.
+    ///
+    /// A packet that fails to decode or breaks the protocol closes the
+    /// connection.
+    fn send(&mut self, packet: &[u8]) -> Result<bool, Error> {
+        if self.closed {
+            return Err(Error::Closed);
+        }
+
+        let mut received = mem::take(&mut self.received);
+        received.extend_from_slice(packet);
+
+        let result = self.handle_all(&received);
+        if result.is_err() {
+            self.closed = true;
+        }
+
+        received.drain(..result.unwrap_or(0));
+        self.received = received;
+
+        result.map(|_| true)
+    }
+}
+
+impl PacketSource for MockBroker {
+    type Error = Error;
+
+    /// Read the queued replies, returns `Err(Error::Closed)` once the
+    /// connection is closed and every reply has been read.
+    fn recv(&mut self, buf: &mut [u8]) -> Result<usize, Error> {
+        if self.outgoing.is_empty() && self.closed {
+            return Err(Error::Closed);
+        }
+
+        let len = buf.len().min(self.outgoing.len());
+        buf[..len].copy_from_slice(&self.outgoing[..len]);
+        self.outgoing.drain(..len);
+
+        Ok(len)
+    }
+}
+
+/// An acknowledgement carrying the packet identifier of `packet`.
+fn ack(first: u8, packet: &Packet) -> Result<Vec<u8>, Error> {
+    let packet_identifier = packet
+        .variable_header()
+        .and_then(VariableHeader::packet_identifier);
+
+    let [high, low] = packet_identifier
+        .ok_or(DecodeError::InvalidLength)?
+        .get()
+        .to_be_bytes();
+    Ok(vec![first, 0x02, high, low])
+}
+
+/// Whether a topic filter matches a topic name.
+///
+/// Wildcards at the first level do not match topic names beginning with `$`
+/// [MQTT-4.7.2-1].
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        payload, template,
+        transport::{self, send_packet},
+        variable_header::packet_identifier::PacketIdentifier,
+    };
+
+    const CONNECT: &[u8] = crate::template!(crate::packet::ConnectBuilder::new("client")
+        .keep_alive(60)
+        .clean_session(true));
+
+    const SUBSCRIBE: &[u8] = crate::template!(template::Subscribe {
+        packet_identifier: 1,
+        topics: &[("site/+/status", QoS::AtLeastOnce)],
+    });
+
+    fn publish(broker: &mut MockBroker, topic: &str, qos: QoS, packet_identifier: u16) {
+        let packet = Packet::publish(
+            PublishFlags::new(qos, false, false),
+            Publish::new(topic, Some(PacketId::new(packet_identifier))),
+            b"up",
+        )
+        .unwrap();
+        let mut scratch = [0u8; 64];
+        send_packet(broker, &packet, &mut scratch).unwrap();
+    }
+
+    /// Read every queued reply, returning the type, first byte and encoding of
+    /// each.
+    fn replies(broker: &mut MockBroker) -> Vec<(PacketType, u8, Vec<u8>)> {
+        let mut bytes = vec![0; 256];
+        let len = broker.recv(&mut bytes).unwrap();
+
+        let mut offset = 0;
+        let mut replies = Vec::new();
+        while offset < len {
+            let (consumed, raw) = Packet::decode_raw(&bytes[offset..len]).unwrap().unwrap();
+            replies.push((
+                raw.fixed_header().r#type(),
+                bytes[offset],
+                bytes[offset..offset + consumed].to_vec(),
+            ));
+            offset += consumed;
+        }
+        replies
+    }
+
+    fn types(broker: &mut MockBroker) -> Vec<PacketType> {
+        replies(broker)
+            .into_iter()
+            .map(|(r#type, ..)| r#type)
+            .collect()
+    }
+
+    #[test]
+    fn session() {
+        let mut broker = MockBroker::new();
+        assert_eq!(Ok(true), broker.send(CONNECT));
+        assert!(broker.is_connected());
+        assert_eq!(
+            vec![(PacketType::Connack, 0x20, vec![0x20, 0x02, 0x00, 0x00])],
+            replies(&mut broker)
+        );
+
+        // split across two writes
+        broker.send(&SUBSCRIBE[..3]).unwrap();
+        assert_eq!(Vec::<PacketType>::new(), types(&mut broker));
+        broker.send(&SUBSCRIBE[3..]).unwrap();
+        assert_eq!(
+            vec![("site/+/status", QoS::AtLeastOnce)],
+            broker.subscriptions().collect::<Vec<_>>()
+        );
+        let suback = replies(&mut broker);
+        assert_eq!(vec![0x90, 0x03, 0x00, 0x01, 0x01], suback[0].2);
+
+        publish(&mut broker, "site/42/status", QoS::ExactlyOnce, 7);
+        let sent = replies(&mut broker);
+        assert_eq!(vec![0x50, 0x02, 0x00, 0x07], sent[0].2);
+        // echoed at the granted QoS with the broker's own identifier
+        let (_, echo) = Packet::decode(&sent[1].2).unwrap().unwrap();
+        assert_eq!(Some(&b"up"[..]), echo.payload_bytes());
+        assert_eq!(
+            Some(PacketId::new(1)),
+            echo.variable_header().and_then(|header| match header {
+                VariableHeader::Publish(publish) => publish.packet_identifier(),
+                _ => None,
+            })
+        );
+        assert_eq!(0x32, sent[1].1);
+
+        broker.send(&[0x62, 0x02, 0x00, 0x07]).unwrap();
+        assert_eq!(vec![PacketType::Pubcomp], types(&mut broker));
+
+        publish(&mut broker, "site/42/config", QoS::AtLeastOnce, 8);
+        assert_eq!(vec![PacketType::Puback], types(&mut broker));
+
+        broker.send(&[0xa2, 0x11, 0x00, 0x02, 0x00, 0x0d]).unwrap();
+        broker.send(b"site/+/status").unwrap();
+        assert_eq!(vec![PacketType::Unsuback], types(&mut broker));
+        assert_eq!(0, broker.subscriptions().count());
+
+        let mut scratch = [0u8; 2];
+        send_packet(&mut broker, &Packet::pingreq(), &mut scratch).unwrap();
+        assert_eq!(vec![PacketType::Pingresp], types(&mut broker));
+
+        broker.send(&[0xe0, 0x00]).unwrap();
+        assert!(broker.is_closed());
+        assert_eq!(Err(Error::Closed), broker.recv(&mut scratch));
+    }
+
+    #[test]
+    fn protocol() {
+        let mut broker = MockBroker::new();
+        assert_eq!(
+            Err(Error::Protocol(PacketType::Subscribe)),
+            broker.send(SUBSCRIBE)
+        );
+        assert_eq!(Err(Error::Closed), broker.send(CONNECT));
+
+        let mut broker = MockBroker::with_behaviour(Behaviour {
+            refuse_connect: Some(connack::ReturnCode::RefusedNotAuthorized),
+            ..Behaviour::default()
+        });
+        broker.send(CONNECT).unwrap();
+        assert!(!broker.is_connected());
+        assert_eq!(vec![0x20, 0x02, 0x00, 0x05], replies(&mut broker)[0].2);
+        assert!(broker.is_closed());
+    }
+
+    #[test]
+    fn hold_acks() {
+        let mut broker = MockBroker::with_behaviour(Behaviour {
+            hold_acks: true,
+            ..Behaviour::default()
+        });
+        broker.send(CONNECT).unwrap();
+        broker.send(SUBSCRIBE).unwrap();
+        publish(&mut broker, "site/42/status", QoS::AtLeastOnce, 2);
+
+        // the echo is not an acknowledgement
+        assert_eq!(vec![PacketType::Publish], types(&mut broker));
+        assert_eq!(3, broker.held_acks());
+
+        broker.release_acks();
+        assert_eq!(
+            vec![PacketType::Connack, PacketType::Suback, PacketType::Puback],
+            types(&mut broker)
+        );
+    }
+
+    #[test]
+    fn bad_flags() {
+        let mut broker = MockBroker::new();
+        broker.send(CONNECT).unwrap();
+        broker.send(SUBSCRIBE).unwrap();
+        types(&mut broker);
+
+        broker.behaviour_mut().bad_flags = true;
+        publish(&mut broker, "site/42/status", QoS::AtLeastOnce, 2);
+
+        let mut buf = [0u8; 64];
+        let mut len = 0;
+        assert_eq!(
+            Err(transport::Error::Decode(DecodeError::PacketFlag)),
+            transport::recv_packet(&mut broker, &mut buf, &mut len).map(|_| ())
+        );
+        assert_eq!(0x46, buf[0]);
+    }
+
+    #[test]
+    fn subscribe_packet() {
+        let topics = [("a/#", QoS::AtMostOnce), ("a/b", QoS::ExactlyOnce)];
+        let subscribe = Packet::subscribe(
+            PacketIdentifier::new(PacketId::new(3)),
+            payload::subscribe::Subscribe::new(&topics),
+        )
+        .unwrap();
+
+        let mut broker = MockBroker::new();
+        broker.send(CONNECT).unwrap();
+        let mut scratch = [0u8; 32];
+        send_packet(&mut broker, &subscribe, &mut scratch).unwrap();
+        let suback = replies(&mut broker);
+        assert_eq!(vec![0x90, 0x04, 0x00, 0x03, 0x00, 0x02], suback[1].2);
+
+        // the highest QoS of the overlapping subscriptions
+        publish(&mut broker, "a/b", QoS::ExactlyOnce, 4);
+        let echo = replies(&mut broker);
+        assert_eq!(0x34, echo[1].1);
+    }
+}
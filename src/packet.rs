@@ -1,13 +1,26 @@
-use core::{cmp::min, convert::TryFrom, default::Default, result::Result};
+#[cfg(any(feature = "encode", feature = "decode"))]
+use core::cmp::min;
+use core::{convert::TryFrom, default::Default, fmt, result::Result};
 
 use crate::{
-    codec::{Decodable, Encodable},
     error::{DecodeError, EncodeError},
-    fixed_header::{self, FixedHeader},
+    fixed_header::{self, FixedHeader, PacketKind},
     payload::{self, Payload},
+    typed,
+    variable_header::{self, VariableHeader},
+};
+
+#[cfg(feature = "encode")]
+use crate::{
+    codec::{self, Encodable},
+    error::Field,
     qos,
+};
+#[cfg(feature = "decode")]
+use crate::{
+    codec::{Decodable, DecodeOptions},
+    debug::Preview,
     status::Status,
-    variable_header::{self, VariableHeader},
 };
 
 #[derive(Debug)]
@@ -23,10 +36,27 @@ pub struct Packet<'a> {
 /// Variable header and payload are optional for some packet types.
 impl<'a> Packet<'a> {
     /// Create a CONNECT packet.
+    ///
+    /// Returns `Err(EncodeError::FieldLength)` naming the first payload field
+    /// too long to encode, or an empty will topic.
+    #[cfg(feature = "encode")]
     pub fn connect(
         variable_header: variable_header::connect::Connect<'a>,
         payload: payload::connect::Connect<'a>,
     ) -> Result<Self, EncodeError> {
+        check_len(Field::ClientId, payload.client_id().len(), 0)?;
+        if let Some(will) = payload.will() {
+            // [MQTT-4.7.3-1]
+            check_len(Field::WillTopic, will.topic().len(), 1)?;
+            check_len(Field::WillMessage, will.message().len(), 0)?;
+        }
+        if let Some(username) = payload.username() {
+            check_len(Field::Username, username.len(), 0)?;
+        }
+        if let Some(password) = payload.password() {
+            check_len(Field::Password, password.expose().len(), 0)?;
+        }
+
         Self::packet(
             fixed_header::PacketType::Connect,
             fixed_header::PacketFlags::CONNECT,
@@ -36,38 +66,150 @@ impl<'a> Packet<'a> {
     }
 
     /// Create a SUBSCRIBE packet.
+    ///
+    /// Returns `Err(EncodeError::FieldLength)` if a topic filter is empty or
+    /// too long to encode.
+    #[cfg(feature = "encode")]
     pub fn subscribe(
         variable_header: variable_header::packet_identifier::PacketIdentifier,
         payload: payload::subscribe::Subscribe<'a>,
     ) -> Result<Self, EncodeError> {
-        Self::packet(
+        // Check each filter and sum the payload length in one pass over the
+        // topics, so `encode` is the only other traversal. [MQTT-4.7.3-1]
+        let payload_len = payload.topics().try_fold(0usize, |len, (filter, _)| {
+            check_len(Field::TopicFilter, filter.len(), 1)?;
+            Ok::<_, EncodeError>(len + filter.encoded_len() + 1)
+        })?;
+
+        Self::packet_with_len(
             fixed_header::PacketType::Subscribe,
             fixed_header::PacketFlags::SUBSCRIBE,
             Some(variable_header::VariableHeader::Subscribe(variable_header)),
             payload::Payload::Subscribe(payload),
+            payload_len,
+        )
+    }
+
+    /// Create an UNSUBSCRIBE packet, e.g. from the payload of an earlier
+    /// SUBSCRIBE with `Unsubscribe::from_subscribe`.
+    ///
+    /// Returns `Err(EncodeError::FieldLength)` if a topic filter is empty or
+    /// too long to encode.
+    #[cfg(feature = "encode")]
+    pub fn unsubscribe(
+        variable_header: variable_header::packet_identifier::PacketIdentifier,
+        payload: payload::unsubscribe::Unsubscribe<'a>,
+    ) -> Result<Self, EncodeError> {
+        // [MQTT-4.7.3-1]
+        let payload_len = payload.filters().try_fold(0usize, |len, filter| {
+            check_len(Field::TopicFilter, filter.len(), 1)?;
+            Ok::<_, EncodeError>(len + filter.encoded_len())
+        })?;
+
+        Self::packet_with_len(
+            fixed_header::PacketType::Unsubscribe,
+            fixed_header::PacketFlags::UNSUBSCRIBE,
+            Some(variable_header::VariableHeader::Unsubscribe(
+                variable_header,
+            )),
+            payload::Payload::Unsubscribe(payload),
+            payload_len,
         )
     }
 
-    /// Create a PUBLISH packet.
-    pub fn publish(
+    /// Create a PUBLISH packet, `publish_message` takes a variable header
+    /// whose QoS always matches its packet identifier.
+    ///
+    /// Returns `Err(EncodeError::MissingPacketId)` if the QoS is 1 or 2 and
+    /// the variable header has no packet identifier,
+    /// `Err(EncodeError::UnexpectedPacketId)` if the QoS is 0 and it has one,
+    /// and
+    /// `Err(EncodeError::FieldLength)` if the topic name is empty or too long
+    /// to encode.
+    #[cfg(feature = "encode")]
+    pub fn publish<P: AsRef<[u8]> + ?Sized>(
         flags: fixed_header::PublishFlags,
         variable_header: variable_header::publish::Publish<'a>,
-        payload: &'a [u8],
+        payload: &'a P,
     ) -> Result<Self, EncodeError> {
-        // TODO encode this using type states
-        assert!(
-            flags.qos().expect("valid qos") == qos::QoS::AtMostOnce
-                || variable_header.packet_identifier().is_some()
-        );
+        check_packet_identifier(flags, &variable_header)?;
+        // [MQTT-4.7.3-1]
+        check_len(Field::TopicName, variable_header.topic_name().len(), 1)?;
 
         Self::packet(
             fixed_header::PacketType::Publish,
             flags.into(),
             Some(variable_header::VariableHeader::Publish(variable_header)),
-            payload::Payload::Bytes(payload),
+            payload::Payload::Bytes(payload.as_ref()),
         )
     }
 
+    /// Create a PUBLISH from a variable header built by
+    /// `Publish::at_most_once`, `at_least_once` or `exactly_once`, which
+    /// carries a packet identifier exactly when its QoS needs one.
+    ///
+    /// Returns `Err(EncodeError::FieldLength)` if the topic name is empty or
+    /// too long to encode.
+    #[cfg(feature = "encode")]
+    pub fn publish_message<P: AsRef<[u8]> + ?Sized>(
+        variable_header: variable_header::publish::Outgoing<'a>,
+        payload: &'a P,
+    ) -> Result<Self, EncodeError> {
+        let (flags, variable_header) = variable_header.into_parts();
+        Self::publish(flags, variable_header, payload)
+    }
+
+    /// Encode a PUBLISH whose payload is written in place by `payload`.
+    ///
+    /// `payload` is called with the space following the variable header and
+    /// returns the number of bytes it wrote, so a serializer such as
+    /// `postcard::to_slice` or `serde_json_core::to_slice` can write straight
+    /// into the packet instead of into a temporary buffer. The remaining length
+    /// is backfilled afterwards, as with `encode_backfilled` the returned packet
+    /// may not start at the beginning of `bytes`.
+    ///
+    /// Returns `Err(PayloadError::Payload)` with the error of `payload`, or
+    /// `Err(PayloadError::Encode)` as `publish` and `encode` would.
+    #[cfg(feature = "encode")]
+    pub fn publish_with<'b, F, E>(
+        flags: fixed_header::PublishFlags,
+        variable_header: variable_header::publish::Publish,
+        bytes: &'b mut [u8],
+        payload: F,
+    ) -> Result<&'b [u8], PayloadError<E>>
+    where
+        F: FnOnce(&mut [u8]) -> Result<usize, E>,
+    {
+        check_packet_identifier(flags, &variable_header)?;
+        // [MQTT-4.7.3-1]
+        check_len(Field::TopicName, variable_header.topic_name().len(), 1)?;
+
+        let mut payload_error = None;
+        let encoded = encode_backfilled(
+            fixed_header::PacketType::Publish,
+            flags.into(),
+            bytes,
+            |body| {
+                let offset = variable_header.encode(body)?;
+                let available = body.len() - offset;
+                match payload(&mut body[offset..]) {
+                    Ok(written) if written <= available => Ok(offset + written),
+                    Ok(_) => Err(EncodeError::OutOfSpace),
+                    Err(e) => {
+                        payload_error = Some(e);
+                        Err(EncodeError::OutOfSpace)
+                    }
+                }
+            },
+        );
+
+        match (encoded, payload_error) {
+            (_, Some(e)) => Err(PayloadError::Payload(e)),
+            (encoded, None) => Ok(encoded?),
+        }
+    }
+
+    #[cfg(feature = "encode")]
     pub fn puback(
         variable_header: variable_header::packet_identifier::PacketIdentifier,
     ) -> Result<Self, EncodeError> {
@@ -79,6 +221,180 @@ impl<'a> Packet<'a> {
         )
     }
 
+    /// Create the UNSUBACK answering the UNSUBSCRIBE with the same packet
+    /// identifier [MQTT-3.10.4-4].
+    #[cfg(feature = "encode")]
+    pub fn unsuback(
+        variable_header: variable_header::packet_identifier::PacketIdentifier,
+    ) -> Result<Self, EncodeError> {
+        Self::packet(
+            fixed_header::PacketType::Unsuback,
+            fixed_header::PacketFlags::UNSUBACK,
+            Some(variable_header::VariableHeader::Unsuback(variable_header)),
+            Default::default(),
+        )
+    }
+
+    /// Create the PUBREC answering a QoS 2 PUBLISH, the first step of the
+    /// receiver's side of the exchange.
+    #[cfg(feature = "encode")]
+    pub fn pubrec(
+        variable_header: variable_header::packet_identifier::PacketIdentifier,
+    ) -> Result<Self, EncodeError> {
+        Self::packet(
+            fixed_header::PacketType::Pubrec,
+            fixed_header::PacketFlags::PUBREC,
+            Some(variable_header::VariableHeader::Pubrec(variable_header)),
+            Default::default(),
+        )
+    }
+
+    /// Create the PUBREL answering a PUBREC, with the reserved flags set to
+    /// 0b0010 as required [MQTT-3.6.1-1].
+    #[cfg(feature = "encode")]
+    pub fn pubrel(
+        variable_header: variable_header::packet_identifier::PacketIdentifier,
+    ) -> Result<Self, EncodeError> {
+        Self::packet(
+            fixed_header::PacketType::Pubrel,
+            fixed_header::PacketFlags::PUBREL,
+            Some(variable_header::VariableHeader::Pubrel(variable_header)),
+            Default::default(),
+        )
+    }
+
+    /// Create the PUBCOMP answering a PUBREL, completing a QoS 2 exchange.
+    #[cfg(feature = "encode")]
+    pub fn pubcomp(
+        variable_header: variable_header::packet_identifier::PacketIdentifier,
+    ) -> Result<Self, EncodeError> {
+        Self::packet(
+            fixed_header::PacketType::Pubcomp,
+            fixed_header::PacketFlags::PUBCOMP,
+            Some(variable_header::VariableHeader::Pubcomp(variable_header)),
+            Default::default(),
+        )
+    }
+
+    /// Create a CONNACK packet.
+    ///
+    /// The session present flag is cleared when `return_code` refuses the
+    /// connection [MQTT-3.2.2-4].
+    pub fn connack(
+        flags: variable_header::connack::Flags,
+        return_code: variable_header::connack::ReturnCode,
+    ) -> Self {
+        let flags = match return_code {
+            variable_header::connack::ReturnCode::Accepted => flags,
+            _ => Default::default(),
+        };
+
+        Self {
+            fixed_header: FixedHeader::new(
+                fixed_header::PacketType::Connack,
+                fixed_header::PacketFlags::CONNACK,
+                2,
+            ),
+            variable_header: Some(variable_header::VariableHeader::Connack(
+                variable_header::connack::Connack::new(flags, return_code),
+            )),
+            payload: Default::default(),
+        }
+    }
+
+    /// Create the CONNACK a server must send before closing the connection when
+    /// decoding a CONNECT fails with `error`.
+    ///
+    /// An unsupported protocol name or level is refused with return code 0x01
+    /// (unacceptable protocol version). Returns `None` for errors that do not
+    /// call for a CONNACK, the server should close the connection without one.
+    pub fn refused_connack(error: DecodeError) -> Option<Self> {
+        match error {
+            DecodeError::InvalidProtocolName | DecodeError::InvalidProtocolLevel => {
+                Some(Self::connack(
+                    Default::default(),
+                    variable_header::connack::ReturnCode::RefusedProtocolVersion,
+                ))
+            }
+            _ => None,
+        }
+    }
+
+    /// Create the SUBACK answering a decoded SUBSCRIBE.
+    ///
+    /// `grant` is called with each topic filter and requested QoS in order and
+    /// returns the maximum QoS granted or `Denied`. The return codes are written
+    /// to `return_codes` and the granted subscriptions to `accepted`, returns
+    /// the SUBACK and the number of accepted subscriptions to install.
+    ///
+    /// Returns `None` if `subscribe` is not a SUBSCRIBE packet.
+    #[cfg(feature = "encode")]
+    pub fn suback_for<'s, F>(
+        subscribe: &'s Packet,
+        return_codes: &'a mut [payload::suback::ReturnCode],
+        accepted: &mut [(&'s str, qos::QoS)],
+        mut grant: F,
+    ) -> Option<Result<(Self, usize), EncodeError>>
+    where
+        F: FnMut(&str, qos::QoS) -> Result<qos::QoS, payload::suback::Denied>,
+    {
+        let (packet_identifier, topics) = match (&subscribe.variable_header, &subscribe.payload) {
+            (Some(VariableHeader::Subscribe(packet_identifier)), Payload::Subscribe(topics)) => (
+                variable_header::packet_identifier::PacketIdentifier::new(
+                    packet_identifier.packet_identifier(),
+                ),
+                topics,
+            ),
+            _ => return None,
+        };
+
+        let mut len = 0;
+        let mut accepted_len = 0;
+        for (topic, requested) in topics.topics() {
+            let return_code = match return_codes.get_mut(len) {
+                Some(return_code) => return_code,
+                None => return Some(Err(EncodeError::OutOfSpace)),
+            };
+
+            let granted = grant(topic, requested);
+            if let Ok(granted) = granted {
+                match accepted.get_mut(accepted_len) {
+                    Some(subscription) => *subscription = (topic, granted),
+                    None => return Some(Err(EncodeError::OutOfSpace)),
+                }
+                accepted_len += 1;
+            }
+            *return_code = payload::suback::ReturnCode::from(granted);
+            len += 1;
+        }
+
+        let return_codes: &'a [payload::suback::ReturnCode] = return_codes;
+        Some(
+            Self::suback(
+                packet_identifier,
+                payload::suback::Suback::new(&return_codes[..len]),
+            )
+            .map(|packet| (packet, accepted_len)),
+        )
+    }
+
+    /// Create a SUBACK packet, with one return code per topic filter of the
+    /// SUBSCRIBE it answers in the same order [MQTT-3.8.4-5].
+    ///
+    /// See `suback_for` to build the return codes from a decoded SUBSCRIBE.
+    #[cfg(feature = "encode")]
+    pub fn suback(
+        variable_header: variable_header::packet_identifier::PacketIdentifier,
+        payload: payload::suback::Suback<'a>,
+    ) -> Result<Self, EncodeError> {
+        Self::packet(
+            fixed_header::PacketType::Suback,
+            fixed_header::PacketFlags::SUBACK,
+            Some(VariableHeader::Suback(variable_header)),
+            Payload::Suback(payload),
+        )
+    }
+
     /// Create a PINGREQ packet.
     pub fn pingreq() -> Self {
         Self {
@@ -105,22 +421,53 @@ impl<'a> Packet<'a> {
         }
     }
 
+    /// Create a DISCONNECT packet, the last packet a client sends before
+    /// closing the connection [MQTT-3.14.4-1].
+    pub fn disconnect() -> Self {
+        Self {
+            fixed_header: FixedHeader::new(
+                fixed_header::PacketType::Disconnect,
+                fixed_header::PacketFlags::DISCONNECT,
+                0,
+            ),
+            variable_header: None,
+            payload: Default::default(),
+        }
+    }
+
     /// Create a packet with the given type, flags, variable header and payload.
     ///
     /// Constructs a fixed header with the appropriate `len` field for the given
     /// variable header and payload.
+    #[cfg(feature = "encode")]
+    #[allow(clippy::self_named_constructors)]
     fn packet(
         r#type: fixed_header::PacketType,
         flags: fixed_header::PacketFlags,
         variable_header: Option<VariableHeader<'a>>,
         payload: Payload<'a>,
+    ) -> Result<Self, EncodeError> {
+        let payload_len = payload.encoded_len();
+        Self::packet_with_len(r#type, flags, variable_header, payload, payload_len)
+    }
+
+    /// Create a packet whose payload length the caller has already computed,
+    /// the remaining length is stored in the fixed header so encoding only
+    /// traverses the payload once more to write it.
+    #[cfg(feature = "encode")]
+    fn packet_with_len(
+        r#type: fixed_header::PacketType,
+        flags: fixed_header::PacketFlags,
+        variable_header: Option<VariableHeader<'a>>,
+        payload: Payload<'a>,
+        payload_len: usize,
     ) -> Result<Self, EncodeError> {
         let len = u32::try_from(
             variable_header
                 .as_ref()
                 .map(VariableHeader::encoded_len)
                 .unwrap_or(0)
-                + payload.encoded_len(),
+                + payload_len,
         )?;
 
         Ok(Self {
@@ -130,6 +477,151 @@ impl<'a> Packet<'a> {
         })
     }
 
+    /// Encode a PUBLISH again with a new packet identifier, for forwarding a
+    /// received message on another connection.
+    ///
+    /// The topic, payload, QoS and retain flag are kept and the DUP flag is
+    /// cleared. `packet_identifier` is ignored for QoS 0 messages, which carry
+    /// none. Returns `None` if the packet is not a PUBLISH.
+    #[cfg(feature = "encode")]
+    pub fn encode_republished(
+        &self,
+        packet_identifier: variable_header::PacketId,
+        bytes: &mut [u8],
+    ) -> Option<Result<usize, EncodeError>> {
+        let (publish, payload) = match (&self.variable_header, &self.payload) {
+            (Some(VariableHeader::Publish(publish)), Payload::Bytes(payload)) => (publish, payload),
+            _ => return None,
+        };
+
+        let mut flags = match fixed_header::PublishFlags::try_from(self.fixed_header.flags()) {
+            Ok(flags) => flags,
+            Err(_) => return None,
+        };
+        flags.dup = false;
+
+        let publish = variable_header::publish::Publish::new(
+            publish.topic_name(),
+            publish.packet_identifier().map(|_| packet_identifier),
+        );
+        let header = FixedHeader::new(
+            fixed_header::PacketType::Publish,
+            flags.into(),
+            self.fixed_header.len(),
+        );
+
+        Some(encode_publish(&header, &publish, payload, bytes))
+    }
+
+    /// Encode a PUBLISH again under another topic, for a bridge forwarding
+    /// messages between topic trees.
+    ///
+    /// `rewrite` is given the current topic name and writes the new one, e.g.
+    /// `write!(topic, "site42/{}", name)`, straight into `bytes`. The flags,
+    /// packet identifier and payload are kept unchanged. Returns `None` if the
+    /// packet is not a PUBLISH.
+    ///
+    /// Returns `Err(EncodeError::FieldLength)` if the new topic is empty or too
+    /// long and `Err(EncodeError::OutOfSpace)` if it does not fit in `bytes`.
+    #[cfg(feature = "encode")]
+    pub fn encode_rewritten<F>(
+        &self,
+        rewrite: F,
+        bytes: &mut [u8],
+    ) -> Option<Result<usize, EncodeError>>
+    where
+        F: FnOnce(&'a str, &mut dyn fmt::Write) -> fmt::Result,
+    {
+        match (&self.variable_header, &self.payload) {
+            (Some(VariableHeader::Publish(publish)), Payload::Bytes(payload)) => {
+                Some(encode_rewritten_publish(
+                    self.fixed_header.flags(),
+                    publish,
+                    payload,
+                    rewrite,
+                    bytes,
+                ))
+            }
+            _ => None,
+        }
+    }
+
+    /// Encode a PUBLISH again with `prefix` prepended to its topic, e.g. to
+    /// forward `local/#` as `site42/local/#`. See `encode_rewritten`.
+    #[cfg(feature = "encode")]
+    pub fn encode_prefixed(
+        &self,
+        prefix: &str,
+        bytes: &mut [u8],
+    ) -> Option<Result<usize, EncodeError>> {
+        self.encode_rewritten(
+            |topic_name, topic| {
+                topic.write_str(prefix)?;
+                topic.write_str(topic_name)
+            },
+            bytes,
+        )
+    }
+
+    /// Copy a PUBLISH for forwarding on a link limited to `max_qos`, e.g. a
+    /// bridge downgrading QoS 2 messages to QoS 1.
+    ///
+    /// The copy has the lower of the current QoS and `max_qos`. At QoS 0 it
+    /// carries no packet identifier, otherwise `packet_identifier` is assigned
+    /// for the outgoing link. The DUP flag is cleared, the topic, payload and
+    /// retain flag are kept. Returns `None` if the packet is not a PUBLISH.
+    #[cfg(feature = "encode")]
+    pub fn downgraded(
+        &self,
+        max_qos: qos::QoS,
+        packet_identifier: variable_header::PacketId,
+    ) -> Option<Result<Packet<'a>, EncodeError>> {
+        let (publish, payload) = match (&self.variable_header, &self.payload) {
+            (Some(VariableHeader::Publish(publish)), Payload::Bytes(payload)) => (publish, payload),
+            _ => return None,
+        };
+
+        let flags = fixed_header::PublishFlags::try_from(self.fixed_header.flags()).ok()?;
+        let qos = min(flags.qos, max_qos);
+
+        let packet_identifier = if qos == qos::QoS::AtMostOnce {
+            None
+        } else {
+            Some(packet_identifier)
+        };
+
+        Some(Packet::publish(
+            fixed_header::PublishFlags::new(qos, false, flags.retain),
+            variable_header::publish::Publish::new(publish.topic_name(), packet_identifier),
+            *payload,
+        ))
+    }
+
+    /// Reassemble a packet from parts, e.g. those returned by `into_parts`.
+    ///
+    /// The type and flags of `fixed_header` are kept and its `len` is
+    /// recomputed from the variable header and payload. The parts are not
+    /// checked against each other, a PUBLISH given a CONNECT payload will be
+    /// encoded as such.
+    #[cfg(feature = "encode")]
+    pub fn from_parts(
+        fixed_header: FixedHeader,
+        variable_header: Option<VariableHeader<'a>>,
+        payload: Payload<'a>,
+    ) -> Result<Self, EncodeError> {
+        Self::packet(
+            fixed_header.r#type(),
+            fixed_header.flags(),
+            variable_header,
+            payload,
+        )
+    }
+
+    /// Split the packet into its fixed header, variable header and payload.
+    pub fn into_parts(self) -> (FixedHeader, Option<VariableHeader<'a>>, Payload<'a>) {
+        (self.fixed_header, self.variable_header, self.payload)
+    }
+
     /// Return a reference to the fixed header of the packet.
     ///
     /// The len field of the returned header will be valid.
@@ -137,142 +629,2121 @@ impl<'a> Packet<'a> {
         &self.fixed_header
     }
 
-    /// Return a reference to the variable header of the packet.
-    pub fn variable_header(&self) -> &Option<VariableHeader> {
-        &self.variable_header
+    /// The type of the packet with the flags of a PUBLISH, see
+    /// `FixedHeader::kind`.
+    pub fn kind(&self) -> Result<PacketKind, DecodeError> {
+        self.fixed_header.kind()
+    }
+
+    /// Return a reference to the variable header of the packet, if it has one.
+    pub fn variable_header(&self) -> Option<&VariableHeader<'a>> {
+        self.variable_header.as_ref()
     }
 
     /// Return a reference to the payload of the packet.
-    pub fn payload(&self) -> &Payload {
+    pub fn payload(&self) -> &Payload<'a> {
         &self.payload
     }
-}
 
-impl<'a> Decodable<'a> for Packet<'a> {
-    /// Decode any MQTT packet from a pre-allocated buffer.
-    ///
-    /// If an unrecoverable error occurs an `Err(x)` is returned, the caller should
-    /// disconnect and network connection and discard the contents of the connection
-    /// receive buffer.
-    ///
-    /// Decoding may return an `Ok(Status::Partial(x))` in which case the caller
-    /// should buffer at most `x` more bytes and then attempt decoding again.
-    ///
-    /// If decoding succeeds an `Ok(Status::Complete(x))` will be returned
-    /// containing the number of bytes read from the buffer and the decoded packet.
-    /// The lifetime of the decoded packet is tied to the input buffer.
-    fn decode(bytes: &'a [u8]) -> Result<Status<(usize, Self)>, DecodeError> {
-        let (fixed_header_offset, fixed_header) = read!(FixedHeader::decode, bytes, 0);
+    /// Return the payload bytes if the payload is left undecoded, e.g. the
+    /// application message of a PUBLISH.
+    pub fn payload_bytes(&self) -> Option<&'a [u8]> {
+        self.payload.as_bytes()
+    }
 
-        let (variable_header_consumed, variable_header) = if let Some(result) =
-            VariableHeader::decode(
-                fixed_header.r#type(),
-                fixed_header.flags(),
-                &bytes[fixed_header_offset..],
-            ) {
-            let (variable_header_offset, variable_header) = complete!(result);
-            (variable_header_offset, Some(variable_header))
-        } else {
-            (0, None)
-        };
+    /// Return the payload if this is a CONNECT packet.
+    pub fn as_connect_payload(&self) -> Option<&payload::connect::Connect<'a>> {
+        self.payload.as_connect()
+    }
 
-        let payload_len = fixed_header.len() as usize - variable_header_consumed;
+    /// Return the payload if this is a SUBSCRIBE packet.
+    pub fn as_subscribe_payload(&self) -> Option<&payload::subscribe::Subscribe<'a>> {
+        self.payload.as_subscribe()
+    }
 
-        let available = bytes.len() - (fixed_header_offset + variable_header_consumed);
-        let needed = payload_len - min(available, payload_len);
-        if needed > 0 {
-            return Ok(Status::Partial(needed));
-        }
+    /// Return the payload if this is a SUBACK packet.
+    pub fn as_suback_payload(&self) -> Option<&payload::suback::Suback<'a>> {
+        self.payload.as_suback()
+    }
 
-        let payload_bytes = &bytes[fixed_header_offset + variable_header_consumed
-            ..fixed_header_offset + variable_header_consumed + payload_len];
+    /// Return the payload if this is an UNSUBSCRIBE packet.
+    pub fn as_unsubscribe_payload(&self) -> Option<&payload::unsubscribe::Unsubscribe<'a>> {
+        self.payload.as_unsubscribe()
+    }
 
-        let payload = if let Some(result) = Payload::decode(fixed_header.r#type(), payload_bytes) {
-            match result {
-                Err(e) => return Err(e),
-                Ok(Status::Partial(n)) => return Ok(Status::Partial(n)),
-                Ok(Status::Complete((_, payload))) => payload,
-            }
-        } else {
-            payload::Payload::Bytes(payload_bytes)
+    /// The variable header and payload fields if this is a CONNECT packet.
+    ///
+    /// A decoded CONNECT keeps its payload as bytes, they are parsed again on
+    /// each call and `None` is returned if they do not match the flags.
+    pub fn as_connect(&self) -> Option<typed::ConnectView<'a>> {
+        let variable_header = match self.variable_header {
+            Some(VariableHeader::Connect(ref variable_header)) => variable_header,
+            _ => return None,
+        };
+        let view = |payload: &payload::connect::Connect<'a>| typed::ConnectView {
+            level: variable_header.level(),
+            flags: variable_header.flags(),
+            keep_alive: variable_header.keep_alive(),
+            client_id: payload.client_id(),
+            will: payload.will().copied(),
+            username: payload.username(),
+            password: payload.password(),
         };
 
-        Ok(Status::Complete((
-            fixed_header_offset + fixed_header.len() as usize,
-            Self {
-                fixed_header,
-                variable_header,
-                payload,
-            },
-        )))
+        match self.payload {
+            Payload::Connect(ref payload) => Some(view(payload)),
+            #[cfg(feature = "decode")]
+            Payload::Bytes(bytes) => {
+                match payload::connect::Connect::decode(variable_header.flags(), bytes) {
+                    Ok(Status::Complete((_, payload))) => Some(view(&payload)),
+                    _ => None,
+                }
+            }
+            _ => None,
+        }
     }
-}
 
-impl<'a> Encodable for Packet<'a> {
-    /// Calculate the exact length of the fully encoded packet.
-    ///
-    /// The encode buffer will need to hold at least this number of bytes.
-    fn encoded_len(&self) -> usize {
-        self.fixed_header.encoded_len() + self.fixed_header.len() as usize
+    /// The topic, flags, packet identifier and message if this is a PUBLISH
+    /// packet with valid flags.
+    pub fn as_publish(&self) -> Option<typed::PublishView<'a>> {
+        match (&self.variable_header, &self.payload) {
+            (Some(VariableHeader::Publish(publish)), Payload::Bytes(payload)) => {
+                Some(typed::PublishView {
+                    topic_name: publish.topic_name(),
+                    flags: fixed_header::PublishFlags::try_from(self.fixed_header.flags()).ok()?,
+                    packet_identifier: publish.packet_identifier(),
+                    payload,
+                })
+            }
+            _ => None,
+        }
     }
 
-    /// Encode a packet for sending over a network connection.
-    ///
-    /// If encoding fails an `Err(x)` is returned.
-    ///
-    /// If encoding succeeds an `Ok(written)` is returned with the number of
-    /// bytes written to the buffer.
-    fn encode(&self, bytes: &mut [u8]) -> Result<usize, EncodeError> {
-        let mut offset = 0;
-
-        offset += self.fixed_header.encode(&mut bytes[offset..])?;
-        if let Some(ref variable_header) = self.variable_header {
-            offset += variable_header.encode(&mut bytes[offset..])?;
+    /// The session present flag and return code if this is a CONNACK packet.
+    pub fn as_connack(&self) -> Option<typed::ConnackView> {
+        match self.variable_header {
+            Some(VariableHeader::Connack(ref connack)) => Some(typed::ConnackView {
+                session_present: connack.flags().session_present,
+                return_code: connack.return_code(),
+            }),
+            _ => None,
         }
-        offset += self.payload.encode(&mut bytes[offset..])?;
+    }
 
-        Ok(offset)
+    /// The packet identifier and return codes if this is a SUBACK packet.
+    pub fn as_suback(&self) -> Option<typed::SubackView<'a>> {
+        match (&self.variable_header, &self.payload) {
+            (Some(VariableHeader::Suback(packet_identifier)), Payload::Suback(suback)) => {
+                Some(typed::SubackView {
+                    packet_identifier: packet_identifier.packet_identifier(),
+                    return_codes: *suback,
+                })
+            }
+            _ => None,
+        }
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    /// The packet identifier and topic filters if this is a SUBSCRIBE packet.
+    pub fn as_subscribe(&self) -> Option<typed::SubscribeView<'a>> {
+        match (&self.variable_header, &self.payload) {
+            (Some(VariableHeader::Subscribe(packet_identifier)), Payload::Subscribe(topics)) => {
+                Some(typed::SubscribeView {
+                    packet_identifier: packet_identifier.packet_identifier(),
+                    topics: *topics,
+                })
+            }
+            _ => None,
+        }
+    }
 
-    #[test]
-    fn encode_publish() {
-        let payload = b"{}";
-        assert_eq!(2, payload.len());
+    /// The packet identifier and topic filters if this is an UNSUBSCRIBE
+    /// packet.
+    pub fn as_unsubscribe(&self) -> Option<typed::UnsubscribeView<'a>> {
+        match (&self.variable_header, &self.payload) {
+            (
+                Some(VariableHeader::Unsubscribe(packet_identifier)),
+                Payload::Unsubscribe(filters),
+            ) => Some(typed::UnsubscribeView {
+                packet_identifier: packet_identifier.packet_identifier(),
+                filters: *filters,
+            }),
+            _ => None,
+        }
+    }
 
-        let mut publish_flags = fixed_header::PublishFlags::default();
-        publish_flags.set_qos(qos::QoS::AtLeastOnce);
-        let publish_id = 2;
-        let publish = Packet::publish(
-            publish_flags,
-            variable_header::publish::Publish::new("a/b", Some(publish_id)),
-            payload,
-        )
-        .expect("valid packet");
+    /// The packet as a `TypedPacket` for dispatching with an exhaustive
+    /// `match`.
+    ///
+    /// Returns `None` if the variable header or payload does not belong to the
+    /// packet type, which can only happen to a packet assembled with
+    /// `from_parts`, or for a PUBLISH with invalid flags.
+    pub fn typed(&self) -> Option<typed::TypedPacket<'a>> {
+        let packet_identifier = self
+            .variable_header()
+            .and_then(VariableHeader::packet_identifier);
 
-        assert_eq!(11, publish.encoded_len());
+        Some(match (self.fixed_header.r#type(), &self.variable_header) {
+            (fixed_header::PacketType::Connect, _) => {
+                typed::TypedPacket::Connect(self.as_connect()?)
+            }
+            (fixed_header::PacketType::Connack, _) => {
+                typed::TypedPacket::Connack(self.as_connack()?)
+            }
+            (fixed_header::PacketType::Publish, _) => {
+                typed::TypedPacket::Publish(self.as_publish()?)
+            }
+            (fixed_header::PacketType::Puback, Some(VariableHeader::Puback(_))) => {
+                typed::TypedPacket::Puback {
+                    packet_identifier: packet_identifier?,
+                }
+            }
+            (fixed_header::PacketType::Pubrec, Some(VariableHeader::Pubrec(_))) => {
+                typed::TypedPacket::Pubrec {
+                    packet_identifier: packet_identifier?,
+                }
+            }
+            (fixed_header::PacketType::Pubrel, Some(VariableHeader::Pubrel(_))) => {
+                typed::TypedPacket::Pubrel {
+                    packet_identifier: packet_identifier?,
+                }
+            }
+            (fixed_header::PacketType::Pubcomp, Some(VariableHeader::Pubcomp(_))) => {
+                typed::TypedPacket::Pubcomp {
+                    packet_identifier: packet_identifier?,
+                }
+            }
+            (fixed_header::PacketType::Subscribe, _) => {
+                typed::TypedPacket::Subscribe(self.as_subscribe()?)
+            }
+            (fixed_header::PacketType::Suback, _) => typed::TypedPacket::Suback(self.as_suback()?),
+            (fixed_header::PacketType::Unsubscribe, _) => {
+                typed::TypedPacket::Unsubscribe(self.as_unsubscribe()?)
+            }
+            (fixed_header::PacketType::Unsuback, Some(VariableHeader::Unsuback(_))) => {
+                typed::TypedPacket::Unsuback {
+                    packet_identifier: packet_identifier?,
+                }
+            }
+            (fixed_header::PacketType::Pingreq, None) => typed::TypedPacket::Pingreq,
+            (fixed_header::PacketType::Pingresp, None) => typed::TypedPacket::Pingresp,
+            (fixed_header::PacketType::Disconnect, None) => typed::TypedPacket::Disconnect,
+            (fixed_header::PacketType::Reserved(r#type), None) => typed::TypedPacket::Reserved {
+                r#type,
+                body: self.payload_bytes()?,
+            },
+            _ => return None,
+        })
+    }
+}
+
+#[cfg(feature = "encode")]
+fn encode_publish(
+    header: &FixedHeader,
+    publish: &variable_header::publish::Publish,
+    payload: &[u8],
+    bytes: &mut [u8],
+) -> Result<usize, EncodeError> {
+    let len = usize::try_from(header.len())?
+        .checked_add(header.encoded_len())
+        .ok_or(EncodeError::ValueTooBig)?;
+    if bytes.len() < len {
+        return Err(EncodeError::OutOfSpace);
+    }
+
+    let mut offset = header.encode(bytes)?;
+    offset += publish.encode(&mut bytes[offset..])?;
+    bytes[offset..offset + payload.len()].copy_from_slice(payload);
+
+    Ok(offset + payload.len())
+}
+
+/// Writes the new topic of `encode_rewritten` after the smallest possible fixed
+/// header and topic length, it is moved right into place once the length of
+/// the fixed header is known. The topic never starts before its final offset,
+/// so a buffer the rewritten packet fits in is always large enough.
+#[cfg(feature = "encode")]
+fn encode_rewritten_publish<'a, F>(
+    flags: fixed_header::PacketFlags,
+    publish: &variable_header::publish::Publish<'a>,
+    payload: &[u8],
+    rewrite: F,
+    bytes: &mut [u8],
+) -> Result<usize, EncodeError>
+where
+    F: FnOnce(&'a str, &mut dyn fmt::Write) -> fmt::Result,
+{
+    // a two byte fixed header and the topic length
+    let reserved = 2 + 2;
+    if bytes.len() < reserved {
+        return Err(EncodeError::OutOfSpace);
+    }
+
+    let mut topic = TopicWriter {
+        bytes: &mut bytes[reserved..],
+        len: 0,
+    };
+    rewrite(publish.topic_name(), &mut topic).map_err(|_| EncodeError::OutOfSpace)?;
+    let topic_len = topic.len;
+    check_len(Field::TopicName, topic_len, 1)?;
+
+    let packet_identifier_len = publish.packet_identifier().map(|_| 2).unwrap_or(0);
+    let len = 2 + topic_len + packet_identifier_len + payload.len();
+    let total = encoded_len(fixed_header::PacketType::Publish, len)?;
+    if bytes.len() < total {
+        return Err(EncodeError::OutOfSpace);
+    }
+
+    let header = FixedHeader::new(
+        fixed_header::PacketType::Publish,
+        flags,
+        u32::try_from(len)?,
+    );
+    let mut offset = header.encode(bytes)?;
+    bytes.copy_within(reserved..reserved + topic_len, offset + 2);
+    offset += codec::values::encode_u16(u16::try_from(topic_len)?, &mut bytes[offset..])?;
+    offset += topic_len;
+    if let Some(packet_identifier) = publish.packet_identifier() {
+        offset += codec::values::encode_u16(packet_identifier.get(), &mut bytes[offset..])?;
+    }
+    bytes[offset..offset + payload.len()].copy_from_slice(payload);
+
+    Ok(offset + payload.len())
+}
+
+#[cfg(feature = "encode")]
+struct TopicWriter<'b> {
+    bytes: &'b mut [u8],
+    len: usize,
+}
+
+#[cfg(feature = "encode")]
+impl<'b> fmt::Write for TopicWriter<'b> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let end = self.len + s.len();
+        self.bytes
+            .get_mut(self.len..end)
+            .ok_or(fmt::Error)?
+            .copy_from_slice(s.as_bytes());
+        self.len = end;
+        Ok(())
+    }
+}
+
+/// Decodes application payloads carried in packets, see
+/// `Packet::decode_with_payload`.
+#[cfg(feature = "decode")]
+pub trait PayloadDecoder<'a> {
+    type Output;
+
+    /// Decode the payload `bytes` of `packet`, or return `None` to leave the
+    /// payload of this packet undecoded, e.g. for other packet types or topics.
+    #[allow(clippy::type_complexity)]
+    fn decode(
+        &self,
+        packet: &Packet<'a>,
+        bytes: &'a [u8],
+    ) -> Option<Result<Status<(usize, Self::Output)>, DecodeError>>;
+}
+
+/// A packet with only the fixed header decoded, see `Packet::decode_raw`.
+#[cfg(feature = "decode")]
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub struct RawPacket<'a> {
+    fixed_header: FixedHeader,
+    body: &'a [u8],
+}
+
+#[cfg(feature = "decode")]
+impl<'a> fmt::Debug for RawPacket<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("RawPacket")
+            .field("fixed_header", &self.fixed_header)
+            .field("body", &Preview(self.body))
+            .finish()
+    }
+}
+
+#[cfg(feature = "decode")]
+impl<'a> RawPacket<'a> {
+    pub fn fixed_header(&self) -> &FixedHeader {
+        &self.fixed_header
+    }
+
+    /// The type of the packet with the flags of a PUBLISH, see
+    /// `FixedHeader::kind`.
+    pub fn kind(&self) -> Result<PacketKind, DecodeError> {
+        self.fixed_header.kind()
+    }
+
+    /// The undecoded variable header and payload.
+    pub fn body(&self) -> &'a [u8] {
+        self.body
+    }
+}
+
+/// The encoded bytes of a PINGREQ packet, which never vary.
+pub const PINGREQ_BYTES: [u8; 2] = [0xc0, 0x00];
+/// The encoded bytes of a PINGRESP packet, which never vary.
+pub const PINGRESP_BYTES: [u8; 2] = [0xd0, 0x00];
+/// The encoded bytes of a DISCONNECT packet, which never vary.
+pub const DISCONNECT_BYTES: [u8; 2] = [0xe0, 0x00];
+
+/// Check a string or binary field fits its two byte length prefix and is at
+/// least `min` bytes long.
+#[cfg(feature = "encode")]
+fn check_len(field: Field, len: usize, min: usize) -> Result<(), EncodeError> {
+    if len < min || len > usize::from(u16::MAX) {
+        return Err(EncodeError::FieldLength(field));
+    }
+
+    Ok(())
+}
+
+/// Check a PUBLISH carries a packet identifier exactly when its QoS is 1 or 2
+/// [MQTT-2.3.1-1] [MQTT-2.3.1-5].
+#[cfg(feature = "encode")]
+fn check_packet_identifier(
+    flags: fixed_header::PublishFlags,
+    variable_header: &variable_header::publish::Publish,
+) -> Result<(), EncodeError> {
+    match (flags.qos, variable_header.packet_identifier()) {
+        (qos::QoS::AtMostOnce, Some(_)) => Err(EncodeError::UnexpectedPacketId),
+        (qos::QoS::AtLeastOnce, None) | (qos::QoS::ExactlyOnce, None) => {
+            Err(EncodeError::MissingPacketId)
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Calculate the exact encoded length of a PUBLISH packet without constructing
+/// it.
+///
+/// Returns `Err(EncodeError::ValueTooBig)` if a packet with these lengths could
+/// never be encoded.
+#[cfg(feature = "encode")]
+pub fn publish_encoded_len(
+    topic_len: usize,
+    payload_len: usize,
+    qos: qos::QoS,
+) -> Result<usize, EncodeError> {
+    u16::try_from(topic_len)?;
+
+    let packet_identifier_len = if qos == qos::QoS::AtMostOnce { 0 } else { 2 };
+
+    encoded_len(
+        fixed_header::PacketType::Publish,
+        2 + topic_len + packet_identifier_len + payload_len,
+    )
+}
+
+/// Calculate the exact encoded length of a SUBSCRIBE packet for the given
+/// topics without constructing it.
+///
+/// Returns `Err(EncodeError::ValueTooBig)` if a packet with these topics could
+/// never be encoded.
+#[cfg(feature = "encode")]
+pub fn subscribe_encoded_len(topics: &[(&str, qos::QoS)]) -> Result<usize, EncodeError> {
+    let payload_len = topics.iter().try_fold(0, |len, (topic, _)| {
+        u16::try_from(topic.len())?;
+        Ok::<_, EncodeError>(len + topic.encoded_len() + 1)
+    })?;
+
+    encoded_len(fixed_header::PacketType::Subscribe, 2 + payload_len)
+}
+
+/// Split a list of topic filters too long for one SUBSCRIBE into several.
+///
+/// Each packet holds as many consecutive topics as fit in `max_packet_size`
+/// bytes, fixed header included, and takes its packet identifier from
+/// `packet_identifier` when it is built. Once a packet cannot be built, e.g.
+/// because a single topic does not fit with `EncodeError::PacketTooLarge`,
+/// the error is yielded and the iterator ends.
+#[cfg(feature = "encode")]
+pub fn split_subscribe<'a, F>(
+    topics: &'a [(&'a str, qos::QoS)],
+    max_packet_size: usize,
+    packet_identifier: F,
+) -> SplitSubscribe<'a, F>
+where
+    F: FnMut() -> variable_header::PacketId,
+{
+    SplitSubscribe {
+        topics,
+        max_packet_size,
+        packet_identifier,
+    }
+}
+
+/// Iterator over the SUBSCRIBE packets of `split_subscribe`.
+#[cfg(feature = "encode")]
+pub struct SplitSubscribe<'a, F> {
+    topics: &'a [(&'a str, qos::QoS)],
+    max_packet_size: usize,
+    packet_identifier: F,
+}
+
+#[cfg(feature = "encode")]
+impl<'a, F> Iterator for SplitSubscribe<'a, F>
+where
+    F: FnMut() -> variable_header::PacketId,
+{
+    type Item = Result<Packet<'a>, EncodeError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.topics.is_empty() {
+            return None;
+        }
+
+        let mut count = 0;
+        // the packet identifier
+        let mut len = 2;
+        for (topic, _) in self.topics {
+            len += topic.encoded_len() + 1;
+            match encoded_len(fixed_header::PacketType::Subscribe, len) {
+                Ok(packet_len) if packet_len <= self.max_packet_size => count += 1,
+                _ => break,
+            }
+        }
+
+        let topics = self.topics;
+        if count == 0 {
+            self.topics = &[];
+            return Some(Err(EncodeError::PacketTooLarge));
+        }
+        self.topics = &topics[count..];
+
+        let packet = Packet::subscribe(
+            variable_header::packet_identifier::PacketIdentifier::new((self.packet_identifier)()),
+            payload::subscribe::Subscribe::new(&topics[..count]),
+        );
+        if packet.is_err() {
+            self.topics = &[];
+        }
+        Some(packet)
+    }
+}
+
+/// Builds a CONNECT from the options of a connection, deriving the flags of
+/// the variable header from the payload fields that are set so the two always
+/// agree.
+///
+/// The keep alive defaults to disabled and the clean session flag to unset,
+/// as in `Flags::default`.
+///
+/// The methods are `const` so a fixed CONNECT can be encoded at compile time
+/// with `template!`.
+#[cfg(feature = "encode")]
+#[derive(Debug, Clone, Copy)]
+pub struct ConnectBuilder<'a> {
+    pub(crate) client_id: &'a str,
+    pub(crate) keep_alive: variable_header::connect::KeepAliveInterval,
+    pub(crate) clean_session: bool,
+    pub(crate) will: Option<(&'a str, &'a [u8], qos::QoS, bool)>,
+    pub(crate) username: Option<&'a str>,
+    pub(crate) password: Option<payload::connect::SecretBytes<'a>>,
+}
+
+#[cfg(feature = "encode")]
+impl<'a> ConnectBuilder<'a> {
+    pub const fn new(client_id: &'a str) -> Self {
+        ConnectBuilder {
+            client_id,
+            keep_alive: variable_header::connect::KeepAliveInterval::Disabled,
+            clean_session: false,
+            will: None,
+            username: None,
+            password: None,
+        }
+    }
+
+    /// The keep alive interval in seconds, zero disables it.
+    pub const fn keep_alive(mut self, secs: u16) -> Self {
+        self.keep_alive = variable_header::connect::KeepAliveInterval::from_secs(secs);
+        self
+    }
+
+    pub const fn clean_session(mut self, clean_session: bool) -> Self {
+        self.clean_session = clean_session;
+        self
+    }
+
+    /// The message the broker publishes to `topic` when the connection is
+    /// lost.
+    pub const fn will(
+        mut self,
+        topic: &'a str,
+        message: &'a [u8],
+        qos: qos::QoS,
+        retain: bool,
+    ) -> Self {
+        self.will = Some((topic, message, qos, retain));
+        self
+    }
+
+    /// A user name without a password.
+    pub const fn username(mut self, username: &'a str) -> Self {
+        self.username = Some(username);
+        self.password = None;
+        self
+    }
+
+    /// A user name and password, a password is only sent with a user name
+    /// [MQTT-3.1.2-22].
+    pub const fn credentials(mut self, username: &'a str, password: &'a [u8]) -> Self {
+        self.username = Some(username);
+        self.password = Some(payload::connect::SecretBytes::from_slice(password));
+        self
+    }
+
+    /// Create the CONNECT packet, see `Packet::connect` for the errors.
+    pub fn build(self) -> Result<Packet<'a>, EncodeError> {
+        let mut flags = variable_header::connect::Flags {
+            has_username: self.username.is_some(),
+            has_password: self.password.is_some(),
+            will: None,
+            clean_session: self.clean_session,
+        };
+        let will = self.will.map(|(topic, message, qos, retain)| {
+            flags.will = Some(variable_header::connect::WillFlags { qos, retain });
+            payload::connect::Will::new(topic, message)
+        });
+
+        Packet::connect(
+            variable_header::connect::Connect::new(
+                variable_header::connect::Protocol::MQTT,
+                variable_header::connect::Level::Level3_1_1,
+                flags,
+                self.keep_alive,
+            ),
+            payload::connect::Connect::new(self.client_id, will, self.username, self.password),
+        )
+    }
+}
+
+#[cfg(feature = "encode")]
+fn encoded_len(r#type: fixed_header::PacketType, len: usize) -> Result<usize, EncodeError> {
+    let remaining_len = u32::try_from(len)?;
+    if remaining_len > fixed_header::MAX_REMAINING_LENGTH {
+        return Err(EncodeError::ValueTooBig);
+    }
+
+    let header = FixedHeader::new(r#type, fixed_header::PacketFlags(0), remaining_len);
+    len.checked_add(header.encoded_len())
+        .ok_or(EncodeError::ValueTooBig)
+}
+
+/// Encode a packet whose variable header and payload length is only known
+/// once it has been written.
+///
+/// Reserves the largest possible fixed header at the start of `bytes` and calls
+/// `write` with the remainder of the buffer. `write` must encode the variable
+/// header and payload and return the number of bytes it wrote.
+///
+/// Once the length is known the fixed header is backfilled immediately before
+/// the written bytes and the slice holding the complete encoded packet is
+/// returned, this may not start at the beginning of `bytes`.
+#[cfg(feature = "encode")]
+pub fn encode_backfilled<F>(
+    r#type: fixed_header::PacketType,
+    flags: fixed_header::PacketFlags,
+    bytes: &mut [u8],
+    write: F,
+) -> Result<&[u8], EncodeError>
+where
+    F: FnOnce(&mut [u8]) -> Result<usize, EncodeError>,
+{
+    let reserved = fixed_header::MAX_ENCODED_LEN;
+    if bytes.len() < reserved {
+        return Err(EncodeError::OutOfSpace);
+    }
+
+    let written = write(&mut bytes[reserved..])?;
+
+    let len = u32::try_from(written)?;
+    if len > fixed_header::MAX_REMAINING_LENGTH {
+        return Err(EncodeError::ValueTooBig);
+    }
+
+    let header = FixedHeader::new(r#type, flags, len);
+    let start = reserved - header.encoded_len();
+    header.encode(&mut bytes[start..reserved])?;
+
+    Ok(&bytes[start..reserved + written])
+}
+
+/// An error from `Packet::publish_with`.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum PayloadError<E> {
+    /// Encoding the packet around the payload failed
+    Encode(EncodeError),
+    /// Writing the payload failed
+    Payload(E),
+}
+
+impl<E: fmt::Display> fmt::Display for PayloadError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PayloadError::Encode(e) => write!(f, "encode failed: {}", e),
+            PayloadError::Payload(e) => write!(f, "payload failed: {}", e),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl<E: fmt::Debug + fmt::Display> ::std::error::Error for PayloadError<E> {}
+
+impl<E> From<EncodeError> for PayloadError<E> {
+    fn from(err: EncodeError) -> Self {
+        PayloadError::Encode(err)
+    }
+}
+
+#[cfg(feature = "decode")]
+impl<'a> Decodable<'a> for Packet<'a> {
+    /// Decode any MQTT packet from a pre-allocated buffer.
+    ///
+    /// If an unrecoverable error occurs an `Err(x)` is returned, the caller should
+    /// disconnect and network connection and discard the contents of the connection
+    /// receive buffer.
+    ///
+    /// Decoding may return an `Ok(Status::Partial(x))` in which case the caller
+    /// should buffer at most `x` more bytes and then attempt decoding again.
+    ///
+    /// If decoding succeeds an `Ok(Status::Complete(x))` will be returned
+    /// containing the number of bytes read from the buffer and the decoded packet.
+    /// The lifetime of the decoded packet is tied to the input buffer.
+    fn decode(bytes: &'a [u8]) -> Result<Status<(usize, Self)>, DecodeError> {
+        Self::decode_with(bytes, &DecodeOptions::default())
+    }
+}
+
+/// Decode a buffer holding exactly one complete packet.
+///
+/// Returns `Err(DecodeError::Incomplete)` with the number of missing bytes if
+/// the buffer ends early and `Err(DecodeError::InvalidLength)` if bytes follow
+/// the packet. Use `Decodable::decode` to read packets from a stream.
+#[cfg(feature = "decode")]
+impl<'a> TryFrom<&'a [u8]> for Packet<'a> {
+    type Error = DecodeError;
+
+    fn try_from(bytes: &'a [u8]) -> Result<Self, DecodeError> {
+        match Self::decode(bytes)? {
+            Status::Complete((consumed, packet)) if consumed == bytes.len() => Ok(packet),
+            Status::Complete(..) => Err(DecodeError::InvalidLength),
+            Status::Partial(needed) => Err(DecodeError::Incomplete(needed)),
+        }
+    }
+}
+
+#[cfg(feature = "decode")]
+impl<'a> Packet<'a> {
+    /// Decode any MQTT packet from a pre-allocated buffer, enforcing the limits
+    /// in `options`.
+    ///
+    /// See `Decodable::decode` for the meaning of the result.
+    pub fn decode_with(
+        bytes: &'a [u8],
+        options: &DecodeOptions,
+    ) -> Result<Status<(usize, Self)>, DecodeError> {
+        #[cfg(feature = "tracing")]
+        let _span = ::tracing::trace_span!("mqtt.decode", available = bytes.len()).entered();
+
+        let result = Self::decode_packet(bytes, options);
+
+        #[cfg(feature = "stats")]
+        crate::stats::record_decode(&result);
+        #[cfg(feature = "tracing")]
+        crate::trace::decoded(&result);
+
+        result
+    }
+
+    /// Decode a packet and return it with the bytes following it, for reading
+    /// several packets out of one buffer.
+    ///
+    /// See `Decodable::decode` for the meaning of the result.
+    pub fn decode_split(bytes: &'a [u8]) -> Result<Status<(Self, &'a [u8])>, DecodeError> {
+        Ok(Self::decode(bytes)?.map(|(offset, packet)| (packet, &bytes[offset..])))
+    }
+
+    /// Decode any MQTT packet and its opaque payload with `decoder` in a single
+    /// pass.
+    ///
+    /// `decoder` is only called once the whole packet is available and only
+    /// for packets whose payload this crate leaves as `Payload::Bytes`. A
+    /// `Partial` result from `decoder` is reported as `InvalidLength` since the
+    /// payload cannot grow.
+    #[allow(clippy::type_complexity)]
+    pub fn decode_with_payload<D>(
+        bytes: &'a [u8],
+        options: &DecodeOptions,
+        decoder: &D,
+    ) -> Result<Status<(usize, (Self, Option<D::Output>))>, DecodeError>
+    where
+        D: PayloadDecoder<'a>,
+    {
+        let (offset, packet) = complete!(Self::decode_with(bytes, options));
+
+        let payload = match packet.payload {
+            Payload::Bytes(payload) => decoder.decode(&packet, payload),
+            _ => None,
+        };
+
+        let payload = match payload {
+            None => None,
+            Some(Err(e)) => return Err(e),
+            Some(Ok(Status::Partial(..))) => return Err(DecodeError::InvalidLength),
+            Some(Ok(Status::Complete((_, payload)))) => Some(payload),
+        };
+
+        Ok(Status::Complete((offset, (packet, payload))))
+    }
+
+    /// Decode only the fixed header and find the end of the packet, leaving
+    /// the variable header and payload undecoded.
+    ///
+    /// Reserved packet types are accepted, this is intended for proxies that
+    /// forward packets without inspecting them. Returns the total number of
+    /// bytes the packet occupies.
+    pub fn decode_raw(bytes: &'a [u8]) -> Result<Status<(usize, RawPacket<'a>)>, DecodeError> {
+        let options = DecodeOptions {
+            allow_reserved_types: true,
+            ..Default::default()
+        };
+        let (offset, fixed_header) = complete!(FixedHeader::decode_with(bytes, &options));
+
+        let end = offset + usize::try_from(fixed_header.len())?;
+        if bytes.len() < end {
+            return Ok(Status::Partial(end - bytes.len()));
+        }
+
+        Ok(Status::Complete((
+            end,
+            RawPacket {
+                fixed_header,
+                body: &bytes[offset..end],
+            },
+        )))
+    }
+
+    fn decode_packet(
+        bytes: &'a [u8],
+        options: &DecodeOptions,
+    ) -> Result<Status<(usize, Self)>, DecodeError> {
+        let (fixed_header_offset, fixed_header) =
+            complete!(FixedHeader::decode_with(bytes, options));
+
+        let (variable_header_consumed, variable_header) = if let Some(result) =
+            VariableHeader::decode(
+                fixed_header.r#type(),
+                fixed_header.flags(),
+                &bytes[fixed_header_offset..],
+            ) {
+            let (variable_header_offset, variable_header) = complete!(result);
+            if let VariableHeader::Publish(ref publish) = variable_header {
+                options.check_topic(publish.topic_name())?;
+            }
+            (variable_header_offset, Some(variable_header))
+        } else {
+            (0, None)
+        };
+
+        let remaining_len = usize::try_from(fixed_header.len())?;
+        let payload_len = remaining_len
+            .checked_sub(variable_header_consumed)
+            .ok_or(DecodeError::InvalidLength)?;
+
+        let available = bytes.len() - (fixed_header_offset + variable_header_consumed);
+        let needed = payload_len - min(available, payload_len);
+        if needed > 0 {
+            return Ok(Status::Partial(needed));
+        }
+
+        let payload_bytes = &bytes[fixed_header_offset + variable_header_consumed
+            ..fixed_header_offset + variable_header_consumed + payload_len];
+
+        match fixed_header.r#type() {
+            // each filter is followed by its requested QoS
+            fixed_header::PacketType::Subscribe => options.check_subscriptions(payload_bytes, 1)?,
+            fixed_header::PacketType::Unsubscribe => {
+                options.check_subscriptions(payload_bytes, 0)?
+            }
+            _ => {}
+        }
+
+        let payload =
+            if options.lazy_suback && fixed_header.r#type() == fixed_header::PacketType::Suback {
+                Payload::Suback(payload::suback::Suback::decode_lazy(payload_bytes))
+            } else if let Some(result) = Payload::decode(fixed_header.r#type(), payload_bytes) {
+                match result {
+                    Err(e) => return Err(e),
+                    Ok(Status::Partial(n)) => return Ok(Status::Partial(n)),
+                    Ok(Status::Complete((_, payload))) => payload,
+                }
+            } else {
+                payload::Payload::Bytes(payload_bytes)
+            };
+
+        match payload {
+            Payload::Subscribe(ref subscribe) => subscribe
+                .topics()
+                .try_for_each(|(topic, _)| options.check_topic(topic))?,
+            Payload::Unsubscribe(ref unsubscribe) => unsubscribe
+                .filters()
+                .try_for_each(|filter| options.check_topic(filter))?,
+            _ => {}
+        }
+
+        let packet = Self {
+            fixed_header,
+            variable_header,
+            payload,
+        };
+
+        #[cfg(feature = "invariant-check")]
+        crate::invariant::check_decoded(&packet, variable_header_consumed, payload_len);
+
+        Ok(Status::Complete((
+            fixed_header_offset + remaining_len,
+            packet,
+        )))
+    }
+}
+
+#[cfg(feature = "encode")]
+impl<'a> Encodable for Packet<'a> {
+    /// Calculate the exact length of the fully encoded packet.
+    ///
+    /// The encode buffer will need to hold at least this number of bytes.
+    ///
+    /// Saturates at `usize::MAX` if the remaining length cannot be addressed
+    /// on this target, encoding will then fail for lack of space.
+    fn encoded_len(&self) -> usize {
+        usize::try_from(self.fixed_header.len())
+            .unwrap_or(usize::MAX)
+            .saturating_add(self.fixed_header.encoded_len())
+    }
+
+    /// Encode a packet for sending over a network connection.
+    ///
+    /// If encoding fails an `Err(x)` is returned.
+    ///
+    /// If encoding succeeds an `Ok(written)` is returned with the number of
+    /// bytes written to the buffer.
+    ///
+    /// The length computed when the packet was constructed is used to check
+    /// the buffer up front, the variable header and payload are only traversed
+    /// once to write them.
+    fn encode(&self, bytes: &mut [u8]) -> Result<usize, EncodeError> {
+        #[cfg(feature = "tracing")]
+        let _span = ::tracing::trace_span!("mqtt.encode", available = bytes.len()).entered();
+
+        let result = self.encode_packet(bytes);
+
+        #[cfg(feature = "stats")]
+        if let Ok(written) = result {
+            crate::stats::record_encode(&self.fixed_header, written);
+        }
+        #[cfg(feature = "tracing")]
+        crate::trace::encoded(self, &result);
+
+        result
+    }
+}
+
+#[cfg(feature = "encode")]
+impl<'a> Packet<'a> {
+    fn encode_packet(&self, bytes: &mut [u8]) -> Result<usize, EncodeError> {
+        if bytes.len() < self.encoded_len() {
+            return Err(EncodeError::OutOfSpace);
+        }
+
+        let mut offset = 0;
+
+        let written = self.fixed_header.encode(&mut bytes[offset..])?;
+        #[cfg(feature = "invariant-check")]
+        crate::invariant::check_encoded(
+            self.fixed_header.r#type(),
+            "fixed header",
+            self.fixed_header.encoded_len(),
+            written,
+        );
+        offset += written;
+        if let Some(ref variable_header) = self.variable_header {
+            let written = variable_header.encode(&mut bytes[offset..])?;
+            #[cfg(feature = "invariant-check")]
+            crate::invariant::check_encoded(
+                self.fixed_header.r#type(),
+                "variable header",
+                variable_header.encoded_len(),
+                written,
+            );
+            offset += written;
+        }
+        let written = self.payload.encode(&mut bytes[offset..])?;
+        #[cfg(feature = "invariant-check")]
+        crate::invariant::check_encoded(
+            self.fixed_header.r#type(),
+            "payload",
+            self.payload.encoded_len(),
+            written,
+        );
+        offset += written;
+
+        Ok(offset)
+    }
+}
+
+#[cfg(all(test, any(feature = "encode", feature = "decode")))]
+mod tests {
+    use super::*;
+    use crate::qos;
+    #[cfg(all(feature = "encode", feature = "decode"))]
+    use crate::test_util;
+
+    #[cfg(feature = "encode")]
+    #[test]
+    fn encode_publish() {
+        let payload = b"{}";
+        assert_eq!(2, payload.len());
+
+        let publish_flags = fixed_header::PublishFlags::new(qos::QoS::AtLeastOnce, false, false);
+        let publish_id = 2;
+        let publish = Packet::publish(
+            publish_flags,
+            variable_header::publish::Publish::new(
+                "a/b",
+                Some(variable_header::PacketId::new(publish_id)),
+            ),
+            payload,
+        )
+        .expect("valid packet");
+
+        assert_eq!(11, publish.encoded_len());
         assert_eq!(2, publish.fixed_header().encoded_len());
         assert_eq!(9, publish.fixed_header().len());
         assert_eq!(
-            7,
-            publish
-                .variable_header()
-                .as_ref()
-                .expect("variable header")
-                .encoded_len()
+            7,
+            publish
+                .variable_header()
+                .expect("variable header")
+                .encoded_len()
+        );
+        assert_eq!(2, publish.payload().encoded_len());
+
+        assert_eq!(
+            Ok(publish.encoded_len()),
+            publish_encoded_len(3, payload.len(), qos::QoS::AtLeastOnce)
+        );
+    }
+
+    #[cfg(feature = "encode")]
+    #[test]
+    fn publish_len_estimate() {
+        assert_eq!(Ok(7), publish_encoded_len(3, 0, qos::QoS::AtMostOnce));
+        assert_eq!(Ok(131), publish_encoded_len(3, 123, qos::QoS::AtMostOnce));
+        assert_eq!(
+            Err(EncodeError::ValueTooBig),
+            publish_encoded_len(65536, 0, qos::QoS::AtMostOnce)
+        );
+        assert_eq!(
+            Err(EncodeError::ValueTooBig),
+            publish_encoded_len(
+                0,
+                fixed_header::MAX_REMAINING_LENGTH as usize,
+                qos::QoS::AtMostOnce
+            )
+        );
+    }
+
+    #[cfg(feature = "encode")]
+    #[test]
+    fn field_lengths() {
+        let long = "a".repeat(65536);
+        let flags = fixed_header::PublishFlags::default();
+
+        assert_eq!(
+            Err(EncodeError::FieldLength(Field::TopicName)),
+            Packet::publish(flags, variable_header::publish::Publish::new("", None), b"")
+                .map(|_| ())
+        );
+        assert_eq!(
+            Err(EncodeError::FieldLength(Field::TopicName)),
+            Packet::publish(
+                flags,
+                variable_header::publish::Publish::new(&long, None),
+                b""
+            )
+            .map(|_| ())
+        );
+
+        let topics = [("a", qos::QoS::AtMostOnce), ("", qos::QoS::AtMostOnce)];
+        assert_eq!(
+            Err(EncodeError::FieldLength(Field::TopicFilter)),
+            Packet::subscribe(
+                variable_header::packet_identifier::PacketIdentifier::new(
+                    variable_header::PacketId::new(1)
+                ),
+                payload::subscribe::Subscribe::new(&topics)
+            )
+            .map(|_| ())
+        );
+
+        let connect = |payload| {
+            Packet::connect(
+                variable_header::connect::Connect::new(
+                    variable_header::connect::Protocol::MQTT,
+                    variable_header::connect::Level::Level3_1_1,
+                    Default::default(),
+                    variable_header::connect::KeepAliveInterval::Disabled,
+                ),
+                payload,
+            )
+            .map(|_| ())
+        };
+        assert_eq!(
+            Ok(()),
+            connect(payload::connect::Connect::new("", None, None, None))
+        );
+        assert_eq!(
+            Err(EncodeError::FieldLength(Field::ClientId)),
+            connect(payload::connect::Connect::new(&long, None, None, None))
+        );
+        assert_eq!(
+            Err(EncodeError::FieldLength(Field::Password)),
+            connect(payload::connect::Connect::new(
+                "c",
+                None,
+                None,
+                Some(payload::connect::SecretBytes::new(long.as_bytes()))
+            ))
+        );
+        assert_eq!(
+            Err(EncodeError::FieldLength(Field::WillTopic)),
+            connect(payload::connect::Connect::new(
+                "c",
+                Some(payload::connect::Will::new("", b"")),
+                None,
+                None
+            ))
+        );
+    }
+
+    #[cfg(feature = "encode")]
+    #[test]
+    fn owned_inputs() {
+        use std::{string::String, vec::Vec};
+
+        let topic = String::from("a/b");
+        let message = Vec::from(&b"{}"[..]);
+        let publish = Packet::publish(
+            fixed_header::PublishFlags::default(),
+            variable_header::publish::Publish::new(&topic, None),
+            &message,
+        )
+        .unwrap();
+        let mut buf = [0u8; 9];
+        assert_eq!(Ok(9), publish.encode(&mut buf));
+        assert_eq!([0x30, 0x07, 0x00, 0x03, b'a', b'/', b'b', b'{', b'}'], buf);
+
+        let client_id = String::from("client");
+        let will = payload::connect::Will::new_str(&topic, &client_id);
+        assert_eq!("a/b", will.topic());
+        assert_eq!(
+            "client",
+            payload::connect::Connect::new(&client_id, Some(will), None, None).client_id()
+        );
+    }
+
+    #[cfg(feature = "encode")]
+    #[test]
+    fn encode_publish_backfilled() {
+        let payload = b"{}";
+
+        let publish_flags = fixed_header::PublishFlags::new(qos::QoS::AtLeastOnce, false, false);
+        let variable_header =
+            variable_header::publish::Publish::new("a/b", Some(variable_header::PacketId::new(2)));
+
+        let publish = Packet::publish(
+            publish_flags,
+            variable_header::publish::Publish::new("a/b", Some(variable_header::PacketId::new(2))),
+            payload,
+        )
+        .expect("valid packet");
+        let mut expected = [0u8; 11];
+        publish.encode(&mut expected).expect("encoded");
+
+        let mut buf = [0u8; 32];
+        let encoded = encode_backfilled(
+            fixed_header::PacketType::Publish,
+            publish_flags.into(),
+            &mut buf,
+            |bytes| {
+                let mut offset = variable_header.encode(bytes)?;
+                offset += payload::Payload::Bytes(payload).encode(&mut bytes[offset..])?;
+                Ok(offset)
+            },
+        )
+        .expect("encoded");
+
+        assert_eq!(&expected[..], encoded);
+    }
+
+    #[cfg(feature = "encode")]
+    #[test]
+    fn encode_backfilled_out_of_space() {
+        let mut buf = [0u8; 4];
+        let result = encode_backfilled(
+            fixed_header::PacketType::Pingreq,
+            fixed_header::PacketFlags::PINGREQ,
+            &mut buf,
+            |_| Ok(0),
+        );
+        assert_eq!(Err(EncodeError::OutOfSpace), result);
+    }
+
+    #[cfg(all(feature = "encode", feature = "decode"))]
+    #[test]
+    fn refuse_protocol_level() {
+        let connect = [
+            0x10, 0x0c, // CONNECT
+            0x00, 0x04, b'M', b'Q', b'T', b'T', // Protocol name
+            0x03, // Level 3
+            0x02, // Clean session
+            0x00, 0x0a, // Keep alive
+            0x00, 0x00, // Client identifier
+        ];
+
+        let error = Packet::decode(&connect).expect_err("unsupported level");
+        assert_eq!(DecodeError::InvalidProtocolLevel, error);
+
+        let connack = Packet::refused_connack(error).expect("connack");
+        let mut buf = [0u8; 4];
+        assert_eq!(Ok(4), connack.encode(&mut buf));
+        assert_eq!([0x20, 0x02, 0x00, 0x01], buf);
+
+        assert!(Packet::refused_connack(DecodeError::Utf8).is_none());
+    }
+
+    #[cfg(all(feature = "encode", feature = "decode"))]
+    #[test]
+    fn suback_for_grants() {
+        let subscribe = [
+            0x82, 0x0f, 0x00, 0x07, // SUBSCRIBE id 7
+            0x00, 0x03, b'a', b'/', b'b', 0x02, // a/b QoS 2
+            0x00, 0x04, b'$', b'S', b'Y', b'S', 0x00, // $SYS QoS 0
+        ];
+        let subscribe = match Packet::decode(&subscribe) {
+            Ok(Status::Complete((_, packet))) => packet,
+            other => panic!("unexpected {:?}", other),
+        };
+
+        let mut return_codes = [payload::suback::ReturnCode::FAILURE; 2];
+        let mut accepted = [("", qos::QoS::AtMostOnce); 2];
+        let (suback, accepted_len) = Packet::suback_for(
+            &subscribe,
+            &mut return_codes,
+            &mut accepted,
+            |topic, qos| {
+                if topic.starts_with('$') {
+                    Err(payload::suback::Denied)
+                } else {
+                    Ok(core::cmp::min(qos, qos::QoS::AtLeastOnce))
+                }
+            },
+        )
+        .expect("subscribe packet")
+        .expect("valid packet");
+
+        assert_eq!(1, accepted_len);
+        assert_eq!(("a/b", qos::QoS::AtLeastOnce), accepted[0]);
+
+        let mut buf = [0u8; 6];
+        assert_eq!(Ok(6), suback.encode(&mut buf));
+        assert_eq!([0x90, 0x04, 0x00, 0x07, 0x01, 0x80], buf);
+
+        let mut return_codes = [payload::suback::ReturnCode::FAILURE; 1];
+        assert_eq!(
+            Some(EncodeError::OutOfSpace),
+            Packet::suback_for(&subscribe, &mut return_codes, &mut accepted, |_, qos| Ok(
+                qos
+            ))
+            .expect("subscribe packet")
+            .err()
+        );
+
+        assert!(Packet::suback_for(
+            &Packet::pingreq(),
+            &mut return_codes,
+            &mut accepted,
+            |_, qos| Ok(qos)
+        )
+        .is_none());
+    }
+
+    #[cfg(all(feature = "encode", feature = "decode"))]
+    #[test]
+    fn republish() {
+        // PUBLISH QoS 1 with DUP and retain, topic a/b, packet identifier 7
+        let mut buf = [0u8; 11];
+        let publish = test_util::packet_from_hex("3b 09 00 03 61 2f 62 00 07 7b 7d", &mut buf)
+            .expect("decoded");
+
+        let mut buf = [0u8; 11];
+        assert_eq!(
+            Some(Ok(11)),
+            publish.encode_republished(variable_header::PacketId::new(0x1234), &mut buf)
+        );
+        assert_eq!("33 09 00 03 61 2f 62 12 34 7b 7d", test_util::to_hex(&buf));
+
+        let mut buf = [0u8; 10];
+        assert_eq!(
+            Some(Err(EncodeError::OutOfSpace)),
+            publish.encode_republished(variable_header::PacketId::new(0x1234), &mut buf)
+        );
+        assert_eq!(
+            None,
+            Packet::pingreq().encode_republished(variable_header::PacketId::new(1), &mut buf)
+        );
+    }
+
+    #[cfg(all(feature = "encode", feature = "decode"))]
+    #[test]
+    fn downgrade() {
+        // PUBLISH QoS 2 with DUP and retain, topic a/b, packet identifier 7
+        let mut buf = [0u8; 11];
+        let publish = test_util::packet_from_hex("3d 09 00 03 61 2f 62 00 07 7b 7d", &mut buf)
+            .expect("decoded");
+
+        let encode = |packet: Packet| {
+            let mut buf = [0u8; 11];
+            let len = packet.encode(&mut buf).expect("encoded");
+            test_util::to_hex(&buf[..len])
+        };
+
+        let id = variable_header::PacketId::new(0x1234);
+        let downgraded = publish
+            .downgraded(qos::QoS::AtLeastOnce, id)
+            .unwrap()
+            .unwrap();
+        assert_eq!("33 09 00 03 61 2f 62 12 34 7b 7d", encode(downgraded));
+
+        let downgraded = publish
+            .downgraded(qos::QoS::AtMostOnce, id)
+            .unwrap()
+            .unwrap();
+        assert_eq!("31 07 00 03 61 2f 62 7b 7d", encode(downgraded));
+
+        let kept = publish
+            .downgraded(qos::QoS::ExactlyOnce, id)
+            .unwrap()
+            .unwrap();
+        assert_eq!("35 09 00 03 61 2f 62 12 34 7b 7d", encode(kept));
+
+        assert!(Packet::pingreq()
+            .downgraded(qos::QoS::AtMostOnce, id)
+            .is_none());
+    }
+
+    #[cfg(all(feature = "encode", feature = "decode"))]
+    #[test]
+    fn unsubscribe_from_subscribe() {
+        let mut buf = [0u8; 16];
+        let subscribe =
+            test_util::packet_from_hex("82 0c 00 01 00 03 61 2f 62 01 00 01 63 00", &mut buf)
+                .expect("decoded");
+
+        let unsubscribe = Packet::unsubscribe(
+            variable_header::packet_identifier::PacketIdentifier::new(
+                variable_header::PacketId::new(2),
+            ),
+            payload::unsubscribe::Unsubscribe::from_subscribe(
+                subscribe.as_subscribe_payload().expect("subscribe"),
+            ),
+        )
+        .expect("valid packet");
+        assert_eq!(
+            Ok(std::string::String::from(
+                "a2 0a 00 02 00 03 61 2f 62 00 01 63"
+            )),
+            test_util::encode_hex(&unsubscribe)
+        );
+
+        assert_eq!(
+            Err(EncodeError::FieldLength(Field::TopicFilter)),
+            Packet::unsubscribe(
+                variable_header::packet_identifier::PacketIdentifier::new(
+                    variable_header::PacketId::new(3),
+                ),
+                payload::unsubscribe::Unsubscribe::new(&[""]),
+            )
+            .map(|_| ())
+        );
+    }
+
+    #[cfg(all(feature = "encode", feature = "decode"))]
+    #[test]
+    fn split_subscribe() {
+        let topics = [
+            ("a/b", qos::QoS::AtMostOnce),
+            ("c/d", qos::QoS::AtLeastOnce),
+            ("e/f", qos::QoS::ExactlyOnce),
+        ];
+        let mut next_id = variable_header::PacketId::default();
+        let mut packet_identifier = || {
+            next_id = next_id.next();
+            next_id
+        };
+
+        // room for two topics per packet
+        let packets: std::vec::Vec<_> = super::split_subscribe(&topics, 17, &mut packet_identifier)
+            .map(|packet| test_util::encode_hex(&packet.expect("valid packet")).unwrap())
+            .collect();
+        assert_eq!(
+            [
+                "82 0e 00 01 00 03 61 2f 62 00 00 03 63 2f 64 01",
+                "82 08 00 02 00 03 65 2f 66 02",
+            ],
+            packets[..]
+        );
+
+        let mut split = super::split_subscribe(&topics, 9, &mut packet_identifier);
+        assert_eq!(
+            Some(Err(EncodeError::PacketTooLarge)),
+            split.next().map(|p| p.map(|_| ()))
+        );
+        assert!(split.next().is_none());
+
+        let empty = [("", qos::QoS::AtMostOnce)];
+        let mut split = super::split_subscribe(&empty, 16, &mut packet_identifier);
+        assert_eq!(
+            Some(Err(EncodeError::FieldLength(Field::TopicFilter))),
+            split.next().map(|p| p.map(|_| ()))
+        );
+        assert!(split.next().is_none());
+    }
+
+    #[cfg(feature = "encode")]
+    #[test]
+    fn publish_message() {
+        let packet = Packet::publish_message(
+            variable_header::publish::Publish::at_least_once(
+                "a/b",
+                variable_header::PacketId::new(2),
+            )
+            .retain(),
+            b"{}",
+        )
+        .unwrap();
+
+        let mut buf = [0u8; 11];
+        assert_eq!(Ok(11), packet.encode(&mut buf));
+        assert_eq!(
+            [0x33, 0x09, 0x00, 0x03, b'a', b'/', b'b', 0x00, 0x02, b'{', b'}'],
+            buf
+        );
+
+        assert_eq!(
+            Err(EncodeError::FieldLength(Field::TopicName)),
+            Packet::publish_message(variable_header::publish::Publish::at_most_once(""), b"")
+                .map(|_| ())
+        );
+    }
+
+    #[cfg(feature = "encode")]
+    #[test]
+    fn publish_with() {
+        let flags = fixed_header::PublishFlags::new(qos::QoS::AtLeastOnce, false, false);
+        let publish = || {
+            variable_header::publish::Publish::new("a/b", Some(variable_header::PacketId::new(2)))
+        };
+
+        let mut buf = [0u8; 32];
+        let encoded = Packet::publish_with(flags, publish(), &mut buf, |payload| {
+            payload[..2].copy_from_slice(b"{}");
+            Ok::<_, ()>(2)
+        })
+        .expect("encoded");
+
+        let packet = Packet::publish(flags, publish(), b"{}").expect("valid packet");
+        let mut expected = [0u8; 11];
+        assert_eq!(Ok(11), packet.encode(&mut expected));
+        assert_eq!(&expected[..], encoded);
+
+        assert_eq!(
+            Err(PayloadError::Payload("too big")),
+            Packet::publish_with(flags, publish(), &mut buf, |_| Err("too big")).map(|_| ())
+        );
+        assert_eq!(
+            Err(PayloadError::Encode(EncodeError::OutOfSpace)),
+            Packet::publish_with(flags, publish(), &mut buf[..8], |_| Ok::<_, ()>(0)).map(|_| ())
+        );
+        assert_eq!(
+            Err(PayloadError::Encode(EncodeError::OutOfSpace)),
+            Packet::publish_with(flags, publish(), &mut buf[..12], |_| Ok::<_, ()>(8)).map(|_| ())
+        );
+        assert_eq!(
+            Err(PayloadError::Encode(EncodeError::MissingPacketId)),
+            Packet::publish_with(
+                flags,
+                variable_header::publish::Publish::new("a/b", None),
+                &mut buf,
+                |_| Ok::<_, ()>(0)
+            )
+            .map(|_| ())
+        );
+    }
+
+    #[cfg(feature = "encode")]
+    #[test]
+    fn publish_missing_packet_id() {
+        let publish = || variable_header::publish::Publish::new("a/b", None);
+        for &qos in &[qos::QoS::AtLeastOnce, qos::QoS::ExactlyOnce] {
+            assert_eq!(
+                Err(EncodeError::MissingPacketId),
+                Packet::publish(
+                    fixed_header::PublishFlags::new(qos, false, false),
+                    publish(),
+                    b"{}"
+                )
+                .map(|_| ())
+            );
+        }
+        assert!(Packet::publish(Default::default(), publish(), b"{}").is_ok());
+
+        assert_eq!(
+            Err(EncodeError::UnexpectedPacketId),
+            Packet::publish(
+                Default::default(),
+                variable_header::publish::Publish::new(
+                    "a/b",
+                    Some(variable_header::PacketId::new(1))
+                ),
+                b"{}"
+            )
+            .map(|_| ())
+        );
+    }
+
+    #[cfg(feature = "decode")]
+    #[test]
+    fn try_from_bytes() {
+        let bytes = [0x40, 0x02, 0x00, 0x07, 0xc0, 0x00];
+        let packet = Packet::try_from(&bytes[..4]).expect("decoded");
+        assert_eq!(Ok(PacketKind::Puback), packet.kind());
+
+        assert_eq!(
+            Err(DecodeError::Incomplete(1)),
+            Packet::try_from(&bytes[..3]).map(|_| ())
+        );
+        assert_eq!(
+            Err(DecodeError::InvalidLength),
+            Packet::try_from(&bytes[..]).map(|_| ())
+        );
+        assert_eq!(
+            Err(DecodeError::PacketType),
+            Packet::try_from(&[0xf0, 0x00][..]).map(|_| ())
+        );
+    }
+
+    #[cfg(feature = "encode")]
+    #[test]
+    fn packet_identifier_bytes() {
+        let id = || {
+            variable_header::packet_identifier::PacketIdentifier::new(
+                variable_header::PacketId::new(0x0102),
+            )
+        };
+        for (packet, first_byte) in [
+            (Packet::unsuback(id()), 0xb0),
+            (Packet::pubrec(id()), 0x50),
+            (Packet::pubrel(id()), 0x62),
+            (Packet::pubcomp(id()), 0x70),
+        ] {
+            let mut buf = [0u8; 4];
+            assert_eq!(Ok(4), packet.unwrap().encode(&mut buf));
+            assert_eq!([first_byte, 0x02, 0x01, 0x02], buf);
+        }
+    }
+
+    #[cfg(feature = "encode")]
+    #[test]
+    fn suback() {
+        use crate::payload::suback::{ReturnCode, Suback};
+
+        let return_codes = [ReturnCode::SUCCESS_QOS_1, ReturnCode::FAILURE];
+        let packet = Packet::suback(
+            variable_header::packet_identifier::PacketIdentifier::new(
+                variable_header::PacketId::new(10),
+            ),
+            Suback::new(&return_codes),
+        )
+        .unwrap();
+
+        let mut buf = [0u8; 6];
+        assert_eq!(Ok(6), packet.encode(&mut buf));
+        assert_eq!([0x90, 0x04, 0x00, 0x0a, 0x01, 0x80], buf);
+    }
+
+    #[cfg(feature = "encode")]
+    #[test]
+    fn connack() {
+        use crate::variable_header::connack::{Flags, ReturnCode};
+
+        let session_present = Flags {
+            session_present: true,
+        };
+        let mut buf = [0u8; 4];
+        assert_eq!(
+            Ok(4),
+            Packet::connack(session_present, ReturnCode::Accepted).encode(&mut buf)
+        );
+        assert_eq!([0x20, 0x02, 0x01, 0x00], buf);
+
+        // [MQTT-3.2.2-4]
+        assert_eq!(
+            Ok(4),
+            Packet::connack(session_present, ReturnCode::RefusedNotAuthorized).encode(&mut buf)
+        );
+        assert_eq!([0x20, 0x02, 0x00, 0x05], buf);
+    }
+
+    #[cfg(all(feature = "encode", feature = "decode"))]
+    #[test]
+    fn connect_builder() {
+        let packet = ConnectBuilder::new("sensor-42")
+            .keep_alive(30)
+            .will("site/42/status", b"offline", qos::QoS::AtLeastOnce, true)
+            .credentials("device", b"secret")
+            .clean_session(true)
+            .build()
+            .unwrap();
+
+        let flags = match packet.variable_header() {
+            Some(VariableHeader::Connect(connect)) => {
+                assert_eq!(
+                    variable_header::connect::KeepAliveInterval::from(30),
+                    connect.keep_alive()
+                );
+                connect.flags()
+            }
+            other => panic!("unexpected variable header {:?}", other),
+        };
+        assert_eq!(0b1110_1110, u8::from(flags));
+
+        let payload = packet.as_connect_payload().unwrap();
+        assert_eq!("sensor-42", payload.client_id());
+        assert_eq!("site/42/status", payload.will().unwrap().topic());
+        assert_eq!(Some("device"), payload.username());
+        assert_eq!(
+            Some(&b"secret"[..]),
+            payload.password().map(|password| password.expose())
+        );
+
+        let mut buf = [0u8; 64];
+        let len = packet.encode(&mut buf).unwrap();
+        let (_, decoded) = Packet::decode(&buf[..len]).unwrap().unwrap();
+        assert!(matches!(
+            decoded.variable_header(),
+            Some(VariableHeader::Connect(connect)) if connect.flags() == flags
+        ));
+
+        let packet = ConnectBuilder::new("c")
+            .credentials("device", b"secret")
+            .username("device")
+            .build()
+            .unwrap();
+        match packet.variable_header() {
+            Some(VariableHeader::Connect(connect)) => {
+                assert_eq!(0b1000_0000, u8::from(connect.flags()))
+            }
+            other => panic!("unexpected variable header {:?}", other),
+        }
+    }
+
+    #[cfg(feature = "decode")]
+    #[test]
+    fn typed_views() {
+        let publish = [0x3b, 0x07, 0x00, 0x01, b'a', 0x00, 0x05, b'{', b'}'];
+        let (_, packet) = Packet::decode(&publish).unwrap().unwrap();
+        assert_eq!(
+            Some(typed::PublishView {
+                topic_name: "a",
+                flags: fixed_header::PublishFlags::new(qos::QoS::AtLeastOnce, true, true),
+                packet_identifier: Some(variable_header::PacketId::new(5)),
+                payload: b"{}",
+            }),
+            packet.as_publish()
+        );
+        assert_eq!(None, packet.as_connack());
+        assert!(packet.as_suback().is_none());
+
+        let (_, packet) = Packet::decode(&[0x20, 0x02, 0x01, 0x00]).unwrap().unwrap();
+        assert_eq!(
+            Some(typed::ConnackView {
+                session_present: true,
+                return_code: variable_header::connack::ReturnCode::Accepted,
+            }),
+            packet.as_connack()
+        );
+        assert_eq!(None, packet.as_publish());
+
+        let (_, packet) = Packet::decode(&[0x90, 0x04, 0x00, 0x07, 0x01, 0x80])
+            .unwrap()
+            .unwrap();
+        let suback = packet.as_suback().unwrap();
+        assert_eq!(variable_header::PacketId::new(7), suback.packet_identifier);
+        assert_eq!(
+            Some(
+                &[
+                    payload::suback::ReturnCode::SUCCESS_QOS_1,
+                    payload::suback::ReturnCode::FAILURE
+                ][..]
+            ),
+            suback.return_codes.return_codes()
+        );
+
+        let subscribe = [0x82, 0x06, 0x00, 0x08, 0x00, 0x01, b'a', 0x02];
+        let (_, packet) = Packet::decode(&subscribe).unwrap().unwrap();
+        let subscribe = packet.as_subscribe().unwrap();
+        assert_eq!(
+            variable_header::PacketId::new(8),
+            subscribe.packet_identifier
+        );
+        assert_eq!(
+            std::vec![("a", qos::QoS::ExactlyOnce)],
+            subscribe.topics.topics().collect::<std::vec::Vec<_>>()
+        );
+
+        let unsubscribe = [0xa2, 0x05, 0x00, 0x09, 0x00, 0x01, b'a'];
+        let (_, packet) = Packet::decode(&unsubscribe).unwrap().unwrap();
+        let unsubscribe = packet.as_unsubscribe().unwrap();
+        assert_eq!(
+            variable_header::PacketId::new(9),
+            unsubscribe.packet_identifier
+        );
+        assert_eq!(
+            std::vec!["a"],
+            unsubscribe.filters.filters().collect::<std::vec::Vec<_>>()
+        );
+    }
+
+    #[cfg(feature = "encode")]
+    #[test]
+    fn debug_preview() {
+        let topic = "t".repeat(100);
+        let payload = [0u8; 4096];
+        let packet = Packet::publish(
+            fixed_header::PublishFlags::default(),
+            variable_header::publish::Publish::new(topic.as_str(), None),
+            &payload,
+        )
+        .unwrap();
+
+        let debug = format!("{:?}", packet);
+        assert!(debug.len() < 400, "{}", debug);
+        assert!(debug.contains(".. (100 bytes)"));
+        assert!(debug.contains(".. (4096 bytes)"));
+    }
+
+    #[cfg(all(feature = "encode", feature = "decode"))]
+    #[test]
+    fn parts() {
+        let mut buf = [0u8; 11];
+        let publish = test_util::packet_from_hex("3b 09 00 03 61 2f 62 00 07 7b 7d", &mut buf)
+            .expect("decoded");
+
+        assert_eq!(
+            Ok(PacketKind::Publish(fixed_header::PublishFlags::new(
+                qos::QoS::AtLeastOnce,
+                true,
+                true
+            ))),
+            publish.kind()
+        );
+
+        let (fixed_header, variable_header, _) = publish.into_parts();
+        let packet =
+            Packet::from_parts(fixed_header, variable_header, Payload::Bytes(b"{\"a\":1}"))
+                .expect("valid packet");
+        assert_eq!(
+            fixed_header::PacketType::Publish,
+            packet.fixed_header().r#type()
+        );
+        assert_eq!(fixed_header.flags(), packet.fixed_header().flags());
+        assert_eq!(14, packet.fixed_header().len());
+
+        let mut buf = [0u8; 16];
+        assert_eq!(Ok(16), packet.encode(&mut buf));
+        assert_eq!(
+            "3b 0e 00 03 61 2f 62 00 07 7b 22 61 22 3a 31 7d",
+            test_util::to_hex(&buf)
         );
-        assert_eq!(2, publish.payload().encoded_len());
     }
 
+    #[cfg(all(feature = "encode", feature = "decode"))]
+    #[test]
+    fn rewrite_topic() {
+        // PUBLISH QoS 1 with DUP and retain, topic a/b, packet identifier 7
+        let mut buf = [0u8; 11];
+        let publish = test_util::packet_from_hex("3b 09 00 03 61 2f 62 00 07 7b 7d", &mut buf)
+            .expect("decoded");
+
+        let mut buf = [0u8; 13];
+        assert_eq!(Some(Ok(13)), publish.encode_prefixed("s/", &mut buf));
+        assert_eq!(
+            "3b 0b 00 05 73 2f 61 2f 62 00 07 7b 7d",
+            test_util::to_hex(&buf)
+        );
+
+        // a topic long enough to need a two byte remaining length
+        let mut buf = [0u8; 256];
+        let len = publish
+            .encode_rewritten(|_, topic| write!(topic, "{:x<130}", ""), &mut buf)
+            .unwrap()
+            .expect("encoded");
+        assert_eq!(139, len);
+        let (_, rewritten) = Packet::decode(&buf[..len]).unwrap().unwrap();
+        match rewritten.variable_header() {
+            Some(VariableHeader::Publish(header)) => {
+                assert_eq!(130, header.topic_name().len());
+                assert_eq!(
+                    Some(variable_header::PacketId::new(7)),
+                    header.packet_identifier()
+                );
+            }
+            _ => panic!("not a publish"),
+        }
+        assert_eq!(Some(&b"{}"[..]), rewritten.payload_bytes());
+
+        assert_eq!(
+            Some(Err(EncodeError::FieldLength(Field::TopicName))),
+            publish.encode_rewritten(|_, _| Ok(()), &mut buf)
+        );
+        assert_eq!(
+            Some(Err(EncodeError::OutOfSpace)),
+            publish.encode_prefixed("s/", &mut buf[..12])
+        );
+        assert_eq!(None, Packet::pingreq().encode_prefixed("s/", &mut buf));
+
+        // QoS 0 without a payload, into a buffer of exactly the encoded length
+        let publish = Packet::publish(
+            fixed_header::PublishFlags::default(),
+            variable_header::publish::Publish::new("a/b", None),
+            b"",
+        )
+        .unwrap();
+        let mut buf = [0u8; 9];
+        assert_eq!(Some(Ok(9)), publish.encode_prefixed("s/", &mut buf));
+        assert_eq!("30 07 00 05 73 2f 61 2f 62", test_util::to_hex(&buf));
+    }
+
+    #[cfg(feature = "encode")]
+    #[test]
+    fn constant_bytes() {
+        for (packet, expected) in [
+            (Packet::pingreq(), PINGREQ_BYTES),
+            (Packet::pingresp(), PINGRESP_BYTES),
+            (Packet::disconnect(), DISCONNECT_BYTES),
+        ] {
+            let mut buf = [0u8; 2];
+            assert_eq!(Ok(2), packet.encode(&mut buf));
+            assert_eq!(expected, buf);
+        }
+    }
+
+    #[cfg(feature = "decode")]
+    #[test]
+    fn decode_raw() {
+        let bytes = [0x30, 0x04, 0x00, 0x01, b'a', b'{', 0xf0];
+        match Packet::decode_raw(&bytes) {
+            Ok(Status::Complete((6, raw))) => {
+                assert_eq!(
+                    fixed_header::PacketType::Publish,
+                    raw.fixed_header().r#type()
+                );
+                assert_eq!(&bytes[2..6], raw.body());
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+
+        assert_eq!(Ok(Status::Partial(2)), Packet::decode_raw(&bytes[..4]));
+
+        match Packet::decode_raw(&bytes[6..]) {
+            Ok(Status::Partial(1)) => {}
+            other => panic!("unexpected {:?}", other),
+        }
+        match Packet::decode_raw(&[0xf0, 0x00]) {
+            Ok(Status::Complete((2, raw))) => assert_eq!(
+                fixed_header::PacketType::Reserved(15),
+                raw.fixed_header().r#type()
+            ),
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[cfg(feature = "decode")]
+    #[test]
+    fn decode_custom_payload() {
+        struct Reading;
+
+        impl<'a> PayloadDecoder<'a> for Reading {
+            type Output = u16;
+
+            fn decode(
+                &self,
+                packet: &Packet<'a>,
+                bytes: &'a [u8],
+            ) -> Option<Result<Status<(usize, u16)>, DecodeError>> {
+                match packet.variable_header() {
+                    Some(VariableHeader::Publish(publish)) if publish.topic_name() == "temp" => {
+                        Some(crate::codec::values::parse_u16(bytes))
+                    }
+                    _ => None,
+                }
+            }
+        }
+
+        let options = DecodeOptions::default();
+
+        let bytes = [0x30, 0x08, 0x00, 0x04, b't', b'e', b'm', b'p', 0x01, 0x02];
+        match Packet::decode_with_payload(&bytes, &options, &Reading) {
+            Ok(Status::Complete((10, (_, Some(0x0102))))) => {}
+            other => panic!("unexpected {:?}", other),
+        }
+        match Packet::decode_with_payload(&bytes[..9], &options, &Reading) {
+            Ok(Status::Partial(1)) => {}
+            other => panic!("unexpected {:?}", other),
+        }
+
+        let bytes = [0x30, 0x07, 0x00, 0x04, b't', b'e', b'm', b'p', 0x01];
+        match Packet::decode_with_payload(&bytes, &options, &Reading) {
+            Err(DecodeError::InvalidLength) => {}
+            other => panic!("unexpected {:?}", other),
+        }
+
+        match Packet::decode_with_payload(&PINGREQ_BYTES, &options, &Reading) {
+            Ok(Status::Complete((2, (_, None)))) => {}
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[cfg(feature = "decode")]
+    #[test]
+    fn decode_topic_limits() {
+        let publish = [0x30, 0x07, 0x00, 0x05, b'a', b'/', b'b', b'/', b'c'];
+        let subscribe = [
+            0x82, 0x0a, 0x00, 0x01, 0x00, 0x05, b'a', b'/', b'+', b'/', b'#', 0x00,
+        ];
+        let unsubscribe = [
+            0xa2, 0x09, 0x00, 0x01, 0x00, 0x05, b'a', b'/', b'+', b'/', b'#',
+        ];
+
+        let options = DecodeOptions::default();
+        assert!(Packet::decode_with(&publish, &options).is_ok());
+        assert!(Packet::decode_with(&subscribe, &options).is_ok());
+        assert!(Packet::decode_with(&unsubscribe, &options).is_ok());
+
+        let options = DecodeOptions {
+            max_topic_len: Some(4),
+            ..Default::default()
+        };
+        assert_eq!(
+            DecodeError::TopicTooLong,
+            Packet::decode_with(&publish, &options).unwrap_err()
+        );
+        assert_eq!(
+            DecodeError::TopicTooLong,
+            Packet::decode_with(&subscribe, &options).unwrap_err()
+        );
+        assert_eq!(
+            DecodeError::TopicTooLong,
+            Packet::decode_with(&unsubscribe, &options).unwrap_err()
+        );
+
+        let options = DecodeOptions {
+            max_topic_levels: Some(2),
+            ..Default::default()
+        };
+        assert_eq!(
+            DecodeError::TooManyTopicLevels,
+            Packet::decode_with(&publish, &options).unwrap_err()
+        );
+        assert_eq!(
+            DecodeError::TooManyTopicLevels,
+            Packet::decode_with(&subscribe, &options).unwrap_err()
+        );
+        assert_eq!(
+            DecodeError::TooManyTopicLevels,
+            Packet::decode_with(&unsubscribe, &options).unwrap_err()
+        );
+
+        let options = DecodeOptions {
+            max_topic_len: Some(5),
+            max_topic_levels: Some(3),
+            ..Default::default()
+        };
+        assert!(Packet::decode_with(&publish, &options).is_ok());
+        assert!(Packet::decode_with(&subscribe, &options).is_ok());
+        assert!(Packet::decode_with(&unsubscribe, &options).is_ok());
+    }
+
+    #[cfg(feature = "decode")]
+    #[test]
+    fn decode_packet_identifiers() {
+        for first in [0x40, 0x50, 0x62, 0x70, 0xb0] {
+            let bytes = [first, 0x02, 0x01, 0x02];
+            let (len, packet) = Packet::decode(&bytes).unwrap().unwrap();
+            assert_eq!(4, len);
+            assert_eq!(
+                Some(variable_header::PacketId::new(0x0102)),
+                packet
+                    .variable_header()
+                    .and_then(VariableHeader::packet_identifier)
+            );
+            assert_eq!(Some(&[][..]), packet.payload_bytes());
+        }
+
+        let unsubscribe = [0xa2, 0x05, 0x00, 0x0a, 0x00, 0x01, b'a'];
+        let (_, packet) = Packet::decode(&unsubscribe).unwrap().unwrap();
+        match packet.variable_header() {
+            Some(VariableHeader::Unsubscribe(id)) => {
+                assert_eq!(variable_header::PacketId::new(10), id.packet_identifier())
+            }
+            other => panic!("unexpected variable header {:?}", other),
+        }
+        assert_eq!(
+            std::vec!["a"],
+            packet
+                .as_unsubscribe_payload()
+                .unwrap()
+                .filters()
+                .collect::<std::vec::Vec<_>>()
+        );
+        assert_eq!(None, packet.payload_bytes());
+    }
+
+    #[cfg(feature = "decode")]
+    #[test]
+    fn decode_subscription_limit() {
+        let subscribe = [
+            0x82, 0x0a, 0x00, 0x01, 0x00, 0x01, b'a', 0x00, 0x00, 0x01, b'b', 0x01,
+        ];
+        let unsubscribe = [0xa2, 0x08, 0x00, 0x01, 0x00, 0x01, b'a', 0x00, 0x01, b'b'];
+
+        let options = DecodeOptions {
+            max_subscriptions: Some(2),
+            ..Default::default()
+        };
+        assert!(Packet::decode_with(&subscribe, &options).is_ok());
+        assert!(Packet::decode_with(&unsubscribe, &options).is_ok());
+
+        let options = DecodeOptions {
+            max_subscriptions: Some(1),
+            ..Default::default()
+        };
+        assert_eq!(
+            DecodeError::TooManySubscriptions,
+            Packet::decode_with(&subscribe, &options).unwrap_err()
+        );
+        assert_eq!(
+            DecodeError::TooManySubscriptions,
+            Packet::decode_with(&unsubscribe, &options).unwrap_err()
+        );
+    }
+
+    #[cfg(feature = "decode")]
+    #[test]
+    fn decode_lazy_suback() {
+        let suback = [0x90, 0x04, 0x00, 0x01, 0x01, 0x40];
+        assert_eq!(
+            DecodeError::InvalidSubackReturnCode,
+            Packet::decode(&suback).unwrap_err()
+        );
+
+        let options = DecodeOptions {
+            lazy_suback: true,
+            ..Default::default()
+        };
+        let (_, packet) = Packet::decode_with(&suback, &options).unwrap().unwrap();
+        let mut return_codes = packet
+            .as_suback_payload()
+            .unwrap()
+            .lazy_return_codes()
+            .unwrap();
+        assert_eq!(
+            Some(Ok(payload::suback::ReturnCode::SUCCESS_QOS_1)),
+            return_codes.next()
+        );
+        assert_eq!(
+            Some(Err(DecodeError::InvalidSubackReturnCode)),
+            return_codes.next()
+        );
+    }
+
+    #[cfg(all(feature = "encode", feature = "decode"))]
+    #[test]
+    fn decode_reserved_passthrough() {
+        let auth = [0xF0, 0x02, 0x18, 0x00];
+        assert_eq!(DecodeError::PacketType, Packet::decode(&auth).unwrap_err());
+
+        let options = DecodeOptions {
+            allow_reserved_types: true,
+            ..Default::default()
+        };
+        let (offset, packet) = Packet::decode_with(&auth, &options).unwrap().unwrap();
+        assert_eq!(4, offset);
+        assert_eq!(
+            fixed_header::PacketType::Reserved(15),
+            packet.fixed_header().r#type()
+        );
+        assert!(packet.variable_header().is_none());
+        assert_eq!(Some(&[0x18, 0x00][..]), packet.payload_bytes());
+
+        let mut buf = [0u8; 4];
+        assert_eq!(Ok(4), packet.encode(&mut buf));
+        assert_eq!(auth, buf);
+    }
+
+    #[cfg(feature = "decode")]
+    #[test]
+    fn decode_split() {
+        let mut bytes: &[u8] = &[0xc0, 0x00, 0xd0, 0x00, 0xe0];
+        let mut types = std::vec::Vec::new();
+        while let Status::Complete((packet, rest)) = Packet::decode_split(bytes).unwrap() {
+            types.push(packet.fixed_header().r#type());
+            bytes = rest;
+        }
+
+        assert_eq!(
+            [
+                fixed_header::PacketType::Pingreq,
+                fixed_header::PacketType::Pingresp
+            ],
+            types[..]
+        );
+        assert_eq!([0xe0], bytes);
+    }
+
+    #[cfg(feature = "decode")]
+    #[test]
+    fn decode_variable_header_overrun() {
+        // remaining length 2 is shorter than the 5 byte topic name
+        let publish = [0x30, 0x02, 0x00, 0x03, b'a', b'/', b'b'];
+        assert_eq!(
+            DecodeError::InvalidLength,
+            Packet::decode(&publish).unwrap_err()
+        );
+    }
+
+    #[cfg(feature = "encode")]
     #[test]
     fn encode_subscribe() {
         let subscribe_id = 1;
         let sub = Packet::subscribe(
-            variable_header::packet_identifier::PacketIdentifier::new(subscribe_id),
+            variable_header::packet_identifier::PacketIdentifier::new(
+                variable_header::PacketId::new(subscribe_id),
+            ),
             payload::subscribe::Subscribe::new(&[
                 ("c/a", qos::QoS::AtMostOnce),
                 ("c/b", qos::QoS::AtLeastOnce),
@@ -287,10 +2758,18 @@ mod tests {
         assert_eq!(
             2,
             sub.variable_header()
-                .as_ref()
                 .expect("variable header")
                 .encoded_len()
         );
         assert_eq!(18, sub.payload().encoded_len());
+
+        assert_eq!(
+            Ok(sub.encoded_len()),
+            subscribe_encoded_len(&[
+                ("c/a", qos::QoS::AtMostOnce),
+                ("c/b", qos::QoS::AtLeastOnce),
+                ("c/c", qos::QoS::ExactlyOnce),
+            ])
+        );
     }
 }
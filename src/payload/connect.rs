@@ -1,20 +1,33 @@
 #![allow(warnings)]
 
-use core::result::Result;
+use core::{fmt, result::Result};
+#[cfg(feature = "zeroize")]
+use core::{ptr, sync::atomic};
 
 use crate::{
     codec::{self, Decodable, Encodable},
+    debug::Preview,
     error::{DecodeError, EncodeError},
     status::Status,
     variable_header::connect::Flags,
 };
 
-#[derive(Debug)]
+#[derive(Clone, Copy)]
 pub struct Will<'buf> {
     topic: &'buf str,
     message: &'buf [u8],
 }
 
+impl<'buf> fmt::Debug for Will<'buf> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Will")
+            .field("topic", &Preview(self.topic))
+            .field("message", &Preview(self.message))
+            .finish()
+    }
+}
+
+#[cfg(feature = "decode")]
 impl<'buf> Decodable<'buf> for Will<'buf> {
     fn decode(bytes: &'buf [u8]) -> Result<Status<(usize, Will<'buf>)>, DecodeError> {
         let offset = 0;
@@ -25,6 +38,7 @@ impl<'buf> Decodable<'buf> for Will<'buf> {
     }
 }
 
+#[cfg(feature = "encode")]
 impl<'buf> Encodable for Will<'buf> {
     fn encoded_len(&self) -> usize {
         2 + self.topic.len() + 2 + self.message.len()
@@ -39,8 +53,122 @@ impl<'buf> Encodable for Will<'buf> {
 }
 
 impl<'buf> Will<'buf> {
-    pub fn new(topic: &'buf str, message: &'buf [u8]) -> Self {
-        Will { topic, message }
+    pub fn new<T, M>(topic: &'buf T, message: &'buf M) -> Self
+    where
+        T: AsRef<str> + ?Sized,
+        M: AsRef<[u8]> + ?Sized,
+    {
+        Will {
+            topic: topic.as_ref(),
+            message: message.as_ref(),
+        }
+    }
+
+    /// Create a will with a text message, such as `"offline"`.
+    ///
+    /// The will QoS and retain flag are set in the `will` field of the CONNECT
+    /// `Flags`.
+    pub fn new_str<T, M>(topic: &'buf T, message: &'buf M) -> Self
+    where
+        T: AsRef<str> + ?Sized,
+        M: AsRef<str> + ?Sized,
+    {
+        Will {
+            topic: topic.as_ref(),
+            message: message.as_ref().as_bytes(),
+        }
+    }
+
+    pub fn topic(&self) -> &'buf str {
+        self.topic
+    }
+
+    pub fn message(&self) -> &'buf [u8] {
+        self.message
+    }
+
+    /// The message as text, failing if it is not valid UTF-8.
+    pub fn message_str(&self) -> Result<&'buf str, DecodeError> {
+        Ok(core::str::from_utf8(self.message)?)
+    }
+}
+
+/// Credential bytes that are redacted from `Debug` and `Display` output.
+///
+/// The wrapped slice is borrowed, with the `zeroize` feature a `SecretBuf`
+/// can own the credential buffer and overwrite it once the packet is encoded.
+#[derive(PartialEq, Eq, Clone, Copy)]
+pub struct SecretBytes<'buf>(&'buf [u8]);
+
+impl<'buf> SecretBytes<'buf> {
+    pub fn new<S: AsRef<[u8]> + ?Sized>(secret: &'buf S) -> Self {
+        SecretBytes(secret.as_ref())
+    }
+
+    /// Wrap a byte slice, usable in constants where `new` is not.
+    pub const fn from_slice(secret: &'buf [u8]) -> Self {
+        SecretBytes(secret)
+    }
+
+    /// Return the underlying bytes, take care not to log them.
+    pub const fn expose(&self) -> &'buf [u8] {
+        self.0
+    }
+}
+
+impl<'buf> From<&'buf [u8]> for SecretBytes<'buf> {
+    fn from(secret: &'buf [u8]) -> Self {
+        SecretBytes(secret)
+    }
+}
+
+impl<'buf> fmt::Debug for SecretBytes<'buf> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("SecretBytes(<redacted>)")
+    }
+}
+
+impl<'buf> fmt::Display for SecretBytes<'buf> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("<redacted>")
+    }
+}
+
+/// A mutable credential buffer that is overwritten with zeroes when dropped,
+/// enabled with the `zeroize` feature.
+///
+/// Borrow it as `SecretBytes` with `secret` to build the CONNECT payload, the
+/// borrow ends before the buffer is dropped.
+#[cfg(feature = "zeroize")]
+pub struct SecretBuf<'buf>(&'buf mut [u8]);
+
+#[cfg(feature = "zeroize")]
+impl<'buf> SecretBuf<'buf> {
+    pub fn new(secret: &'buf mut [u8]) -> Self {
+        SecretBuf(secret)
+    }
+
+    pub fn secret(&self) -> SecretBytes<'_> {
+        SecretBytes(self.0)
+    }
+}
+
+#[cfg(feature = "zeroize")]
+impl<'buf> Drop for SecretBuf<'buf> {
+    fn drop(&mut self) {
+        for byte in self.0.iter_mut() {
+            // volatile so the writes to a buffer about to go out of scope are
+            // not optimised away
+            unsafe { ptr::write_volatile(byte, 0) };
+        }
+        atomic::compiler_fence(atomic::Ordering::SeqCst);
+    }
+}
+
+#[cfg(feature = "zeroize")]
+impl<'buf> fmt::Debug for SecretBuf<'buf> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("SecretBuf(<redacted>)")
     }
 }
 
@@ -49,48 +177,65 @@ pub struct Connect<'buf> {
     client_id: &'buf str,
     will: Option<Will<'buf>>,
     username: Option<&'buf str>,
-    password: Option<&'buf [u8]>,
+    password: Option<SecretBytes<'buf>>,
 }
 
 impl<'buf> Connect<'buf> {
-    pub fn new(
-        client_id: &'buf str,
+    pub fn new<C: AsRef<str> + ?Sized>(
+        client_id: &'buf C,
         will: Option<Will<'buf>>,
         username: Option<&'buf str>,
-        password: Option<&'buf [u8]>,
+        password: Option<SecretBytes<'buf>>,
     ) -> Self {
         Connect {
-            client_id,
+            client_id: client_id.as_ref(),
             will,
             username,
             password,
         }
     }
+
+    pub fn client_id(&self) -> &'buf str {
+        self.client_id
+    }
+
+    pub fn will(&self) -> Option<&Will<'buf>> {
+        self.will.as_ref()
+    }
+
+    pub fn username(&self) -> Option<&'buf str> {
+        self.username
+    }
+
+    pub fn password(&self) -> Option<SecretBytes<'buf>> {
+        self.password
+    }
 }
 
 impl<'buf> Connect<'buf> {
+    #[cfg(feature = "decode")]
     pub fn decode(flags: Flags, bytes: &'buf [u8]) -> Result<Status<(usize, Self)>, DecodeError> {
         let offset = 0;
 
         let (offset, client_id) = read!(codec::string::parse_string, bytes, offset);
 
-        let (offset, will) = if flags.has_will() {
+        let (offset, will) = if flags.will.is_some() {
             let (offset, will) = read!(Will::decode, bytes, offset);
             (offset, Some(will))
         } else {
             (offset, None)
         };
 
-        let (offset, username) = if flags.has_username() {
+        let (offset, username) = if flags.has_username {
             let (offset, username) = read!(codec::string::parse_string, bytes, offset);
             (offset, Some(username))
         } else {
             (offset, None)
         };
 
-        let (offset, password) = if flags.has_password() {
+        let (offset, password) = if flags.has_password {
             let (offset, password) = read!(codec::values::parse_bytes, bytes, offset);
-            (offset, Some(bytes))
+            (offset, Some(SecretBytes(password)))
         } else {
             (offset, None)
         };
@@ -107,12 +252,13 @@ impl<'buf> Connect<'buf> {
     }
 }
 
+#[cfg(feature = "encode")]
 impl<'buf> Encodable for Connect<'buf> {
     fn encoded_len(&self) -> usize {
         self.client_id.encoded_len()
             + self.will.as_ref().map(|w| w.encoded_len()).unwrap_or(0)
             + self.username.as_ref().map(|u| u.encoded_len()).unwrap_or(0)
-            + self.password.as_ref().map(|p| p.encoded_len()).unwrap_or(0)
+            + self.password.map(|p| p.0.encoded_len()).unwrap_or(0)
     }
 
     fn encode(&self, bytes: &mut [u8]) -> Result<usize, EncodeError> {
@@ -129,9 +275,75 @@ impl<'buf> Encodable for Connect<'buf> {
         }
 
         if let Some(password) = self.password {
-            offset += codec::values::encode_bytes(password, &mut bytes[offset..])?;
+            offset += codec::values::encode_bytes(password.0, &mut bytes[offset..])?;
         }
 
         Ok(offset)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::format;
+
+    #[test]
+    fn password_redacted() {
+        let password = b"hunter2";
+        let connect = Connect::new(
+            "client",
+            None,
+            Some("user"),
+            Some(SecretBytes::new(&password[..])),
+        );
+
+        let debug = format!("{:?}", connect);
+        assert!(!debug.contains("104, 117, 110, 116, 101, 114, 50"));
+        assert!(debug.contains("<redacted>"));
+
+        let secret = connect.password().expect("password");
+        assert_eq!(format!("{}", secret), "<redacted>");
+        assert_eq!(secret.expose(), &password[..]);
+    }
+
+    #[cfg(feature = "zeroize")]
+    #[test]
+    fn zeroize() {
+        let mut password = *b"hunter2";
+        {
+            let secret = SecretBuf::new(&mut password);
+            assert_eq!(b"hunter2", secret.secret().expose());
+            assert_eq!("SecretBuf(<redacted>)", format!("{:?}", secret));
+        }
+        assert_eq!([0u8; 7], password);
+    }
+
+    #[test]
+    fn will_str() {
+        let will = Will::new_str("status", "offline");
+        assert_eq!("status", will.topic());
+        assert_eq!(b"offline", will.message());
+        assert_eq!(Ok("offline"), will.message_str());
+
+        let will = Will::new("status", &[0xff]);
+        assert_eq!(Err(DecodeError::Utf8), will.message_str());
+    }
+
+    #[cfg(feature = "decode")]
+    #[test]
+    fn decode_password() {
+        let flags = Flags {
+            has_password: true,
+            ..Default::default()
+        };
+
+        let bytes = [0, 1, 0x61, 0, 2, 0xde, 0xad];
+        let (offset, connect) = Connect::decode(flags, &bytes).unwrap().unwrap();
+        assert_eq!(offset, 7);
+        assert_eq!(connect.client_id(), "a");
+        assert_eq!(
+            connect.password().map(|p| p.expose()),
+            Some(&[0xde, 0xad][..])
+        );
+    }
+}
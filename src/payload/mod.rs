@@ -1,25 +1,92 @@
-use core::{default::Default, result::Result};
+#[cfg(any(feature = "encode", feature = "decode"))]
+use core::result::Result;
+use core::{default::Default, fmt};
 
-use crate::{
-    codec::{Decodable, Encodable},
-    error::{DecodeError, EncodeError},
-    fixed_header::PacketType,
-    status::Status,
-};
+use crate::debug::Preview;
+
+#[cfg(feature = "decode")]
+use crate::{codec::Decodable, error::DecodeError, fixed_header::PacketType, status::Status};
+#[cfg(feature = "encode")]
+use crate::{codec::Encodable, error::EncodeError};
 
 pub mod connect;
 pub mod suback;
 pub mod subscribe;
+pub mod unsubscribe;
 
-#[derive(Debug)]
+/// The payload of a packet.
+///
+/// `Debug` prints a truncated preview of long `Bytes` with their length.
 pub enum Payload<'a> {
     Bytes(&'a [u8]),
     Connect(connect::Connect<'a>),
     Subscribe(subscribe::Subscribe<'a>),
     Suback(suback::Suback<'a>),
+    Unsubscribe(unsubscribe::Unsubscribe<'a>),
+}
+
+impl<'a> fmt::Debug for Payload<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Payload::Bytes(bytes) => f.debug_tuple("Bytes").field(&Preview(*bytes)).finish(),
+            Payload::Connect(connect) => f.debug_tuple("Connect").field(connect).finish(),
+            Payload::Subscribe(subscribe) => f.debug_tuple("Subscribe").field(subscribe).finish(),
+            Payload::Suback(suback) => f.debug_tuple("Suback").field(suback).finish(),
+            Payload::Unsubscribe(unsubscribe) => {
+                f.debug_tuple("Unsubscribe").field(unsubscribe).finish()
+            }
+        }
+    }
 }
 
 impl<'a> Payload<'a> {
+    pub fn as_bytes(&self) -> Option<&'a [u8]> {
+        match self {
+            Payload::Bytes(bytes) => Some(bytes),
+            _ => None,
+        }
+    }
+
+    pub fn as_connect(&self) -> Option<&connect::Connect<'a>> {
+        match self {
+            Payload::Connect(connect) => Some(connect),
+            _ => None,
+        }
+    }
+
+    pub fn as_subscribe(&self) -> Option<&subscribe::Subscribe<'a>> {
+        match self {
+            Payload::Subscribe(subscribe) => Some(subscribe),
+            _ => None,
+        }
+    }
+
+    pub fn as_suback(&self) -> Option<&suback::Suback<'a>> {
+        match self {
+            Payload::Suback(suback) => Some(suback),
+            _ => None,
+        }
+    }
+
+    pub fn as_unsubscribe(&self) -> Option<&unsubscribe::Unsubscribe<'a>> {
+        match self {
+            Payload::Unsubscribe(unsubscribe) => Some(unsubscribe),
+            _ => None,
+        }
+    }
+
+    /// The number of bytes the payload occupies in an encoded packet.
+    #[cfg(feature = "encode")]
+    pub fn len(&self) -> usize {
+        self.encoded_len()
+    }
+
+    #[cfg(feature = "encode")]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    #[cfg(feature = "decode")]
     pub fn decode(
         r#type: PacketType,
         bytes: &'a [u8],
@@ -41,17 +108,26 @@ impl<'a> Payload<'a> {
                     Ok(Status::Complete((offset, Payload::Subscribe(p))))
                 }
             },
+            PacketType::Unsubscribe => match unsubscribe::Unsubscribe::decode(bytes) {
+                Err(e) => Err(e),
+                Ok(Status::Partial(p)) => Ok(Status::Partial(p)),
+                Ok(Status::Complete((offset, p))) => {
+                    Ok(Status::Complete((offset, Payload::Unsubscribe(p))))
+                }
+            },
             _ => return None,
         })
     }
 }
 
+#[cfg(feature = "encode")]
 impl<'a> Encodable for Payload<'a> {
     fn encoded_len(&self) -> usize {
         match self {
             Payload::Connect(ref c) => c.encoded_len(),
             Payload::Subscribe(ref c) => c.encoded_len(),
             Payload::Suback(ref c) => c.encoded_len(),
+            Payload::Unsubscribe(ref c) => c.encoded_len(),
             Payload::Bytes(c) => c.len(),
         }
     }
@@ -61,12 +137,13 @@ impl<'a> Encodable for Payload<'a> {
             Payload::Connect(ref c) => c.encode(bytes),
             Payload::Subscribe(ref c) => c.encode(bytes),
             Payload::Suback(ref c) => c.encode(bytes),
+            Payload::Unsubscribe(ref c) => c.encode(bytes),
             Payload::Bytes(c) => {
                 if bytes.len() < c.len() {
                     return Err(EncodeError::OutOfSpace);
                 }
 
-                (&mut bytes[0..c.len()]).copy_from_slice(c);
+                bytes[0..c.len()].copy_from_slice(c);
 
                 Ok(c.len())
             }
@@ -79,3 +156,21 @@ impl<'a> Default for Payload<'a> {
         Payload::Bytes(&[])
     }
 }
+
+#[cfg(all(test, feature = "encode"))]
+mod tests {
+    use super::*;
+    use crate::qos::QoS;
+
+    #[test]
+    fn len() {
+        assert!(Payload::default().is_empty());
+        assert_eq!(2, Payload::Bytes(b"{}").len());
+
+        let topics = [("a/b", QoS::AtLeastOnce)];
+        let subscribe = Payload::Subscribe(subscribe::Subscribe::new(&topics));
+        assert_eq!(6, subscribe.len());
+        assert_eq!(None, subscribe.as_bytes());
+        assert!(subscribe.as_subscribe().is_some());
+    }
+}
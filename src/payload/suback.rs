@@ -1,19 +1,23 @@
 use core::{
     convert::{From, TryFrom, TryInto},
-    fmt::Debug,
     result::Result,
+    slice,
 };
 
 use crate::{
-    codec::{Decodable, Encodable},
     error::{DecodeError, EncodeError},
     qos,
-    status::Status,
 };
 
+#[cfg(feature = "encode")]
+use crate::codec::Encodable;
+#[cfg(feature = "decode")]
+use crate::{codec::Decodable, status::Status};
+
 use bitfield::BitRange;
 
 #[derive(PartialEq, Eq, Clone, Copy)]
+#[repr(transparent)]
 pub struct ReturnCode(u8);
 
 bitfield_bitrange! {
@@ -36,20 +40,31 @@ impl ReturnCode {
         qos_bits.try_into()
     }
 
-    #[allow(dead_code)]
     pub fn set_max_qos(&mut self, qos: qos::QoS) {
         self.set_bit_range(1, 0, u8::from(qos))
     }
+
+    /// The maximum QoS granted, `None` if the subscription failed.
+    pub fn granted_qos(&self) -> Option<qos::QoS> {
+        if self.failure() {
+            return None;
+        }
+
+        self.max_qos().ok()
+    }
 }
 
-impl Debug for ReturnCode {
-    bitfield_debug! {
-        struct ReturnCode;
-        pub failure, _ : 7;
-        pub into QoS, max_qos, _ : 1, 0;
+impl From<qos::QoS> for ReturnCode {
+    fn from(qos: qos::QoS) -> Self {
+        ReturnCode(u8::from(qos))
     }
 }
 
+flags_debug!(ReturnCode {
+    pub failure, _ : 7;
+    pub into QoS, max_qos, _ : 1, 0;
+});
+
 impl From<ReturnCode> for u8 {
     fn from(val: ReturnCode) -> u8 {
         val.0
@@ -74,50 +89,152 @@ impl TryFrom<u8> for ReturnCode {
     }
 }
 
-#[derive(PartialEq, Eq, Debug)]
-pub struct Suback<'a> {
-    return_codes: &'a [ReturnCode],
+/// A subscription refused by the server, answered with the failure return
+/// code.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct Denied;
+
+impl From<Result<qos::QoS, Denied>> for ReturnCode {
+    fn from(grant: Result<qos::QoS, Denied>) -> Self {
+        match grant {
+            Ok(granted) => ReturnCode::from(granted),
+            Err(Denied) => ReturnCode::FAILURE,
+        }
+    }
+}
+
+/// The return codes of a SUBACK, one per topic filter in the SUBSCRIBE.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Suback<'a> {
+    /// Return codes built with `new` or validated up front by `decode`
+    Validated(&'a [ReturnCode]),
+    /// Payload bytes taken by `decode_lazy`, each return code is validated as
+    /// `lazy_return_codes` yields it
+    Lazy(&'a [u8]),
 }
 
 impl<'a> Suback<'a> {
     pub fn new(return_codes: &'a [ReturnCode]) -> Self {
-        Self { return_codes }
+        Suback::Validated(return_codes)
+    }
+
+    /// Write the return code for each grant decision, the maximum QoS granted
+    /// or `Denied`, to `return_codes` and return the SUBACK payload of them.
+    ///
+    /// Returns `Err(EncodeError::OutOfSpace)` if there are more grants than
+    /// `return_codes` can hold.
+    pub fn from_grants<I>(
+        grants: I,
+        return_codes: &'a mut [ReturnCode],
+    ) -> Result<Self, EncodeError>
+    where
+        I: IntoIterator<Item = Result<qos::QoS, Denied>>,
+    {
+        let mut len = 0;
+        for grant in grants {
+            *return_codes.get_mut(len).ok_or(EncodeError::OutOfSpace)? = ReturnCode::from(grant);
+            len += 1;
+        }
+
+        let return_codes: &'a [ReturnCode] = return_codes;
+        Ok(Self::new(&return_codes[..len]))
+    }
+
+    /// Take the payload bytes without validating the return codes.
+    #[cfg(feature = "decode")]
+    pub fn decode_lazy(bytes: &'a [u8]) -> Self {
+        Suback::Lazy(bytes)
+    }
+
+    /// The return codes, `None` if the SUBACK was taken by `decode_lazy`.
+    pub fn return_codes(&self) -> Option<&'a [ReturnCode]> {
+        match *self {
+            Suback::Validated(return_codes) => Some(return_codes),
+            Suback::Lazy(_) => None,
+        }
+    }
+
+    /// The return codes of a SUBACK taken by `decode_lazy`, validated as they
+    /// are yielded. `None` for validated return codes, see `return_codes`.
+    pub fn lazy_return_codes(&self) -> Option<ReturnCodes<'a>> {
+        match *self {
+            Suback::Validated(_) => None,
+            Suback::Lazy(bytes) => Some(ReturnCodes(bytes.iter())),
+        }
+    }
+
+    /// The number of return codes.
+    pub fn len(&self) -> usize {
+        self.as_bytes().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn as_bytes(&self) -> &'a [u8] {
+        match *self {
+            // ReturnCode is a transparent wrapper of a u8
+            Suback::Validated(return_codes) => unsafe {
+                &*(return_codes as *const [ReturnCode] as *const [u8])
+            },
+            Suback::Lazy(bytes) => bytes,
+        }
+    }
+}
+
+/// Iterator over the return codes of a SUBACK, validating each one as it is
+/// yielded.
+#[derive(Clone, Debug)]
+pub struct ReturnCodes<'a>(slice::Iter<'a, u8>);
+
+impl<'a> Iterator for ReturnCodes<'a> {
+    type Item = Result<ReturnCode, DecodeError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.0.next().map(|byte| {
+            ReturnCode::try_from(*byte).map_err(|_| DecodeError::InvalidSubackReturnCode)
+        })
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.0.size_hint()
     }
 }
 
+impl<'a> ExactSizeIterator for ReturnCodes<'a> {}
+
+#[cfg(feature = "decode")]
 impl<'a> Decodable<'a> for Suback<'a> {
     fn decode(bytes: &'a [u8]) -> Result<Status<(usize, Self)>, DecodeError> {
         // Check all the bytes are valid return codes
-        bytes
-            .iter()
-            .fold(Ok(()), |acc, byte| {
-                acc?;
-                ReturnCode::try_from(*byte).map(|_| ())
-            })
-            .map_err(|_| DecodeError::InvalidSubackReturnCode)?;
+        ReturnCodes(bytes.iter()).try_for_each(|return_code| return_code.map(|_| ()))?;
 
+        // ReturnCode is a transparent wrapper of a u8
         let return_codes = unsafe { &*(bytes as *const [u8] as *const [ReturnCode]) };
 
-        Ok(Status::Complete((bytes.len(), Self { return_codes })))
+        Ok(Status::Complete((
+            bytes.len(),
+            Suback::Validated(return_codes),
+        )))
     }
 }
 
+#[cfg(feature = "encode")]
 impl<'a> Encodable for Suback<'a> {
     fn encoded_len(&self) -> usize {
-        self.return_codes.len()
+        self.len()
     }
 
     fn encode(&self, bytes: &mut [u8]) -> Result<usize, EncodeError> {
-        if bytes.len() < self.return_codes.len() {
+        let encoded = self.as_bytes();
+        if bytes.len() < encoded.len() {
             return Err(EncodeError::OutOfSpace);
         }
 
-        let return_code_bytes =
-            unsafe { &*(self.return_codes as *const [ReturnCode] as *const [u8]) };
+        bytes[..encoded.len()].copy_from_slice(encoded);
 
-        (&mut bytes[..self.return_codes.len()]).copy_from_slice(return_code_bytes);
-
-        Ok(self.return_codes.len())
+        Ok(encoded.len())
     }
 }
 
@@ -125,6 +242,7 @@ impl<'a> Encodable for Suback<'a> {
 mod tests {
     use super::*;
 
+    #[cfg(feature = "encode")]
     #[test]
     fn encode() {
         let return_codes = [ReturnCode::SUCCESS_QOS_0];
@@ -136,6 +254,48 @@ mod tests {
         assert_eq!(buf, [0b0000_0000]);
     }
 
+    #[test]
+    fn from_grants() {
+        let grants = [
+            Ok(qos::QoS::AtLeastOnce),
+            Err(Denied),
+            Ok(qos::QoS::ExactlyOnce),
+        ];
+
+        let mut return_codes = [ReturnCode::FAILURE; 4];
+        let suback = Suback::from_grants(grants.iter().copied(), &mut return_codes).unwrap();
+        assert_eq!(
+            Some(
+                &[
+                    ReturnCode::SUCCESS_QOS_1,
+                    ReturnCode::FAILURE,
+                    ReturnCode::SUCCESS_QOS_2,
+                ][..]
+            ),
+            suback.return_codes()
+        );
+
+        let mut return_codes = [ReturnCode::FAILURE; 2];
+        assert_eq!(
+            Err(EncodeError::OutOfSpace),
+            Suback::from_grants(grants.iter().copied(), &mut return_codes)
+        );
+    }
+
+    #[test]
+    fn granted_qos() {
+        assert_eq!(
+            Some(qos::QoS::ExactlyOnce),
+            ReturnCode::SUCCESS_QOS_2.granted_qos()
+        );
+        assert_eq!(None, ReturnCode::FAILURE.granted_qos());
+        assert_eq!(
+            ReturnCode::SUCCESS_QOS_1,
+            ReturnCode::from(qos::QoS::AtLeastOnce)
+        );
+    }
+
+    #[cfg(feature = "decode")]
     #[test]
     fn decode() {
         let return_code_bytes = [0b1000_0000, 0b0000_0010, 0b0000_0001, 0b0000_0000];
@@ -152,5 +312,31 @@ mod tests {
             payload,
             Ok(Status::Complete((4, Suback::new(&return_codes[..]))))
         );
+        let (_, payload) = payload.unwrap().unwrap();
+        assert_eq!(Some(&return_codes[..]), payload.return_codes());
+        assert!(payload.lazy_return_codes().is_none());
+    }
+
+    #[cfg(feature = "decode")]
+    #[test]
+    fn decode_lazy() {
+        let return_code_bytes = [0b0000_0001, 0b0100_0000, 0b1000_0000];
+
+        assert_eq!(
+            Err(DecodeError::InvalidSubackReturnCode),
+            Suback::decode(&return_code_bytes[..])
+        );
+
+        let payload = Suback::decode_lazy(&return_code_bytes[..]);
+        assert_eq!(None, payload.return_codes());
+        let mut return_codes = payload.lazy_return_codes().unwrap();
+        assert_eq!(3, return_codes.len());
+        assert_eq!(Some(Ok(ReturnCode::SUCCESS_QOS_1)), return_codes.next());
+        assert_eq!(
+            Some(Err(DecodeError::InvalidSubackReturnCode)),
+            return_codes.next()
+        );
+        assert_eq!(Some(Ok(ReturnCode::FAILURE)), return_codes.next());
+        assert_eq!(None, return_codes.next());
     }
 }
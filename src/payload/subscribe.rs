@@ -1,18 +1,23 @@
-use core::{
-    convert::{From, TryFrom},
-    fmt,
-    iter::Iterator,
-    result::Result,
-};
+use core::{cmp::min, convert::TryFrom, fmt, iter::Iterator, result::Result};
+
+use crate::{codec, debug::Preview, error::DecodeError, qos, status::Status};
 
+#[cfg(feature = "decode")]
 use crate::{
-    codec::{self, Decodable, Encodable},
-    error::{DecodeError, EncodeError},
-    qos,
-    status::Status,
+    codec::Decodable,
+    error::{SubscriptionError, SubscriptionErrorKind},
 };
-
+#[cfg(feature = "encode")]
+use crate::{codec::Encodable, error::EncodeError};
+
+/// Iterator over the topic filters and requested QoS of a SUBSCRIBE.
+///
+/// The size is exact for the encode form. Decoded topics are not counted, the
+/// size hint bounds them by the remaining bytes instead. `Subscribe::slice`
+/// gives the encode form's topics to iterate with an exact size and from both
+/// ends.
 pub struct Iter<'a> {
+    /// An index into the encode slice or a byte offset into the decode bytes
     offset: usize,
     sub: &'a Subscribe<'a>,
 }
@@ -28,18 +33,12 @@ impl<'a> Iterator for Iter<'a> {
     fn next(&mut self) -> Option<Self::Item> {
         match self.sub {
             Subscribe::Encode(topics) => {
-                // Offset is an index into the encode slice
-                if self.offset >= topics.len() {
-                    return None;
-                }
-
-                let item = topics[self.offset];
+                let item = *topics.get(self.offset)?;
                 self.offset += 1;
 
                 Some(item)
             }
             Subscribe::Decode(bytes) => {
-                // Offset is a byte offset in the byte slice
                 if self.offset >= bytes.len() {
                     return None;
                 }
@@ -54,8 +53,23 @@ impl<'a> Iterator for Iter<'a> {
             }
         }
     }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        match self.sub {
+            Subscribe::Encode(topics) => {
+                let remaining = topics.len().saturating_sub(self.offset);
+                (remaining, Some(remaining))
+            }
+            Subscribe::Decode(bytes) => {
+                let remaining = bytes.len().saturating_sub(self.offset);
+                // each entry is at least a length prefix and a QoS byte
+                (min(remaining, 1), Some(remaining / 3))
+            }
+        }
+    }
 }
 
+#[derive(Copy, Clone)]
 pub enum Subscribe<'a> {
     Encode(&'a [(&'a str, qos::QoS)]),
     Decode(&'a [u8]),
@@ -66,20 +80,80 @@ impl<'a> Subscribe<'a> {
         Subscribe::Encode(topics)
     }
 
-    pub fn topics(&self) -> Iter {
+    pub fn topics(&self) -> Iter<'_> {
         Iter::new(self)
     }
+
+    /// The topics of the encode form, `None` for decoded payload bytes which
+    /// can only be iterated forwards with `topics`.
+    pub fn slice(&self) -> Option<&'a [(&'a str, qos::QoS)]> {
+        match *self {
+            Subscribe::Encode(topics) => Some(topics),
+            Subscribe::Decode(_) => None,
+        }
+    }
+
+    /// The topic filters repeated within this SUBSCRIBE.
+    ///
+    /// Each repeat is reported with the index of the first occurrence of its
+    /// filter, a broker honouring the request keeps the last. Comparing every
+    /// pair of filters is quadratic, which is cheap for the handful of filters
+    /// a SUBSCRIBE usually carries.
+    pub fn duplicates(&self) -> Duplicates<'_> {
+        Duplicates {
+            topics: self.topics(),
+            index: 0,
+            sub: self,
+        }
+    }
+}
+
+/// A topic filter that appears more than once in a SUBSCRIBE.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct Duplicate {
+    /// The index of the first occurrence of the filter
+    pub first: usize,
+    /// The index of this occurrence
+    pub index: usize,
+}
+
+/// Iterator over the repeated topic filters of a SUBSCRIBE, in order.
+pub struct Duplicates<'a> {
+    topics: Iter<'a>,
+    index: usize,
+    sub: &'a Subscribe<'a>,
+}
+
+impl<'a> Iterator for Duplicates<'a> {
+    type Item = Duplicate;
+    fn next(&mut self) -> Option<Self::Item> {
+        for (filter, _) in &mut self.topics {
+            let index = self.index;
+            self.index += 1;
+
+            let first = self
+                .sub
+                .topics()
+                .take(index)
+                .position(|(earlier, _)| earlier == filter);
+            if let Some(first) = first {
+                return Some(Duplicate { first, index });
+            }
+        }
+
+        None
+    }
 }
 
 impl<'a> fmt::Debug for Subscribe<'a> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         writeln!(f, "Subscribe {{")?;
-        self.topics().fold(Ok(()), |acc, (topic, qos)| {
-            acc?;
+        self.topics().try_for_each(|(topic, qos)| {
             writeln!(
                 f,
                 "    (\n        Topic: {:#?},\n        QoS: {:#?}\n    )",
-                topic, qos
+                Preview(topic),
+                qos
             )
         })?;
         write!(f, "}}")?;
@@ -89,9 +163,7 @@ impl<'a> fmt::Debug for Subscribe<'a> {
 }
 
 #[allow(clippy::type_complexity)]
-fn parse_subscription(
-    bytes: &[u8],
-) -> Result<Status<(usize, (&str, qos::QoS))>, DecodeError> {
+fn parse_subscription(bytes: &[u8]) -> Result<Status<(usize, (&str, qos::QoS))>, DecodeError> {
     let offset = 0;
 
     let (offset, topic) = {
@@ -108,34 +180,80 @@ fn parse_subscription(
     Ok(Status::Complete((offset, (topic, qos))))
 }
 
+/// Check every entry of a topic filter list, each filter followed by its
+/// requested QoS if `with_qos`, returning the number of entries.
+///
+/// A malformed entry is reported as `DecodeError::Subscription` with its
+/// position in the list.
+#[cfg(feature = "decode")]
+pub(crate) fn check_entries(bytes: &[u8], with_qos: bool) -> Result<usize, DecodeError> {
+    let mut offset = 0;
+    let mut index = 0;
+    while offset < bytes.len() {
+        let entry = &bytes[offset..];
+        let parsed = if with_qos {
+            parse_subscription(entry).map(|status| status.map(|(o, _)| o))
+        } else {
+            codec::string::parse_string(entry).map(|status| status.map(|(o, _)| o))
+        };
+
+        let kind = match parsed {
+            Ok(Status::Complete(o)) => {
+                offset += o;
+                index += 1;
+                continue;
+            }
+            Ok(Status::Partial(..)) => SubscriptionErrorKind::Truncated,
+            Err(DecodeError::Utf8) => SubscriptionErrorKind::Utf8,
+            Err(DecodeError::InvalidQoS(err)) => SubscriptionErrorKind::InvalidQoS(err),
+            Err(e) => return Err(e),
+        };
+        return Err(DecodeError::Subscription(SubscriptionError {
+            index,
+            offset,
+            kind,
+        }));
+    }
+
+    Ok(index)
+}
+
+#[cfg(feature = "decode")]
 impl<'a> Decodable<'a> for Subscribe<'a> {
     fn decode(bytes: &'a [u8]) -> Result<Status<(usize, Self)>, DecodeError> {
-        let mut offset = 0;
-        while offset < bytes.len() {
-            let o = match parse_subscription(&bytes[offset..]) {
-                Err(e) => return Err(e),
-                Ok(Status::Partial(..)) => return Err(DecodeError::InvalidLength),
-                Ok(Status::Complete((o, _))) => o,
-            };
-            offset += o;
-        }
+        check_entries(bytes, true)?;
 
         Ok(Status::Complete((bytes.len(), Subscribe::Decode(bytes))))
     }
 }
 
+#[cfg(feature = "encode")]
 impl<'a> Encodable for Subscribe<'a> {
     fn encoded_len(&self) -> usize {
-        self.topics().map(|topic| topic.0.encoded_len() + 1).sum()
+        match self {
+            Subscribe::Encode(topics) => topics.iter().map(|topic| topic.0.encoded_len() + 1).sum(),
+            // Decoded bytes are already validated and encoded
+            Subscribe::Decode(bytes) => bytes.len(),
+        }
     }
 
     fn encode(&self, bytes: &mut [u8]) -> Result<usize, EncodeError> {
-        self.topics().fold(Ok(0), |acc, (topic, qos)| {
-            let mut offset = acc?;
-            offset += codec::string::encode_string(topic, &mut bytes[offset..])?;
-            offset += codec::values::encode_u8(u8::from(qos), &mut bytes[offset..])?;
-            Ok(offset)
-        })
+        match self {
+            Subscribe::Encode(topics) => topics.iter().try_fold(0, |mut offset, (topic, qos)| {
+                offset += codec::string::encode_string(topic, &mut bytes[offset..])?;
+                offset += codec::values::encode_u8(u8::from(*qos), &mut bytes[offset..])?;
+                Ok(offset)
+            }),
+            Subscribe::Decode(encoded) => {
+                if bytes.len() < encoded.len() {
+                    return Err(EncodeError::OutOfSpace);
+                }
+
+                bytes[..encoded.len()].copy_from_slice(encoded);
+
+                Ok(encoded.len())
+            }
+        }
     }
 }
 
@@ -154,9 +272,11 @@ mod tests {
         let sub = Subscribe::new(&topics);
 
         let mut iter = sub.topics();
+        assert_eq!((3, Some(3)), iter.size_hint());
 
         let next = iter.next();
         assert_eq!(next, Some(("a", qos::QoS::AtMostOnce)));
+        assert_eq!((2, Some(2)), iter.size_hint());
 
         let next = iter.next();
         assert_eq!(next, Some(("b", qos::QoS::AtLeastOnce)));
@@ -166,8 +286,10 @@ mod tests {
 
         let next = iter.next();
         assert_eq!(next, None);
+        assert_eq!((0, Some(0)), iter.size_hint());
     }
 
+    #[cfg(feature = "decode")]
     #[test]
     fn decode_bytes() {
         let bytes = [
@@ -188,9 +310,11 @@ mod tests {
         let (_, sub) = Subscribe::decode(&bytes).expect("valid").unwrap();
 
         let mut iter = sub.topics();
+        assert_eq!((1, Some(4)), iter.size_hint());
 
         let next = iter.next();
         assert_eq!(next, Some(("a", qos::QoS::AtMostOnce)));
+        assert_eq!((1, Some(2)), iter.size_hint());
 
         let next = iter.next();
         assert_eq!(next, Some(("b", qos::QoS::AtLeastOnce)));
@@ -200,8 +324,98 @@ mod tests {
 
         let next = iter.next();
         assert_eq!(next, None);
+        assert_eq!((0, Some(0)), iter.size_hint());
+    }
+
+    #[cfg(feature = "decode")]
+    #[test]
+    fn slice() {
+        let topics = [
+            ("a", qos::QoS::AtMostOnce),
+            ("bc", qos::QoS::AtLeastOnce),
+            ("d", qos::QoS::ExactlyOnce),
+        ];
+        let sub = Subscribe::new(&topics);
+        assert_eq!(Some(&topics[..]), sub.slice());
+        assert!(sub.topics().eq(topics.iter().copied()));
+
+        let bytes = [
+            0x00, 0x01, b'a', 0x00, 0x00, 0x02, b'b', b'c', 0x01, 0x00, 0x01, b'd', 0x02,
+        ];
+        let (_, decoded) = Subscribe::decode(&bytes).expect("valid").unwrap();
+        assert_eq!(None, decoded.slice());
+        assert!(decoded.topics().eq(topics.iter().copied()));
+    }
+
+    #[cfg(feature = "decode")]
+    #[test]
+    fn decode_errors() {
+        let entry = |index, offset, kind| {
+            Err(DecodeError::Subscription(SubscriptionError {
+                index,
+                offset,
+                kind,
+            }))
+        };
+
+        let bytes = [0x00, 0x01, b'a', 0x00, 0x00, 0x02, 0xc3, 0x28, 0x01];
+        assert_eq!(
+            entry(1, 4, SubscriptionErrorKind::Utf8),
+            Subscribe::decode(&bytes).map(|_| ())
+        );
+
+        let bytes = [0x00, 0x01, b'a', 0x00, 0x00, 0x01, b'b', 0x03];
+        assert_eq!(
+            entry(
+                1,
+                4,
+                SubscriptionErrorKind::InvalidQoS(qos::Error::BadPattern)
+            ),
+            Subscribe::decode(&bytes).map(|_| ())
+        );
+
+        let bytes = [
+            0x00, 0x01, b'a', 0x00, 0x00, 0x01, b'b', 0x01, 0x00, 0x05, b'c',
+        ];
+        let err = Subscribe::decode(&bytes).map(|_| ()).unwrap_err();
+        assert_eq!(entry(2, 8, SubscriptionErrorKind::Truncated), Err(err));
+        #[cfg(not(feature = "tiny"))]
+        assert_eq!(
+            "truncated topic filter entry at index 2, offset 8",
+            std::format!("{}", err)
+        );
     }
 
+    #[cfg(feature = "decode")]
+    #[test]
+    fn duplicates() {
+        let topics = [
+            ("a/b", qos::QoS::AtMostOnce),
+            ("a/+", qos::QoS::AtLeastOnce),
+            ("a/b", qos::QoS::ExactlyOnce),
+            ("c", qos::QoS::AtMostOnce),
+            ("a/b", qos::QoS::AtLeastOnce),
+        ];
+        let sub = Subscribe::new(&topics);
+        let mut duplicates = sub.duplicates();
+        assert_eq!(Some(Duplicate { first: 0, index: 2 }), duplicates.next());
+        assert_eq!(Some(Duplicate { first: 0, index: 4 }), duplicates.next());
+        assert_eq!(None, duplicates.next());
+
+        let bytes = [
+            0x00, 0x01, b'a', 0x00, 0x00, 0x01, b'b', 0x01, 0x00, 0x01, b'b', 0x02,
+        ];
+        let (_, sub) = Subscribe::decode(&bytes).expect("valid").unwrap();
+        assert_eq!(
+            vec![Duplicate { first: 1, index: 2 }],
+            sub.duplicates().collect::<std::vec::Vec<_>>()
+        );
+
+        let sub = Subscribe::new(&topics[..2]);
+        assert_eq!(0, sub.duplicates().count());
+    }
+
+    #[cfg(feature = "decode")]
     #[test]
     fn decode_bytes_error() {
         let bytes = [
@@ -224,6 +438,26 @@ mod tests {
 
         let sub = Subscribe::decode(&bytes);
         assert!(sub.is_err());
-        assert_eq!(sub.unwrap_err(), DecodeError::InvalidLength);
+        assert_eq!(
+            sub.unwrap_err(),
+            DecodeError::Subscription(SubscriptionError {
+                index: 2,
+                offset: 8,
+                kind: SubscriptionErrorKind::Truncated,
+            })
+        );
+    }
+
+    #[cfg(all(feature = "encode", feature = "decode"))]
+    #[test]
+    fn encode_decoded() {
+        let bytes = [0x00, 0x01, b'a', 0x00, 0x00, 0x01, b'b', 0x02];
+        let (_, sub) = Subscribe::decode(&bytes).expect("valid").unwrap();
+        assert_eq!(bytes.len(), sub.encoded_len());
+
+        let mut buf = [0u8; 8];
+        assert_eq!(Ok(8), sub.encode(&mut buf));
+        assert_eq!(bytes, buf);
+        assert_eq!(Err(EncodeError::OutOfSpace), sub.encode(&mut buf[..7]));
     }
 }
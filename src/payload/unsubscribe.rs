@@ -0,0 +1,202 @@
+#[cfg(any(feature = "encode", feature = "decode"))]
+use core::result::Result;
+use core::{fmt, iter::Iterator, slice};
+
+use crate::{codec, debug::Preview};
+
+#[cfg(feature = "decode")]
+use crate::{codec::Decodable, error::DecodeError, status::Status};
+#[cfg(feature = "encode")]
+use crate::{codec::Encodable, error::EncodeError};
+
+use super::subscribe::{self, Subscribe};
+
+/// The topic filters of an UNSUBSCRIBE.
+///
+/// A decoded UNSUBSCRIBE borrows its filter list from the receive buffer, the
+/// filters are validated by `decode` and only sliced out as `filters` yields
+/// them.
+#[derive(Copy, Clone)]
+pub enum Unsubscribe<'a> {
+    Filters(&'a [&'a str]),
+    /// The filters of an earlier SUBSCRIBE, requested QoS ignored
+    Subscribe(&'a Subscribe<'a>),
+    Decode(&'a [u8]),
+}
+
+impl<'a> Unsubscribe<'a> {
+    pub fn new(filters: &'a [&'a str]) -> Self {
+        Unsubscribe::Filters(filters)
+    }
+
+    /// Unsubscribe from every topic filter of an earlier SUBSCRIBE, encoded
+    /// or decoded, so teardown needs no second list of filters.
+    pub fn from_subscribe(subscribe: &'a Subscribe<'a>) -> Self {
+        Unsubscribe::Subscribe(subscribe)
+    }
+
+    /// The topic filters in order.
+    pub fn filters(&self) -> Filters<'a> {
+        Filters(match *self {
+            Unsubscribe::Filters(filters) => FiltersInner::Filters(filters.iter()),
+            Unsubscribe::Subscribe(subscribe) => FiltersInner::Subscribe(subscribe.topics()),
+            Unsubscribe::Decode(bytes) => FiltersInner::Decode(bytes),
+        })
+    }
+}
+
+/// Iterator over the topic filters of an UNSUBSCRIBE.
+pub struct Filters<'a>(FiltersInner<'a>);
+
+enum FiltersInner<'a> {
+    Filters(slice::Iter<'a, &'a str>),
+    Subscribe(subscribe::Iter<'a>),
+    /// The filters not yet yielded
+    Decode(&'a [u8]),
+}
+
+impl<'a> Iterator for Filters<'a> {
+    type Item = &'a str;
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.0 {
+            FiltersInner::Filters(ref mut filters) => filters.next().copied(),
+            FiltersInner::Subscribe(ref mut topics) => topics.next().map(|(filter, _)| filter),
+            FiltersInner::Decode(ref mut bytes) => {
+                if bytes.is_empty() {
+                    return None;
+                }
+
+                let (offset, filter) = codec::string::parse_string(bytes)
+                    .expect("already validated")
+                    .unwrap();
+                *bytes = &bytes[offset..];
+
+                Some(filter)
+            }
+        }
+    }
+}
+
+/// Check the topic filters of UNSUBSCRIBE payload bytes, returning how many
+/// there are.
+///
+/// A malformed filter is reported as `DecodeError::Subscription` with its
+/// position.
+#[cfg(feature = "decode")]
+pub fn check_filters(payload: &[u8]) -> Result<usize, DecodeError> {
+    subscribe::check_entries(payload, false)
+}
+
+impl<'a> From<&'a Subscribe<'a>> for Unsubscribe<'a> {
+    fn from(subscribe: &'a Subscribe<'a>) -> Self {
+        Self::from_subscribe(subscribe)
+    }
+}
+
+impl<'a> fmt::Debug for Unsubscribe<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_list().entries(self.filters().map(Preview)).finish()
+    }
+}
+
+#[cfg(feature = "decode")]
+impl<'a> Decodable<'a> for Unsubscribe<'a> {
+    fn decode(bytes: &'a [u8]) -> Result<Status<(usize, Self)>, DecodeError> {
+        subscribe::check_entries(bytes, false)?;
+
+        Ok(Status::Complete((bytes.len(), Unsubscribe::Decode(bytes))))
+    }
+}
+
+#[cfg(feature = "encode")]
+impl<'a> Encodable for Unsubscribe<'a> {
+    fn encoded_len(&self) -> usize {
+        match self {
+            // Decoded bytes are already validated and encoded
+            Unsubscribe::Decode(bytes) => bytes.len(),
+            _ => self.filters().map(|filter| filter.encoded_len()).sum(),
+        }
+    }
+
+    fn encode(&self, bytes: &mut [u8]) -> Result<usize, EncodeError> {
+        if let Unsubscribe::Decode(encoded) = self {
+            if bytes.len() < encoded.len() {
+                return Err(EncodeError::OutOfSpace);
+            }
+
+            bytes[..encoded.len()].copy_from_slice(encoded);
+
+            return Ok(encoded.len());
+        }
+
+        self.filters().try_fold(0, |offset, filter| {
+            Ok(offset + codec::string::encode_string(filter, &mut bytes[offset..])?)
+        })
+    }
+}
+
+#[cfg(all(test, feature = "encode"))]
+mod tests {
+    use super::*;
+    use crate::qos::QoS;
+
+    #[test]
+    fn from_subscribe() {
+        let topics = [("a/b", QoS::AtLeastOnce), ("c/#", QoS::AtMostOnce)];
+        let subscribe = Subscribe::new(&topics);
+        let unsubscribe = Unsubscribe::from(&subscribe);
+
+        let mut bytes = [0u8; 10];
+        assert_eq!(10, unsubscribe.encoded_len());
+        assert_eq!(Ok(10), unsubscribe.encode(&mut bytes));
+        assert_eq!(
+            [0x00, 0x03, b'a', b'/', b'b', 0x00, 0x03, b'c', b'/', b'#'],
+            bytes
+        );
+
+        let filters = ["a/b", "c/#"];
+        let mut encoded = [0u8; 10];
+        assert_eq!(Ok(10), Unsubscribe::new(&filters).encode(&mut encoded));
+        assert_eq!(bytes, encoded);
+        assert_eq!(
+            Err(EncodeError::OutOfSpace),
+            unsubscribe.encode(&mut bytes[..9])
+        );
+    }
+
+    #[cfg(feature = "decode")]
+    #[test]
+    fn decode() {
+        let bytes = [0x00, 0x03, b'a', b'/', b'b', 0x00, 0x01, b'c'];
+        let (consumed, unsubscribe) = Unsubscribe::decode(&bytes).unwrap().unwrap();
+        assert_eq!(bytes.len(), consumed);
+        assert_eq!(
+            std::vec!["a/b", "c"],
+            unsubscribe.filters().collect::<std::vec::Vec<_>>()
+        );
+
+        let mut encoded = [0u8; 8];
+        assert_eq!(8, unsubscribe.encoded_len());
+        assert_eq!(Ok(8), unsubscribe.encode(&mut encoded));
+        assert_eq!(bytes, encoded);
+
+        assert!(Unsubscribe::decode(&bytes[..7]).is_err());
+    }
+
+    #[cfg(feature = "decode")]
+    #[test]
+    fn check() {
+        use crate::error::{SubscriptionError, SubscriptionErrorKind};
+
+        let payload = [0x00, 0x01, b'a', 0x00, 0x01, b'b'];
+        assert_eq!(Ok(2), check_filters(&payload));
+        assert_eq!(
+            Err(DecodeError::Subscription(SubscriptionError {
+                index: 1,
+                offset: 3,
+                kind: SubscriptionErrorKind::Truncated,
+            })),
+            check_filters(&payload[..5])
+        );
+    }
+}
@@ -0,0 +1,460 @@
+//! Replay MQTT traffic from packet captures, enabled with the `std` feature.
+//!
+//! Reads pcap and pcapng captures, reassembles the TCP payload of each
+//! connection direction and decodes the MQTT packets it contains. Useful for
+//! validating the decoder against real broker traffic and debugging field
+//! captures.
+//!
+//! Only unfragmented IPv4 and IPv6 over Ethernet, Linux cooked, BSD loopback
+//! and raw IP link types are understood. Missing TCP segments are not
+//! recovered, a stream with a gap will fail to decode past it.
+
+use core::convert::TryInto;
+use std::{
+    collections::HashMap,
+    fmt,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    vec::Vec,
+};
+
+use crate::{codec::Decodable, error::DecodeError, packet::Packet, status::Status};
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Error {
+    /// The capture is truncated or not a pcap or pcapng file
+    Format,
+    /// The capture uses a link type that cannot be parsed
+    UnsupportedLinkType(u32),
+}
+
+impl Error {
+    fn desc(&self) -> &'static str {
+        match *self {
+            Error::Format => "malformed capture file",
+            Error::UnsupportedLinkType(_) => "unsupported capture link type",
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.desc())
+    }
+}
+
+impl ::std::error::Error for Error {}
+
+/// The reassembled payload of one direction of a TCP connection.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Stream {
+    source: SocketAddr,
+    destination: SocketAddr,
+    bytes: Vec<u8>,
+    next_seq: u32,
+}
+
+impl Stream {
+    pub fn source(&self) -> SocketAddr {
+        self.source
+    }
+
+    pub fn destination(&self) -> SocketAddr {
+        self.destination
+    }
+
+    pub fn bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    /// Decode the MQTT packets in the stream.
+    pub fn packets(&self) -> Packets<'_> {
+        packets(&self.bytes)
+    }
+
+    fn push(&mut self, seq: u32, payload: &[u8]) {
+        // Skip any part of the segment that has already been seen
+        let seen = self.next_seq.wrapping_sub(seq) as usize;
+        if seen < payload.len() {
+            self.bytes.extend_from_slice(&payload[seen..]);
+        } else if seen > i32::MAX as usize {
+            // A segment is missing, append what we have
+            self.bytes.extend_from_slice(payload);
+        } else {
+            return;
+        }
+        self.next_seq = seq.wrapping_add(payload.len() as u32);
+    }
+}
+
+/// Iterator over the MQTT packets in a byte stream.
+///
+/// Iteration stops at the first decode error, after yielding it, or when the
+/// end of the stream holds an incomplete packet.
+pub struct Packets<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Iterator for Packets<'a> {
+    type Item = Result<Packet<'a>, DecodeError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.bytes.is_empty() {
+            return None;
+        }
+
+        match Packet::decode(self.bytes) {
+            Ok(Status::Complete((offset, packet))) => {
+                self.bytes = &self.bytes[offset..];
+                Some(Ok(packet))
+            }
+            Ok(Status::Partial(..)) => {
+                self.bytes = &[];
+                None
+            }
+            Err(e) => {
+                self.bytes = &[];
+                Some(Err(e))
+            }
+        }
+    }
+}
+
+/// Decode the MQTT packets in a reassembled byte stream.
+pub fn packets(bytes: &[u8]) -> Packets<'_> {
+    Packets { bytes }
+}
+
+/// Read a pcap or pcapng capture and reassemble the payload of every TCP
+/// connection direction it contains.
+///
+/// Streams are returned in the order they first appear in the capture, filter
+/// them by port to find the MQTT connections.
+pub fn read_streams(capture: &[u8]) -> Result<Vec<Stream>, Error> {
+    let mut streams = Streams::default();
+
+    if capture.len() >= 4 && read_u32(capture, 0, false)? == PCAPNG_SHB {
+        read_pcapng(capture, &mut streams)?;
+    } else {
+        read_pcap(capture, &mut streams)?;
+    }
+
+    Ok(streams.streams)
+}
+
+const PCAP_MAGIC_MICROS: u32 = 0xa1b2_c3d4;
+const PCAP_MAGIC_NANOS: u32 = 0xa1b2_3c4d;
+
+const PCAPNG_SHB: u32 = 0x0a0d_0d0a;
+const PCAPNG_BYTE_ORDER_MAGIC: u32 = 0x1a2b_3c4d;
+const PCAPNG_IDB: u32 = 1;
+const PCAPNG_SPB: u32 = 3;
+const PCAPNG_EPB: u32 = 6;
+
+const LINKTYPE_NULL: u32 = 0;
+const LINKTYPE_ETHERNET: u32 = 1;
+const LINKTYPE_RAW: u32 = 101;
+const LINKTYPE_LINUX_SLL: u32 = 113;
+
+#[derive(Default)]
+struct Streams {
+    streams: Vec<Stream>,
+    index: HashMap<(SocketAddr, SocketAddr), usize>,
+}
+
+impl Streams {
+    fn push(&mut self, source: SocketAddr, destination: SocketAddr, seq: u32, payload: &[u8]) {
+        let streams = &mut self.streams;
+        let index = *self.index.entry((source, destination)).or_insert_with(|| {
+            streams.push(Stream {
+                source,
+                destination,
+                bytes: Vec::new(),
+                next_seq: seq,
+            });
+            streams.len() - 1
+        });
+
+        self.streams[index].push(seq, payload);
+    }
+}
+
+fn read_pcap(capture: &[u8], streams: &mut Streams) -> Result<(), Error> {
+    let big_endian = match read_u32(capture, 0, true)? {
+        PCAP_MAGIC_MICROS | PCAP_MAGIC_NANOS => true,
+        _ => match read_u32(capture, 0, false)? {
+            PCAP_MAGIC_MICROS | PCAP_MAGIC_NANOS => false,
+            _ => return Err(Error::Format),
+        },
+    };
+
+    let link_type = read_u32(capture, 20, big_endian)?;
+
+    let mut offset = 24;
+    while offset < capture.len() {
+        let captured = read_u32(capture, offset + 8, big_endian)? as usize;
+        let data = slice(capture, offset + 16, captured)?;
+        read_frame(link_type, data, streams)?;
+        offset += 16 + captured;
+    }
+
+    Ok(())
+}
+
+fn read_pcapng(capture: &[u8], streams: &mut Streams) -> Result<(), Error> {
+    let mut big_endian = false;
+    let mut interfaces = Vec::new();
+
+    let mut offset = 0;
+    while offset < capture.len() {
+        let block_type = read_u32(capture, offset, big_endian)?;
+
+        if block_type == PCAPNG_SHB {
+            big_endian = match read_u32(capture, offset + 8, true)? {
+                PCAPNG_BYTE_ORDER_MAGIC => true,
+                _ if read_u32(capture, offset + 8, false)? == PCAPNG_BYTE_ORDER_MAGIC => false,
+                _ => return Err(Error::Format),
+            };
+            // Interface ids are scoped to their section
+            interfaces.clear();
+        }
+
+        let block_len = read_u32(capture, offset + 4, big_endian)? as usize;
+        if block_len < 12 {
+            return Err(Error::Format);
+        }
+        let body = slice(capture, offset + 8, block_len - 12)?;
+
+        match block_type {
+            PCAPNG_IDB => interfaces.push(u32::from(read_u16(body, 0, big_endian)?)),
+            PCAPNG_EPB => {
+                let interface = read_u32(body, 0, big_endian)? as usize;
+                let captured = read_u32(body, 12, big_endian)? as usize;
+                let link_type = *interfaces.get(interface).ok_or(Error::Format)?;
+                read_frame(link_type, slice(body, 20, captured)?, streams)?;
+            }
+            PCAPNG_SPB => {
+                let original = read_u32(body, 0, big_endian)? as usize;
+                let captured = original.min(body.len().saturating_sub(4));
+                let link_type = *interfaces.first().ok_or(Error::Format)?;
+                read_frame(link_type, slice(body, 4, captured)?, streams)?;
+            }
+            _ => {}
+        }
+
+        offset += block_len;
+    }
+
+    Ok(())
+}
+
+fn read_frame(link_type: u32, frame: &[u8], streams: &mut Streams) -> Result<(), Error> {
+    let ip = match link_type {
+        LINKTYPE_NULL => slice(frame, 4, frame.len().saturating_sub(4))?,
+        LINKTYPE_RAW => frame,
+        LINKTYPE_ETHERNET => {
+            let mut offset = 12;
+            let mut ether_type = read_u16(frame, offset, true)?;
+            // Skip 802.1Q VLAN tags
+            while ether_type == 0x8100 {
+                offset += 4;
+                ether_type = read_u16(frame, offset, true)?;
+            }
+            match ether_type {
+                0x0800 | 0x86dd => slice(frame, offset + 2, frame.len() - (offset + 2))?,
+                _ => return Ok(()),
+            }
+        }
+        LINKTYPE_LINUX_SLL => match read_u16(frame, 14, true)? {
+            0x0800 | 0x86dd => slice(frame, 16, frame.len().saturating_sub(16))?,
+            _ => return Ok(()),
+        },
+        _ => return Err(Error::UnsupportedLinkType(link_type)),
+    };
+
+    read_ip(ip, streams)
+}
+
+fn read_ip(ip: &[u8], streams: &mut Streams) -> Result<(), Error> {
+    let version = ip.first().ok_or(Error::Format)? >> 4;
+
+    let (source, destination, tcp) = match version {
+        4 => {
+            let header_len = usize::from(ip[0] & 0x0f) * 4;
+            let total_len = usize::from(read_u16(ip, 2, true)?);
+            if ip.get(9) != Some(&6) {
+                return Ok(());
+            }
+
+            let source: [u8; 4] = slice(ip, 12, 4)?.try_into().unwrap();
+            let destination: [u8; 4] = slice(ip, 16, 4)?.try_into().unwrap();
+            let tcp = slice(ip, header_len, total_len.saturating_sub(header_len))?;
+
+            (
+                IpAddr::from(Ipv4Addr::from(source)),
+                IpAddr::from(Ipv4Addr::from(destination)),
+                tcp,
+            )
+        }
+        6 => {
+            let payload_len = usize::from(read_u16(ip, 4, true)?);
+            if ip.get(6) != Some(&6) {
+                return Ok(());
+            }
+
+            let source: [u8; 16] = slice(ip, 8, 16)?.try_into().unwrap();
+            let destination: [u8; 16] = slice(ip, 24, 16)?.try_into().unwrap();
+            let tcp = slice(ip, 40, payload_len)?;
+
+            (
+                IpAddr::from(Ipv6Addr::from(source)),
+                IpAddr::from(Ipv6Addr::from(destination)),
+                tcp,
+            )
+        }
+        _ => return Ok(()),
+    };
+
+    let source_port = read_u16(tcp, 0, true)?;
+    let destination_port = read_u16(tcp, 2, true)?;
+    let mut seq = read_u32(tcp, 4, true)?;
+    let data_offset = usize::from(tcp.get(12).ok_or(Error::Format)? >> 4) * 4;
+    let syn = tcp.get(13).ok_or(Error::Format)? & 0x02 != 0;
+    let payload = slice(tcp, data_offset, tcp.len().saturating_sub(data_offset))?;
+
+    // The SYN flag occupies a sequence number before the payload
+    if syn {
+        seq = seq.wrapping_add(1);
+    }
+
+    if payload.is_empty() && !syn {
+        return Ok(());
+    }
+
+    streams.push(
+        SocketAddr::new(source, source_port),
+        SocketAddr::new(destination, destination_port),
+        seq,
+        payload,
+    );
+
+    Ok(())
+}
+
+fn slice(bytes: &[u8], offset: usize, len: usize) -> Result<&[u8], Error> {
+    bytes.get(offset..offset + len).ok_or(Error::Format)
+}
+
+fn read_u16(bytes: &[u8], offset: usize, big_endian: bool) -> Result<u16, Error> {
+    let bytes: [u8; 2] = slice(bytes, offset, 2)?.try_into().unwrap();
+    Ok(if big_endian {
+        u16::from_be_bytes(bytes)
+    } else {
+        u16::from_le_bytes(bytes)
+    })
+}
+
+fn read_u32(bytes: &[u8], offset: usize, big_endian: bool) -> Result<u32, Error> {
+    let bytes: [u8; 4] = slice(bytes, offset, 4)?.try_into().unwrap();
+    Ok(if big_endian {
+        u32::from_be_bytes(bytes)
+    } else {
+        u32::from_le_bytes(bytes)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixed_header::PacketType;
+
+    fn tcp_frame(source_port: u16, seq: u32, flags: u8, payload: &[u8]) -> Vec<u8> {
+        let mut frame = Vec::new();
+        // Ethernet
+        frame.extend_from_slice(&[0; 12]);
+        frame.extend_from_slice(&[0x08, 0x00]);
+        // IPv4
+        let total_len = (20 + 20 + payload.len()) as u16;
+        frame.extend_from_slice(&[0x45, 0]);
+        frame.extend_from_slice(&total_len.to_be_bytes());
+        frame.extend_from_slice(&[0, 0, 0, 0, 64, 6, 0, 0]);
+        frame.extend_from_slice(&[10, 0, 0, 1]);
+        frame.extend_from_slice(&[10, 0, 0, 2]);
+        // TCP
+        frame.extend_from_slice(&source_port.to_be_bytes());
+        frame.extend_from_slice(&1883u16.to_be_bytes());
+        frame.extend_from_slice(&seq.to_be_bytes());
+        frame.extend_from_slice(&[0, 0, 0, 0, 0x50, flags, 0xff, 0xff, 0, 0, 0, 0]);
+        frame.extend_from_slice(payload);
+        frame
+    }
+
+    fn frames() -> Vec<Vec<u8>> {
+        let publish = [0x30, 0x05, 0x00, 0x01, 0x61, 0x7b, 0x7d];
+        vec![
+            tcp_frame(50000, 99, 0x02, &[]),
+            tcp_frame(50000, 100, 0x18, &[0xc0, 0x00, 0x30]),
+            tcp_frame(50000, 103, 0x18, &publish[1..]),
+            // Retransmission of the first segment
+            tcp_frame(50000, 100, 0x18, &[0xc0, 0x00, 0x30]),
+        ]
+    }
+
+    fn assert_streams(streams: &[Stream]) {
+        assert_eq!(streams.len(), 1);
+        assert_eq!(streams[0].destination().port(), 1883);
+
+        let packets = streams[0]
+            .packets()
+            .map(|p| p.expect("valid packet").fixed_header().r#type())
+            .collect::<Vec<_>>();
+        assert_eq!(packets, [PacketType::Pingreq, PacketType::Publish]);
+    }
+
+    #[test]
+    fn pcap() {
+        let mut capture = Vec::new();
+        capture.extend_from_slice(&PCAP_MAGIC_MICROS.to_le_bytes());
+        capture.extend_from_slice(&[2, 0, 4, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0xff, 0xff, 0, 0]);
+        capture.extend_from_slice(&LINKTYPE_ETHERNET.to_le_bytes());
+        for frame in frames() {
+            capture.extend_from_slice(&[0; 8]);
+            capture.extend_from_slice(&(frame.len() as u32).to_le_bytes());
+            capture.extend_from_slice(&(frame.len() as u32).to_le_bytes());
+            capture.extend_from_slice(&frame);
+        }
+
+        assert_streams(&read_streams(&capture).expect("valid capture"));
+    }
+
+    #[test]
+    fn pcapng() {
+        fn block(capture: &mut Vec<u8>, block_type: u32, body: &[u8]) {
+            let len = (12 + body.len()) as u32;
+            capture.extend_from_slice(&block_type.to_be_bytes());
+            capture.extend_from_slice(&len.to_be_bytes());
+            capture.extend_from_slice(body);
+            capture.extend_from_slice(&len.to_be_bytes());
+        }
+
+        let mut capture = Vec::new();
+        let mut shb = PCAPNG_BYTE_ORDER_MAGIC.to_be_bytes().to_vec();
+        shb.extend_from_slice(&[0, 1, 0, 0, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff]);
+        block(&mut capture, PCAPNG_SHB, &shb);
+        block(&mut capture, PCAPNG_IDB, &[0, 1, 0, 0, 0, 0, 0, 0]);
+        for mut frame in frames() {
+            let mut epb = vec![0; 12];
+            epb.extend_from_slice(&(frame.len() as u32).to_be_bytes());
+            epb.extend_from_slice(&(frame.len() as u32).to_be_bytes());
+            frame.resize((frame.len() + 3) & !3, 0);
+            epb.extend_from_slice(&frame);
+            block(&mut capture, PCAPNG_EPB, &epb);
+        }
+
+        assert_streams(&read_streams(&capture).expect("valid capture"));
+    }
+
+    #[test]
+    fn not_a_capture() {
+        assert_eq!(read_streams(b"MQTT"), Err(Error::Format));
+    }
+}
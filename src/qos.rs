@@ -4,7 +4,7 @@ use core::{
     result::Result,
 };
 
-#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Debug)]
 pub enum QoS {
     AtMostOnce,
     AtLeastOnce,
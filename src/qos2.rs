@@ -0,0 +1,192 @@
+//! Receive side of the QoS 2 exactly once delivery flow.
+//!
+//! The receiver stores the packet identifier of a QoS 2 PUBLISH when it first
+//! arrives and responds with PUBREC. Until the matching PUBREL releases the
+//! identifier any PUBLISH redelivered with the same identifier is acknowledged
+//! again but must not be passed on to the application.
+
+use core::{fmt, result::Result};
+
+use crate::variable_header::PacketId;
+
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub enum Error {
+    /// No room to store another packet identifier
+    Full,
+}
+
+impl Error {
+    #[cfg(not(feature = "tiny"))]
+    fn desc(&self) -> &'static str {
+        match *self {
+            Error::Full => "too many QoS 2 packet identifiers awaiting PUBREL",
+        }
+    }
+
+    #[cfg(feature = "tiny")]
+    fn desc(&self) -> &'static str {
+        "QoS 2 error"
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.desc())
+    }
+}
+
+#[cfg(feature = "std")]
+impl ::std::error::Error for Error {
+    fn description(&self) -> &str {
+        self.desc()
+    }
+}
+
+/// What to do with a received QoS 2 PUBLISH, in both cases the receiver must
+/// respond with PUBREC.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Reception {
+    /// First reception, pass the message on to the application.
+    Deliver,
+    /// The identifier has not yet been released, discard the message.
+    Duplicate,
+}
+
+/// Packet identifiers of up to `N` QoS 2 messages received but not yet
+/// released by PUBREL.
+#[derive(Clone, Debug)]
+pub struct Receiver<const N: usize> {
+    ids: [PacketId; N],
+    len: usize,
+}
+
+impl<const N: usize> Default for Receiver<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> Receiver<N> {
+    pub fn new() -> Self {
+        Self {
+            ids: [PacketId::new(0); N],
+            len: 0,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Whether `id` has been received and not yet released.
+    pub fn contains(&self, id: PacketId) -> bool {
+        self.ids[..self.len].contains(&id)
+    }
+
+    /// Record a received QoS 2 PUBLISH.
+    ///
+    /// Returns `Error::Full` if the identifier is new and there is no room to
+    /// store it, the receiver must not send PUBREC and should close the
+    /// connection so the sender retries once identifiers have been released.
+    pub fn publish_received(&mut self, id: PacketId) -> Result<Reception, Error> {
+        if self.contains(id) {
+            #[cfg(feature = "tracing")]
+            ::tracing::debug!(packet_id = id.get(), "qos2 duplicate publish");
+            return Ok(Reception::Duplicate);
+        }
+
+        if self.len == N {
+            #[cfg(feature = "tracing")]
+            ::tracing::warn!(packet_id = id.get(), "qos2 receiver full");
+            return Err(Error::Full);
+        }
+
+        self.ids[self.len] = id;
+        self.len += 1;
+
+        #[cfg(feature = "tracing")]
+        ::tracing::debug!(
+            packet_id = id.get(),
+            stored = self.len,
+            "qos2 publish stored"
+        );
+
+        Ok(Reception::Deliver)
+    }
+
+    /// Release the identifier named by a received PUBREL, the receiver must
+    /// respond with PUBCOMP whether or not the identifier was stored.
+    ///
+    /// Returns whether the identifier was stored.
+    pub fn pubrel_received(&mut self, id: PacketId) -> bool {
+        match self.ids[..self.len].iter().position(|&stored| stored == id) {
+            Some(index) => {
+                self.len -= 1;
+                self.ids.swap(index, self.len);
+
+                #[cfg(feature = "tracing")]
+                ::tracing::debug!(packet_id = id.get(), stored = self.len, "qos2 released");
+                true
+            }
+            None => {
+                #[cfg(feature = "tracing")]
+                ::tracing::debug!(packet_id = id.get(), "qos2 pubrel for unknown id");
+                false
+            }
+        }
+    }
+
+    /// Forget all stored identifiers, when a clean session starts.
+    pub fn clear(&mut self) {
+        self.len = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn exactly_once() {
+        let mut receiver = Receiver::<2>::new();
+
+        assert_eq!(
+            receiver.publish_received(PacketId::new(7)),
+            Ok(Reception::Deliver)
+        );
+        assert_eq!(
+            receiver.publish_received(PacketId::new(7)),
+            Ok(Reception::Duplicate)
+        );
+        assert_eq!(
+            receiver.publish_received(PacketId::new(8)),
+            Ok(Reception::Deliver)
+        );
+        assert_eq!(
+            receiver.publish_received(PacketId::new(9)),
+            Err(Error::Full)
+        );
+        assert_eq!(
+            receiver.publish_received(PacketId::new(8)),
+            Ok(Reception::Duplicate)
+        );
+
+        assert!(receiver.pubrel_received(PacketId::new(7)));
+        assert!(!receiver.pubrel_received(PacketId::new(7)));
+        assert!(!receiver.contains(PacketId::new(7)));
+        assert!(receiver.contains(PacketId::new(8)));
+
+        assert_eq!(
+            receiver.publish_received(PacketId::new(7)),
+            Ok(Reception::Deliver)
+        );
+        assert_eq!(receiver.len(), 2);
+
+        receiver.clear();
+        assert!(receiver.is_empty());
+    }
+}
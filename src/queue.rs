@@ -0,0 +1,169 @@
+//! A fixed capacity queue of outgoing packets.
+//!
+//! Protocol control packets are sent ahead of application packets so that
+//! acknowledgements and keep alive pings are not delayed behind a backlog of
+//! publishes on a slow link.
+
+use crate::{fixed_header::PacketType, packet::Packet};
+
+/// Which class of the queue a packet is sent from.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Priority {
+    /// Acknowledgements and pings, sent before any application packet.
+    Control,
+    /// Everything else, sent in the order it was queued.
+    Application,
+}
+
+impl Priority {
+    /// The priority a packet of the given type is queued with.
+    pub fn of(r#type: PacketType) -> Self {
+        match r#type {
+            PacketType::Puback
+            | PacketType::Pubrec
+            | PacketType::Pubrel
+            | PacketType::Pubcomp
+            | PacketType::Pingreq
+            | PacketType::Pingresp => Priority::Control,
+            _ => Priority::Application,
+        }
+    }
+}
+
+/// A queue of up to `N` outgoing packets.
+///
+/// Packets of the same priority are dequeued in the order they were pushed.
+#[derive(Debug)]
+pub struct OutboundQueue<'a, const N: usize> {
+    packets: [Option<Packet<'a>>; N],
+    len: usize,
+}
+
+impl<'a, const N: usize> Default for OutboundQueue<'a, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<'a, const N: usize> OutboundQueue<'a, N> {
+    pub fn new() -> Self {
+        Self {
+            packets: core::array::from_fn(|_| None),
+            len: 0,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.len == N
+    }
+
+    /// Queue a packet, returning it if the queue is full.
+    #[allow(clippy::result_large_err)]
+    pub fn push(&mut self, packet: Packet<'a>) -> Result<(), Packet<'a>> {
+        if self.is_full() {
+            return Err(packet);
+        }
+
+        self.packets[self.len] = Some(packet);
+        self.len += 1;
+
+        Ok(())
+    }
+
+    /// The packet that `pop` would return.
+    pub fn peek(&self) -> Option<&Packet<'a>> {
+        self.next_index()
+            .and_then(|index| self.packets[index].as_ref())
+    }
+
+    /// Remove the next packet to send, the oldest control packet if any are
+    /// queued, otherwise the oldest application packet.
+    pub fn pop(&mut self) -> Option<Packet<'a>> {
+        let index = self.next_index()?;
+        let packet = self.packets[index].take();
+
+        self.packets[index..self.len].rotate_left(1);
+        self.len -= 1;
+
+        packet
+    }
+
+    fn next_index(&self) -> Option<usize> {
+        if self.is_empty() {
+            return None;
+        }
+
+        let control = self.packets[..self.len].iter().position(|packet| {
+            packet.as_ref().is_some_and(|packet| {
+                Priority::of(packet.fixed_header().r#type()) == Priority::Control
+            })
+        });
+
+        Some(control.unwrap_or(0))
+    }
+}
+
+#[cfg(all(test, feature = "encode"))]
+mod tests {
+    use super::*;
+    use crate::{fixed_header, qos, variable_header};
+
+    fn publish(id: u16) -> Packet<'static> {
+        let flags = fixed_header::PublishFlags::new(qos::QoS::AtLeastOnce, false, false);
+        Packet::publish(
+            flags,
+            variable_header::publish::Publish::new("a/b", Some(variable_header::PacketId::new(id))),
+            b"",
+        )
+        .expect("valid packet")
+    }
+
+    fn puback(id: u16) -> Packet<'static> {
+        Packet::puback(variable_header::packet_identifier::PacketIdentifier::new(
+            variable_header::PacketId::new(id),
+        ))
+        .expect("valid packet")
+    }
+
+    fn packet_identifier(packet: &Packet) -> Option<u16> {
+        match packet.variable_header() {
+            Some(variable_header::VariableHeader::Publish(publish)) => {
+                publish.packet_identifier().map(u16::from)
+            }
+            Some(variable_header::VariableHeader::Puback(puback)) => {
+                Some(puback.packet_identifier().get())
+            }
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn control_before_application() {
+        let mut queue = OutboundQueue::<4>::new();
+        queue.push(publish(1)).unwrap();
+        queue.push(publish(2)).unwrap();
+        queue.push(puback(3)).unwrap();
+        queue.push(Packet::pingreq()).unwrap();
+        assert!(queue.is_full());
+        assert!(queue.push(publish(5)).is_err());
+
+        assert_eq!(queue.peek().and_then(packet_identifier), Some(3));
+        assert_eq!(queue.pop().as_ref().and_then(packet_identifier), Some(3));
+        assert_eq!(
+            queue.pop().map(|packet| packet.fixed_header().r#type()),
+            Some(PacketType::Pingreq)
+        );
+        assert_eq!(queue.pop().as_ref().and_then(packet_identifier), Some(1));
+        assert_eq!(queue.pop().as_ref().and_then(packet_identifier), Some(2));
+        assert!(queue.pop().is_none());
+        assert!(queue.is_empty());
+    }
+}
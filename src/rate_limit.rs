@@ -0,0 +1,114 @@
+//! Token bucket rate limiting of outgoing application messages.
+//!
+//! The session consults the limiter before releasing an application PUBLISH.
+//! Protocol packets, `queue::Priority::Control`, must never be held back by it
+//! or the broker would see acknowledgements late.
+
+use crate::clock::{Clock, Duration, Instant};
+
+/// Tokens are counted in thousandths so rates below one message per
+/// millisecond refill exactly.
+const SCALE: u64 = 1000;
+
+/// Allows up to `burst` messages at once and refills at `per_second`
+/// messages per second.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct RateLimiter {
+    per_second: u32,
+    capacity: u64,
+    tokens: u64,
+    refilled_at: Instant,
+}
+
+impl RateLimiter {
+    /// Create a limiter with a full bucket, a `per_second` of zero never
+    /// refills once the burst is spent.
+    pub fn new(per_second: u32, burst: u32, clock: impl Clock) -> Self {
+        let capacity = u64::from(burst) * SCALE;
+        Self {
+            per_second,
+            capacity,
+            tokens: capacity,
+            refilled_at: clock.now(),
+        }
+    }
+
+    /// Take a token to send one message, returns `false` if the message must
+    /// wait.
+    pub fn try_acquire(&mut self, clock: impl Clock) -> bool {
+        self.refill(clock.now());
+
+        if self.tokens < SCALE {
+            return false;
+        }
+
+        self.tokens -= SCALE;
+        true
+    }
+
+    /// Time until a message may be sent, zero if one may be sent now and
+    /// `None` if the limiter never refills.
+    pub fn wait_time(&mut self, clock: impl Clock) -> Option<Duration> {
+        self.refill(clock.now());
+
+        let missing = SCALE.saturating_sub(self.tokens);
+        if missing == 0 {
+            return Some(Duration::ZERO);
+        }
+        if self.per_second == 0 || self.capacity < SCALE {
+            return None;
+        }
+
+        let refill_per_milli = u64::from(self.per_second);
+        Some(Duration::from_millis(
+            missing.div_ceil(refill_per_milli).min(u64::from(u32::MAX)) as u32,
+        ))
+    }
+
+    fn refill(&mut self, now: Instant) {
+        let elapsed = u64::from(now.duration_since(self.refilled_at).as_millis());
+        self.refilled_at = now;
+
+        // one message per second is one thousandth of a token per millisecond
+        let added = elapsed.saturating_mul(u64::from(self.per_second));
+        self.tokens = self.tokens.saturating_add(added).min(self.capacity);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(millis: u32) -> Instant {
+        Instant::from_millis(millis)
+    }
+
+    #[test]
+    fn burst_then_rate() {
+        let mut limiter = RateLimiter::new(2, 3, at(0));
+        assert!(limiter.try_acquire(at(0)));
+        assert!(limiter.try_acquire(at(0)));
+        assert!(limiter.try_acquire(at(0)));
+        assert!(!limiter.try_acquire(at(0)));
+        assert_eq!(Some(Duration::from_millis(500)), limiter.wait_time(at(0)));
+
+        assert!(!limiter.try_acquire(at(499)));
+        assert_eq!(Some(Duration::from_millis(1)), limiter.wait_time(at(499)));
+        assert!(limiter.try_acquire(at(500)));
+
+        // refilling stops at the burst size
+        assert_eq!(Some(Duration::ZERO), limiter.wait_time(at(60_000)));
+        for _ in 0..3 {
+            assert!(limiter.try_acquire(at(60_000)));
+        }
+        assert!(!limiter.try_acquire(at(60_000)));
+    }
+
+    #[test]
+    fn never_refills() {
+        let mut limiter = RateLimiter::new(0, 1, at(0));
+        assert!(limiter.try_acquire(at(0)));
+        assert!(!limiter.try_acquire(at(u32::MAX)));
+        assert_eq!(None, limiter.wait_time(at(0)));
+    }
+}
@@ -0,0 +1,187 @@
+//! Ordering of unacknowledged QoS 1 and QoS 2 messages for redelivery.
+//!
+//! When a session is resumed the client must resend unacknowledged PUBLISH
+//! and PUBREL packets in the order the original packets were sent
+//! [MQTT-4.6.0-1]. The tracker only records packet identifiers, the messages
+//! themselves live in the caller's retransmission store.
+
+use core::{fmt, result::Result};
+
+use crate::variable_header::PacketId;
+
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub enum Error {
+    /// No room to track another message
+    Full,
+    /// The packet identifier is already in flight
+    InUse,
+}
+
+impl Error {
+    #[cfg(not(feature = "tiny"))]
+    fn desc(&self) -> &'static str {
+        match *self {
+            Error::Full => "too many messages in flight",
+            Error::InUse => "packet identifier already in flight",
+        }
+    }
+
+    #[cfg(feature = "tiny")]
+    fn desc(&self) -> &'static str {
+        "redelivery error"
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.desc())
+    }
+}
+
+#[cfg(feature = "std")]
+impl ::std::error::Error for Error {
+    fn description(&self) -> &str {
+        self.desc()
+    }
+}
+
+/// The packet to resend for an in flight message.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Resend {
+    /// The PUBLISH has not been acknowledged, resend it with the DUP flag set.
+    Publish,
+    /// PUBREC has been received, resend the PUBREL.
+    Pubrel,
+}
+
+/// The send order of up to `N` in flight messages.
+#[derive(Clone, Debug)]
+pub struct Redelivery<const N: usize> {
+    inflight: [(PacketId, Resend); N],
+    len: usize,
+}
+
+impl<const N: usize> Default for Redelivery<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> Redelivery<N> {
+    pub fn new() -> Self {
+        Self {
+            inflight: [(PacketId::new(0), Resend::Publish); N],
+            len: 0,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Record that a QoS 1 or QoS 2 PUBLISH was sent for the first time.
+    pub fn publish_sent(&mut self, id: PacketId) -> Result<(), Error> {
+        if self.position(id).is_some() {
+            return Err(Error::InUse);
+        }
+
+        if self.len == N {
+            return Err(Error::Full);
+        }
+
+        self.inflight[self.len] = (id, Resend::Publish);
+        self.len += 1;
+
+        Ok(())
+    }
+
+    /// Record a PUBREC, the message keeps its place in the order but a PUBREL
+    /// is resent in place of the PUBLISH.
+    ///
+    /// Returns whether the identifier was in flight.
+    pub fn pubrec_received(&mut self, id: PacketId) -> bool {
+        match self.position(id) {
+            Some(index) => {
+                self.inflight[index].1 = Resend::Pubrel;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Record a PUBACK or PUBCOMP completing the message.
+    ///
+    /// Returns whether the identifier was in flight, the caller can then drop
+    /// the message from its retransmission store.
+    pub fn completed(&mut self, id: PacketId) -> bool {
+        match self.position(id) {
+            Some(index) => {
+                self.inflight[index..self.len].rotate_left(1);
+                self.len -= 1;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// The in flight messages oldest first, the order they must be resent in
+    /// when the session is resumed.
+    pub fn iter(&self) -> impl Iterator<Item = (PacketId, Resend)> + '_ {
+        self.inflight[..self.len].iter().cloned()
+    }
+
+    /// Forget all in flight messages, when a clean session starts.
+    pub fn clear(&mut self) {
+        self.len = 0;
+    }
+
+    fn position(&self, id: PacketId) -> Option<usize> {
+        self.inflight[..self.len]
+            .iter()
+            .position(|&(stored, _)| stored == id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::vec::Vec;
+
+    #[test]
+    fn original_order() {
+        let mut redelivery = Redelivery::<3>::new();
+        redelivery.publish_sent(PacketId::new(5)).unwrap();
+        redelivery.publish_sent(PacketId::new(1)).unwrap();
+        redelivery.publish_sent(PacketId::new(9)).unwrap();
+        assert_eq!(redelivery.publish_sent(PacketId::new(2)), Err(Error::Full));
+        assert_eq!(redelivery.publish_sent(PacketId::new(1)), Err(Error::InUse));
+
+        assert!(redelivery.pubrec_received(PacketId::new(5)));
+        assert!(redelivery.completed(PacketId::new(1)));
+        assert!(!redelivery.completed(PacketId::new(1)));
+
+        assert_eq!(
+            redelivery
+                .iter()
+                .map(|(id, resend)| (id.get(), resend))
+                .collect::<Vec<_>>(),
+            [(5, Resend::Pubrel), (9, Resend::Publish)]
+        );
+
+        redelivery.publish_sent(PacketId::new(1)).unwrap();
+        assert_eq!(
+            redelivery
+                .iter()
+                .map(|(id, _)| id.get())
+                .collect::<Vec<_>>(),
+            [5, 9, 1]
+        );
+
+        redelivery.clear();
+        assert!(redelivery.is_empty());
+    }
+}
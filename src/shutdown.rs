@@ -0,0 +1,154 @@
+//! Sequencing of a graceful client disconnect.
+//!
+//! Once a shutdown begins no new application messages should be published.
+//! In flight QoS 1 and QoS 2 messages, e.g. `redelivery::Redelivery::len`, are
+//! either drained for up to a timeout or abandoned straight away, then
+//! DISCONNECT is sent and the connection can be closed [MQTT-3.14.4-1].
+
+use crate::clock::{Clock, Duration, Instant};
+
+/// What to do with in flight messages when a shutdown begins.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Policy {
+    /// Wait up to the timeout for in flight messages to be acknowledged
+    Drain(Duration),
+    /// Disconnect immediately, failing in flight messages
+    Abandon,
+}
+
+/// The next step of the shutdown for the caller to take.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Action {
+    /// Keep processing acknowledgements, the drain times out after the given
+    /// duration
+    Wait(Duration),
+    /// Send DISCONNECT, e.g. `packet::DISCONNECT_BYTES`, the given number of
+    /// in flight messages were not acknowledged and should be failed
+    SendDisconnect { abandoned: usize },
+    /// DISCONNECT has been sent, the connection can be closed
+    Close,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+enum State {
+    Draining,
+    DisconnectDue,
+    Closed,
+}
+
+/// A shutdown in progress.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct Shutdown {
+    policy: Policy,
+    started: Instant,
+    state: State,
+}
+
+impl Shutdown {
+    /// Begin shutting down, the caller must stop publishing new messages.
+    pub fn begin(policy: Policy, clock: impl Clock) -> Self {
+        #[cfg(feature = "tracing")]
+        ::tracing::debug!(?policy, "shutdown draining");
+
+        Self {
+            policy,
+            started: clock.now(),
+            state: State::Draining,
+        }
+    }
+
+    /// The next step given the number of messages still in flight.
+    ///
+    /// Returns `SendDisconnect` until `disconnect_sent` is called.
+    pub fn poll(&mut self, inflight: usize, clock: impl Clock) -> Action {
+        let now = clock.now();
+        match self.state {
+            State::Draining => {
+                let remaining = match self.policy {
+                    Policy::Drain(timeout) => {
+                        timeout.saturating_sub(now.duration_since(self.started))
+                    }
+                    Policy::Abandon => Duration::ZERO,
+                };
+
+                if inflight > 0 && remaining != Duration::ZERO {
+                    return Action::Wait(remaining);
+                }
+
+                #[cfg(feature = "tracing")]
+                ::tracing::debug!(abandoned = inflight, "shutdown disconnect due");
+
+                self.state = State::DisconnectDue;
+                Action::SendDisconnect {
+                    abandoned: inflight,
+                }
+            }
+            State::DisconnectDue => Action::SendDisconnect {
+                abandoned: inflight,
+            },
+            State::Closed => Action::Close,
+        }
+    }
+
+    /// Record that DISCONNECT has been sent in full.
+    pub fn disconnect_sent(&mut self) {
+        #[cfg(feature = "tracing")]
+        ::tracing::debug!("shutdown closed");
+
+        self.state = State::Closed;
+    }
+
+    /// Whether the connection can be closed.
+    pub fn is_closed(&self) -> bool {
+        self.state == State::Closed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(millis: u32) -> Instant {
+        Instant::from_millis(millis)
+    }
+
+    #[test]
+    fn drain() {
+        let mut shutdown = Shutdown::begin(Policy::Drain(Duration::from_secs(5)), at(1_000));
+        assert_eq!(
+            Action::Wait(Duration::from_millis(4_000)),
+            shutdown.poll(2, at(2_000))
+        );
+        assert_eq!(
+            Action::SendDisconnect { abandoned: 0 },
+            shutdown.poll(0, at(3_000))
+        );
+        assert_eq!(
+            Action::SendDisconnect { abandoned: 0 },
+            shutdown.poll(0, at(3_000))
+        );
+        assert!(!shutdown.is_closed());
+
+        shutdown.disconnect_sent();
+        assert!(shutdown.is_closed());
+        assert_eq!(Action::Close, shutdown.poll(0, at(3_000)));
+    }
+
+    #[test]
+    fn drain_timeout() {
+        let mut shutdown = Shutdown::begin(Policy::Drain(Duration::from_secs(5)), at(0));
+        assert_eq!(
+            Action::SendDisconnect { abandoned: 1 },
+            shutdown.poll(1, at(5_000))
+        );
+    }
+
+    #[test]
+    fn abandon() {
+        let mut shutdown = Shutdown::begin(Policy::Abandon, at(0));
+        assert_eq!(
+            Action::SendDisconnect { abandoned: 3 },
+            shutdown.poll(3, at(0))
+        );
+    }
+}
@@ -0,0 +1,256 @@
+//! Helpers for sending and receiving packets over a `smoltcp` TCP socket,
+//! enabled with the `smoltcp` feature.
+//!
+//! The socket implements `PacketSink` and `PacketSource`, these helpers build
+//! on the functions in `transport`. Packets are encoded straight into the
+//! socket transmit buffer where possible. Received bytes are accumulated in a
+//! caller provided buffer until a complete packet can be decoded from it.
+
+use core::fmt;
+
+use smoltcp::socket::tcp::{RecvError, SendError, Socket};
+
+use crate::{
+    codec::Encodable,
+    error::{DecodeError, EncodeError},
+    packet::Packet,
+    transport::{self, PacketSink, PacketSource},
+};
+
+pub use crate::transport::consume;
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Error {
+    /// Encoding the outgoing packet failed
+    Encode(EncodeError),
+    /// Decoding the incoming packet failed
+    Decode(DecodeError),
+    /// The receive buffer is full but does not hold a complete packet
+    BufferFull,
+    /// The socket could not send
+    Send(SendError),
+    /// The socket could not receive
+    Recv(RecvError),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Encode(e) => write!(f, "encode failed: {}", e),
+            Error::Decode(e) => write!(f, "decode failed: {}", e),
+            Error::BufferFull => f.write_str("receive buffer too small for packet"),
+            Error::Send(e) => write!(f, "send failed: {}", e),
+            Error::Recv(e) => write!(f, "receive failed: {}", e),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl ::std::error::Error for Error {}
+
+impl From<EncodeError> for Error {
+    fn from(err: EncodeError) -> Self {
+        Error::Encode(err)
+    }
+}
+
+impl From<DecodeError> for Error {
+    fn from(err: DecodeError) -> Self {
+        Error::Decode(err)
+    }
+}
+
+impl From<SendError> for Error {
+    fn from(err: SendError) -> Self {
+        Error::Send(err)
+    }
+}
+
+impl From<RecvError> for Error {
+    fn from(err: RecvError) -> Self {
+        Error::Recv(err)
+    }
+}
+
+impl<E: Into<Error>> From<transport::Error<E>> for Error {
+    fn from(err: transport::Error<E>) -> Self {
+        match err {
+            transport::Error::Encode(e) => Error::Encode(e),
+            transport::Error::Decode(e) => Error::Decode(e),
+            transport::Error::BufferFull => Error::BufferFull,
+            transport::Error::Transport(e) => e.into(),
+        }
+    }
+}
+
+/// Encode a packet into the socket transmit buffer.
+///
+/// Returns `Ok(false)` without sending anything if the socket cannot send or
+/// the transmit buffer does not have room for the whole packet, the caller
+/// should poll the interface and try again.
+///
+/// The packet is encoded in place when the free space in the transmit buffer is
+/// contiguous. When the free space wraps around the end of the buffer the
+/// packet is encoded into `scratch` first, which must be large enough to hold
+/// it.
+pub fn send_packet(
+    socket: &mut Socket,
+    packet: &Packet,
+    scratch: &mut [u8],
+) -> Result<bool, Error> {
+    let len = packet.encoded_len();
+    if !socket.can_send() || socket.send_capacity() - socket.send_queue() < len {
+        return Ok(false);
+    }
+
+    let encoded = socket.send(|bytes| {
+        if bytes.len() < len {
+            return (0, None);
+        }
+
+        match packet.encode(bytes) {
+            Ok(written) => (written, Some(Ok(()))),
+            Err(e) => (0, Some(Err(e))),
+        }
+    })?;
+
+    if let Some(result) = encoded {
+        result?;
+        return Ok(true);
+    }
+
+    Ok(transport::send_packet(socket, packet, scratch)?)
+}
+
+/// Enqueue already encoded bytes into the socket transmit buffer.
+///
+/// Returns the number of bytes enqueued, which may be less than `bytes.len()`
+/// when the transmit buffer fills up.
+pub fn send_encoded(socket: &mut Socket, bytes: &[u8]) -> Result<usize, Error> {
+    if !socket.can_send() {
+        return Ok(0);
+    }
+
+    Ok(socket.send_slice(bytes)?)
+}
+
+/// Read any received bytes into `buf[*len..]` and attempt to decode a packet
+/// from `buf[..*len]`.
+///
+/// Returns `Ok(None)` when more bytes are needed. Once a packet is returned and
+/// has been handled, call `consume` with the number of bytes it occupied to
+/// make room for the next packet.
+pub fn recv_packet<'b>(
+    socket: &mut Socket,
+    buf: &'b mut [u8],
+    len: &mut usize,
+) -> Result<Option<(usize, Packet<'b>)>, Error> {
+    Ok(transport::recv_packet(socket, buf, len)?)
+}
+
+impl<'a> PacketSink for Socket<'a> {
+    type Error = SendError;
+
+    fn send(&mut self, packet: &[u8]) -> Result<bool, Self::Error> {
+        if !self.can_send() || self.send_capacity() - self.send_queue() < packet.len() {
+            return Ok(false);
+        }
+
+        self.send_slice(packet)?;
+        Ok(true)
+    }
+}
+
+impl<'a> PacketSource for Socket<'a> {
+    type Error = RecvError;
+
+    fn recv(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        if !self.can_recv() {
+            return Ok(0);
+        }
+
+        self.recv_slice(buf)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixed_header::PacketType;
+
+    use smoltcp::{
+        iface::{Config, Interface, SocketSet},
+        phy::{Loopback, Medium},
+        socket::tcp,
+        time::Instant,
+        wire::{HardwareAddress, IpAddress, IpCidr},
+    };
+
+    #[test]
+    fn loopback() {
+        let mut device = Loopback::new(Medium::Ip);
+        let mut iface = Interface::new(
+            Config::new(HardwareAddress::Ip),
+            &mut device,
+            Instant::from_millis(0),
+        );
+        iface.update_ip_addrs(|addrs| {
+            addrs
+                .push(IpCidr::new(IpAddress::v4(127, 0, 0, 1), 8))
+                .unwrap();
+        });
+
+        let mut buffers = [[0u8; 64]; 4];
+        let [a, b, c, d] = &mut buffers;
+        let server = tcp::Socket::new(
+            tcp::SocketBuffer::new(&mut a[..]),
+            tcp::SocketBuffer::new(&mut b[..]),
+        );
+        let client = tcp::Socket::new(
+            tcp::SocketBuffer::new(&mut c[..]),
+            tcp::SocketBuffer::new(&mut d[..]),
+        );
+
+        let mut storage: [_; 2] = Default::default();
+        let mut sockets = SocketSet::new(&mut storage[..]);
+        let server = sockets.add(server);
+        let client = sockets.add(client);
+
+        sockets.get_mut::<tcp::Socket>(server).listen(1883).unwrap();
+        sockets
+            .get_mut::<tcp::Socket>(client)
+            .connect(iface.context(), (IpAddress::v4(127, 0, 0, 1), 1883), 49152)
+            .unwrap();
+
+        let mut scratch = [0u8; 8];
+        let mut sent = 0;
+        let mut buf = [0u8; 8];
+        let mut len = 0;
+        let mut received = 0;
+
+        for now in 0..100 {
+            iface.poll(Instant::from_millis(now), &mut device, &mut sockets);
+
+            if sent < 3
+                && send_packet(
+                    sockets.get_mut::<tcp::Socket>(client),
+                    &Packet::pingreq(),
+                    &mut scratch,
+                )
+                .unwrap()
+            {
+                sent += 1;
+            }
+
+            while let Some((consumed, packet)) =
+                recv_packet(sockets.get_mut::<tcp::Socket>(server), &mut buf, &mut len).unwrap()
+            {
+                assert_eq!(packet.fixed_header().r#type(), PacketType::Pingreq);
+                received += 1;
+                consume(&mut buf, &mut len, consumed);
+            }
+        }
+
+        assert_eq!(3, received);
+    }
+}
@@ -0,0 +1,304 @@
+//! Protocol statistics, enabled with the `stats` feature.
+//!
+//! Counters are updated by `Packet::decode` and `Packet::encode` and can be
+//! read at any time with `snapshot()`, for example to report health metrics or
+//! publish `$SYS` style topics.
+//!
+//! The counters are global atomics, the target must support atomic
+//! read-modify-write operations on `u32`.
+//!
+//! `SessionStats` keeps separate counters for a single session, for telemetry
+//! and watchdog decisions.
+
+use core::{
+    convert::TryFrom,
+    sync::atomic::{AtomicU32, Ordering},
+};
+
+use crate::{
+    clock::Instant,
+    fixed_header::{PacketType, PublishFlags},
+    packet::Packet,
+    qos::QoS,
+};
+
+#[cfg(feature = "encode")]
+use crate::fixed_header::FixedHeader;
+#[cfg(feature = "decode")]
+use crate::{error::DecodeError, status::Status};
+
+const PACKET_TYPES: usize = 15;
+
+#[allow(clippy::declare_interior_mutable_const)]
+const ZERO: AtomicU32 = AtomicU32::new(0);
+
+static PACKETS_ENCODED: [AtomicU32; PACKET_TYPES] = [ZERO; PACKET_TYPES];
+static PACKETS_DECODED: [AtomicU32; PACKET_TYPES] = [ZERO; PACKET_TYPES];
+static BYTES_ENCODED: AtomicU32 = AtomicU32::new(0);
+static BYTES_DECODED: AtomicU32 = AtomicU32::new(0);
+static DECODE_ERRORS: AtomicU32 = AtomicU32::new(0);
+
+fn index(r#type: PacketType) -> usize {
+    match r#type {
+        PacketType::Connect => 0,
+        PacketType::Connack => 1,
+        PacketType::Publish => 2,
+        PacketType::Puback => 3,
+        PacketType::Pubrec => 4,
+        PacketType::Pubrel => 5,
+        PacketType::Pubcomp => 6,
+        PacketType::Subscribe => 7,
+        PacketType::Suback => 8,
+        PacketType::Unsubscribe => 9,
+        PacketType::Unsuback => 10,
+        PacketType::Pingreq => 11,
+        PacketType::Pingresp => 12,
+        PacketType::Disconnect => 13,
+        PacketType::Reserved(_) => 14,
+    }
+}
+
+/// A point in time copy of the protocol statistics.
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default)]
+pub struct Snapshot {
+    packets_encoded: [u32; PACKET_TYPES],
+    packets_decoded: [u32; PACKET_TYPES],
+    bytes_encoded: u32,
+    bytes_decoded: u32,
+    decode_errors: u32,
+}
+
+impl Snapshot {
+    /// Number of packets of the given type successfully encoded.
+    pub fn packets_encoded(&self, r#type: PacketType) -> u32 {
+        self.packets_encoded[index(r#type)]
+    }
+
+    /// Number of packets of the given type successfully decoded.
+    pub fn packets_decoded(&self, r#type: PacketType) -> u32 {
+        self.packets_decoded[index(r#type)]
+    }
+
+    /// Total number of bytes written by successful encodes.
+    pub fn bytes_encoded(&self) -> u32 {
+        self.bytes_encoded
+    }
+
+    /// Total number of bytes consumed by successful decodes.
+    pub fn bytes_decoded(&self) -> u32 {
+        self.bytes_decoded
+    }
+
+    /// Number of decodes that failed with an unrecoverable error.
+    pub fn decode_errors(&self) -> u32 {
+        self.decode_errors
+    }
+}
+
+/// Take a copy of the current counters.
+pub fn snapshot() -> Snapshot {
+    let mut snapshot = Snapshot::default();
+    for i in 0..PACKET_TYPES {
+        snapshot.packets_encoded[i] = PACKETS_ENCODED[i].load(Ordering::Relaxed);
+        snapshot.packets_decoded[i] = PACKETS_DECODED[i].load(Ordering::Relaxed);
+    }
+    snapshot.bytes_encoded = BYTES_ENCODED.load(Ordering::Relaxed);
+    snapshot.bytes_decoded = BYTES_DECODED.load(Ordering::Relaxed);
+    snapshot.decode_errors = DECODE_ERRORS.load(Ordering::Relaxed);
+    snapshot
+}
+
+/// Reset all counters to zero.
+pub fn reset() {
+    for i in 0..PACKET_TYPES {
+        PACKETS_ENCODED[i].store(0, Ordering::Relaxed);
+        PACKETS_DECODED[i].store(0, Ordering::Relaxed);
+    }
+    BYTES_ENCODED.store(0, Ordering::Relaxed);
+    BYTES_DECODED.store(0, Ordering::Relaxed);
+    DECODE_ERRORS.store(0, Ordering::Relaxed);
+}
+
+#[cfg(feature = "encode")]
+pub(crate) fn record_encode(header: &FixedHeader, written: usize) {
+    PACKETS_ENCODED[index(header.r#type())].fetch_add(1, Ordering::Relaxed);
+    BYTES_ENCODED.fetch_add(written as u32, Ordering::Relaxed);
+}
+
+#[cfg(feature = "decode")]
+pub(crate) fn record_decode(result: &Result<Status<(usize, Packet)>, DecodeError>) {
+    match result {
+        Ok(Status::Complete((read, packet))) => {
+            PACKETS_DECODED[index(packet.fixed_header().r#type())].fetch_add(1, Ordering::Relaxed);
+            BYTES_DECODED.fetch_add(*read as u32, Ordering::Relaxed);
+        }
+        Ok(Status::Partial(..)) => {}
+        Err(..) => {
+            DECODE_ERRORS.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+/// Statistics for one client session.
+///
+/// The session passes every packet it sends and receives to `packet_sent` and
+/// `packet_received`, the application can read the counters at any time. Like
+/// the global counters they wrap on overflow, except `acks_outstanding` which
+/// saturates.
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default)]
+pub struct SessionStats {
+    publishes_sent: [u32; 3],
+    publishes_received: [u32; 3],
+    retransmissions: u32,
+    acks_outstanding: u32,
+    last_sent: Option<Instant>,
+    last_received: Option<Instant>,
+}
+
+impl SessionStats {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record a packet sent to the server.
+    ///
+    /// A PUBLISH with the DUP flag set counts as a retransmission, a first
+    /// QoS 1 or QoS 2 PUBLISH adds an outstanding acknowledgement.
+    pub fn packet_sent(&mut self, packet: &Packet, now: Instant) {
+        self.last_sent = Some(now);
+
+        if let Some((qos, dup)) = publish_qos(packet) {
+            let sent = &mut self.publishes_sent[usize::from(u8::from(qos))];
+            *sent = sent.wrapping_add(1);
+            if dup {
+                self.retransmissions = self.retransmissions.wrapping_add(1);
+            } else if qos != QoS::AtMostOnce {
+                self.acks_outstanding = self.acks_outstanding.saturating_add(1);
+            }
+        }
+    }
+
+    /// Record a packet received from the server.
+    ///
+    /// PUBACK and PUBCOMP complete an outstanding acknowledgement.
+    pub fn packet_received(&mut self, packet: &Packet, now: Instant) {
+        self.last_received = Some(now);
+
+        match packet.fixed_header().r#type() {
+            PacketType::Puback | PacketType::Pubcomp => {
+                self.acks_outstanding = self.acks_outstanding.saturating_sub(1);
+            }
+            _ => {
+                if let Some((qos, _)) = publish_qos(packet) {
+                    let received = &mut self.publishes_received[usize::from(u8::from(qos))];
+                    *received = received.wrapping_add(1);
+                }
+            }
+        }
+    }
+
+    /// Forget outstanding acknowledgements, when a clean session starts.
+    pub fn clear_outstanding(&mut self) {
+        self.acks_outstanding = 0;
+    }
+
+    /// Number of PUBLISH packets sent at the given QoS, retransmissions
+    /// included.
+    pub fn publishes_sent(&self, qos: QoS) -> u32 {
+        self.publishes_sent[usize::from(u8::from(qos))]
+    }
+
+    /// Number of PUBLISH packets received at the given QoS.
+    pub fn publishes_received(&self, qos: QoS) -> u32 {
+        self.publishes_received[usize::from(u8::from(qos))]
+    }
+
+    /// Number of PUBLISH packets resent with the DUP flag set.
+    pub fn retransmissions(&self) -> u32 {
+        self.retransmissions
+    }
+
+    /// Number of QoS 1 and QoS 2 messages sent and not yet completed.
+    pub fn acks_outstanding(&self) -> u32 {
+        self.acks_outstanding
+    }
+
+    /// When a packet was last sent, `None` if none has been.
+    pub fn last_sent(&self) -> Option<Instant> {
+        self.last_sent
+    }
+
+    /// When a packet was last received, `None` if none has been.
+    pub fn last_received(&self) -> Option<Instant> {
+        self.last_received
+    }
+}
+
+fn publish_qos(packet: &Packet) -> Option<(QoS, bool)> {
+    if packet.fixed_header().r#type() != PacketType::Publish {
+        return None;
+    }
+
+    let flags = PublishFlags::try_from(packet.fixed_header().flags()).ok()?;
+    Some((flags.qos, flags.dup))
+}
+
+#[cfg(all(test, feature = "encode", feature = "decode"))]
+mod tests {
+    use super::*;
+    use crate::codec::{Decodable, Encodable};
+
+    #[test]
+    fn counts_encode_and_decode() {
+        let before = snapshot();
+
+        let mut buf = [0u8; 2];
+        Packet::pingreq().encode(&mut buf).expect("encoded");
+        Packet::decode(&buf).expect("decoded");
+        Packet::decode(&[0xF0, 0]).expect_err("reserved type");
+
+        let after = snapshot();
+        assert!(
+            after.packets_encoded(PacketType::Pingreq)
+                > before.packets_encoded(PacketType::Pingreq)
+        );
+        assert!(
+            after.packets_decoded(PacketType::Pingreq)
+                > before.packets_decoded(PacketType::Pingreq)
+        );
+        assert!(after.bytes_encoded() >= before.bytes_encoded() + 2);
+        assert!(after.bytes_decoded() >= before.bytes_decoded() + 2);
+        assert!(after.decode_errors() > before.decode_errors());
+    }
+
+    #[test]
+    fn session() {
+        use crate::variable_header::{
+            packet_identifier::PacketIdentifier, publish::Publish, PacketId,
+        };
+
+        let mut stats = SessionStats::new();
+        assert_eq!(None, stats.last_sent());
+
+        let flags = PublishFlags::new(QoS::AtLeastOnce, false, false);
+        let publish =
+            Packet::publish(flags, Publish::new("a", Some(PacketId::new(1))), b"").unwrap();
+        stats.packet_sent(&publish, Instant::from_millis(10));
+        assert_eq!(1, stats.acks_outstanding());
+
+        let flags = PublishFlags::new(QoS::AtLeastOnce, true, false);
+        let resent =
+            Packet::publish(flags, Publish::new("a", Some(PacketId::new(1))), b"").unwrap();
+        stats.packet_sent(&resent, Instant::from_millis(20));
+        assert_eq!(2, stats.publishes_sent(QoS::AtLeastOnce));
+        assert_eq!(1, stats.retransmissions());
+        assert_eq!(1, stats.acks_outstanding());
+
+        let puback = Packet::puback(PacketIdentifier::new(PacketId::new(1))).unwrap();
+        stats.packet_received(&puback, Instant::from_millis(30));
+        assert_eq!(0, stats.acks_outstanding());
+        assert_eq!(0, stats.publishes_received(QoS::AtLeastOnce));
+        assert_eq!(Some(Instant::from_millis(20)), stats.last_sent());
+        assert_eq!(Some(Instant::from_millis(30)), stats.last_received());
+    }
+}
@@ -41,21 +41,62 @@ impl<T> Status<T> {
     }
 }
 
+impl<T> Status<T> {
+    /// Map a `Complete` value, leaving `Partial` untouched.
+    #[inline]
+    pub fn map<U, F: FnOnce(T) -> U>(self, f: F) -> Status<U> {
+        match self {
+            Status::Complete(t) => Status::Complete(f(t)),
+            Status::Partial(n) => Status::Partial(n),
+        }
+    }
+}
+
+/// Unwrap a `Result<Status<T>, E>` inside a parser, returning early with the
+/// error or with `Ok(Status::Partial(n))` if more bytes are needed.
+///
+/// The enclosing function must return `Result<Status<_>, E2>` where `E2`
+/// implements `From<E>`.
 #[macro_export]
 macro_rules! complete {
     ($e:expr) => {
         match $e? {
-            Status::Complete(v) => v,
-            Status::Partial(x) => return Ok(Status::Partial(x)),
+            $crate::status::Status::Complete(v) => v,
+            $crate::status::Status::Partial(x) => return Ok($crate::status::Status::Partial(x)),
         }
     };
 }
 
+/// Run the parser `$fn` on `$bytes[$offset..]` and evaluate to the offset
+/// after the parsed value and the value, returning early like `complete!`.
+///
+/// `$fn` must have the shape of the parsers in `codec`, taking a byte slice
+/// and returning `Result<Status<(usize, T)>, E>` with the number of bytes it
+/// consumed.
+///
+/// ```
+/// #[macro_use]
+/// extern crate embedded_mqtt;
+///
+/// use embedded_mqtt::{codec::values::parse_u16, error::DecodeError, status::Status};
+///
+/// fn parse_pair(bytes: &[u8]) -> Result<Status<(usize, (u16, u16))>, DecodeError> {
+///     let (offset, first) = read!(parse_u16, bytes, 0);
+///     let (offset, second) = read!(parse_u16, bytes, offset);
+///     Ok(Status::Complete((offset, (first, second))))
+/// }
+///
+/// fn main() {
+///     assert_eq!(Ok(Status::Complete((4, (1, 2)))), parse_pair(&[0, 1, 0, 2]));
+///     assert_eq!(Ok(Status::Partial(1)), parse_pair(&[0, 1, 0]));
+/// }
+/// ```
+#[macro_export]
 macro_rules! read {
     ($fn:path, $bytes:expr, $offset:expr) => {
         match $fn(&$bytes[$offset..])? {
-            Status::Complete(v) => ($offset + v.0, v.1),
-            Status::Partial(x) => return Ok(Status::Partial(x)),
+            $crate::status::Status::Complete(v) => ($offset + v.0, v.1),
+            $crate::status::Status::Partial(x) => return Ok($crate::status::Status::Partial(x)),
         }
     };
 }
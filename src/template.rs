@@ -0,0 +1,301 @@
+//! Packets encoded at compile time.
+//!
+//! A device whose CONNECT and SUBSCRIBE never change can describe them with a
+//! `packet::ConnectBuilder` or a `Subscribe` template and have `template!` turn
+//! it into a constant byte array, leaving no encoding or buffers in the
+//! connection setup sequence. Templates which could never be encoded, e.g.
+//! with an empty topic filter, fail to compile.
+//!
+//! ```
+//! #[macro_use]
+//! extern crate embedded_mqtt;
+//!
+//! use embedded_mqtt::{packet::ConnectBuilder, qos::QoS, template::Subscribe};
+//!
+//! const CONNECT: &[u8] = template!(ConnectBuilder::new("sensor-42")
+//!     .keep_alive(60)
+//!     .credentials("device", b"secret"));
+//!
+//! const SUBSCRIBE: &[u8] = template!(Subscribe {
+//!     packet_identifier: 1,
+//!     topics: &[("site/42/commands", QoS::AtLeastOnce)],
+//! });
+//!
+//! fn main() {
+//!     assert_eq!(0x10, CONNECT[0]);
+//!     assert_eq!(0x82, SUBSCRIBE[0]);
+//! }
+//! ```
+
+use crate::{fixed_header::MAX_REMAINING_LENGTH, packet::ConnectBuilder, qos::QoS};
+
+/// Encode a `ConnectBuilder` or `Subscribe` template into a `&'static [u8]` at
+/// compile time.
+#[macro_export]
+macro_rules! template {
+    ($template:expr) => {{
+        const BYTES: [u8; $template.encoded_len()] = $template.encode();
+        &BYTES
+    }};
+}
+
+/// CONNECT packets are encoded at compile time from a `ConnectBuilder`, whose
+/// methods are `const`.
+impl<'a> ConnectBuilder<'a> {
+    /// The length of the encoded packet, fixed header included.
+    pub const fn encoded_len(&self) -> usize {
+        packet_len(self.remaining_len())
+    }
+
+    /// Encode the packet at compile time, `N` must be `encoded_len()`. Use
+    /// `build` to encode at run time.
+    pub const fn encode<const N: usize>(&self) -> [u8; N] {
+        let mut bytes = Writer::new();
+        bytes.fixed_header(0x10, self.remaining_len());
+        bytes.bytes(b"MQTT", 0);
+        // protocol level 3.1.1
+        bytes.u8(4);
+
+        let mut flags = (self.clean_session as u8) << 1;
+        if let Some((_, _, qos, retain)) = self.will {
+            flags |= (retain as u8) << 5 | qos_bits(qos) << 3 | 1 << 2;
+        }
+        if self.username.is_some() {
+            flags |= 1 << 7;
+        }
+        if self.password.is_some() {
+            flags |= 1 << 6;
+        }
+        bytes.u8(flags);
+        bytes.u16(self.keep_alive.as_secs());
+
+        bytes.bytes(self.client_id.as_bytes(), 0);
+        if let Some((topic, message, _, _)) = self.will {
+            // [MQTT-4.7.3-1]
+            bytes.bytes(topic.as_bytes(), 1);
+            bytes.bytes(message, 0);
+        }
+        if let Some(username) = self.username {
+            bytes.bytes(username.as_bytes(), 0);
+        }
+        if let Some(password) = self.password {
+            bytes.bytes(password.expose(), 0);
+        }
+
+        bytes.finish()
+    }
+
+    const fn remaining_len(&self) -> usize {
+        let mut len = 6 + 1 + 1 + 2 + 2 + self.client_id.len();
+        if let Some((topic, message, _, _)) = self.will {
+            len += 2 + topic.len() + 2 + message.len();
+        }
+        if let Some(username) = self.username {
+            len += 2 + username.len();
+        }
+        if let Some(password) = self.password {
+            len += 2 + password.expose().len();
+        }
+        len
+    }
+}
+
+/// A SUBSCRIBE packet fixed at build time.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct Subscribe<'a> {
+    pub packet_identifier: u16,
+    pub topics: &'a [(&'a str, QoS)],
+}
+
+impl<'a> Subscribe<'a> {
+    /// The length of the encoded packet, fixed header included.
+    pub const fn encoded_len(&self) -> usize {
+        packet_len(self.remaining_len())
+    }
+
+    /// Encode the packet, `N` must be `encoded_len()`.
+    pub const fn encode<const N: usize>(&self) -> [u8; N] {
+        // [MQTT-2.3.1-1] [MQTT-3.8.3-3]
+        assert!(self.packet_identifier != 0, "packet identifier is zero");
+        assert!(!self.topics.is_empty(), "no topic filters");
+
+        let mut bytes = Writer::new();
+        bytes.fixed_header(0x82, self.remaining_len());
+        bytes.u16(self.packet_identifier);
+
+        let mut index = 0;
+        while index < self.topics.len() {
+            let (filter, qos) = self.topics[index];
+            bytes.bytes(filter.as_bytes(), 1);
+            bytes.u8(qos_bits(qos));
+            index += 1;
+        }
+
+        bytes.finish()
+    }
+
+    const fn remaining_len(&self) -> usize {
+        let mut len = 2;
+        let mut index = 0;
+        while index < self.topics.len() {
+            len += 2 + self.topics[index].0.len() + 1;
+            index += 1;
+        }
+        len
+    }
+}
+
+const fn qos_bits(qos: QoS) -> u8 {
+    match qos {
+        QoS::AtMostOnce => 0b00,
+        QoS::AtLeastOnce => 0b01,
+        QoS::ExactlyOnce => 0b10,
+    }
+}
+
+const fn packet_len(remaining_len: usize) -> usize {
+    let mut header_len = 2;
+    let mut len = remaining_len / 128;
+    while len > 0 {
+        header_len += 1;
+        len /= 128;
+    }
+    header_len + remaining_len
+}
+
+struct Writer<const N: usize> {
+    bytes: [u8; N],
+    offset: usize,
+}
+
+impl<const N: usize> Writer<N> {
+    const fn new() -> Self {
+        Writer {
+            bytes: [0; N],
+            offset: 0,
+        }
+    }
+
+    const fn fixed_header(&mut self, first: u8, remaining_len: usize) {
+        assert!(
+            remaining_len <= MAX_REMAINING_LENGTH as usize,
+            "packet too large"
+        );
+        assert!(N == packet_len(remaining_len), "N must be encoded_len()");
+
+        self.u8(first);
+        let mut len = remaining_len;
+        loop {
+            let mut byte = (len % 128) as u8;
+            len /= 128;
+            if len > 0 {
+                byte |= 128;
+            }
+            self.u8(byte);
+            if len == 0 {
+                break;
+            }
+        }
+    }
+
+    const fn u8(&mut self, value: u8) {
+        self.bytes[self.offset] = value;
+        self.offset += 1;
+    }
+
+    const fn u16(&mut self, value: u16) {
+        self.u8((value >> 8) as u8);
+        self.u8(value as u8);
+    }
+
+    /// A length prefixed string or binary field at least `min` bytes long.
+    const fn bytes(&mut self, value: &[u8], min: usize) {
+        assert!(
+            value.len() >= min && value.len() <= u16::MAX as usize,
+            "field empty or longer than 65535 bytes"
+        );
+
+        self.u16(value.len() as u16);
+        let mut index = 0;
+        while index < value.len() {
+            self.u8(value[index]);
+            index += 1;
+        }
+    }
+
+    const fn finish(self) -> [u8; N] {
+        self.bytes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        codec::Encodable,
+        packet::Packet,
+        payload,
+        variable_header::{self, PacketId},
+    };
+
+    const BUILDER: ConnectBuilder = ConnectBuilder::new("sensor-42")
+        .keep_alive(60)
+        .clean_session(true)
+        .will("site/42/status", b"offline", QoS::AtLeastOnce, true)
+        .credentials("device", b"secret");
+    const CONNECT: &[u8] = template!(BUILDER);
+
+    const TOPICS: &[(&str, QoS)] = &[
+        ("site/42/commands", QoS::AtLeastOnce),
+        ("site/+/broadcast", QoS::AtMostOnce),
+    ];
+    const SUBSCRIBE: &[u8] = template!(Subscribe {
+        packet_identifier: 1,
+        topics: TOPICS,
+    });
+
+    #[test]
+    fn connect() {
+        let mut buf = [0u8; 64];
+        let len = BUILDER.build().unwrap().encode(&mut buf).unwrap();
+        assert_eq!(&buf[..len], CONNECT);
+
+        // [MQTT-3.1.2-22]
+        const USERNAME: &[u8] = template!(BUILDER.username("device"));
+        assert_eq!(0b1010_1110, USERNAME[9]);
+        let len = BUILDER
+            .username("device")
+            .build()
+            .unwrap()
+            .encode(&mut buf)
+            .unwrap();
+        assert_eq!(&buf[..len], USERNAME);
+    }
+
+    #[test]
+    fn subscribe() {
+        let packet = Packet::subscribe(
+            variable_header::packet_identifier::PacketIdentifier::new(PacketId::new(1)),
+            payload::subscribe::Subscribe::new(TOPICS),
+        )
+        .unwrap();
+
+        let mut buf = [0u8; 64];
+        let len = packet.encode(&mut buf).unwrap();
+        assert_eq!(&buf[..len], SUBSCRIBE);
+    }
+
+    #[test]
+    fn long_remaining_length() {
+        const LONG: &[u8] = template!(Subscribe {
+            packet_identifier: 1,
+            topics: &[(
+                "a/very/long/topic/filter/that/needs/more/than/one/hundred/and/twenty/seven/bytes/to/encode/a/two/byte/remaining/length/extra/#",
+                QoS::AtMostOnce,
+            )],
+        });
+
+        assert_eq!([0x82, 0x83, 0x01], LONG[..3]);
+        assert_eq!(134, LONG.len());
+    }
+}
@@ -0,0 +1,346 @@
+//! Hex helpers for writing readable wire format test vectors and a packet diff
+//! for readable assertion failures, enabled with the `test-util` feature.
+
+use core::{fmt, fmt::Write, result::Result};
+
+use std::string::String;
+
+use crate::{
+    codec::{Decodable, Encodable},
+    error::{DecodeError, EncodeError},
+    fixed_header::FixedHeader,
+    packet::Packet,
+    status::Status,
+    variable_header::VariableHeader,
+};
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Error {
+    /// A character that is not a hex digit, at the given byte offset
+    InvalidHex(usize),
+    /// An odd number of hex digits
+    OddLength,
+    /// The decoded bytes do not fit in the buffer
+    OutOfSpace,
+    /// Decoding the packet failed
+    Decode(DecodeError),
+    /// The bytes do not contain a complete packet
+    Partial(usize),
+    /// The bytes contain more than one packet
+    TrailingBytes(usize),
+    /// Encoding the packet failed
+    Encode(EncodeError),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::InvalidHex(offset) => write!(f, "invalid hex digit at offset {}", offset),
+            Error::OddLength => f.write_str("odd number of hex digits"),
+            Error::OutOfSpace => f.write_str("not enough space in buffer"),
+            Error::Decode(e) => write!(f, "decode failed: {}", e),
+            Error::Partial(n) => write!(f, "incomplete packet, {} more bytes needed", n),
+            Error::TrailingBytes(n) => write!(f, "{} bytes after packet", n),
+            Error::Encode(e) => write!(f, "encode failed: {}", e),
+        }
+    }
+}
+
+impl ::std::error::Error for Error {}
+
+impl From<DecodeError> for Error {
+    fn from(err: DecodeError) -> Self {
+        Error::Decode(err)
+    }
+}
+
+impl From<EncodeError> for Error {
+    fn from(err: EncodeError) -> Self {
+        Error::Encode(err)
+    }
+}
+
+/// Parse hex digits into `buf`, returning the number of bytes written.
+///
+/// Whitespace between bytes is ignored, so `"30 0b 00"` and `"300b00"` are
+/// equivalent.
+pub fn from_hex(hex: &str, buf: &mut [u8]) -> Result<usize, Error> {
+    let mut len = 0;
+    let mut high = None;
+
+    for (offset, c) in hex.char_indices() {
+        if c.is_ascii_whitespace() {
+            continue;
+        }
+
+        let digit = c.to_digit(16).ok_or(Error::InvalidHex(offset))? as u8;
+        match high.take() {
+            None => high = Some(digit),
+            Some(high) => {
+                *buf.get_mut(len).ok_or(Error::OutOfSpace)? = high << 4 | digit;
+                len += 1;
+            }
+        }
+    }
+
+    if high.is_some() {
+        return Err(Error::OddLength);
+    }
+
+    Ok(len)
+}
+
+/// Parse hex digits into `buf` and decode exactly one packet from them.
+pub fn packet_from_hex<'a>(hex: &str, buf: &'a mut [u8]) -> Result<Packet<'a>, Error> {
+    let len = from_hex(hex, buf)?;
+
+    let buf: &'a [u8] = buf;
+    match Packet::decode(&buf[..len])? {
+        Status::Complete((consumed, _)) if consumed < len => {
+            Err(Error::TrailingBytes(len - consumed))
+        }
+        Status::Complete((_, packet)) => Ok(packet),
+        Status::Partial(n) => Err(Error::Partial(n)),
+    }
+}
+
+/// Format bytes as space separated lowercase hex pairs.
+pub fn to_hex(bytes: &[u8]) -> String {
+    let mut hex = String::with_capacity(bytes.len() * 3);
+    for (index, byte) in bytes.iter().enumerate() {
+        if index > 0 {
+            hex.push(' ');
+        }
+        write!(hex, "{:02x}", byte).expect("write to string");
+    }
+    hex
+}
+
+/// Encode a packet and format it as space separated lowercase hex pairs.
+pub fn encode_hex(packet: &Packet) -> Result<String, Error> {
+    let mut buf = vec![0u8; packet.encoded_len()];
+    let len = packet.encode(&mut buf)?;
+    Ok(to_hex(&buf[..len]))
+}
+
+/// The part of a packet a difference was found in.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Field {
+    /// The high nibble of the first byte
+    PacketType,
+    /// The low nibble of the first byte
+    Flags,
+    RemainingLength,
+    VariableHeader,
+    Payload,
+    /// Bytes after the end declared by the remaining length
+    Trailing,
+}
+
+impl fmt::Display for Field {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Field::PacketType => "packet type",
+            Field::Flags => "flags",
+            Field::RemainingLength => "remaining length",
+            Field::VariableHeader => "variable header",
+            Field::Payload => "payload",
+            Field::Trailing => "trailing data",
+        })
+    }
+}
+
+/// The first difference between two encoded packets.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct Difference {
+    pub field: Field,
+    /// The offset of the first differing byte from the start of the packet
+    pub offset: usize,
+    /// The byte at `offset` on the left, `None` if the left ends first
+    pub left: Option<u8>,
+    /// The byte at `offset` on the right, `None` if the right ends first
+    pub right: Option<u8>,
+}
+
+impl fmt::Display for Difference {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} differs at offset {}: ", self.field, self.offset)?;
+        write_byte(f, self.left)?;
+        f.write_str(" != ")?;
+        write_byte(f, self.right)
+    }
+}
+
+fn write_byte(f: &mut fmt::Formatter, byte: Option<u8>) -> fmt::Result {
+    match byte {
+        Some(byte) => write!(f, "{:#04x}", byte),
+        None => f.write_str("end of packet"),
+    }
+}
+
+/// Encode two packets and find the first field they differ in, `None` if they
+/// encode identically.
+///
+/// Meant for assertions, e.g.
+/// `if let Some(d) = diff(&a, &b)? { panic!("{}", d) }`.
+pub fn diff(left: &Packet, right: &Packet) -> Result<Option<Difference>, Error> {
+    let mut left_buf = vec![0u8; left.encoded_len()];
+    let left_len = left.encode(&mut left_buf)?;
+    let mut right_buf = vec![0u8; right.encoded_len()];
+    let right_len = right.encode(&mut right_buf)?;
+
+    Ok(diff_encoded(&left_buf[..left_len], &right_buf[..right_len]))
+}
+
+/// Find the first byte two encoded packets differ in and the field it belongs
+/// to, `None` if they are identical.
+///
+/// The field is worked out from whichever side is longer, so it is still
+/// reported for bytes that do not decode as a complete packet.
+pub fn diff_encoded(left: &[u8], right: &[u8]) -> Option<Difference> {
+    let offset = left
+        .iter()
+        .zip(right)
+        .position(|(l, r)| l != r)
+        .or_else(|| (left.len() != right.len()).then(|| left.len().min(right.len())))?;
+
+    let (l, r) = (left.get(offset).copied(), right.get(offset).copied());
+    let field = match (offset, l, r) {
+        (0, Some(l), Some(r)) if l & 0x0f != r & 0x0f && l >> 4 == r >> 4 => Field::Flags,
+        (0, ..) => Field::PacketType,
+        _ => field_at(
+            if left.len() >= right.len() {
+                left
+            } else {
+                right
+            },
+            offset,
+        ),
+    };
+
+    Some(Difference {
+        field,
+        offset,
+        left: l,
+        right: r,
+    })
+}
+
+fn field_at(bytes: &[u8], offset: usize) -> Field {
+    let (fixed_len, header) = match FixedHeader::decode(bytes) {
+        Ok(Status::Complete(decoded)) => decoded,
+        _ => return Field::RemainingLength,
+    };
+    if offset < fixed_len {
+        return Field::RemainingLength;
+    }
+
+    let body_offset = offset - fixed_len;
+    if body_offset as u64 >= u64::from(header.len()) {
+        return Field::Trailing;
+    }
+
+    match VariableHeader::decode(header.r#type(), header.flags(), &bytes[fixed_len..]) {
+        None => Field::Payload,
+        Some(Ok(Status::Complete((len, _)))) if body_offset >= len => Field::Payload,
+        Some(_) => Field::VariableHeader,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let hex = "c0 00";
+        let mut buf = [0u8; 2];
+        let packet = packet_from_hex(hex, &mut buf).expect("decoded");
+        assert_eq!(Ok(String::from(hex)), encode_hex(&packet));
+        assert_eq!(Ok(String::from(hex)), encode_hex(&Packet::pingreq()));
+    }
+
+    #[test]
+    fn errors() {
+        let mut buf = [0u8; 2];
+        assert_eq!(Ok(2), from_hex("C00\n0", &mut buf));
+        assert_eq!(Err(Error::InvalidHex(3)), from_hex("c0 x0", &mut buf));
+        assert_eq!(Err(Error::OddLength), from_hex("c00", &mut buf));
+        assert_eq!(Err(Error::OutOfSpace), from_hex("c0 00 00", &mut buf));
+        assert_eq!(
+            Err(Error::Partial(1)),
+            packet_from_hex("c0", &mut buf).map(|_| ())
+        );
+
+        let mut buf = [0u8; 4];
+        assert_eq!(
+            Err(Error::TrailingBytes(2)),
+            packet_from_hex("c0 00 c0 00", &mut buf).map(|_| ())
+        );
+    }
+
+    #[test]
+    fn differences() {
+        use crate::{
+            fixed_header::PublishFlags, qos::QoS, variable_header::publish::Publish,
+            variable_header::PacketId,
+        };
+        use std::string::ToString;
+
+        let publish = |flags, payload: &'static [u8]| {
+            Packet::publish(flags, Publish::new("a/b", Some(PacketId::new(7))), payload).unwrap()
+        };
+        let flags = PublishFlags::new(QoS::AtLeastOnce, false, false);
+
+        let left = publish(flags, b"abc");
+        assert_eq!(Ok(None), diff(&left, &publish(flags, b"abc")));
+
+        let difference = diff(&left, &publish(flags, b"abd")).unwrap().unwrap();
+        assert_eq!(
+            Difference {
+                field: Field::Payload,
+                offset: 11,
+                left: Some(b'c'),
+                right: Some(b'd'),
+            },
+            difference
+        );
+        assert_eq!(
+            "payload differs at offset 11: 0x63 != 0x64",
+            difference.to_string()
+        );
+
+        let retained = PublishFlags::new(QoS::AtLeastOnce, false, true);
+        assert_eq!(
+            Some(Field::Flags),
+            diff(&left, &publish(retained, b"abc"))
+                .unwrap()
+                .map(|d| d.field)
+        );
+        assert_eq!(
+            Some(Field::RemainingLength),
+            diff(&left, &publish(flags, b"ab"))
+                .unwrap()
+                .map(|d| d.field)
+        );
+        assert_eq!(
+            Some(Field::PacketType),
+            diff(&left, &Packet::pingreq()).unwrap().map(|d| d.field)
+        );
+
+        let mut bytes = [0u8; 16];
+        let len = left.encode(&mut bytes).unwrap();
+        let mut other = bytes;
+        other[8] = 8;
+        assert_eq!(
+            Some(Field::VariableHeader),
+            diff_encoded(&bytes[..len], &other[..len]).map(|d| d.field)
+        );
+
+        let difference = diff_encoded(&bytes[..len], &bytes[..len + 1]).unwrap();
+        assert_eq!(
+            "trailing data differs at offset 12: end of packet != 0x00",
+            difference.to_string()
+        );
+    }
+}
@@ -0,0 +1,229 @@
+//! Validated topic names and filters.
+//!
+//! Topics compare, hash and sort byte for byte, matching is case sensitive and
+//! no normalisation is applied [MQTT-4.7.3-4], so both types can be used
+//! directly as keys in maps and sorted tables. They borrow as `str` so a map
+//! keyed by them can be queried with a plain string.
+
+use core::{borrow::Borrow, fmt, ops::Deref, result::Result};
+
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub enum Error {
+    /// The topic is empty [MQTT-4.7.3-1]
+    Empty,
+    /// The topic does not fit a two byte length prefix
+    TooLong,
+    /// A topic name contains a wildcard or a filter misuses one
+    InvalidWildcard,
+}
+
+impl Error {
+    #[cfg(not(feature = "tiny"))]
+    fn desc(&self) -> &'static str {
+        match *self {
+            Error::Empty => "empty topic",
+            Error::TooLong => "topic longer than 65535 bytes",
+            Error::InvalidWildcard => "invalid use of topic wildcard",
+        }
+    }
+
+    #[cfg(feature = "tiny")]
+    fn desc(&self) -> &'static str {
+        "topic error"
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.desc())
+    }
+}
+
+#[cfg(feature = "std")]
+impl ::std::error::Error for Error {
+    fn description(&self) -> &str {
+        self.desc()
+    }
+}
+
+/// A topic name a PUBLISH is sent to, without wildcards.
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub struct TopicName<'a>(&'a str);
+
+impl<'a> TopicName<'a> {
+    pub fn new<T: AsRef<str> + ?Sized>(name: &'a T) -> Result<Self, Error> {
+        let name = name.as_ref();
+        check_len(name)?;
+
+        // [MQTT-4.7.1-1]
+        if name.contains(['+', '#']) {
+            return Err(Error::InvalidWildcard);
+        }
+
+        Ok(TopicName(name))
+    }
+
+    pub fn as_str(&self) -> &'a str {
+        self.0
+    }
+}
+
+/// A topic filter a SUBSCRIBE asks for, possibly with wildcards.
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub struct TopicFilter<'a>(&'a str);
+
+impl<'a> TopicFilter<'a> {
+    pub fn new<T: AsRef<str> + ?Sized>(filter: &'a T) -> Result<Self, Error> {
+        let filter = filter.as_ref();
+        check_len(filter)?;
+
+        if !valid_wildcards(filter) {
+            return Err(Error::InvalidWildcard);
+        }
+
+        Ok(TopicFilter(filter))
+    }
+
+    pub fn as_str(&self) -> &'a str {
+        self.0
+    }
+
+    /// Whether a topic name matches this filter, see `TopicTrie` to match
+    /// against many filters at once.
+    ///
+    /// Wildcards at the first level do not match topic names beginning with
+    /// `$` [MQTT-4.7.2-1].
+    pub fn matches(&self, topic: TopicName) -> bool {
+        if topic.starts_with('$') && (self.starts_with('+') || self.starts_with('#')) {
+            return false;
+        }
+
+        let mut filter = self.split('/');
+        let mut topic = topic.split('/');
+        loop {
+            match (filter.next(), topic.next()) {
+                (Some("#"), _) => return true,
+                (Some(filter), Some(topic)) if filter == "+" || filter == topic => {}
+                (None, None) => return true,
+                _ => return false,
+            }
+        }
+    }
+}
+
+macro_rules! str_traits {
+    ($($name:ident),+) => ($(
+        impl<'a> Deref for $name<'a> {
+            type Target = str;
+
+            fn deref(&self) -> &str {
+                self.0
+            }
+        }
+
+        impl<'a> Borrow<str> for $name<'a> {
+            fn borrow(&self) -> &str {
+                self.0
+            }
+        }
+
+        impl<'a> AsRef<str> for $name<'a> {
+            fn as_ref(&self) -> &str {
+                self.0
+            }
+        }
+
+        impl<'a> fmt::Display for $name<'a> {
+            fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str(self.0)
+            }
+        }
+    )+)
+}
+
+str_traits!(TopicName, TopicFilter);
+
+fn check_len(topic: &str) -> Result<(), Error> {
+    if topic.is_empty() {
+        return Err(Error::Empty);
+    }
+
+    if topic.len() > usize::from(u16::MAX) {
+        return Err(Error::TooLong);
+    }
+
+    Ok(())
+}
+
+/// Whether `+` only appears as a whole level and `#` only as the whole last
+/// level [MQTT-4.7.1-2] [MQTT-4.7.1-3].
+pub(crate) fn valid_wildcards(filter: &str) -> bool {
+    let mut levels = filter.split('/').peekable();
+    while let Some(level) = levels.next() {
+        let valid = match level {
+            "+" => true,
+            "#" => levels.peek().is_none(),
+            _ => !level.contains(['+', '#']),
+        };
+        if !valid {
+            return false;
+        }
+    }
+
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::{BTreeMap, HashSet};
+
+    #[test]
+    fn validate() {
+        assert!(TopicName::new("a/b").is_ok());
+        assert_eq!(Err(Error::Empty), TopicName::new(""));
+        assert_eq!(Err(Error::InvalidWildcard), TopicName::new("a/+"));
+
+        assert!(TopicFilter::new("a/+/#").is_ok());
+        assert_eq!(Err(Error::InvalidWildcard), TopicFilter::new("a/#/b"));
+        assert_eq!(Err(Error::InvalidWildcard), TopicFilter::new("a+"));
+    }
+
+    #[test]
+    fn matches() {
+        let matches = |filter, topic| {
+            TopicFilter::new(filter)
+                .unwrap()
+                .matches(TopicName::new(topic).unwrap())
+        };
+        assert!(matches("a/+/c", "a/b/c"));
+        assert!(matches("a/#", "a"));
+        assert!(matches("a/#", "a/b/c"));
+        assert!(matches("#", "a/b"));
+        assert!(!matches("a/+", "a/b/c"));
+        assert!(!matches("a/b", "a/b/c"));
+        assert!(!matches("#", "$SYS/uptime"));
+        assert!(matches("$SYS/#", "$SYS/uptime"));
+    }
+
+    #[test]
+    fn keys() {
+        let upper = TopicName::new("Sensors").unwrap();
+        let lower = TopicName::new("sensors").unwrap();
+        assert_ne!(upper, lower);
+        // byte order puts upper case first
+        assert!(upper < lower);
+
+        let mut map = BTreeMap::new();
+        map.insert(lower, 1);
+        map.insert(upper, 2);
+        assert_eq!(Some(&1), map.get("sensors"));
+
+        let filters: HashSet<_> = ["a/#", "a/+", "a/#"]
+            .iter()
+            .map(|filter| TopicFilter::new(filter).unwrap())
+            .collect();
+        assert_eq!(2, filters.len());
+        assert!(filters.contains("a/+"));
+    }
+}
@@ -0,0 +1,172 @@
+//! A fixed capacity registry interning topic names and filters to small ids.
+//!
+//! A node publishing to a handful of long topics can register each one once
+//! and pass the `TopicId` around instead, comparing ids rather than strings.
+//! Ids also make compact subscription table values, a
+//! `topic_trie::TopicTrie<TopicId, N>` yields the id of every filter matching a
+//! PUBLISH for application dispatch code to switch on.
+
+use core::{convert::TryFrom, fmt, result::Result};
+
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub enum Error {
+    /// Every slot is in use
+    Full,
+    /// The topic is empty [MQTT-4.7.3-1]
+    InvalidTopic,
+}
+
+impl Error {
+    #[cfg(not(feature = "tiny"))]
+    fn desc(&self) -> &'static str {
+        match *self {
+            Error::Full => "topic registry full",
+            Error::InvalidTopic => "empty topic",
+        }
+    }
+
+    #[cfg(feature = "tiny")]
+    fn desc(&self) -> &'static str {
+        "topic registry error"
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.desc())
+    }
+}
+
+#[cfg(feature = "std")]
+impl ::std::error::Error for Error {
+    fn description(&self) -> &str {
+        self.desc()
+    }
+}
+
+/// The id of a topic in a `TopicRegistry`, valid until the topic is removed.
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub struct TopicId(u16);
+
+impl TopicId {
+    pub fn get(self) -> u16 {
+        self.0
+    }
+}
+
+/// Interns up to `N` topic names or filters, at most 65536 are usable.
+#[derive(Debug)]
+pub struct TopicRegistry<'a, const N: usize> {
+    topics: [Option<&'a str>; N],
+    len: usize,
+}
+
+impl<'a, const N: usize> Default for TopicRegistry<'a, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<'a, const N: usize> TopicRegistry<'a, N> {
+    pub fn new() -> Self {
+        Self {
+            topics: [None; N],
+            len: 0,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// The id of `topic`, registering it if it is not already registered.
+    pub fn intern(&mut self, topic: &'a str) -> Result<TopicId, Error> {
+        if topic.is_empty() {
+            return Err(Error::InvalidTopic);
+        }
+
+        if let Some(id) = self.id(topic) {
+            return Ok(id);
+        }
+
+        let index = self
+            .topics
+            .iter()
+            .position(Option::is_none)
+            .ok_or(Error::Full)?;
+        let id = u16::try_from(index).map_err(|_| Error::Full)?;
+        self.topics[index] = Some(topic);
+        self.len += 1;
+
+        Ok(TopicId(id))
+    }
+
+    /// The id of a registered topic, compared byte for byte.
+    pub fn id(&self, topic: &str) -> Option<TopicId> {
+        self.topics
+            .iter()
+            .position(|registered| *registered == Some(topic))
+            .map(|index| TopicId(index as u16))
+    }
+
+    /// The topic registered with `id`.
+    pub fn topic(&self, id: TopicId) -> Option<&'a str> {
+        self.topics.get(usize::from(id.0)).copied().flatten()
+    }
+
+    /// Unregister a topic, its id may be reused by a later `intern`.
+    pub fn remove(&mut self, id: TopicId) -> Option<&'a str> {
+        let topic = self.topics.get_mut(usize::from(id.0))?.take()?;
+        self.len -= 1;
+        Some(topic)
+    }
+
+    pub fn clear(&mut self) {
+        self.topics = [None; N];
+        self.len = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::topic_trie::TopicTrie;
+    use std::vec::Vec;
+
+    #[test]
+    fn intern() {
+        let mut registry = TopicRegistry::<2>::new();
+        let temp = registry.intern("site/42/sensors/temp").unwrap();
+        assert_eq!(Ok(temp), registry.intern("site/42/sensors/temp"));
+        assert_eq!(Some("site/42/sensors/temp"), registry.topic(temp));
+        assert_eq!(Err(Error::InvalidTopic), registry.intern(""));
+
+        let humidity = registry.intern("site/42/sensors/humidity").unwrap();
+        assert_ne!(temp, humidity);
+        assert_eq!(Err(Error::Full), registry.intern("site/42/alerts"));
+        assert_eq!(2, registry.len());
+
+        assert_eq!(Some("site/42/sensors/temp"), registry.remove(temp));
+        assert_eq!(None, registry.topic(temp));
+        assert_eq!(None, registry.id("site/42/sensors/temp"));
+        assert_eq!(Ok(temp), registry.intern("site/42/alerts"));
+    }
+
+    #[test]
+    fn subscription_table() {
+        let mut registry = TopicRegistry::<4>::new();
+        let mut trie = TopicTrie::<TopicId, 8>::new();
+        for filter in ["site/+/sensors/#", "site/42/alerts"] {
+            trie.insert(filter, registry.intern(filter).unwrap())
+                .unwrap();
+        }
+
+        let mut matched = Vec::new();
+        trie.matches("site/42/sensors/temp", |id| matched.push(*id));
+        assert_eq!([registry.id("site/+/sensors/#").unwrap()], matched[..]);
+    }
+}
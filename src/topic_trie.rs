@@ -0,0 +1,354 @@
+//! A fixed capacity trie of topic filters for matching PUBLISH topic names.
+//!
+//! Each node holds one level of a topic filter, so matching a topic name only
+//! visits the filters that share its levels instead of every subscription.
+//! Filters and topic names are borrowed, nothing is allocated.
+
+use core::{fmt, result::Result};
+
+use crate::topic;
+
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub enum Error {
+    /// Not enough free nodes to insert the filter
+    Full,
+    /// The topic filter is empty or misuses a wildcard
+    InvalidFilter,
+}
+
+impl Error {
+    #[cfg(not(feature = "tiny"))]
+    fn desc(&self) -> &'static str {
+        match *self {
+            Error::Full => "not enough free nodes in topic trie",
+            Error::InvalidFilter => "invalid topic filter",
+        }
+    }
+
+    #[cfg(feature = "tiny")]
+    fn desc(&self) -> &'static str {
+        "topic trie error"
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.desc())
+    }
+}
+
+#[cfg(feature = "std")]
+impl ::std::error::Error for Error {
+    fn description(&self) -> &str {
+        self.desc()
+    }
+}
+
+#[derive(Debug)]
+struct Node<'a, T> {
+    level: &'a str,
+    parent: Option<usize>,
+    child: Option<usize>,
+    sibling: Option<usize>,
+    value: Option<T>,
+}
+
+/// A trie of topic filters, each mapped to a value, using up to `N` nodes.
+///
+/// A filter uses one node per level, levels shared with other filters are
+/// stored once.
+#[derive(Debug)]
+pub struct TopicTrie<'a, T, const N: usize> {
+    nodes: [Option<Node<'a, T>>; N],
+    root: Option<usize>,
+    free: usize,
+}
+
+impl<'a, T, const N: usize> Default for TopicTrie<'a, T, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<'a, T, const N: usize> TopicTrie<'a, T, N> {
+    pub fn new() -> Self {
+        Self {
+            nodes: core::array::from_fn(|_| None),
+            root: None,
+            free: N,
+        }
+    }
+
+    /// Number of unused nodes.
+    pub fn free(&self) -> usize {
+        self.free
+    }
+
+    /// Insert a topic filter, returning the value it previously mapped to.
+    pub fn insert(&mut self, filter: &'a str, value: T) -> Result<Option<T>, Error> {
+        validate(filter)?;
+
+        let mut parent = None;
+        let mut levels = filter.split('/');
+        let mut missing = 0;
+        for level in levels.by_ref() {
+            match self.find(self.children(parent), level) {
+                Some(index) => parent = Some(index),
+                None => {
+                    missing = 1;
+                    break;
+                }
+            }
+        }
+        missing += levels.count();
+
+        if missing > self.free {
+            return Err(Error::Full);
+        }
+
+        let mut parent = None;
+        for level in filter.split('/') {
+            parent = Some(match self.find(self.children(parent), level) {
+                Some(index) => index,
+                None => self.allocate(parent, level),
+            });
+        }
+
+        let node = self.node_mut(parent.expect("filter has at least one level"));
+        Ok(node.value.replace(value))
+    }
+
+    /// The value a topic filter maps to.
+    pub fn get(&self, filter: &str) -> Option<&T> {
+        self.lookup(filter)
+            .and_then(|index| self.node(index).value.as_ref())
+    }
+
+    /// Remove a topic filter, returning the value it mapped to.
+    pub fn remove(&mut self, filter: &str) -> Option<T> {
+        let index = self.lookup(filter)?;
+        let value = self.node_mut(index).value.take();
+
+        let mut index = Some(index);
+        while let Some(current) = index {
+            let node = self.node(current);
+            if node.value.is_some() || node.child.is_some() {
+                break;
+            }
+
+            index = node.parent;
+            self.release(current);
+        }
+
+        value
+    }
+
+    /// Call `f` with the value of every filter matching a topic name.
+    ///
+    /// Wildcards at the first level do not match topic names beginning with
+    /// `$` [MQTT-4.7.2-1].
+    pub fn matches<F>(&self, topic: &str, mut f: F)
+    where
+        F: FnMut(&T),
+    {
+        self.visit(self.root, topic, true, &mut f);
+    }
+
+    fn visit<F>(&self, head: Option<usize>, topic: &str, first: bool, f: &mut F)
+    where
+        F: FnMut(&T),
+    {
+        let (level, rest) = match topic.find('/') {
+            Some(index) => (&topic[..index], Some(&topic[index + 1..])),
+            None => (topic, None),
+        };
+        let system = first && level.starts_with('$');
+
+        let mut child = head;
+        while let Some(index) = child {
+            let node = self.node(index);
+            child = node.sibling;
+
+            if node.level == "#" {
+                if !system {
+                    node.value.iter().for_each(&mut *f);
+                }
+                continue;
+            }
+
+            if node.level != level && (node.level != "+" || system) {
+                continue;
+            }
+
+            match rest {
+                Some(rest) => self.visit(node.child, rest, false, f),
+                None => {
+                    node.value.iter().for_each(&mut *f);
+                    if let Some(index) = self.find(node.child, "#") {
+                        self.node(index).value.iter().for_each(&mut *f);
+                    }
+                }
+            }
+        }
+    }
+
+    fn lookup(&self, filter: &str) -> Option<usize> {
+        let mut parent = None;
+        for level in filter.split('/') {
+            parent = Some(self.find(self.children(parent), level)?);
+        }
+        parent
+    }
+
+    fn children(&self, parent: Option<usize>) -> Option<usize> {
+        match parent {
+            Some(index) => self.node(index).child,
+            None => self.root,
+        }
+    }
+
+    fn find(&self, mut child: Option<usize>, level: &str) -> Option<usize> {
+        while let Some(index) = child {
+            let node = self.node(index);
+            if node.level == level {
+                return Some(index);
+            }
+            child = node.sibling;
+        }
+        None
+    }
+
+    fn allocate(&mut self, parent: Option<usize>, level: &'a str) -> usize {
+        let index = self
+            .nodes
+            .iter()
+            .position(Option::is_none)
+            .expect("free nodes counted before allocating");
+
+        self.nodes[index] = Some(Node {
+            level,
+            parent,
+            child: None,
+            sibling: self.children(parent),
+            value: None,
+        });
+        self.free -= 1;
+
+        match parent {
+            Some(parent) => self.node_mut(parent).child = Some(index),
+            None => self.root = Some(index),
+        }
+
+        index
+    }
+
+    fn release(&mut self, index: usize) {
+        let node = self.nodes[index].take().expect("released node in use");
+        self.free += 1;
+
+        let head = self.children(node.parent);
+        if head == Some(index) {
+            match node.parent {
+                Some(parent) => self.node_mut(parent).child = node.sibling,
+                None => self.root = node.sibling,
+            }
+            return;
+        }
+
+        let mut previous = head;
+        while let Some(current) = previous {
+            let sibling = self.node(current).sibling;
+            if sibling == Some(index) {
+                self.node_mut(current).sibling = node.sibling;
+                return;
+            }
+            previous = sibling;
+        }
+    }
+
+    fn node(&self, index: usize) -> &Node<'a, T> {
+        self.nodes[index].as_ref().expect("linked node in use")
+    }
+
+    fn node_mut(&mut self, index: usize) -> &mut Node<'a, T> {
+        self.nodes[index].as_mut().expect("linked node in use")
+    }
+}
+
+fn validate(filter: &str) -> Result<(), Error> {
+    if filter.is_empty() || !topic::valid_wildcards(filter) {
+        return Err(Error::InvalidFilter);
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::vec::Vec;
+
+    fn matches<const N: usize>(trie: &TopicTrie<u8, N>, topic: &str) -> Vec<u8> {
+        let mut values = Vec::new();
+        trie.matches(topic, |&value| values.push(value));
+        values.sort();
+        values
+    }
+
+    #[test]
+    fn wildcards() {
+        let mut trie = TopicTrie::<u8, 16>::new();
+        trie.insert("sport/tennis/player1", 1).unwrap();
+        trie.insert("sport/tennis/+", 2).unwrap();
+        trie.insert("sport/#", 3).unwrap();
+        trie.insert("+/+", 4).unwrap();
+        trie.insert("#", 5).unwrap();
+        trie.insert("sport/tennis/player1/#", 6).unwrap();
+
+        assert_eq!(matches(&trie, "sport/tennis/player1"), [1, 2, 3, 5, 6]);
+        assert_eq!(matches(&trie, "sport/tennis"), [3, 4, 5]);
+        assert_eq!(matches(&trie, "sport"), [3, 5]);
+        assert_eq!(matches(&trie, "weather/"), [4, 5]);
+        assert_eq!(matches(&trie, "$SYS/uptime"), Vec::<u8>::new());
+    }
+
+    #[test]
+    fn system_topics() {
+        let mut trie = TopicTrie::<u8, 8>::new();
+        trie.insert("$SYS/#", 1).unwrap();
+        trie.insert("$SYS/+", 2).unwrap();
+        trie.insert("+/uptime", 3).unwrap();
+
+        assert_eq!(matches(&trie, "$SYS/uptime"), [1, 2]);
+    }
+
+    #[test]
+    fn insert_remove() {
+        let mut trie = TopicTrie::<u8, 4>::new();
+        assert_eq!(trie.insert("a/b/c", 1), Ok(None));
+        assert_eq!(trie.insert("a/b/c", 2), Ok(Some(1)));
+        assert_eq!(trie.insert("a/d", 3), Ok(None));
+        assert_eq!(trie.free(), 0);
+        assert_eq!(trie.insert("a/e", 4), Err(Error::Full));
+        assert_eq!(trie.insert("a/b", 4), Ok(None));
+
+        assert_eq!(trie.remove("a/b/c"), Some(2));
+        assert_eq!(trie.remove("a/b/c"), None);
+        assert_eq!(trie.free(), 1);
+        assert_eq!(trie.get("a/b"), Some(&4));
+
+        assert_eq!(trie.remove("a/b"), Some(4));
+        assert_eq!(trie.remove("a/d"), Some(3));
+        assert_eq!(trie.free(), 4);
+        assert_eq!(matches(&trie, "a/d"), Vec::<u8>::new());
+    }
+
+    #[test]
+    fn invalid_filters() {
+        let mut trie = TopicTrie::<u8, 4>::new();
+        for filter in ["", "a/#/b", "a+", "a/b#"] {
+            assert_eq!(trie.insert(filter, 0), Err(Error::InvalidFilter));
+        }
+        assert_eq!(trie.free(), 4);
+    }
+}
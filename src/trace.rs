@@ -0,0 +1,57 @@
+//! `tracing` instrumentation, enabled with the `tracing` feature.
+//!
+//! `Packet::decode` and `Packet::encode` run inside `mqtt.decode` and
+//! `mqtt.encode` spans and emit an event with the packet type, identifier and
+//! size or the error once they finish. Decode failures are logged at `debug`
+//! since the bytes come from the remote end, encode failures at `warn`. The
+//! shutdown and QoS 2 state machines emit an event on every transition, so MQTT
+//! activity can be correlated with the rest of an application's traces.
+
+use core::result::Result;
+
+use tracing::{debug, trace, warn};
+
+use crate::{packet::Packet, variable_header::PacketId};
+
+#[cfg(feature = "encode")]
+use crate::error::EncodeError;
+#[cfg(feature = "decode")]
+use crate::{error::DecodeError, status::Status};
+
+fn packet_id(packet: &Packet) -> Option<u16> {
+    packet
+        .variable_header()?
+        .packet_identifier()
+        .map(PacketId::get)
+}
+
+#[cfg(feature = "decode")]
+pub(crate) fn decoded(result: &Result<Status<(usize, Packet)>, DecodeError>) {
+    match result {
+        Ok(Status::Complete((len, packet))) => debug!(
+            packet_type = ?packet.fixed_header().r#type(),
+            packet_id = ?packet_id(packet),
+            len,
+            "decoded packet"
+        ),
+        Ok(Status::Partial(needed)) => trace!(needed, "partial packet"),
+        Err(error) => debug!(%error, "decode failed"),
+    }
+}
+
+#[cfg(feature = "encode")]
+pub(crate) fn encoded(packet: &Packet, result: &Result<usize, EncodeError>) {
+    match result {
+        Ok(len) => debug!(
+            packet_type = ?packet.fixed_header().r#type(),
+            packet_id = ?packet_id(packet),
+            len,
+            "encoded packet"
+        ),
+        Err(error) => warn!(
+            packet_type = ?packet.fixed_header().r#type(),
+            %error,
+            "encode failed"
+        ),
+    }
+}
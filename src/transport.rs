@@ -0,0 +1,291 @@
+//! Transport agnostic traits for sending encoded packets and receiving bytes.
+//!
+//! Code written against `PacketSink` and `PacketSource` works over any
+//! transport with an adapter, the `smoltcp` TCP socket implements both with
+//! the `smoltcp` feature, `Io` wraps a `std::io` stream with the `std`
+//! feature and `EmbeddedIo` wraps an `embedded-io` stream with the
+//! `embedded-io` feature.
+
+use core::{fmt, result::Result};
+
+use crate::error::{DecodeError, EncodeError};
+
+#[cfg(feature = "encode")]
+use crate::codec::Encodable;
+#[cfg(any(feature = "encode", feature = "decode"))]
+use crate::packet::Packet;
+#[cfg(feature = "decode")]
+use crate::{codec::Decodable, status::Status};
+
+/// Something that can accept an encoded packet.
+pub trait PacketSink {
+    type Error;
+
+    /// Send a complete encoded packet.
+    ///
+    /// Returns `Ok(false)` without sending anything if the packet cannot be
+    /// accepted in full right now, the caller should try again later.
+    fn send(&mut self, packet: &[u8]) -> Result<bool, Self::Error>;
+}
+
+/// Something that yields received byte chunks.
+pub trait PacketSource {
+    type Error;
+
+    /// Read received bytes into `buf`, returning the number read.
+    ///
+    /// Returns `Ok(0)` when no bytes are available right now.
+    fn recv(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error>;
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Error<E> {
+    /// Encoding the outgoing packet failed
+    Encode(EncodeError),
+    /// Decoding the incoming packet failed
+    Decode(DecodeError),
+    /// The receive buffer is full but does not hold a complete packet
+    BufferFull,
+    /// The transport failed
+    Transport(E),
+}
+
+impl<E: fmt::Display> fmt::Display for Error<E> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Encode(e) => write!(f, "encode failed: {}", e),
+            Error::Decode(e) => write!(f, "decode failed: {}", e),
+            Error::BufferFull => f.write_str("receive buffer too small for packet"),
+            Error::Transport(e) => write!(f, "transport failed: {}", e),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl<E: fmt::Debug + fmt::Display> ::std::error::Error for Error<E> {}
+
+impl<E> From<EncodeError> for Error<E> {
+    fn from(err: EncodeError) -> Self {
+        Error::Encode(err)
+    }
+}
+
+impl<E> From<DecodeError> for Error<E> {
+    fn from(err: DecodeError) -> Self {
+        Error::Decode(err)
+    }
+}
+
+/// Encode a packet into `scratch` and send it.
+///
+/// Returns `Ok(false)` if the sink cannot accept the packet right now.
+#[cfg(feature = "encode")]
+pub fn send_packet<S: PacketSink>(
+    sink: &mut S,
+    packet: &Packet,
+    scratch: &mut [u8],
+) -> Result<bool, Error<S::Error>> {
+    let written = packet.encode(scratch)?;
+    sink.send(&scratch[..written]).map_err(Error::Transport)
+}
+
+/// Read any received bytes into `buf[*len..]` and attempt to decode a packet
+/// from `buf[..*len]`.
+///
+/// Returns `Ok(None)` when more bytes are needed. Once a packet is returned and
+/// has been handled, call `consume` with the number of bytes it occupied to
+/// make room for the next packet.
+#[cfg(feature = "decode")]
+pub fn recv_packet<'b, S: PacketSource>(
+    source: &mut S,
+    buf: &'b mut [u8],
+    len: &mut usize,
+) -> Result<Option<(usize, Packet<'b>)>, Error<S::Error>> {
+    if *len < buf.len() {
+        *len += source.recv(&mut buf[*len..]).map_err(Error::Transport)?;
+    }
+
+    let buf: &'b [u8] = buf;
+    match Packet::decode(&buf[..*len])? {
+        Status::Complete((consumed, packet)) => Ok(Some((consumed, packet))),
+        Status::Partial(..) if *len == buf.len() => Err(Error::BufferFull),
+        Status::Partial(..) => Ok(None),
+    }
+}
+
+/// Discard a decoded packet of `consumed` bytes from the front of `buf`.
+pub fn consume(buf: &mut [u8], len: &mut usize, consumed: usize) {
+    buf.copy_within(consumed..*len, 0);
+    *len -= consumed;
+}
+
+/// Adapts a `std::io` stream, such as a `TcpStream`, to the transport traits.
+///
+/// A non-blocking stream that would block sends or receives nothing, end of
+/// stream is reported as an `UnexpectedEof` error.
+#[cfg(feature = "std")]
+#[derive(Debug)]
+pub struct Io<T>(pub T);
+
+#[cfg(feature = "std")]
+impl<T: ::std::io::Write> PacketSink for Io<T> {
+    type Error = ::std::io::Error;
+
+    fn send(&mut self, packet: &[u8]) -> Result<bool, Self::Error> {
+        self.0.write_all(packet)?;
+        Ok(true)
+    }
+}
+
+#[cfg(feature = "std")]
+impl<T: ::std::io::Read> PacketSource for Io<T> {
+    type Error = ::std::io::Error;
+
+    fn recv(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        use std::io::ErrorKind;
+
+        match self.0.read(buf) {
+            Ok(0) if !buf.is_empty() => Err(ErrorKind::UnexpectedEof.into()),
+            Err(ref e) if e.kind() == ErrorKind::WouldBlock => Ok(0),
+            result => result,
+        }
+    }
+}
+
+/// Adapts an `embedded-io` stream, such as a HAL UART or an `embassy-net` TCP
+/// socket in blocking mode, to the transport traits.
+///
+/// Sends and receives block like the underlying stream, end of stream is
+/// reported as `ReadExactError::UnexpectedEof`.
+#[cfg(feature = "embedded-io")]
+#[derive(Debug)]
+pub struct EmbeddedIo<T>(pub T);
+
+#[cfg(feature = "embedded-io")]
+impl<T: embedded_io::Write> PacketSink for EmbeddedIo<T> {
+    type Error = T::Error;
+
+    fn send(&mut self, packet: &[u8]) -> Result<bool, Self::Error> {
+        self.0.write_all(packet)?;
+        Ok(true)
+    }
+}
+
+#[cfg(feature = "embedded-io")]
+impl<T: embedded_io::Read> PacketSource for EmbeddedIo<T> {
+    type Error = embedded_io::ReadExactError<T::Error>;
+
+    fn recv(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        match self.0.read(buf) {
+            Ok(0) if !buf.is_empty() => Err(embedded_io::ReadExactError::UnexpectedEof),
+            result => result.map_err(embedded_io::ReadExactError::Other),
+        }
+    }
+}
+
+#[cfg(all(test, feature = "encode", feature = "decode"))]
+mod tests {
+    use super::*;
+    use crate::fixed_header::PacketType;
+    use std::vec::Vec;
+
+    /// Accepts up to `capacity` bytes and yields them back in two byte chunks.
+    struct Pipe {
+        bytes: Vec<u8>,
+        read: usize,
+        capacity: usize,
+    }
+
+    impl PacketSink for Pipe {
+        type Error = ();
+
+        fn send(&mut self, packet: &[u8]) -> Result<bool, ()> {
+            if self.bytes.len() + packet.len() > self.capacity {
+                return Ok(false);
+            }
+
+            self.bytes.extend_from_slice(packet);
+            Ok(true)
+        }
+    }
+
+    impl PacketSource for Pipe {
+        type Error = ();
+
+        fn recv(&mut self, buf: &mut [u8]) -> Result<usize, ()> {
+            let n = buf.len().min(2).min(self.bytes.len() - self.read);
+            buf[..n].copy_from_slice(&self.bytes[self.read..self.read + n]);
+            self.read += n;
+            Ok(n)
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        let mut pipe = Pipe {
+            bytes: Vec::new(),
+            read: 0,
+            capacity: 4,
+        };
+        let mut scratch = [0u8; 8];
+        assert_eq!(
+            Ok(true),
+            send_packet(&mut pipe, &Packet::pingreq(), &mut scratch)
+        );
+        assert_eq!(
+            Ok(true),
+            send_packet(&mut pipe, &Packet::pingresp(), &mut scratch)
+        );
+        assert_eq!(
+            Ok(false),
+            send_packet(&mut pipe, &Packet::pingreq(), &mut scratch)
+        );
+
+        let mut buf = [0u8; 3];
+        let mut len = 0;
+        let mut received = Vec::new();
+        while received.len() < 2 {
+            if let Some((consumed, packet)) = recv_packet(&mut pipe, &mut buf, &mut len).unwrap() {
+                received.push(packet.fixed_header().r#type());
+                consume(&mut buf, &mut len, consumed);
+            }
+        }
+
+        assert_eq!([PacketType::Pingreq, PacketType::Pingresp], received[..]);
+        assert_eq!(
+            Ok(None),
+            recv_packet(&mut pipe, &mut buf, &mut len).map(|p| p.map(|_| ()))
+        );
+    }
+
+    #[cfg(feature = "embedded-io")]
+    #[test]
+    fn embedded_io() {
+        let mut bytes = [0u8; 4];
+        let mut scratch = [0u8; 2];
+        let mut sink = EmbeddedIo(&mut bytes[..]);
+        assert_eq!(
+            Ok(true),
+            send_packet(&mut sink, &Packet::pingreq(), &mut scratch)
+        );
+        assert_eq!(
+            Ok(true),
+            send_packet(&mut sink, &Packet::pingresp(), &mut scratch)
+        );
+
+        let mut source = EmbeddedIo(&bytes[..]);
+        let mut buf = [0u8; 2];
+        let mut len = 0;
+        for expected in [PacketType::Pingreq, PacketType::Pingresp] {
+            let (consumed, packet) = recv_packet(&mut source, &mut buf, &mut len)
+                .unwrap()
+                .unwrap();
+            assert_eq!(expected, packet.fixed_header().r#type());
+            consume(&mut buf, &mut len, consumed);
+        }
+        assert_eq!(
+            Err(Error::Transport(embedded_io::ReadExactError::UnexpectedEof)),
+            recv_packet(&mut source, &mut buf, &mut len).map(|p| p.map(|_| ()))
+        );
+    }
+}
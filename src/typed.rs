@@ -0,0 +1,276 @@
+//! Typed views of decoded packets.
+//!
+//! A `Packet` keeps its variable header and payload apart, so reading a
+//! PUBLISH means matching both and parsing the flags from the fixed header.
+//! The views gather the fields of one packet type into a single struct, they
+//! are returned by `Packet::as_publish`, `Packet::as_connack` etc. when the
+//! packet has the matching type.
+//!
+//! Each view also converts from a packet with `TryFrom`, failing with
+//! `WrongType`, so dispatch code can convert once and propagate the error with
+//! `?`. `Packet::typed` returns a `TypedPacket` covering every packet type, to
+//! dispatch on with an exhaustive `match`.
+
+use core::{convert::TryFrom, fmt, result::Result};
+
+use crate::{
+    fixed_header::{PacketType, PublishFlags},
+    packet::Packet,
+    payload::{
+        connect::{SecretBytes, Will},
+        suback::Suback,
+        subscribe::Subscribe,
+        unsubscribe::Unsubscribe,
+    },
+    variable_header::{
+        connack::ReturnCode,
+        connect::{Flags, KeepAliveInterval, Level},
+        PacketId,
+    },
+};
+
+/// A packet of any type, returned by `Packet::typed`.
+#[derive(Clone, Debug)]
+pub enum TypedPacket<'a> {
+    Connect(ConnectView<'a>),
+    Connack(ConnackView),
+    Publish(PublishView<'a>),
+    Puback {
+        packet_identifier: PacketId,
+    },
+    Pubrec {
+        packet_identifier: PacketId,
+    },
+    Pubrel {
+        packet_identifier: PacketId,
+    },
+    Pubcomp {
+        packet_identifier: PacketId,
+    },
+    Subscribe(SubscribeView<'a>),
+    Suback(SubackView<'a>),
+    Unsubscribe(UnsubscribeView<'a>),
+    Unsuback {
+        packet_identifier: PacketId,
+    },
+    Pingreq,
+    Pingresp,
+    Disconnect,
+    /// A reserved packet type, only decoded when
+    /// `DecodeOptions::allow_reserved_types` is set.
+    Reserved {
+        r#type: u8,
+        body: &'a [u8],
+    },
+}
+
+/// A CONNECT, with the payload fields its flags announce.
+#[derive(Copy, Clone, Debug)]
+pub struct ConnectView<'a> {
+    pub level: Level,
+    pub flags: Flags,
+    pub keep_alive: KeepAliveInterval,
+    pub client_id: &'a str,
+    pub will: Option<Will<'a>>,
+    pub username: Option<&'a str>,
+    pub password: Option<SecretBytes<'a>>,
+}
+
+/// A PUBLISH.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct PublishView<'a> {
+    pub topic_name: &'a str,
+    pub flags: PublishFlags,
+    /// `None` at QoS 0
+    pub packet_identifier: Option<PacketId>,
+    /// The application message
+    pub payload: &'a [u8],
+}
+
+/// A CONNACK.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct ConnackView {
+    pub session_present: bool,
+    pub return_code: ReturnCode,
+}
+
+/// A SUBACK, with a return code per topic filter of the SUBSCRIBE.
+#[derive(Copy, Clone, Debug)]
+pub struct SubackView<'a> {
+    pub packet_identifier: PacketId,
+    pub return_codes: Suback<'a>,
+}
+
+/// A SUBSCRIBE.
+#[derive(Copy, Clone, Debug)]
+pub struct SubscribeView<'a> {
+    pub packet_identifier: PacketId,
+    pub topics: Subscribe<'a>,
+}
+
+/// An UNSUBSCRIBE.
+#[derive(Copy, Clone, Debug)]
+pub struct UnsubscribeView<'a> {
+    pub packet_identifier: PacketId,
+    pub filters: Unsubscribe<'a>,
+}
+
+/// A packet converted into the view of another packet type, or a PUBLISH with
+/// invalid flags.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct WrongType {
+    pub expected: PacketType,
+    pub actual: PacketType,
+}
+
+impl WrongType {
+    fn desc(&self) -> &'static str {
+        "wrong packet type"
+    }
+}
+
+impl fmt::Display for WrongType {
+    #[cfg(not(feature = "tiny"))]
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}, expected {:?} got {:?}",
+            self.desc(),
+            self.expected,
+            self.actual
+        )
+    }
+
+    #[cfg(feature = "tiny")]
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.desc())
+    }
+}
+
+#[cfg(feature = "std")]
+impl ::std::error::Error for WrongType {
+    fn description(&self) -> &str {
+        self.desc()
+    }
+}
+
+macro_rules! try_from_packet {
+    ($($view:ty, $as:ident, $type:ident;)*) => {
+        $(
+            impl<'p, 'a> TryFrom<&'p Packet<'a>> for $view {
+                type Error = WrongType;
+
+                fn try_from(packet: &'p Packet<'a>) -> Result<Self, WrongType> {
+                    packet.$as().ok_or(WrongType {
+                        expected: PacketType::$type,
+                        actual: packet.fixed_header().r#type(),
+                    })
+                }
+            }
+        )*
+    };
+}
+
+try_from_packet!(
+    ConnectView<'a>,     as_connect,     Connect;
+    PublishView<'a>,     as_publish,     Publish;
+    ConnackView,         as_connack,     Connack;
+    SubackView<'a>,      as_suback,      Suback;
+    SubscribeView<'a>,   as_subscribe,   Subscribe;
+    UnsubscribeView<'a>, as_unsubscribe, Unsubscribe;
+);
+
+#[cfg(all(test, feature = "decode"))]
+mod tests {
+    use super::*;
+    use crate::codec::Decodable;
+
+    #[test]
+    fn try_from() {
+        let (_, packet) = Packet::decode(&[0x20, 0x02, 0x00, 0x05]).unwrap().unwrap();
+        assert_eq!(
+            Ok(ConnackView {
+                session_present: false,
+                return_code: ReturnCode::RefusedNotAuthorized,
+            }),
+            ConnackView::try_from(&packet)
+        );
+
+        let error = PublishView::try_from(&packet).unwrap_err();
+        assert_eq!(
+            WrongType {
+                expected: PacketType::Publish,
+                actual: PacketType::Connack,
+            },
+            error
+        );
+        #[cfg(not(feature = "tiny"))]
+        assert_eq!(
+            "wrong packet type, expected Publish got Connack",
+            std::string::ToString::to_string(&error)
+        );
+    }
+
+    #[test]
+    fn typed() {
+        let connect = [
+            0x10, 0x10, 0x00, 0x04, b'M', b'Q', b'T', b'T', 0x04, 0x82, 0x00, 0x3c, 0x00, 0x01,
+            b'c', 0x00, 0x01, b'u',
+        ];
+        let (_, packet) = Packet::decode(&connect).unwrap().unwrap();
+        match packet.typed() {
+            Some(TypedPacket::Connect(connect)) => {
+                assert_eq!(Level::Level3_1_1, connect.level);
+                assert_eq!(KeepAliveInterval::from(60), connect.keep_alive);
+                assert_eq!(
+                    ("c", Some("u"), None),
+                    (connect.client_id, connect.username, connect.password)
+                );
+                assert!(connect.will.is_none());
+            }
+            other => panic!("unexpected packet {:?}", other),
+        }
+
+        let (_, packet) = Packet::decode(&[0x62, 0x02, 0x00, 0x03]).unwrap().unwrap();
+        match packet.typed() {
+            Some(TypedPacket::Pubrel { packet_identifier }) => {
+                assert_eq!(PacketId::new(3), packet_identifier)
+            }
+            other => panic!("unexpected packet {:?}", other),
+        }
+
+        let (_, packet) = Packet::decode(&[0x30, 0x03, 0x00, 0x01, b'a'])
+            .unwrap()
+            .unwrap();
+        match packet.typed() {
+            Some(TypedPacket::Publish(publish)) => {
+                assert_eq!("a", publish.topic_name);
+                assert!(publish.payload.is_empty());
+            }
+            other => panic!("unexpected packet {:?}", other),
+        }
+
+        assert!(matches!(
+            Packet::pingreq().typed(),
+            Some(TypedPacket::Pingreq)
+        ));
+        assert!(matches!(
+            Packet::disconnect().typed(),
+            Some(TypedPacket::Disconnect)
+        ));
+
+        let options = crate::codec::DecodeOptions {
+            allow_reserved_types: true,
+            ..Default::default()
+        };
+        let (_, packet) = Packet::decode_with(&[0xf0, 0x01, 0x18], &options)
+            .unwrap()
+            .unwrap();
+        match packet.typed() {
+            Some(TypedPacket::Reserved { r#type, body }) => {
+                assert_eq!((15, &[0x18][..]), (r#type, body))
+            }
+            other => panic!("unexpected packet {:?}", other),
+        }
+    }
+}
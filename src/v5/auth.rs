@@ -0,0 +1,327 @@
+//! Client side of MQTT 5 enhanced authentication.
+//!
+//! The exchange drives a challenge/response method such as SCRAM through an
+//! `Authenticator` supplied by the caller. It produces the Authentication
+//! Method and Authentication Data properties for CONNECT and each AUTH packet
+//! in turn, encoding them is left to the caller's MQTT 5 codec.
+
+use core::{fmt, result::Result};
+
+/// AUTH reason code accepting the authentication.
+pub const SUCCESS: u8 = 0x00;
+/// AUTH reason code continuing the exchange with another step.
+pub const CONTINUE_AUTHENTICATION: u8 = 0x18;
+/// AUTH reason code starting re-authentication of a connected client.
+pub const REAUTHENTICATE: u8 = 0x19;
+
+/// The steps of an authentication method, implemented by the caller.
+pub trait Authenticator {
+    type Error;
+
+    /// Write the client's first message into `out`, returning its length.
+    fn initial(&mut self, out: &mut [u8]) -> Result<usize, Self::Error>;
+
+    /// Write the response to a server challenge into `out`, returning its
+    /// length.
+    fn challenge(&mut self, challenge: &[u8], out: &mut [u8]) -> Result<usize, Self::Error>;
+
+    /// Check the server's final message when authentication succeeds, for
+    /// methods where the client also authenticates the server.
+    fn verify(&mut self, data: &[u8]) -> Result<(), Self::Error>;
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Error<E> {
+    /// A packet arrived that is not valid in the current state
+    Protocol,
+    /// The server named a different authentication method
+    MethodMismatch,
+    /// The server refused the connection with the given reason code
+    Refused(u8),
+    /// The authenticator failed
+    Authenticator(E),
+}
+
+impl<E: fmt::Display> fmt::Display for Error<E> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Protocol => f.write_str("unexpected packet during authentication"),
+            Error::MethodMismatch => f.write_str("authentication method mismatch"),
+            Error::Refused(reason) => write!(f, "authentication refused: {:#04x}", reason),
+            Error::Authenticator(e) => write!(f, "authenticator failed: {}", e),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl<E: fmt::Debug + fmt::Display> ::std::error::Error for Error<E> {}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum State {
+    /// No exchange has started
+    Idle,
+    /// CONNECT has been sent, waiting for AUTH or CONNACK
+    Connecting,
+    /// The server accepted the authentication
+    Authenticated,
+    /// AUTH with reason code re-authenticate has been sent
+    Reauthenticating,
+    /// The exchange failed, the connection must be closed
+    Failed,
+}
+
+/// The Authentication Method and Authentication Data properties to send.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct Properties<'a> {
+    pub method: &'a str,
+    pub data: &'a [u8],
+}
+
+/// An AUTH packet to send.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct Auth<'a> {
+    pub reason_code: u8,
+    pub properties: Properties<'a>,
+}
+
+/// The state of one authentication exchange.
+#[derive(Debug)]
+pub struct Exchange<'m, A> {
+    method: &'m str,
+    authenticator: A,
+    state: State,
+}
+
+impl<'m, A: Authenticator> Exchange<'m, A> {
+    pub fn new(method: &'m str, authenticator: A) -> Self {
+        Self {
+            method,
+            authenticator,
+            state: State::Idle,
+        }
+    }
+
+    pub fn state(&self) -> State {
+        self.state
+    }
+
+    /// Start authenticating a new connection, returning the properties to
+    /// include in CONNECT.
+    pub fn start<'o>(&mut self, out: &'o mut [u8]) -> Result<Properties<'o>, Error<A::Error>>
+    where
+        'm: 'o,
+    {
+        if let State::Connecting | State::Reauthenticating = self.state {
+            return Err(Error::Protocol);
+        }
+
+        let data = self.step(|authenticator, out| authenticator.initial(out), out)?;
+        self.state = State::Connecting;
+
+        Ok(Properties {
+            method: self.method,
+            data,
+        })
+    }
+
+    /// Start re-authenticating a connected client, returning the AUTH packet
+    /// to send.
+    pub fn reauthenticate<'o>(&mut self, out: &'o mut [u8]) -> Result<Auth<'o>, Error<A::Error>>
+    where
+        'm: 'o,
+    {
+        if self.state != State::Authenticated {
+            return Err(Error::Protocol);
+        }
+
+        let data = self.step(|authenticator, out| authenticator.initial(out), out)?;
+        self.state = State::Reauthenticating;
+
+        Ok(Auth {
+            reason_code: REAUTHENTICATE,
+            properties: Properties {
+                method: self.method,
+                data,
+            },
+        })
+    }
+
+    /// Handle an AUTH packet from the server.
+    ///
+    /// Returns the AUTH packet to respond with, or `None` when the server
+    /// accepted a re-authentication.
+    pub fn auth_received<'o>(
+        &mut self,
+        reason_code: u8,
+        method: &str,
+        data: &[u8],
+        out: &'o mut [u8],
+    ) -> Result<Option<Auth<'o>>, Error<A::Error>>
+    where
+        'm: 'o,
+    {
+        if let State::Idle | State::Authenticated | State::Failed = self.state {
+            return self.fail(Error::Protocol);
+        }
+
+        if method != self.method {
+            return self.fail(Error::MethodMismatch);
+        }
+
+        match (reason_code, self.state) {
+            (CONTINUE_AUTHENTICATION, _) => {
+                let data =
+                    self.step(|authenticator, out| authenticator.challenge(data, out), out)?;
+                Ok(Some(Auth {
+                    reason_code: CONTINUE_AUTHENTICATION,
+                    properties: Properties {
+                        method: self.method,
+                        data,
+                    },
+                }))
+            }
+            (SUCCESS, State::Reauthenticating) => {
+                self.verify(data)?;
+                Ok(None)
+            }
+            _ => self.fail(Error::Protocol),
+        }
+    }
+
+    /// Handle the CONNACK ending the exchange for a new connection.
+    pub fn connack_received(
+        &mut self,
+        reason_code: u8,
+        data: &[u8],
+    ) -> Result<(), Error<A::Error>> {
+        if self.state != State::Connecting {
+            return self.fail(Error::Protocol);
+        }
+
+        if reason_code != SUCCESS {
+            return self.fail(Error::Refused(reason_code));
+        }
+
+        self.verify(data)
+    }
+
+    fn step<'o, F>(&mut self, step: F, out: &'o mut [u8]) -> Result<&'o [u8], Error<A::Error>>
+    where
+        F: FnOnce(&mut A, &mut [u8]) -> Result<usize, A::Error>,
+    {
+        match step(&mut self.authenticator, out) {
+            Ok(len) => Ok(&out[..len]),
+            Err(e) => self.fail(Error::Authenticator(e)),
+        }
+    }
+
+    fn verify(&mut self, data: &[u8]) -> Result<(), Error<A::Error>> {
+        match self.authenticator.verify(data) {
+            Ok(()) => {
+                self.state = State::Authenticated;
+                Ok(())
+            }
+            Err(e) => self.fail(Error::Authenticator(e)),
+        }
+    }
+
+    fn fail<T>(&mut self, error: Error<A::Error>) -> Result<T, Error<A::Error>> {
+        self.state = State::Failed;
+        Err(error)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Answers each challenge by echoing it back, accepts a server final
+    /// message of "ok".
+    struct Echo;
+
+    impl Authenticator for Echo {
+        type Error = ();
+
+        fn initial(&mut self, out: &mut [u8]) -> Result<usize, ()> {
+            out[..5].copy_from_slice(b"hello");
+            Ok(5)
+        }
+
+        fn challenge(&mut self, challenge: &[u8], out: &mut [u8]) -> Result<usize, ()> {
+            out[..challenge.len()].copy_from_slice(challenge);
+            Ok(challenge.len())
+        }
+
+        fn verify(&mut self, data: &[u8]) -> Result<(), ()> {
+            if data == b"ok" {
+                Ok(())
+            } else {
+                Err(())
+            }
+        }
+    }
+
+    #[test]
+    fn exchange() {
+        let mut exchange = Exchange::new("ECHO", Echo);
+        let mut out = [0u8; 16];
+
+        assert_eq!(
+            Ok(Properties {
+                method: "ECHO",
+                data: &b"hello"[..],
+            }),
+            exchange.start(&mut out)
+        );
+        assert_eq!(State::Connecting, exchange.state());
+
+        let response = exchange
+            .auth_received(CONTINUE_AUTHENTICATION, "ECHO", b"nonce", &mut out)
+            .unwrap()
+            .unwrap();
+        assert_eq!(CONTINUE_AUTHENTICATION, response.reason_code);
+        assert_eq!(b"nonce", response.properties.data);
+
+        assert_eq!(Ok(()), exchange.connack_received(SUCCESS, b"ok"));
+        assert_eq!(State::Authenticated, exchange.state());
+
+        let auth = exchange.reauthenticate(&mut out).unwrap();
+        assert_eq!(REAUTHENTICATE, auth.reason_code);
+        assert_eq!(
+            Ok(None),
+            exchange.auth_received(SUCCESS, "ECHO", b"ok", &mut out)
+        );
+        assert_eq!(State::Authenticated, exchange.state());
+    }
+
+    #[test]
+    fn failures() {
+        let mut exchange = Exchange::new("ECHO", Echo);
+        let mut out = [0u8; 16];
+
+        assert_eq!(
+            Err(Error::Protocol),
+            exchange.connack_received(SUCCESS, b"")
+        );
+        assert_eq!(State::Failed, exchange.state());
+
+        exchange.start(&mut out).unwrap();
+        assert_eq!(
+            Err(Error::MethodMismatch),
+            exchange.auth_received(CONTINUE_AUTHENTICATION, "PLAIN", b"", &mut out)
+        );
+
+        exchange.start(&mut out).unwrap();
+        assert_eq!(
+            Err(Error::Refused(0x8C)),
+            exchange.connack_received(0x8C, b"")
+        );
+
+        exchange.start(&mut out).unwrap();
+        assert_eq!(
+            Err(Error::Authenticator(())),
+            exchange.connack_received(SUCCESS, b"forged")
+        );
+        assert_eq!(State::Failed, exchange.state());
+    }
+}
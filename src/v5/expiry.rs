@@ -0,0 +1,83 @@
+//! Message Expiry Interval countdown for queued and retained messages.
+//!
+//! A server forwarding an MQTT 5 PUBLISH must send the Message Expiry Interval
+//! reduced by the time the message waited, and must not forward it at all once
+//! the interval has passed. The caller reads the property from the received
+//! PUBLISH, measures how long the message was held with `clock::Instant` and
+//! writes the adjusted value into the outgoing properties.
+
+use crate::clock::Duration;
+
+/// What to do with a message that has been held for some time.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Expiry {
+    /// The message had no Message Expiry Interval, forward it without one
+    Never,
+    /// Forward the message with this Message Expiry Interval in seconds
+    Remaining(u32),
+    /// The interval has passed, the message must be discarded
+    Expired,
+}
+
+impl Expiry {
+    /// The Message Expiry Interval to re-encode, `None` if the property must
+    /// be left out or the message discarded.
+    pub fn interval(self) -> Option<u32> {
+        match self {
+            Expiry::Remaining(interval) => Some(interval),
+            Expiry::Never | Expiry::Expired => None,
+        }
+    }
+
+    pub fn is_expired(self) -> bool {
+        self == Expiry::Expired
+    }
+}
+
+/// Count a Message Expiry Interval in seconds down by the time the message was
+/// held.
+///
+/// The remaining interval is rounded up to whole seconds so a message is never
+/// forwarded with an interval of zero, it expires once the full interval has
+/// elapsed.
+pub fn countdown(interval: Option<u32>, elapsed: Duration) -> Expiry {
+    let interval = match interval {
+        Some(interval) => u64::from(interval) * 1000,
+        None => return Expiry::Never,
+    };
+
+    match interval.checked_sub(u64::from(elapsed.as_millis())) {
+        Some(remaining) if remaining > 0 => Expiry::Remaining(remaining.div_ceil(1000) as u32),
+        _ => Expiry::Expired,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::Instant;
+
+    #[test]
+    fn countdown_interval() {
+        assert_eq!(Expiry::Never, countdown(None, Duration::from_secs(60)));
+        assert_eq!(Expiry::Remaining(10), countdown(Some(10), Duration::ZERO));
+        assert_eq!(
+            Expiry::Remaining(7),
+            countdown(Some(10), Duration::from_millis(3_000))
+        );
+        // part of a second left rounds up
+        assert_eq!(
+            Some(1),
+            countdown(Some(10), Duration::from_millis(9_001)).interval()
+        );
+        assert!(countdown(Some(10), Duration::from_secs(10)).is_expired());
+        assert!(countdown(Some(0), Duration::ZERO).is_expired());
+
+        let received = Instant::from_millis(u32::MAX - 499);
+        let now = Instant::from_millis(1_500);
+        assert_eq!(
+            Expiry::Remaining(u32::MAX - 2),
+            countdown(Some(u32::MAX), now.duration_since(received))
+        );
+    }
+}
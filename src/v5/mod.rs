@@ -0,0 +1,171 @@
+//! Translation between MQTT 5 reason codes and MQTT 3.1.1 return codes, for
+//! gateways bridging 3.1.1 devices to MQTT 5 brokers.
+//!
+//! This crate does not encode or decode MQTT 5 packets in general. Besides the
+//! reason code mapping and sans-io helpers that work on values read from
+//! properties by the caller, `translate` converts PUBLISH packets between the
+//! two versions, stripping properties and resolving topic aliases.
+
+use crate::{error::DecodeError, payload::suback, variable_header::connack};
+
+pub mod auth;
+pub mod expiry;
+pub mod subscription;
+#[cfg(all(feature = "encode", feature = "decode"))]
+pub mod translate;
+
+/// DISCONNECT reason code for a packet that could not be parsed.
+pub const MALFORMED_PACKET: u8 = 0x81;
+/// DISCONNECT reason code for a parsed packet that breaks the protocol.
+pub const PROTOCOL_ERROR: u8 = 0x82;
+/// DISCONNECT reason code for a valid packet this implementation will not
+/// process, e.g. one over a configured limit.
+pub const IMPLEMENTATION_SPECIFIC_ERROR: u8 = 0x83;
+/// DISCONNECT reason code for a packet larger than the maximum packet size.
+pub const PACKET_TOO_LARGE: u8 = 0x95;
+
+/// The MQTT 5 DISCONNECT reason code to close a connection with when a packet
+/// from the peer fails to decode with `error`.
+///
+/// A server refusing a CONNECT should answer with a CONNACK instead, see
+/// `packet::Packet::refused_connack`.
+pub fn disconnect_reason_code(error: DecodeError) -> u8 {
+    match error {
+        DecodeError::PacketType
+        | DecodeError::PacketFlag
+        | DecodeError::RemainingLength
+        | DecodeError::InvalidLength
+        | DecodeError::Utf8
+        | DecodeError::InvalidQoS(_)
+        | DecodeError::InvalidConnectFlag(_)
+        | DecodeError::InvalidConnackFlag
+        | DecodeError::InvalidConnackReturnCode
+        | DecodeError::InvalidSubackReturnCode
+        | DecodeError::Incomplete(_)
+        | DecodeError::Subscription(_) => MALFORMED_PACKET,
+        DecodeError::InvalidProtocolName | DecodeError::InvalidProtocolLevel => PROTOCOL_ERROR,
+        DecodeError::TopicTooLong
+        | DecodeError::TooManyTopicLevels
+        | DecodeError::TooManySubscriptions => IMPLEMENTATION_SPECIFIC_ERROR,
+        DecodeError::PacketTooLarge => PACKET_TOO_LARGE,
+    }
+}
+
+/// The encoded MQTT 5 DISCONNECT for a packet that failed to decode with
+/// `error`, carrying the reason code and no properties.
+pub fn disconnect_bytes(error: DecodeError) -> [u8; 3] {
+    [0xe0, 0x01, disconnect_reason_code(error)]
+}
+
+/// Downgrade an MQTT 5 CONNACK reason code to the closest 3.1.1 return code.
+///
+/// Failure reason codes without a 3.1.1 equivalent map to server unavailable.
+/// Returns `None` if `reason` is not a CONNACK reason code.
+pub fn connack_return_code(reason: u8) -> Option<connack::ReturnCode> {
+    Some(match reason {
+        0x00 => connack::ReturnCode::Accepted,
+        0x84 => connack::ReturnCode::RefusedProtocolVersion,
+        0x85 => connack::ReturnCode::RefusedClientIdentifier,
+        0x86 => connack::ReturnCode::RefusedUsernameOrPassword,
+        0x87 => connack::ReturnCode::RefusedNotAuthorized,
+        0x80..=0x83
+        | 0x88..=0x8A
+        | 0x8C
+        | 0x90
+        | 0x95
+        | 0x97
+        | 0x99..=0x9A
+        | 0x9C..=0x9D
+        | 0x9F => connack::ReturnCode::RefusedServerUnavailable,
+        _ => return None,
+    })
+}
+
+/// Upgrade a 3.1.1 CONNACK return code to the equivalent MQTT 5 reason code.
+pub fn connack_reason_code(return_code: connack::ReturnCode) -> u8 {
+    match return_code {
+        connack::ReturnCode::Accepted => 0x00,
+        connack::ReturnCode::RefusedProtocolVersion => 0x84,
+        connack::ReturnCode::RefusedClientIdentifier => 0x85,
+        connack::ReturnCode::RefusedServerUnavailable => 0x88,
+        connack::ReturnCode::RefusedUsernameOrPassword => 0x86,
+        connack::ReturnCode::RefusedNotAuthorized => 0x87,
+    }
+}
+
+/// Downgrade an MQTT 5 SUBACK reason code to a 3.1.1 return code, every
+/// failure reason maps to the single 3.1.1 failure return code.
+///
+/// Returns `None` if `reason` is not a SUBACK reason code.
+pub fn suback_return_code(reason: u8) -> Option<suback::ReturnCode> {
+    Some(match reason {
+        0x00 => suback::ReturnCode::SUCCESS_QOS_0,
+        0x01 => suback::ReturnCode::SUCCESS_QOS_1,
+        0x02 => suback::ReturnCode::SUCCESS_QOS_2,
+        0x80 | 0x83 | 0x87 | 0x8F | 0x91 | 0x97 | 0x9E | 0xA1 | 0xA2 => suback::ReturnCode::FAILURE,
+        _ => return None,
+    })
+}
+
+/// Upgrade a 3.1.1 SUBACK return code to the equivalent MQTT 5 reason code,
+/// failure maps to unspecified error.
+pub fn suback_reason_code(return_code: suback::ReturnCode) -> u8 {
+    match return_code.granted_qos() {
+        Some(qos) => u8::from(qos),
+        None => 0x80,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::convert::TryFrom;
+
+    #[test]
+    fn connack_round_trip() {
+        for byte in 0..=5u8 {
+            let return_code = connack::ReturnCode::try_from(byte).unwrap();
+            assert_eq!(
+                Some(return_code),
+                connack_return_code(connack_reason_code(return_code))
+            );
+        }
+
+        assert_eq!(
+            Some(connack::ReturnCode::RefusedServerUnavailable),
+            connack_return_code(0x89)
+        );
+        assert_eq!(None, connack_return_code(0x04));
+    }
+
+    #[test]
+    fn suback_round_trip() {
+        for return_code in [
+            suback::ReturnCode::SUCCESS_QOS_0,
+            suback::ReturnCode::SUCCESS_QOS_1,
+            suback::ReturnCode::SUCCESS_QOS_2,
+            suback::ReturnCode::FAILURE,
+        ] {
+            assert_eq!(
+                Some(return_code),
+                suback_return_code(suback_reason_code(return_code))
+            );
+        }
+
+        assert_eq!(Some(suback::ReturnCode::FAILURE), suback_return_code(0x87));
+        assert_eq!(None, suback_return_code(0x03));
+    }
+
+    #[test]
+    fn disconnect() {
+        assert_eq!(MALFORMED_PACKET, disconnect_reason_code(DecodeError::Utf8));
+        assert_eq!(
+            IMPLEMENTATION_SPECIFIC_ERROR,
+            disconnect_reason_code(DecodeError::TooManySubscriptions)
+        );
+        assert_eq!(
+            [0xe0, 0x01, 0x95],
+            disconnect_bytes(DecodeError::PacketTooLarge)
+        );
+    }
+}
@@ -0,0 +1,177 @@
+//! Dispatch of MQTT 5 PUBLISH packets by subscription identifier.
+//!
+//! When a SUBSCRIBE carries a subscription identifier the broker includes it
+//! in every matching PUBLISH, so the handler can be found without matching
+//! the topic against every filter. The caller reads the identifiers out of the
+//! PUBLISH properties and passes them to the router.
+
+use core::{fmt, result::Result};
+
+/// The largest subscription identifier, a variable byte integer.
+pub const MAX_IDENTIFIER: u32 = 268_435_455;
+
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub enum Error {
+    /// No room to store another route
+    Full,
+    /// Identifiers must be between 1 and `MAX_IDENTIFIER`
+    InvalidIdentifier,
+}
+
+impl Error {
+    #[cfg(not(feature = "tiny"))]
+    fn desc(&self) -> &'static str {
+        match *self {
+            Error::Full => "too many subscription identifier routes",
+            Error::InvalidIdentifier => "invalid subscription identifier",
+        }
+    }
+
+    #[cfg(feature = "tiny")]
+    fn desc(&self) -> &'static str {
+        "subscription router error"
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.desc())
+    }
+}
+
+#[cfg(feature = "std")]
+impl ::std::error::Error for Error {
+    fn description(&self) -> &str {
+        self.desc()
+    }
+}
+
+/// Maps up to `N` subscription identifiers to application handler indices.
+#[derive(Clone, Debug)]
+pub struct Router<const N: usize> {
+    routes: [(u32, usize); N],
+    len: usize,
+}
+
+impl<const N: usize> Default for Router<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> Router<N> {
+    pub fn new() -> Self {
+        Self {
+            routes: [(0, 0); N],
+            len: 0,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Route `identifier` to `handler`, returning the handler it previously
+    /// routed to.
+    pub fn insert(&mut self, identifier: u32, handler: usize) -> Result<Option<usize>, Error> {
+        if identifier == 0 || identifier > MAX_IDENTIFIER {
+            return Err(Error::InvalidIdentifier);
+        }
+
+        if let Some(index) = self.position(identifier) {
+            let previous = self.routes[index].1;
+            self.routes[index].1 = handler;
+            return Ok(Some(previous));
+        }
+
+        if self.len == N {
+            return Err(Error::Full);
+        }
+
+        self.routes[self.len] = (identifier, handler);
+        self.len += 1;
+
+        Ok(None)
+    }
+
+    /// Remove the route for `identifier`, when its subscription is removed.
+    pub fn remove(&mut self, identifier: u32) -> Option<usize> {
+        let index = self.position(identifier)?;
+        let handler = self.routes[index].1;
+
+        self.len -= 1;
+        self.routes.swap(index, self.len);
+
+        Some(handler)
+    }
+
+    /// The handler `identifier` routes to.
+    pub fn get(&self, identifier: u32) -> Option<usize> {
+        self.position(identifier).map(|index| self.routes[index].1)
+    }
+
+    /// Call `handler` with the handler index of each routed identifier in a
+    /// received PUBLISH.
+    ///
+    /// Returns whether any identifier was routed, if not the caller should
+    /// fall back to matching the topic name.
+    pub fn dispatch<I, F>(&self, identifiers: I, mut handler: F) -> bool
+    where
+        I: IntoIterator<Item = u32>,
+        F: FnMut(usize),
+    {
+        let mut routed = false;
+        for handler_index in identifiers.into_iter().filter_map(|id| self.get(id)) {
+            handler(handler_index);
+            routed = true;
+        }
+        routed
+    }
+
+    /// Forget all routes, when a clean session starts.
+    pub fn clear(&mut self) {
+        self.len = 0;
+    }
+
+    fn position(&self, identifier: u32) -> Option<usize> {
+        self.routes[..self.len]
+            .iter()
+            .position(|&(stored, _)| stored == identifier)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::vec::Vec;
+
+    #[test]
+    fn routes() {
+        let mut router = Router::<2>::new();
+        assert_eq!(Err(Error::InvalidIdentifier), router.insert(0, 0));
+        assert_eq!(
+            Err(Error::InvalidIdentifier),
+            router.insert(MAX_IDENTIFIER + 1, 0)
+        );
+        assert_eq!(Ok(None), router.insert(7, 0));
+        assert_eq!(Ok(None), router.insert(MAX_IDENTIFIER, 1));
+        assert_eq!(Ok(Some(0)), router.insert(7, 2));
+        assert_eq!(Err(Error::Full), router.insert(8, 3));
+
+        let mut handled = Vec::new();
+        assert!(router.dispatch([7, 9, MAX_IDENTIFIER], |handler| handled.push(handler)));
+        assert_eq!([2, 1], handled[..]);
+        assert!(!router.dispatch([9], |_| unreachable!()));
+
+        assert_eq!(Some(2), router.remove(7));
+        assert_eq!(None, router.get(7));
+        assert_eq!(Some(1), router.get(MAX_IDENTIFIER));
+
+        router.clear();
+        assert!(router.is_empty());
+    }
+}
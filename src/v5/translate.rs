@@ -0,0 +1,408 @@
+//! Translation of PUBLISH packets between MQTT 5 and MQTT 3.1.1.
+//!
+//! An MQTT 5 PUBLISH has the layout of a 3.1.1 PUBLISH with a property list
+//! between the packet identifier and the payload, so `Packet::decode` reads it
+//! with the properties at the start of the payload. `downgrade_publish`
+//! strips them and resolves the Topic Alias property against a
+//! `TopicAliases` table, `upgrade_publish` inserts an empty property list.
+
+use core::{fmt, result::Result, str};
+
+use crate::{
+    codec::{self, Encodable},
+    error::{DecodeError, EncodeError},
+    fixed_header::{self, PacketType},
+    packet::{self, Packet},
+    status::Status,
+    variable_header::publish::Publish,
+};
+
+/// Payload Format Indicator property identifier.
+const PAYLOAD_FORMAT_INDICATOR: u8 = 0x01;
+/// Message Expiry Interval property identifier.
+const MESSAGE_EXPIRY_INTERVAL: u8 = 0x02;
+/// Content Type property identifier.
+const CONTENT_TYPE: u8 = 0x03;
+/// Response Topic property identifier.
+const RESPONSE_TOPIC: u8 = 0x08;
+/// Correlation Data property identifier.
+const CORRELATION_DATA: u8 = 0x09;
+/// Subscription Identifier property identifier.
+const SUBSCRIPTION_IDENTIFIER: u8 = 0x0B;
+/// Topic Alias property identifier.
+const TOPIC_ALIAS: u8 = 0x23;
+/// User Property property identifier.
+const USER_PROPERTY: u8 = 0x26;
+
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub enum Error {
+    /// The packet is not a PUBLISH
+    NotPublish,
+    /// The properties are malformed
+    Decode(DecodeError),
+    /// The translated packet could not be encoded
+    Encode(EncodeError),
+    /// A property that is not valid in a PUBLISH
+    InvalidProperty(u8),
+    /// A topic alias of zero or above the topic alias maximum
+    InvalidTopicAlias(u16),
+    /// An empty topic name with a topic alias that was never set
+    UnknownTopicAlias(u16),
+    /// An empty topic name without a topic alias
+    MissingTopic,
+    /// A topic name longer than the topic alias table stores
+    TopicTooLong,
+}
+
+impl Error {
+    #[cfg(not(feature = "tiny"))]
+    fn desc(&self) -> &'static str {
+        match *self {
+            Error::NotPublish => "not a PUBLISH",
+            Error::Decode(_) => "malformed MQTT 5 PUBLISH",
+            Error::Encode(_) => "translated PUBLISH could not be encoded",
+            Error::InvalidProperty(_) => "property not valid in a PUBLISH",
+            Error::InvalidTopicAlias(_) => "invalid topic alias",
+            Error::UnknownTopicAlias(_) => "unknown topic alias",
+            Error::MissingTopic => "PUBLISH without topic name or topic alias",
+            Error::TopicTooLong => "topic too long for the topic alias table",
+        }
+    }
+
+    #[cfg(feature = "tiny")]
+    fn desc(&self) -> &'static str {
+        "PUBLISH translation error"
+    }
+}
+
+impl fmt::Display for Error {
+    #[cfg(not(feature = "tiny"))]
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Decode(e) => write!(f, "{}: {}", self.desc(), e),
+            Error::Encode(e) => write!(f, "{}: {}", self.desc(), e),
+            Error::InvalidProperty(id) => write!(f, "{}: {:#04x}", self.desc(), id),
+            Error::InvalidTopicAlias(alias) | Error::UnknownTopicAlias(alias) => {
+                write!(f, "{}: {}", self.desc(), alias)
+            }
+            _ => f.write_str(self.desc()),
+        }
+    }
+
+    #[cfg(feature = "tiny")]
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.desc())
+    }
+}
+
+#[cfg(feature = "std")]
+impl ::std::error::Error for Error {
+    fn description(&self) -> &str {
+        self.desc()
+    }
+}
+
+impl From<DecodeError> for Error {
+    fn from(err: DecodeError) -> Self {
+        Error::Decode(err)
+    }
+}
+
+impl From<EncodeError> for Error {
+    fn from(err: EncodeError) -> Self {
+        Error::Encode(err)
+    }
+}
+
+/// The topic aliases set by the MQTT 5 side of a connection, up to `N` aliases
+/// of topics up to `LEN` bytes long.
+///
+/// Send `N` as the Topic Alias Maximum property in CONNECT or CONNACK so the
+/// peer does not use aliases above it.
+#[derive(Clone, Debug)]
+pub struct TopicAliases<const N: usize, const LEN: usize> {
+    topics: [([u8; LEN], usize); N],
+}
+
+impl<const N: usize, const LEN: usize> Default for TopicAliases<N, LEN> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize, const LEN: usize> TopicAliases<N, LEN> {
+    pub const fn new() -> Self {
+        Self {
+            topics: [([0; LEN], 0); N],
+        }
+    }
+
+    /// The Topic Alias Maximum to announce to the peer.
+    pub fn maximum(&self) -> u16 {
+        if N > usize::from(u16::MAX) {
+            u16::MAX
+        } else {
+            N as u16
+        }
+    }
+
+    /// Map `alias` to `topic`, replacing any topic it was mapped to.
+    pub fn set(&mut self, alias: u16, topic: &str) -> Result<(), Error> {
+        let index = self.index(alias)?;
+        if topic.len() > LEN {
+            return Err(Error::TopicTooLong);
+        }
+
+        let (buf, len) = &mut self.topics[index];
+        buf[..topic.len()].copy_from_slice(topic.as_bytes());
+        *len = topic.len();
+        Ok(())
+    }
+
+    /// The topic `alias` is mapped to.
+    pub fn get(&self, alias: u16) -> Result<&str, Error> {
+        let (buf, len) = &self.topics[self.index(alias)?];
+        if *len == 0 {
+            return Err(Error::UnknownTopicAlias(alias));
+        }
+
+        // Only ever copied from a `&str`
+        str::from_utf8(&buf[..*len]).map_err(|_| Error::UnknownTopicAlias(alias))
+    }
+
+    /// Forget every alias, topic aliases only last for one network connection.
+    pub fn clear(&mut self) {
+        for (_, len) in self.topics.iter_mut() {
+            *len = 0;
+        }
+    }
+
+    fn index(&self, alias: u16) -> Result<usize, Error> {
+        match usize::from(alias) {
+            0 => Err(Error::InvalidTopicAlias(alias)),
+            alias if alias > N => Err(Error::InvalidTopicAlias(alias as u16)),
+            alias => Ok(alias - 1),
+        }
+    }
+}
+
+/// Encode the MQTT 5 PUBLISH `packet` into `out` as a 3.1.1 PUBLISH,
+/// returning the encoded packet.
+///
+/// `packet` is the MQTT 5 PUBLISH decoded by `Packet::decode`, so its payload
+/// starts with the property list. The properties are dropped, the Topic Alias
+/// property is recorded in `aliases` or resolved from it when the topic name
+/// is empty.
+pub fn downgrade_publish<'o, const N: usize, const LEN: usize>(
+    packet: &Packet,
+    aliases: &mut TopicAliases<N, LEN>,
+    out: &'o mut [u8],
+) -> Result<&'o [u8], Error> {
+    let publish = packet.as_publish().ok_or(Error::NotPublish)?;
+    let (alias, payload) = strip_properties(publish.payload)?;
+
+    let topic_name = match (publish.topic_name, alias) {
+        ("", None) => return Err(Error::MissingTopic),
+        ("", Some(alias)) => aliases.get(alias)?,
+        (topic_name, Some(alias)) => {
+            aliases.set(alias, topic_name)?;
+            topic_name
+        }
+        (topic_name, None) => topic_name,
+    };
+
+    let packet = Packet::publish(
+        publish.flags,
+        Publish::new(topic_name, publish.packet_identifier),
+        payload,
+    )?;
+    let written = packet.encode(out)?;
+    Ok(&out[..written])
+}
+
+/// Encode the 3.1.1 PUBLISH `packet` into `out` as an MQTT 5 PUBLISH with no
+/// properties, returning the encoded packet.
+///
+/// The packet may not start at the beginning of `out`, see
+/// `packet::encode_backfilled`.
+pub fn upgrade_publish<'o>(packet: &Packet, out: &'o mut [u8]) -> Result<&'o [u8], Error> {
+    let publish = packet.as_publish().ok_or(Error::NotPublish)?;
+
+    let encoded =
+        packet::encode_backfilled(PacketType::Publish, publish.flags.into(), out, |bytes| {
+            let mut offset = codec::string::encode_string(publish.topic_name, bytes)?;
+            if let Some(packet_identifier) = publish.packet_identifier {
+                offset += codec::values::encode_u16(packet_identifier.get(), &mut bytes[offset..])?;
+            }
+            // Property length
+            offset += codec::values::encode_u8(0, &mut bytes[offset..])?;
+
+            let payload = bytes
+                .get_mut(offset..offset + publish.payload.len())
+                .ok_or(EncodeError::OutOfSpace)?;
+            payload.copy_from_slice(publish.payload);
+            Ok(offset + publish.payload.len())
+        })?;
+    Ok(encoded)
+}
+
+/// Split the property list off the front of an MQTT 5 PUBLISH payload,
+/// returning the Topic Alias property and the application message.
+fn strip_properties(payload: &[u8]) -> Result<(Option<u16>, &[u8]), Error> {
+    let (offset, len) = whole(fixed_header::parse_remaining_length(payload))?;
+    let end = offset
+        .checked_add(len as usize)
+        .filter(|end| *end <= payload.len())
+        .ok_or(DecodeError::InvalidLength)?;
+
+    let mut alias = None;
+    let mut properties = &payload[offset..end];
+    while let Some((&id, rest)) = properties.split_first() {
+        let len = match id {
+            PAYLOAD_FORMAT_INDICATOR => 1,
+            MESSAGE_EXPIRY_INTERVAL => 4,
+            TOPIC_ALIAS => {
+                let (len, value) = whole(codec::values::parse_u16(rest))?;
+                // [MQTT-3.3.2-7]
+                if alias.replace(value).is_some() {
+                    return Err(Error::InvalidProperty(id));
+                }
+                len
+            }
+            CONTENT_TYPE | RESPONSE_TOPIC => whole(codec::string::parse_string(rest))?.0,
+            CORRELATION_DATA => whole(codec::values::parse_bytes(rest))?.0,
+            SUBSCRIPTION_IDENTIFIER => whole(fixed_header::parse_remaining_length(rest))?.0,
+            USER_PROPERTY => {
+                let (key, _) = whole(codec::string::parse_string(rest))?;
+                key + whole(codec::string::parse_string(&rest[key..]))?.0
+            }
+            _ => return Err(Error::InvalidProperty(id)),
+        };
+
+        properties = rest.get(len..).ok_or(DecodeError::InvalidLength)?;
+    }
+
+    Ok((alias, &payload[end..]))
+}
+
+/// The properties are inside a complete packet, so running out of bytes means
+/// the lengths are inconsistent.
+fn whole<T>(status: Result<Status<(usize, T)>, DecodeError>) -> Result<(usize, T), Error> {
+    match status? {
+        Status::Complete(value) => Ok(value),
+        Status::Partial(_) => Err(Error::Decode(DecodeError::InvalidLength)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::codec::Decodable;
+
+    const V5_PUBLISH: [u8; 22] = [
+        0x32, 0x14, // QoS 1
+        0x00, 0x03, b'a', b'/', b'b', // topic
+        0x00, 0x07, // packet identifier
+        0x0b, // property length
+        0x01, 0x01, // payload format indicator
+        0x23, 0x00, 0x02, // topic alias 2
+        0x26, 0x00, 0x01, b'k', 0x00, 0x00, // user property
+        b'm', // payload
+    ];
+
+    #[test]
+    fn downgrade() {
+        let mut aliases = TopicAliases::<2, 8>::new();
+        let mut out = [0u8; 32];
+
+        let (_, packet) = Packet::decode(&V5_PUBLISH).unwrap().unwrap();
+        let downgraded = downgrade_publish(&packet, &mut aliases, &mut out).unwrap();
+        assert_eq!(
+            &[0x32, 0x08, 0x00, 0x03, b'a', b'/', b'b', 0x00, 0x07, b'm'][..],
+            downgraded
+        );
+        assert_eq!(Ok("a/b"), aliases.get(2));
+
+        // Empty topic name resolved from the alias
+        let aliased = [0x30, 0x07, 0x00, 0x00, 0x03, 0x23, 0x00, 0x02, b'n'];
+        let (_, packet) = Packet::decode(&aliased).unwrap().unwrap();
+        let downgraded = downgrade_publish(&packet, &mut aliases, &mut out).unwrap();
+        assert_eq!(
+            &[0x30, 0x06, 0x00, 0x03, b'a', b'/', b'b', b'n'][..],
+            downgraded
+        );
+
+        aliases.clear();
+        assert_eq!(
+            Err(Error::UnknownTopicAlias(2)),
+            downgrade_publish(&packet, &mut aliases, &mut out)
+        );
+    }
+
+    #[test]
+    fn downgrade_invalid() {
+        let mut aliases = TopicAliases::<2, 8>::new();
+        let mut out = [0u8; 32];
+
+        for (bytes, error) in [
+            (
+                &[0x30, 0x06, 0x00, 0x00, 0x00, b'a', b'b', b'c'][..],
+                Error::MissingTopic,
+            ),
+            (
+                &[0x30, 0x07, 0x00, 0x01, b'a', 0x03, 0x23, 0x00, 0x03][..],
+                Error::InvalidTopicAlias(3),
+            ),
+            (
+                &[0x30, 0x07, 0x00, 0x01, b'a', 0x03, 0x23, 0x00, 0x00][..],
+                Error::InvalidTopicAlias(0),
+            ),
+            (
+                &[0x30, 0x05, 0x00, 0x01, b'a', 0x01, 0x24][..],
+                Error::InvalidProperty(0x24),
+            ),
+            (
+                &[0x30, 0x05, 0x00, 0x01, b'a', 0x05, 0x02][..],
+                Error::Decode(DecodeError::InvalidLength),
+            ),
+            (
+                &[0x30, 0x06, 0x00, 0x01, b'a', 0x02, 0x02, 0x00][..],
+                Error::Decode(DecodeError::InvalidLength),
+            ),
+        ] {
+            let (_, packet) = Packet::decode(bytes).unwrap().unwrap();
+            assert_eq!(
+                Err(error),
+                downgrade_publish(&packet, &mut aliases, &mut out)
+            );
+        }
+
+        assert_eq!(
+            Err(Error::NotPublish),
+            downgrade_publish(&Packet::pingreq(), &mut aliases, &mut out)
+        );
+    }
+
+    #[test]
+    fn upgrade_round_trip() {
+        let (_, packet) =
+            Packet::decode(&[0x34, 0x08, 0x00, 0x03, b'a', b'/', b'b', 0x00, 0x07, b'm'])
+                .unwrap()
+                .unwrap();
+
+        let mut out = [0u8; 32];
+        let upgraded = upgrade_publish(&packet, &mut out).unwrap();
+        assert_eq!(
+            &[0x34, 0x09, 0x00, 0x03, b'a', b'/', b'b', 0x00, 0x07, 0x00, b'm'][..],
+            upgraded
+        );
+
+        let (_, packet) = Packet::decode(upgraded).unwrap().unwrap();
+        let mut aliases = TopicAliases::<1, 8>::new();
+        let mut downgraded = [0u8; 32];
+        assert_eq!(
+            &[0x34, 0x08, 0x00, 0x03, b'a', b'/', b'b', 0x00, 0x07, b'm'][..],
+            downgrade_publish(&packet, &mut aliases, &mut downgraded).unwrap()
+        );
+    }
+}
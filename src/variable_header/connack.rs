@@ -1,38 +1,23 @@
-use core::{
-    convert::{TryFrom, TryInto},
-    fmt::Debug,
-    result::Result,
-};
-
-use crate::{
-    codec::{self, Encodable},
-    error::{DecodeError, EncodeError},
-    fixed_header::PacketFlags,
-    status::Status,
-};
+#[cfg(feature = "decode")]
+use core::convert::TryInto;
+use core::{convert::TryFrom, result::Result};
 
-use super::HeaderDecode;
+#[cfg(feature = "decode")]
+use crate::{codec, error::DecodeError, fixed_header::PacketFlags, status::Status};
+#[cfg(feature = "encode")]
+use crate::{codec::Encodable, error::EncodeError};
 
-#[derive(PartialEq, Clone, Copy)]
-pub struct Flags(u8);
+#[cfg(feature = "decode")]
+use super::HeaderDecode;
 
-bitfield_bitrange! {
-    struct Flags(u8)
+/// The flags of a CONNACK variable header.
+#[derive(PartialEq, Eq, Clone, Copy, Default)]
+#[cfg_attr(not(feature = "tiny"), derive(Debug))]
+pub struct Flags {
+    pub session_present: bool,
 }
 
-impl Flags {
-    bitfield_fields! {
-        bool;
-        pub session_present, _ : 1;
-    }
-}
-
-impl Debug for Flags {
-    bitfield_debug! {
-        struct Flags;
-        pub session_present, _ : 1;
-    }
-}
+bits_debug!(Flags);
 
 impl TryFrom<u8> for Flags {
     type Error = ();
@@ -40,11 +25,20 @@ impl TryFrom<u8> for Flags {
         if 0b11111110 & from != 0 {
             Err(())
         } else {
-            Ok(Flags(from))
+            Ok(Flags {
+                session_present: from == 1,
+            })
         }
     }
 }
 
+impl From<Flags> for u8 {
+    fn from(flags: Flags) -> u8 {
+        flags.session_present as u8
+    }
+}
+
+#[cfg(feature = "encode")]
 impl Encodable for Flags {
     fn encoded_len(&self) -> usize {
         1
@@ -55,7 +49,7 @@ impl Encodable for Flags {
             return Err(EncodeError::OutOfSpace);
         }
 
-        bytes[0] = self.0;
+        bytes[0] = u8::from(*self);
 
         Ok(1)
     }
@@ -86,6 +80,7 @@ impl TryFrom<u8> for ReturnCode {
     }
 }
 
+#[cfg(feature = "encode")]
 impl Encodable for ReturnCode {
     fn encoded_len(&self) -> usize {
         1
@@ -119,6 +114,10 @@ pub struct Connack {
 }
 
 impl Connack {
+    pub fn new(flags: Flags, return_code: ReturnCode) -> Self {
+        Self { flags, return_code }
+    }
+
     pub fn flags(&self) -> Flags {
         self.flags
     }
@@ -128,6 +127,7 @@ impl Connack {
     }
 }
 
+#[cfg(feature = "decode")]
 impl<'buf> HeaderDecode<'buf> for Connack {
     fn decode(_flags: PacketFlags, bytes: &[u8]) -> Result<Status<(usize, Self)>, DecodeError> {
         if bytes.len() < 2 {
@@ -152,6 +152,7 @@ impl<'buf> HeaderDecode<'buf> for Connack {
     }
 }
 
+#[cfg(feature = "encode")]
 impl Encodable for Connack {
     fn encoded_len(&self) -> usize {
         2
@@ -165,4 +166,18 @@ impl Encodable for Connack {
 }
 
 #[cfg(test)]
-mod tests {}
+mod tests {
+    use super::*;
+
+    #[test]
+    fn flags() {
+        assert_eq!(
+            Ok(Flags {
+                session_present: true
+            }),
+            Flags::try_from(0b0000_0001)
+        );
+        assert_eq!(Err(()), Flags::try_from(0b0000_0010));
+        assert_eq!(0, u8::from(Flags::default()));
+    }
+}
@@ -1,20 +1,25 @@
 use core::{
     convert::{From, TryFrom, TryInto},
-    fmt::Debug,
+    marker::PhantomData,
+    num::NonZeroU16,
     result::Result,
 };
 
 use crate::{
-    codec::{self, Encodable},
-    error::{DecodeError, EncodeError},
-    fixed_header::PacketFlags,
+    clock::Duration,
+    error::{ConnectFlagError, DecodeError},
     qos,
-    status::Status,
 };
 
-use super::HeaderDecode;
+#[cfg(any(feature = "encode", feature = "decode"))]
+use crate::codec;
+#[cfg(feature = "encode")]
+use crate::{codec::Encodable, error::EncodeError};
+#[cfg(feature = "decode")]
+use crate::{fixed_header::PacketFlags, status::Status};
 
-use bitfield::BitRange;
+#[cfg(feature = "decode")]
+use super::HeaderDecode;
 
 #[derive(PartialEq, Eq, Debug, Clone, Copy)]
 pub enum Protocol {
@@ -53,50 +58,235 @@ impl From<Level> for u8 {
     }
 }
 
-#[derive(PartialEq, Clone, Copy, Default)]
-pub struct Flags(u8);
+/// The flags of a CONNECT variable header, converted to and from the wire byte
+/// at the codec boundary.
+///
+/// Flags are only decoded from a valid byte or built with `Flags::builder`, so
+/// they never break [MQTT-3.1.2-11] or [MQTT-3.1.2-22].
+#[derive(PartialEq, Eq, Clone, Copy, Default)]
+#[cfg_attr(not(feature = "tiny"), derive(Debug))]
+pub struct Flags {
+    pub(crate) has_username: bool,
+    pub(crate) has_password: bool,
+    pub(crate) will: Option<WillFlags>,
+    pub(crate) clean_session: bool,
+}
+
+/// The QoS and retain flag of a will, which are only sent together with the
+/// will flag [MQTT-3.1.2-11].
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub struct WillFlags {
+    pub qos: qos::QoS,
+    pub retain: bool,
+}
+
+impl TryFrom<u8> for Flags {
+    type Error = DecodeError;
+    fn try_from(bits: u8) -> Result<Self, Self::Error> {
+        // [MQTT-3.1.2-3]
+        if bits & 0b0000_0001 != 0 {
+            return Err(DecodeError::InvalidConnectFlag(ConnectFlagError::Reserved));
+        }
+
+        let will = if bits & 0b0000_0100 != 0 {
+            // [MQTT-3.1.2-14]
+            Some(WillFlags {
+                qos: ((bits >> 3) & 0b11)
+                    .try_into()
+                    .map_err(|_| DecodeError::InvalidConnectFlag(ConnectFlagError::WillQoS))?,
+                retain: bits & 0b0010_0000 != 0,
+            })
+        } else if bits & 0b0001_1000 != 0 {
+            // [MQTT-3.1.2-13]
+            return Err(DecodeError::InvalidConnectFlag(
+                ConnectFlagError::WillQoSWithoutWill,
+            ));
+        } else if bits & 0b0010_0000 != 0 {
+            // [MQTT-3.1.2-15]
+            return Err(DecodeError::InvalidConnectFlag(
+                ConnectFlagError::WillRetainWithoutWill,
+            ));
+        } else {
+            None
+        };
+
+        let has_username = bits & 0b1000_0000 != 0;
+        let has_password = bits & 0b0100_0000 != 0;
+        // [MQTT-3.1.2-22]
+        if has_password && !has_username {
+            return Err(DecodeError::InvalidConnectFlag(
+                ConnectFlagError::PasswordWithoutUsername,
+            ));
+        }
+
+        Ok(Flags {
+            has_username,
+            has_password,
+            will,
+            clean_session: bits & 0b0000_0010 != 0,
+        })
+    }
+}
+
+/// Builder state of a `FlagsBuilder` without a will.
+#[derive(Debug, Clone, Copy)]
+pub struct NoWill;
+
+/// Builder state of a `FlagsBuilder` with a will attached.
+#[derive(Debug, Clone, Copy)]
+pub struct WithWill;
+
+/// Builder state of a `FlagsBuilder` without a username.
+#[derive(Debug, Clone, Copy)]
+pub struct NoUsername;
+
+/// Builder state of a `FlagsBuilder` with a username, the password flag can
+/// only be set in this state.
+#[derive(Debug, Clone, Copy)]
+pub struct WithUsername;
 
-bitfield_bitrange! {
-    struct Flags(u8)
+/// Builds connect `Flags` that are always valid, the will QoS and retain bits
+/// can only be set together with the will flag [MQTT-3.1.2-11] and the
+/// password flag only together with the username flag [MQTT-3.1.2-22].
+#[derive(Debug, Clone, Copy)]
+pub struct FlagsBuilder<W, U = NoUsername> {
+    flags: Flags,
+    will: PhantomData<W>,
+    username: PhantomData<U>,
 }
 
 impl Flags {
-    bitfield_fields! {
-        bool;
-        pub has_username,  set_has_username  : 7;
-        pub has_password,  set_has_password  : 6;
-        pub will_retain,   set_will_retain   : 5;
+    pub fn builder() -> FlagsBuilder<NoWill> {
+        FlagsBuilder {
+            flags: Flags::default(),
+            will: PhantomData,
+            username: PhantomData,
+        }
+    }
+
+    pub fn has_username(&self) -> bool {
+        self.has_username
+    }
 
-        pub has_will,      set_has_will_flag : 2;
-        pub clean_session, set_clean_session : 1;
+    pub fn has_password(&self) -> bool {
+        self.has_password
     }
 
-    pub fn will_qos(&self) -> Result<qos::QoS, qos::Error> {
-        let qos_bits: u8 = self.bit_range(4, 3);
-        qos_bits.try_into()
+    /// The will QoS and retain flag, `None` if the payload carries no will
+    pub fn will(&self) -> Option<WillFlags> {
+        self.will
     }
 
-    #[allow(dead_code)]
-    pub fn set_will_qos(&mut self, qos: qos::QoS) {
-        self.set_bit_range(4, 3, u8::from(qos))
+    pub fn clean_session(&self) -> bool {
+        self.clean_session
+    }
+}
+
+impl<W, U> FlagsBuilder<W, U> {
+    pub fn clean_session(mut self, clean_session: bool) -> Self {
+        self.flags.clean_session = clean_session;
+        self
+    }
+
+    pub fn build(self) -> Flags {
+        self.flags
+    }
+}
+
+impl<U> FlagsBuilder<NoWill, U> {
+    /// Attach a will with the given QoS and retain flag.
+    pub fn will(mut self, qos: qos::QoS, retain: bool) -> FlagsBuilder<WithWill, U> {
+        self.flags.will = Some(WillFlags { qos, retain });
+
+        FlagsBuilder {
+            flags: self.flags,
+            will: PhantomData,
+            username: PhantomData,
+        }
+    }
+}
+
+impl<W> FlagsBuilder<W, NoUsername> {
+    /// Set the username flag.
+    pub fn username(mut self) -> FlagsBuilder<W, WithUsername> {
+        self.flags.has_username = true;
+
+        FlagsBuilder {
+            flags: self.flags,
+            will: PhantomData,
+            username: PhantomData,
+        }
+    }
+}
+
+impl<W> FlagsBuilder<W, WithUsername> {
+    pub fn password(mut self, has_password: bool) -> Self {
+        self.flags.has_password = has_password;
+        self
     }
 }
 
 impl From<Flags> for u8 {
-    fn from(val: Flags) -> u8 {
-        val.0
+    fn from(flags: Flags) -> u8 {
+        let will = match flags.will {
+            Some(will) => (will.retain as u8) << 5 | u8::from(will.qos) << 3 | 1 << 2,
+            None => 0,
+        };
+
+        (flags.has_username as u8) << 7
+            | (flags.has_password as u8) << 6
+            | will
+            | (flags.clean_session as u8) << 1
     }
 }
 
-impl Debug for Flags {
-    bitfield_debug! {
-        struct Flags;
-        pub has_username, _       : 7;
-        pub has_password, _       : 6;
-        pub will_retain, _        : 5;
-        pub into QoS, will_qos, _ : 4, 3;
-        pub has_will, _           : 2;
-        pub clean_session, _      : 1;
+bits_debug!(Flags);
+
+/// The keep alive interval a client sends in CONNECT, zero on the wire
+/// disables it.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum KeepAliveInterval {
+    Disabled,
+    Seconds(NonZeroU16),
+}
+
+impl KeepAliveInterval {
+    /// The keep alive interval, `None` if disabled.
+    pub fn interval(self) -> Option<Duration> {
+        match self {
+            KeepAliveInterval::Disabled => None,
+            KeepAliveInterval::Seconds(secs) => Some(Duration::from_secs(u32::from(secs.get()))),
+        }
+    }
+}
+
+impl KeepAliveInterval {
+    /// The interval as sent on the wire, zero disables it.
+    pub const fn from_secs(secs: u16) -> Self {
+        match NonZeroU16::new(secs) {
+            Some(secs) => KeepAliveInterval::Seconds(secs),
+            None => KeepAliveInterval::Disabled,
+        }
+    }
+
+    /// The interval as sent on the wire, zero if disabled.
+    pub const fn as_secs(self) -> u16 {
+        match self {
+            KeepAliveInterval::Disabled => 0,
+            KeepAliveInterval::Seconds(secs) => secs.get(),
+        }
+    }
+}
+
+impl From<u16> for KeepAliveInterval {
+    fn from(secs: u16) -> Self {
+        KeepAliveInterval::from_secs(secs)
+    }
+}
+
+impl From<KeepAliveInterval> for u16 {
+    fn from(keep_alive: KeepAliveInterval) -> u16 {
+        keep_alive.as_secs()
     }
 }
 
@@ -106,11 +296,16 @@ pub struct Connect<'buf> {
     name: &'buf str,
     level: Level,
     flags: Flags,
-    keep_alive: u16,
+    keep_alive: KeepAliveInterval,
 }
 
 impl<'buf> Connect<'buf> {
-    pub fn new(protocol: Protocol, level: Level, flags: Flags, keep_alive: u16) -> Self {
+    pub fn new(
+        protocol: Protocol,
+        level: Level,
+        flags: Flags,
+        keep_alive: KeepAliveInterval,
+    ) -> Self {
         let name = protocol.name();
         Connect {
             name,
@@ -132,11 +327,12 @@ impl<'buf> Connect<'buf> {
         self.flags
     }
 
-    pub fn keep_alive(&self) -> u16 {
+    pub fn keep_alive(&self) -> KeepAliveInterval {
         self.keep_alive
     }
 }
 
+#[cfg(feature = "decode")]
 impl<'buf> HeaderDecode<'buf> for Connect<'buf> {
     fn decode(
         _flags: PacketFlags,
@@ -146,6 +342,9 @@ impl<'buf> HeaderDecode<'buf> for Connect<'buf> {
 
         // read protocol name
         let (offset, name) = read!(codec::string::parse_string, bytes, offset);
+        if name != Protocol::MQTT.name() {
+            return Err(DecodeError::InvalidProtocolName);
+        }
 
         // read protocol revision
         let (offset, level) = read!(codec::values::parse_u8, bytes, offset);
@@ -160,13 +359,7 @@ impl<'buf> HeaderDecode<'buf> for Connect<'buf> {
         // read protocol flags
         let (offset, flags) = read!(codec::values::parse_u8, bytes, offset);
 
-        let flags = Flags(flags);
-
-        if let Err(e) = flags.will_qos() {
-            match e {
-                qos::Error::BadPattern => return Err(DecodeError::InvalidConnectFlag),
-            }
-        }
+        let flags = Flags::try_from(flags)?;
 
         // read protocol keep alive
         let (offset, keep_alive) = read!(codec::values::parse_u16, bytes, offset);
@@ -177,12 +370,13 @@ impl<'buf> HeaderDecode<'buf> for Connect<'buf> {
                 name,
                 level,
                 flags,
-                keep_alive,
+                keep_alive: keep_alive.into(),
             },
         )))
     }
 }
 
+#[cfg(feature = "encode")]
 impl<'buf> Encodable for Connect<'buf> {
     fn encoded_len(&self) -> usize {
         self.name.encoded_len() + 1 + 1 + 2
@@ -193,7 +387,7 @@ impl<'buf> Encodable for Connect<'buf> {
         offset += codec::string::encode_string(self.name, &mut bytes[offset..])?;
         offset += codec::values::encode_u8(self.level.into(), &mut bytes[offset..])?;
         offset += codec::values::encode_u8(self.flags.into(), &mut bytes[offset..])?;
-        offset += codec::values::encode_u16(self.keep_alive, &mut bytes[offset..])?;
+        offset += codec::values::encode_u16(self.keep_alive.into(), &mut bytes[offset..])?;
         Ok(offset)
     }
 }
@@ -203,34 +397,79 @@ mod tests {
     use super::*;
 
     #[test]
-    fn parse_flags() {
-        let flags = Flags(0b11100110);
-        assert_eq!(flags.has_username(), true);
-        assert_eq!(flags.has_password(), true);
-        assert_eq!(flags.will_retain(), true);
-        assert_eq!(flags.has_will(), true);
-        assert_eq!(flags.clean_session(), true);
-
-        let flags = Flags(0b00000000);
-        assert_eq!(flags.has_username(), false);
-        assert_eq!(flags.has_password(), false);
-        assert_eq!(flags.will_retain(), false);
-        assert_eq!(flags.has_will(), false);
-        assert_eq!(flags.clean_session(), false);
+    fn build_flags() {
+        let flags = Flags::builder().clean_session(true).build();
+        assert_eq!(0b0000_0010, u8::from(flags));
+
+        let flags = Flags::builder()
+            .username()
+            .password(true)
+            .will(qos::QoS::AtLeastOnce, true)
+            .clean_session(true)
+            .build();
+        assert_eq!(0b1110_1110, u8::from(flags));
+        assert_eq!(
+            Some(WillFlags {
+                qos: qos::QoS::AtLeastOnce,
+                retain: true
+            }),
+            flags.will
+        );
     }
 
     #[test]
-    fn parse_qos() {
-        let flags = Flags(0b00010000);
-        assert_eq!(flags.will_qos(), Ok(qos::QoS::ExactlyOnce));
+    fn parse_flags() {
+        let flags = Flags::try_from(0b11110110).unwrap();
+        assert!(flags.has_username);
+        assert!(flags.has_password);
+        assert!(flags.clean_session);
+        assert_eq!(
+            Some(WillFlags {
+                qos: qos::QoS::ExactlyOnce,
+                retain: true
+            }),
+            flags.will
+        );
+        assert_eq!(0b11110110, u8::from(flags));
 
-        let flags = Flags(0b00001000);
-        assert_eq!(flags.will_qos(), Ok(qos::QoS::AtLeastOnce));
+        assert_eq!(Ok(Flags::default()), Flags::try_from(0b00000000));
+    }
 
-        let flags = Flags(0b00000000);
-        assert_eq!(flags.will_qos(), Ok(qos::QoS::AtMostOnce));
+    #[test]
+    fn invalid_flags() {
+        // reserved bit
+        assert_eq!(
+            Err(DecodeError::InvalidConnectFlag(ConnectFlagError::Reserved)),
+            Flags::try_from(0b00000001)
+        );
+        // will QoS 3
+        assert_eq!(
+            Err(DecodeError::InvalidConnectFlag(ConnectFlagError::WillQoS)),
+            Flags::try_from(0b00011100)
+        );
+        // will QoS and retain without a will
+        assert_eq!(
+            Err(DecodeError::InvalidConnectFlag(
+                ConnectFlagError::WillQoSWithoutWill
+            )),
+            Flags::try_from(0b00001000)
+        );
+        assert_eq!(
+            Err(DecodeError::InvalidConnectFlag(
+                ConnectFlagError::WillRetainWithoutWill
+            )),
+            Flags::try_from(0b00100000)
+        );
+        // password without a username
+        assert_eq!(
+            Err(DecodeError::InvalidConnectFlag(
+                ConnectFlagError::PasswordWithoutUsername
+            )),
+            Flags::try_from(0b01000000)
+        );
     }
 
+    #[cfg(feature = "decode")]
     #[test]
     fn parse_connect() {
         let buf = [
@@ -260,10 +499,21 @@ mod tests {
                 Connect {
                     name: "MQTT",
                     level: Level::Level3_1_1,
-                    flags: Flags(0b11001110),
-                    keep_alive: 10,
+                    flags: Flags::try_from(0b11001110).unwrap(),
+                    keep_alive: KeepAliveInterval::from(10),
                 }
             )))
         );
     }
+
+    #[test]
+    fn keep_alive() {
+        assert_eq!(KeepAliveInterval::Disabled, KeepAliveInterval::from(0));
+        assert_eq!(None, KeepAliveInterval::Disabled.interval());
+        assert_eq!(0, u16::from(KeepAliveInterval::Disabled));
+
+        let keep_alive = KeepAliveInterval::from(60);
+        assert_eq!(Some(Duration::from_secs(60)), keep_alive.interval());
+        assert_eq!(60, u16::from(keep_alive));
+    }
 }
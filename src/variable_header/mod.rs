@@ -1,11 +1,11 @@
-use core::result::Result;
+use core::{fmt, result::Result};
 
-use crate::{
-    codec::Encodable,
-    error::{DecodeError, EncodeError},
-    fixed_header::{PacketFlags, PacketType},
-    status::Status,
-};
+use crate::{error::DecodeError, fixed_header::PacketFlags, status::Status};
+
+#[cfg(feature = "decode")]
+use crate::fixed_header::PacketType;
+#[cfg(feature = "encode")]
+use crate::{codec::Encodable, error::EncodeError};
 
 pub mod connack;
 pub mod connect;
@@ -20,6 +20,30 @@ pub enum VariableHeader<'a> {
     Suback(packet_identifier::PacketIdentifier),
     Publish(publish::Publish<'a>),
     Puback(packet_identifier::PacketIdentifier),
+    Unsubscribe(packet_identifier::PacketIdentifier),
+    Unsuback(packet_identifier::PacketIdentifier),
+    Pubrec(packet_identifier::PacketIdentifier),
+    Pubrel(packet_identifier::PacketIdentifier),
+    Pubcomp(packet_identifier::PacketIdentifier),
+}
+
+impl<'a> VariableHeader<'a> {
+    /// The packet identifier of a PUBLISH at QoS 1 or 2, an acknowledgement
+    /// or a (UN)SUBSCRIBE, `None` for other packets.
+    pub fn packet_identifier(&self) -> Option<PacketId> {
+        match self {
+            VariableHeader::Publish(publish) => publish.packet_identifier(),
+            VariableHeader::Subscribe(id)
+            | VariableHeader::Suback(id)
+            | VariableHeader::Puback(id)
+            | VariableHeader::Unsubscribe(id)
+            | VariableHeader::Unsuback(id)
+            | VariableHeader::Pubrec(id)
+            | VariableHeader::Pubrel(id)
+            | VariableHeader::Pubcomp(id) => Some(id.packet_identifier()),
+            VariableHeader::Connect(_) | VariableHeader::Connack(_) => None,
+        }
+    }
 }
 
 pub trait HeaderDecode<'a>
@@ -29,8 +53,49 @@ where
     fn decode(flags: PacketFlags, bytes: &'a [u8]) -> Result<Status<(usize, Self)>, DecodeError>;
 }
 
-pub type PacketId = u16;
+/// The identifier of a packet in a QoS 1 or QoS 2 exchange or a subscription
+/// request.
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Default, Debug)]
+pub struct PacketId(u16);
+
+impl PacketId {
+    pub const fn new(id: u16) -> Self {
+        PacketId(id)
+    }
+
+    pub const fn get(self) -> u16 {
+        self.0
+    }
+
+    /// The identifier to use after this one, wrapping from 65535 back to 1
+    /// since zero is not a valid identifier [MQTT-2.3.1-1].
+    pub fn next(self) -> Self {
+        match self.0.wrapping_add(1) {
+            0 => PacketId(1),
+            id => PacketId(id),
+        }
+    }
+}
 
+impl From<u16> for PacketId {
+    fn from(id: u16) -> Self {
+        PacketId(id)
+    }
+}
+
+impl From<PacketId> for u16 {
+    fn from(id: PacketId) -> Self {
+        id.0
+    }
+}
+
+impl fmt::Display for PacketId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(&self.0, f)
+    }
+}
+
+#[cfg(feature = "decode")]
 macro_rules! decode {
     ($($name:ident, $parser:path;)+) => (
         pub fn decode(r#type: PacketType, flags: PacketFlags, bytes: &'a [u8]) -> Option<Result<Status<(usize, Self)>, DecodeError>> {
@@ -51,6 +116,7 @@ macro_rules! decode {
     )
 }
 
+#[cfg(feature = "decode")]
 impl<'a> VariableHeader<'a> {
     decode!(
         Connect,   connect::Connect::decode;
@@ -59,9 +125,15 @@ impl<'a> VariableHeader<'a> {
         Suback,    packet_identifier::PacketIdentifier::decode;
         Publish,   publish::Publish::decode;
         Puback,    packet_identifier::PacketIdentifier::decode;
+        Pubrec,    packet_identifier::PacketIdentifier::decode;
+        Pubrel,    packet_identifier::PacketIdentifier::decode;
+        Pubcomp,   packet_identifier::PacketIdentifier::decode;
+        Unsubscribe, packet_identifier::PacketIdentifier::decode;
+        Unsuback,  packet_identifier::PacketIdentifier::decode;
     );
 }
 
+#[cfg(feature = "encode")]
 macro_rules! encode {
     ($($enum:ident;)+) => (
         fn encoded_len(&self) -> usize {
@@ -78,6 +150,7 @@ macro_rules! encode {
     )
 }
 
+#[cfg(feature = "encode")]
 impl<'buf> Encodable for VariableHeader<'buf> {
     encode!(
         Connect;
@@ -86,5 +159,23 @@ impl<'buf> Encodable for VariableHeader<'buf> {
         Suback;
         Publish;
         Puback;
+        Unsubscribe;
+        Unsuback;
+        Pubrec;
+        Pubrel;
+        Pubcomp;
     );
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn packet_id_next() {
+        assert_eq!(PacketId::new(2), PacketId::new(1).next());
+        assert_eq!(PacketId::new(1), PacketId::new(u16::MAX).next());
+        assert_eq!(PacketId::new(1), PacketId::default().next());
+        assert_eq!("513", std::format!("{}", PacketId::from(513)));
+    }
+}
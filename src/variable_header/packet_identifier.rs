@@ -1,13 +1,16 @@
+#[cfg(any(feature = "encode", feature = "decode"))]
 use core::result::Result;
 
-use crate::{
-    codec::{self, Encodable},
-    error::{DecodeError, EncodeError},
-    fixed_header::PacketFlags,
-    status::Status,
-};
+#[cfg(any(feature = "encode", feature = "decode"))]
+use crate::codec;
+#[cfg(feature = "encode")]
+use crate::{codec::Encodable, error::EncodeError};
+#[cfg(feature = "decode")]
+use crate::{error::DecodeError, fixed_header::PacketFlags, status::Status};
 
-use super::{HeaderDecode, PacketId};
+#[cfg(feature = "decode")]
+use super::HeaderDecode;
+use super::PacketId;
 
 // TODO make this a non-zero u16 when it is stable
 #[derive(PartialEq, Debug)]
@@ -23,6 +26,7 @@ impl PacketIdentifier {
     }
 }
 
+#[cfg(feature = "decode")]
 impl<'buf> HeaderDecode<'buf> for PacketIdentifier {
     fn decode(
         _flags: PacketFlags,
@@ -31,17 +35,21 @@ impl<'buf> HeaderDecode<'buf> for PacketIdentifier {
         // read connack flags
         let (offset, packet_identifier) = read!(codec::values::parse_u16, bytes, 0);
 
-        Ok(Status::Complete((offset, Self(packet_identifier))))
+        Ok(Status::Complete((
+            offset,
+            Self(PacketId::new(packet_identifier)),
+        )))
     }
 }
 
+#[cfg(feature = "encode")]
 impl Encodable for PacketIdentifier {
     fn encoded_len(&self) -> usize {
         2
     }
 
     fn encode(&self, bytes: &mut [u8]) -> Result<usize, EncodeError> {
-        codec::values::encode_u16(self.0, bytes)
+        codec::values::encode_u16(self.0.get(), bytes)
     }
 }
 
@@ -1,25 +1,44 @@
-use core::{convert::TryFrom, result::Result};
+#[cfg(feature = "decode")]
+use core::convert::TryFrom;
+use core::fmt;
+#[cfg(any(feature = "encode", feature = "decode"))]
+use core::result::Result;
+
+#[cfg(any(feature = "encode", feature = "decode"))]
+use crate::codec;
+#[cfg(feature = "encode")]
+use crate::{codec::Encodable, error::EncodeError};
+use crate::{debug::Preview, fixed_header::PublishFlags, qos};
+#[cfg(feature = "decode")]
+use crate::{error::DecodeError, fixed_header::PacketFlags, status::Status};
+
+#[cfg(feature = "decode")]
+use super::HeaderDecode;
+use super::PacketId;
 
-use crate::{
-    codec::{self, Encodable},
-    error::{DecodeError, EncodeError},
-    fixed_header::{PacketFlags, PublishFlags},
-    qos,
-    status::Status,
-};
-
-use super::{HeaderDecode, PacketId};
-
-#[derive(Debug)]
 pub struct Publish<'a> {
     topic_name: &'a str,
     packet_identifier: Option<PacketId>,
 }
 
+impl<'a> fmt::Debug for Publish<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Publish")
+            .field("topic_name", &Preview(self.topic_name))
+            .field("packet_identifier", &self.packet_identifier)
+            .finish()
+    }
+}
+
 impl<'a> Publish<'a> {
-    pub fn new(topic_name: &'a str, packet_identifier: Option<PacketId>) -> Self {
+    /// Create a PUBLISH variable header, the topic name can be borrowed from
+    /// any string type such as a `heapless::String`.
+    pub fn new<T>(topic_name: &'a T, packet_identifier: Option<PacketId>) -> Self
+    where
+        T: AsRef<str> + ?Sized,
+    {
         Self {
-            topic_name,
+            topic_name: topic_name.as_ref(),
             packet_identifier,
         }
     }
@@ -31,8 +50,122 @@ impl<'a> Publish<'a> {
     pub fn packet_identifier(&self) -> Option<PacketId> {
         self.packet_identifier
     }
+
+    /// A message to `topic_name` delivered at most once, without a packet
+    /// identifier.
+    pub fn at_most_once<T>(topic_name: &'a T) -> Outgoing<'a>
+    where
+        T: AsRef<str> + ?Sized,
+    {
+        Outgoing::new(topic_name.as_ref(), Delivery::AtMostOnce)
+    }
+
+    /// A message to `topic_name` delivered at least once, acknowledged by a
+    /// PUBACK with `packet_identifier`.
+    pub fn at_least_once<T>(topic_name: &'a T, packet_identifier: PacketId) -> Outgoing<'a>
+    where
+        T: AsRef<str> + ?Sized,
+    {
+        Outgoing::new(
+            topic_name.as_ref(),
+            Delivery::AtLeastOnce(packet_identifier),
+        )
+    }
+
+    /// A message to `topic_name` delivered exactly once, acknowledged by a
+    /// PUBREC with `packet_identifier`.
+    pub fn exactly_once<T>(topic_name: &'a T, packet_identifier: PacketId) -> Outgoing<'a>
+    where
+        T: AsRef<str> + ?Sized,
+    {
+        Outgoing::new(
+            topic_name.as_ref(),
+            Delivery::ExactlyOnce(packet_identifier),
+        )
+    }
+}
+
+/// The QoS of a PUBLISH together with the packet identifier QoS 1 and 2
+/// require [MQTT-2.3.1-1].
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Delivery {
+    AtMostOnce,
+    AtLeastOnce(PacketId),
+    ExactlyOnce(PacketId),
+}
+
+impl Delivery {
+    pub fn qos(self) -> qos::QoS {
+        match self {
+            Delivery::AtMostOnce => qos::QoS::AtMostOnce,
+            Delivery::AtLeastOnce(_) => qos::QoS::AtLeastOnce,
+            Delivery::ExactlyOnce(_) => qos::QoS::ExactlyOnce,
+        }
+    }
+
+    pub fn packet_identifier(self) -> Option<PacketId> {
+        match self {
+            Delivery::AtMostOnce => None,
+            Delivery::AtLeastOnce(packet_identifier) | Delivery::ExactlyOnce(packet_identifier) => {
+                Some(packet_identifier)
+            }
+        }
+    }
 }
 
+/// A PUBLISH variable header and flags whose QoS is chosen when it is created
+/// by `Publish::at_most_once`, `at_least_once` or `exactly_once`, so a packet
+/// identifier is present exactly when the QoS needs one.
+///
+/// Encoded with `Packet::publish_message`.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct Outgoing<'a> {
+    topic_name: &'a str,
+    delivery: Delivery,
+    dup: bool,
+    retain: bool,
+}
+
+impl<'a> Outgoing<'a> {
+    fn new(topic_name: &'a str, delivery: Delivery) -> Self {
+        Outgoing {
+            topic_name,
+            delivery,
+            dup: false,
+            retain: false,
+        }
+    }
+
+    /// Ask the broker to keep the message for future subscribers.
+    pub fn retain(mut self) -> Self {
+        self.retain = true;
+        self
+    }
+
+    /// Mark a QoS 1 or 2 message as a redelivery, the DUP flag of a QoS 0
+    /// message is always unset [MQTT-3.3.1-2].
+    pub fn dup(mut self) -> Self {
+        self.dup = self.delivery != Delivery::AtMostOnce;
+        self
+    }
+
+    pub fn delivery(&self) -> Delivery {
+        self.delivery
+    }
+
+    /// The flags of the fixed header and the variable header.
+    pub fn into_parts(self) -> (PublishFlags, Publish<'a>) {
+        (
+            PublishFlags::new(self.delivery.qos(), self.dup, self.retain),
+            Publish {
+                topic_name: self.topic_name,
+                packet_identifier: self.delivery.packet_identifier(),
+            },
+        )
+    }
+}
+
+#[cfg(feature = "decode")]
 impl<'a> HeaderDecode<'a> for Publish<'a> {
     fn decode(flags: PacketFlags, bytes: &'a [u8]) -> Result<Status<(usize, Self)>, DecodeError> {
         let flags = PublishFlags::try_from(flags)?;
@@ -40,9 +173,9 @@ impl<'a> HeaderDecode<'a> for Publish<'a> {
         let offset = 0;
         let (offset, topic_name) = read!(codec::string::parse_string, bytes, offset);
 
-        let (offset, packet_identifier) = if flags.qos()? != qos::QoS::AtMostOnce {
+        let (offset, packet_identifier) = if flags.qos != qos::QoS::AtMostOnce {
             let (offset, packet_identifier) = read!(codec::values::parse_u16, bytes, offset);
-            (offset, Some(packet_identifier))
+            (offset, Some(PacketId::new(packet_identifier)))
         } else {
             (offset, None)
         };
@@ -57,6 +190,7 @@ impl<'a> HeaderDecode<'a> for Publish<'a> {
     }
 }
 
+#[cfg(feature = "encode")]
 impl<'a> Encodable for Publish<'a> {
     fn encoded_len(&self) -> usize {
         self.topic_name.encoded_len() + self.packet_identifier.map(|_| 2).unwrap_or(0)
@@ -66,7 +200,7 @@ impl<'a> Encodable for Publish<'a> {
         let mut offset = 0;
         offset += self.topic_name.encode(&mut bytes[offset..])?;
         if let Some(packet_identifier) = self.packet_identifier {
-            offset += codec::values::encode_u16(packet_identifier, &mut bytes[offset..])?;
+            offset += codec::values::encode_u16(packet_identifier.get(), &mut bytes[offset..])?;
         }
         Ok(offset)
     }
@@ -76,11 +210,12 @@ impl<'a> Encodable for Publish<'a> {
 mod tests {
     use super::*;
 
+    #[cfg(feature = "encode")]
     #[test]
     fn encode() {
         let header = Publish {
             topic_name: "a/b",
-            packet_identifier: Some(1),
+            packet_identifier: Some(PacketId::new(1)),
         };
 
         assert_eq!(7, header.encoded_len());
@@ -102,4 +237,18 @@ mod tests {
             ]
         );
     }
+
+    #[test]
+    fn outgoing() {
+        let (flags, header) = Publish::at_most_once("a/b").retain().dup().into_parts();
+        assert_eq!(PublishFlags::new(qos::QoS::AtMostOnce, false, true), flags);
+        assert_eq!(None, header.packet_identifier());
+
+        let (flags, header) = Publish::exactly_once("a/b", PacketId::new(7))
+            .dup()
+            .into_parts();
+        assert_eq!(PublishFlags::new(qos::QoS::ExactlyOnce, true, false), flags);
+        assert_eq!(Some(PacketId::new(7)), header.packet_identifier());
+        assert_eq!("a/b", header.topic_name());
+    }
 }
@@ -0,0 +1,212 @@
+//! Lazy access to the fields of an encoded packet.
+//!
+//! Building a `Packet` parses the whole variable header and payload. A
+//! `PacketView` only decodes the fixed header up front and parses a field when
+//! it is asked for, for hot paths that count or forward packets and look at one
+//! or two fields at most.
+
+use core::{convert::TryFrom, result::Result};
+
+use crate::{
+    codec,
+    error::DecodeError,
+    fixed_header::{FixedHeader, PacketKind, PacketType, PublishFlags},
+    qos,
+    status::Status,
+    variable_header::PacketId,
+};
+
+/// An encoded packet whose fixed header has been decoded.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct PacketView<'a> {
+    fixed_header: FixedHeader,
+    bytes: &'a [u8],
+    body_offset: usize,
+}
+
+impl<'a> PacketView<'a> {
+    /// Decode the fixed header of the packet at the start of `bytes` and check
+    /// the rest of the packet has been received.
+    ///
+    /// Returns the number of bytes the packet occupies and the view, or how
+    /// many more bytes are needed.
+    pub fn decode(bytes: &'a [u8]) -> Result<Status<(usize, Self)>, DecodeError> {
+        let (body_offset, fixed_header) = complete!(FixedHeader::decode_with(
+            bytes,
+            &codec::DecodeOptions::default()
+        ));
+
+        let end = body_offset + usize::try_from(fixed_header.len())?;
+        if bytes.len() < end {
+            return Ok(Status::Partial(end - bytes.len()));
+        }
+
+        Ok(Status::Complete((
+            end,
+            Self {
+                fixed_header,
+                bytes: &bytes[..end],
+                body_offset,
+            },
+        )))
+    }
+
+    pub fn fixed_header(&self) -> &FixedHeader {
+        &self.fixed_header
+    }
+
+    /// The type of the packet with the flags of a PUBLISH, see
+    /// `FixedHeader::kind`.
+    pub fn kind(&self) -> Result<PacketKind, DecodeError> {
+        self.fixed_header.kind()
+    }
+
+    /// The complete encoded packet, for forwarding it unchanged.
+    pub fn as_bytes(&self) -> &'a [u8] {
+        self.bytes
+    }
+
+    /// The undecoded variable header and payload.
+    pub fn body(&self) -> &'a [u8] {
+        &self.bytes[self.body_offset..]
+    }
+
+    /// The flags of a PUBLISH, `None` for other packet types.
+    pub fn publish_flags(&self) -> Option<PublishFlags> {
+        if self.fixed_header.r#type() != PacketType::Publish {
+            return None;
+        }
+
+        PublishFlags::try_from(self.fixed_header.flags()).ok()
+    }
+
+    /// The topic name of a PUBLISH, `None` for other packet types.
+    pub fn topic_name(&self) -> Result<Option<&'a str>, DecodeError> {
+        if self.fixed_header.r#type() != PacketType::Publish {
+            return Ok(None);
+        }
+
+        let (_, topic_name) = field(codec::string::parse_string(self.body()))?;
+        Ok(Some(topic_name))
+    }
+
+    /// The packet identifier, `None` for packet types without one and for a
+    /// QoS 0 PUBLISH.
+    pub fn packet_identifier(&self) -> Result<Option<PacketId>, DecodeError> {
+        let offset = match self.fixed_header.r#type() {
+            PacketType::Publish => {
+                if self.qos()? == qos::QoS::AtMostOnce {
+                    return Ok(None);
+                }
+                self.topic_end()?
+            }
+            PacketType::Puback
+            | PacketType::Pubrec
+            | PacketType::Pubrel
+            | PacketType::Pubcomp
+            | PacketType::Subscribe
+            | PacketType::Suback
+            | PacketType::Unsubscribe
+            | PacketType::Unsuback => 0,
+            _ => return Ok(None),
+        };
+
+        let (_, packet_identifier) = field(codec::values::parse_u16(&self.body()[offset..]))?;
+        Ok(Some(PacketId::new(packet_identifier)))
+    }
+
+    /// The application message of a PUBLISH, `None` for other packet types.
+    pub fn payload(&self) -> Result<Option<&'a [u8]>, DecodeError> {
+        if self.fixed_header.r#type() != PacketType::Publish {
+            return Ok(None);
+        }
+
+        let mut offset = self.topic_end()?;
+        if self.qos()? != qos::QoS::AtMostOnce {
+            offset += 2;
+        }
+
+        self.body()
+            .get(offset..)
+            .map(Some)
+            .ok_or(DecodeError::InvalidLength)
+    }
+
+    fn qos(&self) -> Result<qos::QoS, DecodeError> {
+        Ok(PublishFlags::try_from(self.fixed_header.flags())?.qos)
+    }
+
+    fn topic_end(&self) -> Result<usize, DecodeError> {
+        field(codec::string::parse_string(self.body())).map(|(offset, _)| offset)
+    }
+}
+
+/// A field cut short by the end of the packet is malformed, not partial.
+fn field<T>(result: Result<Status<(usize, T)>, DecodeError>) -> Result<(usize, T), DecodeError> {
+    match result? {
+        Status::Complete(field) => Ok(field),
+        Status::Partial(_) => Err(DecodeError::InvalidLength),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn publish() {
+        let bytes = [
+            0x33, 0x09, 0x00, 0x03, b'a', b'/', b'b', 0x12, 0x34, b'{', b'}', 0xc0,
+        ];
+
+        let (consumed, view) = PacketView::decode(&bytes).unwrap().unwrap();
+        assert_eq!(11, consumed);
+        assert_eq!(&bytes[..11], view.as_bytes());
+        assert_eq!(Ok(Some("a/b")), view.topic_name());
+        assert_eq!(Ok(Some(PacketId::new(0x1234))), view.packet_identifier());
+        assert_eq!(Ok(Some(&b"{}"[..])), view.payload());
+
+        let flags = view.publish_flags().unwrap();
+        assert_eq!(qos::QoS::AtLeastOnce, flags.qos);
+        assert!(flags.retain);
+
+        assert_eq!(Ok(Status::Partial(1)), PacketView::decode(&bytes[..10]));
+    }
+
+    #[test]
+    fn other_types() {
+        let puback = [0x40, 0x02, 0x00, 0x07];
+        let (_, view) = PacketView::decode(&puback).unwrap().unwrap();
+        assert_eq!(None, view.publish_flags());
+        assert_eq!(Ok(None), view.topic_name());
+        assert_eq!(Ok(Some(PacketId::new(7))), view.packet_identifier());
+        assert_eq!(Ok(None), view.payload());
+
+        let truncated = [0x40, 0x01, 0x00];
+        let (_, view) = PacketView::decode(&truncated).unwrap().unwrap();
+        assert_eq!(Err(DecodeError::InvalidLength), view.packet_identifier());
+    }
+
+    #[test]
+    fn kind() {
+        let (_, view) = PacketView::decode(&[0x40, 0x02, 0x00, 0x07])
+            .unwrap()
+            .unwrap();
+        assert_eq!(Ok(PacketKind::Puback), view.kind());
+
+        let (_, view) = PacketView::decode(&[0x3b, 0x00]).unwrap().unwrap();
+        let kind = view.kind().unwrap();
+        assert_eq!(PacketType::Publish, kind.r#type());
+        assert_eq!(
+            Some(PublishFlags::new(qos::QoS::AtLeastOnce, true, true)),
+            kind.publish_flags()
+        );
+
+        // both QoS bits set
+        let (_, view) = PacketView::decode(&[0x36, 0x00]).unwrap().unwrap();
+        assert_eq!(
+            Err(DecodeError::InvalidQoS(qos::Error::BadPattern)),
+            view.kind()
+        );
+    }
+}